@@ -13,6 +13,8 @@ fn main() {
     BuildEnv::request_reruns();
     let config = load_config_from_env_or_default();
     config.print_boolean_feature_flags();
+    print_smp_flag(&config);
+    print_domains_flag(&config);
     println!("ferros build.rs config: {:#?}", config);
 
     let out_dir = PathBuf::from(env::var("OUT_DIR").expect("Required env var OUT_DIR not set"));
@@ -20,7 +22,40 @@ fn main() {
         panic!("OUT_DIR is not an extant directory");
     }
     generate_root_task_stack_types(&out_dir, &config);
-    generate_kernel_retype_fan_out_limit_types(&out_dir, &config)
+    generate_kernel_retype_fan_out_limit_types(&out_dir, &config);
+    generate_alloc_seed(&out_dir, &config)
+}
+
+/// `print_boolean_feature_flags` only passes along boolean properties,
+/// so the node count is turned into a `KernelSMP` cfg here.
+fn print_smp_flag(config: &Contextualized) {
+    const NODES_PROP: &'static str = "KernelMaxNumNodes";
+    match config.sel4_config.get(NODES_PROP) {
+        None => (),
+        Some(SingleValue::Integer(i)) if *i > 1 => println!("cargo:rustc-cfg=KernelSMP"),
+        Some(SingleValue::Integer(i)) if *i == 1 => (),
+        Some(_) => panic!(
+            "{} sel4.toml property is required to be a positive integer",
+            NODES_PROP
+        ),
+    }
+}
+
+/// Likewise, the domain count is turned into a `KernelMultipleDomains`
+/// cfg, as only then is there any use for the domain set cap.
+fn print_domains_flag(config: &Contextualized) {
+    const DOMAINS_PROP: &'static str = "KernelNumDomains";
+    match config.sel4_config.get(DOMAINS_PROP) {
+        None => (),
+        Some(SingleValue::Integer(i)) if *i > 1 => {
+            println!("cargo:rustc-cfg=KernelMultipleDomains")
+        }
+        Some(SingleValue::Integer(i)) if *i == 1 => (),
+        Some(_) => panic!(
+            "{} sel4.toml property is required to be a positive integer",
+            DOMAINS_PROP
+        ),
+    }
 }
 
 fn generate_root_task_stack_types(out_dir: &Path, config: &Contextualized) {
@@ -111,6 +146,26 @@ fn generate_kernel_retype_fan_out_limit_types(out_dir: &Path, config: &Contextua
         .expect(&format!("Could not write to {}", FILE_NAME))
 }
 
+/// The seed for the `deterministic_alloc` feature's ordering of
+/// untypeds, from the optional `alloc_seed` metadata property.
+fn generate_alloc_seed(out_dir: &Path, config: &Contextualized) {
+    const SEED_PROP: &'static str = "alloc_seed";
+    let seed = match config.metadata.get(SEED_PROP) {
+        None => 0,
+        Some(SingleValue::Integer(i)) if *i >= 0 => *i as u64,
+        Some(_) => panic!(
+            "{} metadata property is required to be a non-negative integer",
+            SEED_PROP
+        ),
+    };
+    let seed_const = format!("pub const ALLOC_SEED: u64 = {};", seed);
+    const FILE_NAME: &'static str = "ALLOC_SEED";
+    let mut file = File::create(out_dir.join(FILE_NAME))
+        .expect(&format!("Could not create {} file", FILE_NAME));
+    file.write_all(seed_const.as_bytes())
+        .expect(&format!("Could not write to {}", FILE_NAME))
+}
+
 fn is_typenum_const(check: u64) -> bool {
     check.is_power_of_two() || (check == ((check / 10) * 10)) || check <= 1024
 }
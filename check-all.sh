@@ -87,6 +87,12 @@ echo "====================== ./smart_alloc ==========================="
     cargo test
 )
 
+echo "====================== ./ferros-derive ==========================="
+(
+    cd ferros-derive
+    cargo test
+)
+
 echo "====================== ./cross_queue ==========================="
 (
     cd cross_queue
@@ -3,11 +3,8 @@
 use ferros::userland::RetypeForSetup;
 
 #[repr(C)]
+#[derive(RetypeForSetup)]
 pub struct ProcParams {
     pub number_of_hellos: u32,
     pub data: [u8; 124],
 }
-
-impl RetypeForSetup for ProcParams {
-    type Output = ProcParams;
-}
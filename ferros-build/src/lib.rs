@@ -1,5 +1,6 @@
 //! Code you might need in a build script for a program built with ferros.
 
+use lz4_flex;
 use memmap::Mmap;
 use selfe_arc;
 use std::fs::{self, File};
@@ -12,6 +13,15 @@ pub trait Resource {
     /// The name this will get in the embedded selfe-arc
     fn image_name(&self) -> &str;
     fn codegen(&self) -> String;
+    /// The name of the type generated for this resource, if any. Each
+    /// typed resource gets a field in the generated `AllResources`.
+    fn type_name(&self) -> Option<&str> {
+        None
+    }
+    /// Whether to LZ4-compress the resource before embedding it
+    fn compress(&self) -> bool {
+        false
+    }
 }
 
 /// A data file resource
@@ -35,15 +45,31 @@ impl Resource for DataResource {
 }
 
 /// An elf binary resource. This will generate a struct and an `impl ElfProc`,
-/// based on the binary's structure.
+/// based on the binary's structure. The struct holds the image's data once
+/// it has been found in the archive by `AllResources::load`.
 pub struct ElfResource {
     pub path: PathBuf,
     /// The name this will get in the embedded selfe-arc
     pub image_name: String,
     /// The name of the generated type for this elf file
     pub type_name: String,
-    /// Explicitly specify the process stack size
+    /// Explicitly specify the process stack size, as a bitsize. When
+    /// `None`, ferros' `DefaultStackBitSize` is used.
     pub stack_size_bits: Option<u8>,
+    /// Embed the image LZ4-compressed. It must then be decompressed (with
+    /// `ferros::lz4::decompress`) before being loaded, and all of its pages
+    /// are copied rather than shared with the root task's image.
+    pub compress: bool,
+}
+
+impl ElfResource {
+    /// The type to use for the generated `ElfProc::StackSizeBits`
+    fn stack_size_bits_type(&self) -> String {
+        match self.stack_size_bits {
+            Some(ssb) => format_as_typenum(ssb.into()),
+            None => "ferros::userland::DefaultStackBitSize".to_owned(),
+        }
+    }
 }
 
 /// Format n as a fully expanded typenum (in binary form), so allowing arbitrary
@@ -79,6 +105,14 @@ impl Resource for ElfResource {
         &self.image_name
     }
 
+    fn type_name(&self) -> Option<&str> {
+        Some(&self.type_name)
+    }
+
+    fn compress(&self) -> bool {
+        self.compress
+    }
+
     fn codegen(&self) -> String {
         let file = File::open(&self.path).expect(&format!(
             "ElfResource::codegen: Couldn't open file {}",
@@ -105,59 +139,158 @@ impl Resource for ElfResource {
             }
         }
 
-        let stack_size_bits = self
-            .stack_size_bits
-            .map(|ssb| ssb as u64)
-            .unwrap_or_else(|| {
-                println!(
-                    "cargo:warning=Using default stack size of 64k for elf process {}",
-                    self.image_name
-                );
-                16u64
-            });
-
-        let required_memory_bits = (writable_pages as f64).log2().ceil() as u32 + 12;
-        let required_pages = (1 << (required_memory_bits - 12)) + read_only_pages;
+        // A compressed image gets decompressed into memory which isn't part
+        // of the user image, so its read-only pages must be copied too.
+        let (copied_pages, shared_pages) = if self.compress {
+            (writable_pages + read_only_pages, 0)
+        } else {
+            (writable_pages, read_only_pages)
+        };
+        let required_memory_bits = (copied_pages as f64).log2().ceil() as u32 + 12;
+        let required_pages = (1 << (required_memory_bits - 12)) + shared_pages;
+        let decompressed_size = if self.compress {
+            format!("Some({})", data.len())
+        } else {
+            "None".to_owned()
+        };
 
         format!(
             r#"
-pub struct {} {{ }}
-impl ferros::vspace::ElfProc for {} {{
+pub struct {type_name} {{
+    pub data: &'static [u8],
+}}
+impl ferros::vspace::ElfProc for {type_name} {{
     const IMAGE_NAME: &'static str = "{}";
     type RequiredPages = {};
     type WritablePages = {};
     type RequiredMemoryBits = {};
     type StackSizeBits = {};
+    const DECOMPRESSED_SIZE: Option<usize> = {};
+}}
+impl {type_name} {{
+    /// The bitsize of this process' stack, as configured in the build script.
+    pub const STACK_SIZE_BITS: u8 =
+        <<{type_name} as ferros::vspace::ElfProc>::StackSizeBits as typenum::Unsigned>::U8;
 }}
 "#,
-            self.type_name,
-            self.type_name,
             self.image_name,
             format_as_typenum(required_pages),
             format_as_typenum(writable_pages),
             format_as_typenum(required_memory_bits.into()),
-            format_as_typenum(stack_size_bits)
+            self.stack_size_bits_type(),
+            decompressed_size,
+            type_name = self.type_name,
         )
     }
 }
 
+/// The name of the `AllResources` field for an image.
+fn field_name(image_name: &str) -> String {
+    image_name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// Generate `AllResources`, which gathers every typed resource from the
+/// archive linked into the binary.
+fn registry_codegen(typed_resources: &[(String, String)]) -> String {
+    let mut fields = "".to_owned();
+    let mut loads = "".to_owned();
+    for (field, type_name) in typed_resources {
+        fields += &format!("    pub {}: {},\n", field, type_name);
+        loads += &format!(
+            r#"            {}: {} {{
+                data: archive
+                    .file(<{} as ferros::vspace::ElfProc>::IMAGE_NAME)
+                    .expect("resource missing from the embedded archive"),
+            }},
+"#,
+            field, type_name, type_name
+        );
+    }
+
+    format!(
+        r#"
+extern "C" {{
+    static _selfe_arc_data_start: u8;
+    static _selfe_arc_data_end: usize;
+}}
+
+/// Every typed resource embedded in this binary.
+pub struct AllResources {{
+{}}}
+
+impl AllResources {{
+    /// Find every typed resource in the embedded archive, which is
+    /// part of the root task's image as described by the bootinfo;
+    /// with a `BootInfo` to hand, pass `&bootinfo.user_image`.
+    ///
+    /// Panics if the archive doesn't lie within that image, as when
+    /// called from anywhere but the root task.
+    pub fn load(
+        user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
+    ) -> AllResources {{
+        let start = unsafe {{ &_selfe_arc_data_start as *const _ as usize }};
+        let end = unsafe {{ &_selfe_arc_data_end as *const _ as usize }};
+        assert!(
+            user_image.contains_vaddr(start) && (start == end || user_image.contains_vaddr(end - 1)),
+            "the embedded archive isn't in the root task's image"
+        );
+        let archive_slice: &'static [u8] =
+            unsafe {{ core::slice::from_raw_parts(start as *const u8, end - start) }};
+        let archive = selfe_arc::read::Archive::from_slice(archive_slice);
+        AllResources {{
+{}        }}
+    }}
+}}
+"#,
+        fields, loads
+    )
+}
+
 /// Embed the given resources into a selfe-arc. If any code generation is required,
-/// put it into the file at `codegen_path`.
+/// put it into the file at `codegen_path`, along with an `AllResources` struct
+/// holding every typed resource.
 pub fn embed_resources<'a, P: AsRef<Path>, I: IntoIterator<Item = &'a dyn Resource>>(
     codegen_path: P,
     resources: I,
 ) {
     let mut code = "".to_owned();
     let mut arc_params: Vec<(String, PathBuf)> = Vec::new();
+    let mut typed_resources: Vec<(String, String)> = Vec::new();
+
+    let p = codegen_path.as_ref();
 
     for res in resources.into_iter() {
         code += &res.codegen();
         code += "\n";
 
-        arc_params.push((res.image_name().to_owned(), res.path().to_owned()));
-    }
+        if let Some(type_name) = res.type_name() {
+            typed_resources.push((field_name(res.image_name()), type_name.to_owned()));
+        }
 
-    let p = codegen_path.as_ref();
+        let arc_path = if res.compress() {
+            let data = fs::read(res.path()).expect(&format!(
+                "embed_resources: Couldn't read file {}",
+                res.path().display()
+            ));
+            let compressed_path = p.with_file_name(format!("{}.lz4", res.image_name()));
+            fs::write(&compressed_path, lz4_flex::block::compress(&data))
+                .expect("Unable to write compressed resource");
+            compressed_path
+        } else {
+            res.path().to_owned()
+        };
+        arc_params.push((res.image_name().to_owned(), arc_path));
+    }
+    code += &registry_codegen(&typed_resources);
     let _f = fs::write(p, code).expect("Unable to write generated code for resources");
 
     selfe_arc::build::link_with_archive(arc_params.iter().map(|(a, b)| (a.as_str(), b.as_path())));
@@ -185,4 +318,27 @@ mod test {
         assert_eq!(format_as_typenum(4), "typenum::UInt<typenum::UInt<typenum::UInt<typenum::UTerm, typenum::B1>, typenum::B0>, typenum::B0>".to_string());
     }
 
+    #[test]
+    fn test_stack_size_bits_type() {
+        let mut res = ElfResource {
+            path: PathBuf::from("proc"),
+            image_name: "proc".to_owned(),
+            type_name: "Proc".to_owned(),
+            stack_size_bits: None,
+            compress: false,
+        };
+        assert_eq!(
+            res.stack_size_bits_type(),
+            "ferros::userland::DefaultStackBitSize".to_string()
+        );
+
+        res.stack_size_bits = Some(3);
+        assert_eq!(res.stack_size_bits_type(), format_as_typenum(3));
+    }
+
+    #[test]
+    fn test_field_name() {
+        assert_eq!(field_name("elf-process"), "elf_process".to_string());
+        assert_eq!(field_name("Driver.v2"), "driver_v2".to_string());
+    }
 }
@@ -0,0 +1,509 @@
+//! `#[derive(RetypeForSetup)]`, for the parameter types handed to a
+//! child process or thread, and `#[derive(AddressSpaceIndependent)]`
+//! for the types of their fields.
+//!
+//! A parameter struct which is generic over a `CNodeRole` gets the
+//! usual pair: the `role::Local` version is what the child's entry
+//! point receives, and its `Output` is the same struct in
+//! `role::Child`. A struct with no role parameter is its own `Output`.
+//!
+//! The parameters are copied byte for byte onto the child's stack, so
+//! both derives implement `AddressSpaceIndependent` only when every
+//! field's type does, and refuse outright any field holding a
+//! reference or raw pointer, which would point into the parent's
+//! address space rather than the child's. Neither goes as far as
+//! requiring `Copy`: caps aren't `Copy`, and they're most of what a
+//! child is handed. Nor can they see an address smuggled through as a
+//! `usize`.
+extern crate proc_macro;
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::fold::{self, Fold};
+use syn::spanned::Spanned;
+use syn::{
+    parse_macro_input, parse_quote, Data, DeriveInput, Error as SynError, Fields, GenericArgument,
+    GenericParam, Ident, PathArguments, Type, TypeParamBound, TypePath, WherePredicate,
+};
+
+#[proc_macro_derive(RetypeForSetup)]
+pub fn derive_retype_for_setup(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match retype_for_setup_impls(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+#[proc_macro_derive(AddressSpaceIndependent)]
+pub fn derive_address_space_independent(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match address_space_independent_impl(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn address_space_independent_impl(input: &DeriveInput) -> Result<TokenStream2, SynError> {
+    if let Some(lifetime) = input.generics.lifetimes().next() {
+        return Err(SynError::new(
+            lifetime.span(),
+            "a type with lifetime parameters can't be handed to a child, \
+             as a borrow doesn't survive the trip into another address space",
+        ));
+    }
+    let field_types = field_types(&input.data, input.ident.span())?;
+    for ty in field_types.iter() {
+        check_type(ty)?;
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let mut predicates: Vec<WherePredicate> = where_clause
+        .map(|w| w.predicates.iter().cloned().collect())
+        .unwrap_or_default();
+    for ty in field_types {
+        predicates.push(parse_quote!(#ty: ferros::userland::AddressSpaceIndependent));
+    }
+    Ok(quote! {
+        unsafe impl #impl_generics ferros::userland::AddressSpaceIndependent
+            for #ident #ty_generics
+        where
+            #(#predicates,)*
+        {
+        }
+    })
+}
+
+/// A parameter type has to be `AddressSpaceIndependent` too, so its
+/// derive writes both impls.
+fn retype_for_setup_impls(input: &DeriveInput) -> Result<TokenStream2, SynError> {
+    let independent = address_space_independent_impl(input)?;
+    let retype = retype_for_setup_impl(input)?;
+    Ok(quote!(#independent #retype))
+}
+
+fn retype_for_setup_impl(input: &DeriveInput) -> Result<TokenStream2, SynError> {
+    let ident = &input.ident;
+    let role = match find_role_param(&input.generics)? {
+        Some(role) => role,
+        None => {
+            let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+            return Ok(quote! {
+                impl #impl_generics ferros::userland::RetypeForSetup for #ident #ty_generics
+                    #where_clause
+                {
+                    type Output = Self;
+                }
+            });
+        }
+    };
+
+    let mut local = RoleReplacer {
+        role: role.clone(),
+        replacement: parse_quote!(ferros::cap::role::Local),
+    };
+    let mut child = RoleReplacer {
+        role: role.clone(),
+        replacement: parse_quote!(ferros::cap::role::Child),
+    };
+
+    // Every bound on the other parameters ends up in the where clause.
+    // Any which mention the role have to hold for both the local and
+    // the child version of the type.
+    let mut impl_params: Vec<TokenStream2> = Vec::new();
+    let mut local_args: Vec<TokenStream2> = Vec::new();
+    let mut child_args: Vec<TokenStream2> = Vec::new();
+    let mut predicates: Vec<WherePredicate> = Vec::new();
+    for param in input.generics.params.iter() {
+        match param {
+            GenericParam::Type(tp) if tp.ident == role => {
+                local_args.push(quote!(ferros::cap::role::Local));
+                child_args.push(quote!(ferros::cap::role::Child));
+            }
+            GenericParam::Type(tp) => {
+                let param_ident = &tp.ident;
+                impl_params.push(quote!(#param_ident));
+                local_args.push(quote!(#param_ident));
+                child_args.push(quote!(#param_ident));
+                if !tp.bounds.is_empty() {
+                    let bounds = &tp.bounds;
+                    predicates.push(parse_quote!(#param_ident: #bounds));
+                }
+            }
+            GenericParam::Const(cp) => {
+                let param_ident = &cp.ident;
+                let ty = &cp.ty;
+                impl_params.push(quote!(const #param_ident: #ty));
+                local_args.push(quote!(#param_ident));
+                child_args.push(quote!(#param_ident));
+            }
+            GenericParam::Lifetime(_) => unreachable!("lifetimes are rejected before we get here"),
+        }
+    }
+    if let Some(where_clause) = &input.generics.where_clause {
+        for predicate in where_clause.predicates.iter() {
+            if is_role_predicate(predicate, &role) {
+                continue;
+            }
+            predicates.push(predicate.clone());
+        }
+    }
+    let mut impl_predicates: Vec<WherePredicate> = Vec::new();
+    for predicate in predicates {
+        let local_predicate = local.fold_where_predicate(predicate.clone());
+        let child_predicate = child.fold_where_predicate(predicate);
+        let differs = quote!(#local_predicate).to_string() != quote!(#child_predicate).to_string();
+        impl_predicates.push(local_predicate);
+        if differs {
+            impl_predicates.push(child_predicate);
+        }
+    }
+
+    Ok(quote! {
+        impl<#(#impl_params),*> ferros::userland::RetypeForSetup for #ident<#(#local_args),*>
+        where
+            #(#impl_predicates,)*
+        {
+            type Output = #ident<#(#child_args),*>;
+        }
+    })
+}
+
+/// The type parameter bounded by `CNodeRole`, if there is one.
+fn find_role_param(generics: &syn::Generics) -> Result<Option<Ident>, SynError> {
+    let mut roles: Vec<Ident> = generics
+        .type_params()
+        .filter(|tp| tp.bounds.iter().any(is_cnode_role_bound))
+        .map(|tp| tp.ident.clone())
+        .collect();
+    if let Some(where_clause) = &generics.where_clause {
+        for predicate in where_clause.predicates.iter() {
+            if let WherePredicate::Type(pt) = predicate {
+                if let Some(ident) = bare_ident(&pt.bounded_ty) {
+                    if pt.bounds.iter().any(is_cnode_role_bound) && !roles.contains(ident) {
+                        roles.push(ident.clone());
+                    }
+                }
+            }
+        }
+    }
+    if roles.len() > 1 {
+        return Err(SynError::new(
+            roles[1].span(),
+            "RetypeForSetup can only be derived for a type with at most one CNodeRole parameter",
+        ));
+    }
+    Ok(roles.pop())
+}
+
+fn is_cnode_role_bound(bound: &TypeParamBound) -> bool {
+    match bound {
+        TypeParamBound::Trait(tb) => tb
+            .path
+            .segments
+            .last()
+            .map(|s| s.value().ident == "CNodeRole")
+            .unwrap_or(false),
+        TypeParamBound::Lifetime(_) => false,
+    }
+}
+
+/// Whether `predicate` bounds the role parameter itself, e.g. `Role:
+/// CNodeRole`. Those are satisfied by both concrete roles.
+fn is_role_predicate(predicate: &WherePredicate, role: &Ident) -> bool {
+    match predicate {
+        WherePredicate::Type(pt) => bare_ident(&pt.bounded_ty) == Some(role),
+        _ => false,
+    }
+}
+
+fn bare_ident(ty: &Type) -> Option<&Ident> {
+    match ty {
+        Type::Path(TypePath { qself: None, path })
+            if path.leading_colon.is_none() && path.segments.len() == 1 =>
+        {
+            let segment = &path.segments[0];
+            match segment.arguments {
+                PathArguments::None => Some(&segment.ident),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+struct RoleReplacer {
+    role: Ident,
+    replacement: Type,
+}
+
+impl Fold for RoleReplacer {
+    fn fold_type(&mut self, ty: Type) -> Type {
+        if bare_ident(&ty) == Some(&self.role) {
+            return self.replacement.clone();
+        }
+        fold::fold_type(self, ty)
+    }
+}
+
+fn field_types(data: &Data, span: proc_macro2::Span) -> Result<Vec<&Type>, SynError> {
+    let fields: Vec<&Fields> = match data {
+        Data::Struct(s) => vec![&s.fields],
+        Data::Enum(e) => e.variants.iter().map(|v| &v.fields).collect(),
+        Data::Union(_) => {
+            return Err(SynError::new(
+                span,
+                "a union can't be handed to a child, as there's no telling which field is live",
+            ))
+        }
+    };
+    Ok(fields
+        .into_iter()
+        .flat_map(|f| f.iter())
+        .map(|field| &field.ty)
+        .collect())
+}
+
+/// Refuse any reference or raw pointer in `ty`, however deeply it's
+/// nested. `PhantomData` holds no data, so whatever it's over is fine.
+fn check_type(ty: &Type) -> Result<(), SynError> {
+    match ty {
+        Type::Reference(_) => Err(SynError::new(
+            ty.span(),
+            "a type handed to a child can't hold references, as they would point into the \
+             parent's address space",
+        )),
+        Type::Ptr(_) => Err(SynError::new(
+            ty.span(),
+            "a type handed to a child can't hold raw pointers, as they would point into the \
+             parent's address space",
+        )),
+        Type::Array(a) => check_type(&a.elem),
+        Type::Slice(s) => check_type(&s.elem),
+        Type::Paren(p) => check_type(&p.elem),
+        Type::Group(g) => check_type(&g.elem),
+        Type::Tuple(t) => t.elems.iter().try_for_each(check_type),
+        Type::Path(tp) => {
+            if let Some(qself) = &tp.qself {
+                check_type(&qself.ty)?;
+            }
+            let last = match tp.path.segments.last() {
+                Some(last) => last,
+                None => return Ok(()),
+            };
+            if last.value().ident == "PhantomData" {
+                return Ok(());
+            }
+            for segment in tp.path.segments.iter() {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    for arg in args.args.iter() {
+                        match arg {
+                            GenericArgument::Type(ty) => check_type(ty)?,
+                            GenericArgument::Binding(b) => check_type(&b.ty)?,
+                            _ => (),
+                        }
+                    }
+                }
+            }
+            Ok(())
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(input: DeriveInput) -> String {
+        retype_for_setup_impl(&input).unwrap().to_string()
+    }
+
+    fn error(input: DeriveInput) -> String {
+        retype_for_setup_impls(&input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn no_role_is_its_own_output() {
+        let input = parse_quote! {
+            struct ProcParams {
+                pub value: usize,
+            }
+        };
+        let expected = quote! {
+            impl ferros::userland::RetypeForSetup for ProcParams {
+                type Output = Self;
+            }
+        };
+        assert_eq!(expected.to_string(), expand(input));
+    }
+
+    #[test]
+    fn role_is_local_in_child_out() {
+        let input = parse_quote! {
+            struct ProcParams<Role: CNodeRole> {
+                pub consumer: Consumer1<Role, u32>,
+            }
+        };
+        let expected = quote! {
+            impl<> ferros::userland::RetypeForSetup for ProcParams<ferros::cap::role::Local>
+            where
+            {
+                type Output = ProcParams<ferros::cap::role::Child>;
+            }
+        };
+        assert_eq!(expected.to_string(), expand(input));
+    }
+
+    #[test]
+    fn other_params_keep_their_bounds() {
+        let input = parse_quote! {
+            struct UartParams<IRQ: Unsigned + Sync + Send, Role: CNodeRole>
+            where
+                IRQ: IsLess<MaxIRQCount, Output = True>,
+            {
+                pub base_ptr: usize,
+                pub consumer: Consumer1<Role, u32, IRQ>,
+            }
+        };
+        let expected = quote! {
+            impl<IRQ> ferros::userland::RetypeForSetup
+                for UartParams<IRQ, ferros::cap::role::Local>
+            where
+                IRQ: Unsigned + Sync + Send,
+                IRQ: IsLess<MaxIRQCount, Output = True>,
+            {
+                type Output = UartParams<IRQ, ferros::cap::role::Child>;
+            }
+        };
+        assert_eq!(expected.to_string(), expand(input));
+    }
+
+    #[test]
+    fn bounds_mentioning_the_role_hold_for_both() {
+        let input = parse_quote! {
+            struct Params<T, Role>
+            where
+                Role: cap::CNodeRole,
+                T: Holds<Role>,
+            {
+                pub thing: T,
+                pub slots: CNodeSlots<U4, Role>,
+            }
+        };
+        let expected = quote! {
+            impl<T> ferros::userland::RetypeForSetup for Params<T, ferros::cap::role::Local>
+            where
+                T: Holds<ferros::cap::role::Local>,
+                T: Holds<ferros::cap::role::Child>,
+            {
+                type Output = Params<T, ferros::cap::role::Child>;
+            }
+        };
+        assert_eq!(expected.to_string(), expand(input));
+    }
+
+    #[test]
+    fn independent_when_every_field_is() {
+        let input = parse_quote! {
+            struct ProcParams<Role: CNodeRole> {
+                pub nums: [usize; 10],
+                pub outcome_sender: Sender<bool, Role>,
+            }
+        };
+        let expected = quote! {
+            unsafe impl<Role: CNodeRole> ferros::userland::AddressSpaceIndependent
+                for ProcParams<Role>
+            where
+                [usize; 10]: ferros::userland::AddressSpaceIndependent,
+                Sender<bool, Role>: ferros::userland::AddressSpaceIndependent,
+            {
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            address_space_independent_impl(&input).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn independent_enum_keeps_its_where_clause() {
+        let input = parse_quote! {
+            enum Command<T>
+            where
+                T: Copy,
+            {
+                Fault,
+                Send(T),
+                Pair { left: u8, right: u8 },
+            }
+        };
+        let expected = quote! {
+            unsafe impl<T> ferros::userland::AddressSpaceIndependent for Command<T>
+            where
+                T: Copy,
+                T: ferros::userland::AddressSpaceIndependent,
+                u8: ferros::userland::AddressSpaceIndependent,
+                u8: ferros::userland::AddressSpaceIndependent,
+            {
+            }
+        };
+        assert_eq!(
+            expected.to_string(),
+            address_space_independent_impl(&input).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn rejects_references() {
+        let input = parse_quote! {
+            struct Params {
+                pub name: Option<&'static str>,
+            }
+        };
+        assert!(error(input).contains("can't hold references"));
+    }
+
+    #[test]
+    fn rejects_raw_pointers() {
+        let input = parse_quote! {
+            enum Params {
+                Buffer([*mut u8; 2]),
+                Empty,
+            }
+        };
+        assert!(error(input).contains("can't hold raw pointers"));
+    }
+
+    #[test]
+    fn allows_phantom_data_over_anything() {
+        let input = parse_quote! {
+            struct Params {
+                _marker: PhantomData<*const ()>,
+            }
+        };
+        assert!(retype_for_setup_impls(&input).is_ok());
+    }
+
+    #[test]
+    fn rejects_lifetimes() {
+        let input = parse_quote! {
+            struct Params<'a> {
+                _marker: PhantomData<&'a ()>,
+            }
+        };
+        assert!(error(input).contains("lifetime parameters"));
+    }
+
+    #[test]
+    fn rejects_two_roles() {
+        let input = parse_quote! {
+            struct Params<A: CNodeRole, B: CNodeRole> {
+                a: CNodeSlots<U1, A>,
+                b: CNodeSlots<U1, B>,
+            }
+        };
+        assert!(error(input).contains("at most one CNodeRole"));
+    }
+}
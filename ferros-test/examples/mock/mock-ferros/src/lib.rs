@@ -95,4 +95,12 @@ pub mod test_support {
         Failure,
     }
     pub type MaxMappedMemoryRegionBitSize = U20;
+    pub type TestDomainSet = ();
+    pub enum TestContext {
+        Local,
+        Process,
+    }
+    pub trait TestCase {
+        const CONTEXT: TestContext;
+    }
 }
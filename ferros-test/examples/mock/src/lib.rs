@@ -31,6 +31,9 @@ fn zero_parameters_returns_result_err() -> Result<(), ()> {
 #[ferros_test]
 fn zero_parameters_returns_unit() -> () {}
 
+#[ferros_test(process)]
+fn zero_parameters_in_a_process() {}
+
 #[ferros_test]
 fn localcap_slots_before_untyped_parameter(
     slots: LocalCNodeSlots<U5>,
@@ -5,7 +5,13 @@ pub use test_macro_impl::ferros_test;
 
 #[cfg(feature = "sel4_start_main")]
 #[doc(hidden)]
-pub fn sel4_start_main(tests: &[&ferros::test_support::RunTest]) {
+pub fn sel4_start_main(
+    tests: &[(
+        &'static str,
+        &ferros::test_support::RunTest,
+        ferros::test_support::TestContext,
+    )],
+) {
     let raw_boot_info = unsafe { &*sel4_start::BOOTINFO };
     let allocator = ferros::alloc::micro_alloc::Allocator::bootstrap(raw_boot_info)
         .expect("Test allocator setup failure");
@@ -32,9 +38,17 @@ pub fn sel4_start_main(tests: &[&ferros::test_support::RunTest]) {
 #[cfg(feature = "sel4_start_main")]
 #[macro_export]
 macro_rules! ferros_test_main {
-    ($tests:expr) => {
+    (&[$(&$test:path),* $(,)?]) => {
         fn main() {
-            $crate::sel4_start_main($tests)
+            // Named as `#[ferros_test]` names them, so that a test which
+            // never finishes is reported under the same name.
+            $crate::sel4_start_main(&[$(
+                (
+                    concat!(module_path!(), "::", stringify!($test)),
+                    &$test,
+                    <$test as ::ferros::test_support::TestCase>::CONTEXT,
+                )
+            ),*])
         }
     };
 }
@@ -1,5 +1,6 @@
 use crate::model::*;
-use proc_macro2::Span;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{quote, ToTokens};
 use syn::{parse_quote, Block, Expr, ExprCall, ExprPath, FnDecl, Ident, ItemFn};
 
 impl TestModel {
@@ -30,17 +31,45 @@ impl TestModel {
         parse_quote!(#transformed_fn)
     }
 
+    /// A type of the same name as the test, implementing
+    /// `ferros::test_support::TestCase`, by which the runner learns
+    /// where the test wants to run.
+    pub(crate) fn generate_test_case(&self) -> TokenStream2 {
+        let name = &self.fn_under_test.ident;
+        let vis = &self.fn_under_test.vis;
+        let cfgs: TokenStream2 = self
+            .fn_under_test
+            .attrs
+            .iter()
+            .filter(|a| a.path.is_ident("cfg"))
+            .map(|a| a.into_token_stream())
+            .collect();
+        let context: Expr = match self.execution_context {
+            TestExecutionContext::Local => parse_quote!(ferros::test_support::TestContext::Local),
+            TestExecutionContext::Process => {
+                parse_quote!(ferros::test_support::TestContext::Process)
+            }
+        };
+        quote! {
+            #cfgs
+            #[allow(non_camel_case_types, dead_code)]
+            #[doc(hidden)]
+            #vis struct #name {}
+            #cfgs
+            impl ferros::test_support::TestCase for #name {
+                const CONTEXT: ferros::test_support::TestContext = #context;
+            }
+        }
+    }
+
+    /// The runner picks the thread or process the test runs in, so
+    /// the body is the same for either context.
     fn map_under_test_invocation_to_outcome<G: IdGenerator>(
         self,
         id_generator: &mut G,
         fn_under_test_ident: Ident,
     ) -> Block {
-        match self.execution_context {
-            TestExecutionContext::Local => {
-                local_test_execution(self, id_generator, fn_under_test_ident)
-            }
-            TestExecutionContext::Process => process_test_execution(self, fn_under_test_ident),
-        }
+        test_execution(self, id_generator, fn_under_test_ident)
     }
 }
 
@@ -66,13 +95,6 @@ fn single_segment_path(ident: Ident) -> syn::Path {
     }
 }
 
-fn process_test_execution(model: TestModel, _fn_under_test_ident: Ident) -> Block {
-    assert_eq!(model.execution_context, TestExecutionContext::Process);
-    // TODO - produce a Proc/ThreadParams structure and RetypeForSetup impl
-    // TODO - if Process, generate a test thread entry point function
-    unimplemented!()
-}
-
 fn call_fn_under_test(
     fn_under_test_ident: Ident,
     fn_under_test_output: UserTestFnOutput,
@@ -114,12 +136,11 @@ fn call_fn_under_test(
     }
 }
 
-fn local_test_execution<G: IdGenerator>(
+fn test_execution<G: IdGenerator>(
     model: TestModel,
     id_generator: &mut G,
     fn_under_test_ident: Ident,
 ) -> Block {
-    assert_eq!(model.execution_context, TestExecutionContext::Local);
     let (mut alloc_block, allocated_params) = local_allocations(id_generator, &model.resources);
     let call_block = call_fn_under_test(
         fn_under_test_ident,
@@ -149,6 +170,7 @@ fn local_allocations<G: IdGenerator>(
     let ut_buddy_instance = Ident::new("ut_buddy_instance", Span::call_site());
     let mapped_memory_region = Ident::new("mapped_memory_region", Span::call_site());
     let irq_control = Ident::new("irq_control", Span::call_site());
+    let domain_set = Ident::new("domain_set", Span::call_site());
     let is_untyped = |p: &Param| {
         if let ParamKind::Untyped { .. } = p.kind {
             true
@@ -214,6 +236,7 @@ fn local_allocations<G: IdGenerator>(
             ParamKind::ThreadPriorityAuthority => (parse_quote!({}), thread_authority.clone()),
             ParamKind::PagingRoot => (parse_quote!({}), vspace_paging_root.clone()),
             ParamKind::UserImage => (parse_quote!({}), user_image.clone()),
+            ParamKind::DomainSet => (parse_quote!({}), domain_set.clone()),
         };
         stmts.extend(p_block.stmts);
         allocated_params.push(AllocatedParam {
@@ -288,6 +311,9 @@ fn run_test_decl() -> FnDecl {
     run_test_inputs.push(parse_quote!(
         irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
     ));
+    run_test_inputs.push(parse_quote!(
+        domain_set: &ferros::test_support::TestDomainSet
+    ));
     FnDecl {
         fn_token: syn::token::Fn::default(),
         generics: syn::Generics::default(),
@@ -355,7 +381,8 @@ mod tests {
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                domain_set: &ferros::test_support::TestDomainSet
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test() {
                     assert!(true);
@@ -418,7 +445,8 @@ mod tests {
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                domain_set: &ferros::test_support::TestDomainSet
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test(ut: LocalCap<Untyped<U5>>, sl: LocalCNodeSlots<U4>) -> Result<(), SeL4Error> {
                     let r = ut.split(sl);
@@ -449,6 +477,33 @@ mod tests {
             test.into_token_stream().to_string()
         );
     }
+    #[test]
+    fn test_case_names_the_execution_context() {
+        let fn_under_test: ItemFn = parse_quote! {
+            #[cfg(KernelIsMCS)]
+            pub fn original_target() {}
+        };
+        let model = TestModel {
+            execution_context: TestExecutionContext::Process,
+            fn_under_test,
+            fn_under_test_output: UserTestFnOutput::Unit,
+            resources: Vec::new(),
+        };
+        let expected = quote! {
+            #[cfg(KernelIsMCS)]
+            #[allow(non_camel_case_types, dead_code)]
+            #[doc(hidden)]
+            pub struct original_target {}
+            #[cfg(KernelIsMCS)]
+            impl ferros::test_support::TestCase for original_target {
+                const CONTEXT: ferros::test_support::TestContext =
+                    ferros::test_support::TestContext::Process;
+            }
+        };
+
+        assert_eq!(expected.to_string(), model.generate_test_case().to_string());
+    }
+
     #[test]
     fn happy_path_mapped_memory_region() {
         let fn_under_test = parse_quote! {
@@ -485,7 +540,8 @@ mod tests {
                 thread_authority: &ferros::cap::LocalCap<ferros::cap::ThreadPriorityAuthority>,
                 vspace_paging_root: &ferros::cap::LocalCap<ferros::arch::PagingRoot>,
                 user_image: &ferros::bootstrap::UserImage<ferros::cap::role::Local>,
-                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>
+                irq_control: ferros::cap::LocalCap<ferros::cap::IRQControl>,
+                domain_set: &ferros::test_support::TestDomainSet
             ) -> (&'static str, ferros::test_support::TestOutcome) {
                 fn under_test(mem: MappedMemoryRegion<U12, shared_status::Exclusive>) -> Result<(), SeL4Error> {
                     Ok(())
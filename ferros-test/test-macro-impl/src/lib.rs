@@ -19,8 +19,10 @@ pub fn ferros_test(attr: TokenStream, item: TokenStream) -> TokenStream {
         Ok(m) => m,
         Err(e) => return e.to_compile_error().into(),
     };
-    model
+    let test_case = model.generate_test_case();
+    let mut tokens = model
         .generate_runnable_test(codegen::UuidGenerator::default())
-        .into_token_stream()
-        .into()
+        .into_token_stream();
+    tokens.extend(test_case);
+    tokens.into()
 }
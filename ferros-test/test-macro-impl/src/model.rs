@@ -46,6 +46,7 @@ pub(crate) enum ParamKind {
     UserImage,
     IRQControl,
     PagingRoot,
+    DomainSet,
 }
 
 #[derive(Debug, Clone)]
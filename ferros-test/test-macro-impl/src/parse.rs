@@ -260,6 +260,14 @@ fn parse_localcap_param_kind(
                     span: segment.span() })
             }
         }
+        "DomainSet" => {
+            if arg_kind == ArgKind::Ref {
+                Ok(ParamKind::DomainSet)
+            } else {
+                Err(ParseError::InvalidArgumentType {msg: format!("{} is only available as a type parameter of &LocalCap<>, not an owned or mutable LocalCap<>", type_name),
+                    span: segment.span() })
+            }
+        }
         _ => Err(ParseError::InvalidArgumentType {
             msg: format!(
                 "Found an unsupported LocalCap type parameter, {}",
@@ -495,4 +503,23 @@ mod tests {
             panic!("Should have produced an ArgumentConstraint error")
         }
     }
+
+    #[test]
+    fn parse_model_accepts_domain_set_ref_only() {
+        let user_fn = quote! {
+            fn user_fn(domain_set: &LocalCap<DomainSet>) {
+            }
+        };
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        let model = TestModel::parse(content).expect("TestModel not parsed");
+        assert_eq!(1, model.resources.len());
+        assert_eq!(ParamKind::DomainSet, model.resources[0].kind);
+
+        let user_fn = quote! {
+            fn user_fn(domain_set: LocalCap<DomainSet>) {
+            }
+        };
+        let content = SynContent::parse(quote!(), user_fn).expect("SynContent not parsed");
+        assert!(TestModel::parse(content).is_err());
+    }
 }
@@ -161,7 +161,7 @@ mod tests {
         fn unified_tests_sabre() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 49 passed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -174,7 +174,33 @@ mod tests {
         fn unified_tests_virt() {
             run_qemu_test::<fn()>(
                 "unified_tests",
-                Regex::new(".*test result: ok\\. 23 passed;.*").unwrap(),
+                Regex::new(".*test result: ok\\. 49 passed;.*").unwrap(),
+                Regex::new(".*Root task should never return from main.*").unwrap(),
+                None,
+                None,
+                TestPlatform::VirtTx1Aarch64,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn test_timeout_sabre() {
+            run_qemu_test::<fn()>(
+                "test_timeout",
+                Regex::new(".*test result: FAILED\\. 1 passed; 2 failed;.*").unwrap(),
+                Regex::new(".*Root task should never return from main.*").unwrap(),
+                None,
+                None,
+                TestPlatform::SabreAarch32,
+            );
+        }
+    }
+
+    sequential_test! {
+        fn test_timeout_virt() {
+            run_qemu_test::<fn()>(
+                "test_timeout",
+                Regex::new(".*test result: FAILED\\. 1 passed; 2 failed;.*").unwrap(),
                 Regex::new(".*Root task should never return from main.*").unwrap(),
                 None,
                 None,
@@ -0,0 +1,12 @@
+#include <config.h>
+#include <object/structures.h>
+#include <model/statedata.h>
+
+/* Domains 0 and 1 take turns, a tick at a time, so a thread in either
+ * one runs however busy the other is kept. */
+const dschedule_t ksDomSchedule[] = {
+    { .domain = 0, .length = 1 },
+    { .domain = 1, .length = 1 },
+};
+
+const word_t ksDomScheduleLength = sizeof(ksDomSchedule) / sizeof(dschedule_t);
@@ -28,6 +28,7 @@ fn main() {
         image_name: "elf-process".to_owned(),
         type_name: "ElfProcess".to_owned(),
         stack_size_bits: None,
+        compress: false,
     };
 
     embed_resources(&resources, vec![&elf_proc as &dyn Resource]);
@@ -0,0 +1,100 @@
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, RetypeForSetup, Sender, StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Test that a process parameter can be a list of however many entries
+/// the parent chooses, up to a fixed capacity
+#[ferros_test::ferros_test]
+pub fn array_vec_params(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, _child_slots) = child_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let mut params: ArrayVec<[Entry<role::Child>; 8]> = ArrayVec::new();
+        params.push(Entry {
+            value: 1,
+            outcome_sender: Some(outcome_sender),
+        });
+        params.push(Entry {
+            value: 2,
+            outcome_sender: None,
+        });
+        params.push(Entry {
+            value: 3,
+            outcome_sender: None,
+        });
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            None,
+        )?;
+    });
+
+    child_process.start()?;
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Child process should have received all three entries",
+        )),
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct Entry<Role: CNodeRole> {
+    pub value: usize,
+    pub outcome_sender: Option<Sender<bool, Role>>,
+}
+
+pub extern "C" fn proc_main(params: ArrayVec<[Entry<role::Local>; 8]>) {
+    let sum: usize = params.iter().map(|e| e.value).sum();
+    let outcome = params.len() == 3 && sum == 6;
+    params[0]
+        .outcome_sender
+        .as_ref()
+        .expect("The first entry carries the sender")
+        .blocking_send(&outcome)
+        .expect("Failure sending test assertion outcome");
+}
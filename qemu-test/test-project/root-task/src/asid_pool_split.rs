@@ -0,0 +1,76 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Splitting a pool that has had an ASID reclaimed into it leaves each
+/// half with ASIDs the other can't hand out, and none past the end of
+/// the original pool, which here is the sentinel pool's ASID.
+#[ferros_test::ferros_test]
+pub fn asid_pool_split_after_reclaim(
+    local_slots: LocalCNodeSlots<U8192>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U3>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let pool_slot: LocalCNodeSlot = slots;
+        let sentinel_pool_slot: LocalCNodeSlot = slots;
+        let (pool, sentinel_pool): (LocalCap<ASIDPool<U2>>, LocalCap<ASIDPool<U1>>) =
+            asid_pool.split(pool_slot, sentinel_pool_slot, root_cnode)?;
+
+        let (asid, pool) = pool.alloc();
+        let vspace = build_vspace(asid, slots, ut, root_cnode, user_image)?;
+        let (freed, _root_slot) = vspace.destroy(root_cnode)?;
+        let pool = pool.reclaim(freed).map_err(|_| {
+            TopLevelError::TestAssertionFailure("The ASID should go back to its own pool")
+        })?;
+
+        let left_pool_slot: LocalCNodeSlot = slots;
+        let right_pool_slot: LocalCNodeSlot = slots;
+        let (left_pool, right_pool): (LocalCap<ASIDPool<U1>>, LocalCap<ASIDPool<U1>>) =
+            pool.split(left_pool_slot, right_pool_slot, root_cnode)?;
+
+        let (sentinel_asid, _sentinel_pool) = sentinel_pool.alloc();
+        let _sentinel_vspace = build_vspace(sentinel_asid, slots, ut, root_cnode, user_image)?;
+        let (left_asid, _left_pool) = left_pool.alloc();
+        let _left_vspace = build_vspace(left_asid, slots, ut, root_cnode, user_image)?;
+        let (right_asid, _right_pool) = right_pool.alloc();
+        let _right_vspace = build_vspace(right_asid, slots, ut, root_cnode, user_image)?;
+    });
+
+    Ok(())
+}
+
+fn build_vspace(
+    asid: LocalCap<UnassignedASID>,
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U17>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<VSpace, TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let vspace = VSpace::new(
+            retype(ut, slots)?,
+            asid,
+            vspace_slots.weaken(),
+            vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+    });
+
+    Ok(vspace)
+}
@@ -0,0 +1,42 @@
+use ferros::test_support::TestOutcome;
+
+use super::TopLevelError;
+
+/// `assert_test!` and `assert_eq_test!` let a test that holds carry on,
+/// and return a failure from one that doesn't rather than panicking,
+/// whether it returns a `Result` or a `TestOutcome`.
+#[ferros_test::ferros_test]
+pub fn assertion_macros() -> Result<(), TopLevelError> {
+    assert_test!(checks_result(1, 1).is_ok());
+    assert_eq_test!(checks_outcome(1, 1), TestOutcome::Success);
+
+    match checks_result(1, 2) {
+        Err(TopLevelError::TestAssertionFailure("left == right")) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A failed assert_eq_test! should return its condition",
+            ))
+        }
+    }
+    match checks_result(2, 2) {
+        Err(TopLevelError::TestAssertionFailure("left % 2 == 1")) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A failed assert_test! should return its condition",
+            ))
+        }
+    }
+    assert_eq_test!(checks_outcome(1, 2), TestOutcome::Failure);
+    Ok(())
+}
+
+fn checks_result(left: usize, right: usize) -> Result<(), TopLevelError> {
+    assert_eq_test!(left, right);
+    assert_test!(left % 2 == 1, "{} should be odd", left);
+    Ok(())
+}
+
+fn checks_outcome(left: usize, right: usize) -> TestOutcome {
+    assert_eq_test!(left, right, "expected {} to equal {}", left, right);
+    TestOutcome::Success
+}
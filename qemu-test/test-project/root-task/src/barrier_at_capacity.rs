@@ -0,0 +1,123 @@
+use arrayvec::ArrayVec;
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, Barrier, BarrierError, BarrierSetup, FaultOrMessage, RetypeForSetup,
+    Sender, StandardProcess, MAX_BARRIER_PARTICIPANTS,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Test that a barrier with as many participants as it can take
+/// releases them, i.e. that every participant's arrival is seen.
+#[ferros_test::ferros_test]
+pub fn barrier_at_capacity(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (child_fault_source_slot, child_slots) = child_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, child_fault_source_slot, slots)?;
+
+        let mut setup = BarrierSetup::new(ut, ut, slots)?;
+        let process_slots: LocalCNodeSlots<Sum<NumPages<U17>, U2>> = slots;
+        let ipc_buffer_ut: LocalCap<Untyped<U12>> = ut;
+        let tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>> = ut;
+    });
+
+    let mut child_slots = child_slots.weaken();
+    let mut barriers = ArrayVec::new();
+    for _ in 0..MAX_BARRIER_PARTICIPANTS {
+        barriers.push(setup.add_participant(
+            root_cnode,
+            child_slots.alloc_strong().map_err(RetypeError::from)?,
+        )?);
+    }
+    match setup.add_participant(
+        root_cnode,
+        child_slots.alloc_strong().map_err(RetypeError::from)?,
+    ) {
+        Err(BarrierError::TooManyParticipants) => (),
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A full barrier should turn away another participant",
+            ))
+        }
+    }
+
+    let mut child_process = StandardProcess::new(
+        &mut child_vspace,
+        child_cnode,
+        local_mapped_region,
+        root_cnode,
+        proc_main as extern "C" fn(_) -> (),
+        ProcParams {
+            barriers,
+            outcome_sender,
+        },
+        ipc_buffer_ut,
+        tcb_ut,
+        process_slots,
+        tpa,
+        None,
+    )?;
+    child_process.start()?;
+
+    // The participant with the highest bit is the one whose arrival
+    // used to go missing; waiting here would then never return.
+    setup.coordinator().release_when_ready();
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Every participant should have been released",
+        )),
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub barriers: ArrayVec<[Barrier<Role>; 64]>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    // One thread stands in for all the participants, so it can't
+    // block in `wait` until they've all arrived.
+    for barrier in params.barriers.iter() {
+        barrier.arrive();
+    }
+    for barrier in params.barriers.iter() {
+        barrier.await_release();
+    }
+    params
+        .outcome_sender
+        .blocking_send(&(params.barriers.len() == MAX_BARRIER_PARTICIPANTS))
+        .expect("Failure sending test assertion outcome");
+}
@@ -0,0 +1,55 @@
+use typenum::*;
+
+use ferros::arch::PageBytes;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// Cache maintenance over parts of pages is accepted by the kernel,
+/// which refuses any offsets outside a page, and leaves the region's
+/// contents as they were.
+#[ferros_test::ferros_test]
+pub fn cache_maintenance_ranges(
+    mut local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+) -> Result<(), TopLevelError> {
+    let vaddr = local_mapped_region.vaddr();
+    let size_bytes = local_mapped_region.size_bytes();
+    let page = PageBytes::USIZE;
+
+    for (i, byte) in local_mapped_region.as_mut_slice().iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+
+    // (start, size) pairs, each touching part of a page at least.
+    let ranges = [
+        // Within one page
+        (vaddr + 8, 16),
+        // Straddling a page boundary
+        (vaddr + page - 8, 16),
+        // The tail of one page, a whole page and the head of another
+        (vaddr + page / 2, 2 * page),
+        // Hanging off the start of the region
+        (vaddr - page / 2, page),
+        // Hanging off the end of the region
+        (vaddr + size_bytes - page / 2, page),
+    ];
+    for &(start, size) in ranges.iter() {
+        local_mapped_region.flush_range(start, size)?;
+        // Cleaning first writes any dirty lines back, so invalidating
+        // the same range afterwards loses nothing.
+        local_mapped_region.clean_range(start, size)?;
+        local_mapped_region.invalidate_range(start, size)?;
+    }
+
+    // Nothing of the region is touched by these, so they're no-ops.
+    local_mapped_region.flush_range(vaddr + 8, 0)?;
+    local_mapped_region.flush_range(vaddr + size_bytes, page)?;
+    local_mapped_region.flush_range(vaddr - page, page)?;
+    local_mapped_region.clean_range(vaddr + size_bytes, page)?;
+    local_mapped_region.invalidate_range(vaddr - page, page)?;
+
+    for (i, byte) in local_mapped_region.as_slice().iter().enumerate() {
+        assert_eq_test!(*byte, i as u8, "region contents changed");
+    }
+    Ok(())
+}
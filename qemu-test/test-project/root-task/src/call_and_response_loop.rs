@@ -133,26 +133,18 @@ pub struct AdditionResponse {
     sum: u32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, RetypeForSetup)]
 pub struct CallerParams<Role: CNodeRole> {
     pub caller: Caller<AdditionRequest, AdditionResponse, Role>,
     pub notification: Cap<Notification, Role>,
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for CallerParams<role::Local> {
-    type Output = CallerParams<role::Child>;
-}
-
-#[derive(Debug)]
+#[derive(Debug, RetypeForSetup)]
 pub struct ResponderParams<Role: CNodeRole> {
     pub responder: Responder<AdditionRequest, AdditionResponse, Role>,
 }
 
-impl RetypeForSetup for ResponderParams<role::Local> {
-    type Output = ResponderParams<role::Child>;
-}
-
 pub extern "C" fn caller_proc(p: CallerParams<role::Local>) {
     let mut current_sum: u32 = 1;
     let caller = p.caller;
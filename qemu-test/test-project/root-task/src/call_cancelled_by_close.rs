@@ -0,0 +1,322 @@
+use core::ptr;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// A caller left waiting on a reply from a responder that died partway
+/// through its call gets `IPCError::ResponderDead` once the channel is
+/// closed and the call abandoned, as do its calls after that.
+#[ferros_test::ferros_test]
+pub fn call_cancelled_by_close(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (caller_asid, asid_pool) = asid_pool.alloc();
+        let (responder_asid, _asid_pool) = asid_pool.alloc();
+
+        let caller_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let caller_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut caller_vspace = VSpace::new(
+            retype(ut, slots)?,
+            caller_asid,
+            caller_vspace_slots.weaken(),
+            caller_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let responder_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let responder_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut responder_vspace = VSpace::new(
+            retype(ut, slots)?,
+            responder_asid,
+            responder_vspace_slots.weaken(),
+            responder_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (caller_cnode, caller_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (responder_cnode, responder_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (responder_endpoint_slot, responder_slots) = responder_slots.alloc();
+        let (ipc_setup, responder) = call_channel(ut, &root_cnode, slots, responder_endpoint_slot)?;
+        let (caller_endpoint_slot, caller_slots) = caller_slots.alloc();
+        let caller = ipc_setup.create_caller(caller_endpoint_slot)?;
+        let connection = ipc_setup.into_connection();
+
+        let (outcome_sender_slot, _caller_slots) = caller_slots.alloc();
+        let (_caller_fault_source, outcome_sender, outcome_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, outcome_sender_slot, slots)?;
+
+        let responder_fault_setup = FaultSinkSetup::new(&root_cnode, ut, slots, slots)?;
+        let (responder_fault_source_slot, _responder_slots) = responder_slots.alloc();
+        let responder_fault_source = responder_fault_setup.add_fault_source(
+            &root_cnode,
+            responder_fault_source_slot,
+            Badge::from(0),
+        )?;
+        let responder_fault_sink = responder_fault_setup.sink();
+
+        let (caller_region, responder_region) = local_mapped_region.split()?;
+
+        let mut caller_process = StandardProcess::new(
+            &mut caller_vspace,
+            caller_cnode,
+            caller_region,
+            root_cnode,
+            caller_proc as extern "C" fn(_) -> (),
+            CallerParams {
+                caller,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        let mut responder_process = StandardProcess::new(
+            &mut responder_vspace,
+            responder_cnode,
+            responder_region,
+            root_cnode,
+            responder_proc as extern "C" fn(_) -> (),
+            ResponderParams { responder },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(responder_fault_source),
+        )?;
+    });
+    caller_process.start()?;
+    responder_process.start()?;
+
+    // The responder dies handling the call, leaving the caller waiting
+    // on its reply.
+    let _ = responder_fault_sink.wait_for_fault();
+    connection.close(root_cnode)?;
+    caller_process.abandon_call()?;
+
+    match outcome_handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Calls over the closed channel should have been abandoned",
+        )),
+    }
+}
+
+/// A caller already waiting on a reply from a responder that died
+/// partway through its call gets `IPCError::ResponderDead` as soon as
+/// its cancel notification is signalled, and so do its calls after
+/// that.
+#[ferros_test::ferros_test]
+pub fn call_cancelled_while_waiting(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (caller_asid, asid_pool) = asid_pool.alloc();
+        let (responder_asid, _asid_pool) = asid_pool.alloc();
+
+        let caller_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let caller_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut caller_vspace = VSpace::new(
+            retype(ut, slots)?,
+            caller_asid,
+            caller_vspace_slots.weaken(),
+            caller_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let responder_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let responder_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut responder_vspace = VSpace::new(
+            retype(ut, slots)?,
+            responder_asid,
+            responder_vspace_slots.weaken(),
+            responder_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (caller_cnode, caller_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (responder_cnode, responder_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (caller_channel_slots, caller_slots) = caller_slots.alloc();
+        let (responder_channel_slots, responder_slots) = responder_slots.alloc();
+        let (caller, responder) = cancellable_call_channel(
+            ut,
+            ut,
+            &root_cnode,
+            slots,
+            caller_channel_slots,
+            responder_channel_slots,
+        )?;
+
+        let cancel: LocalCap<Notification> = retype(ut, slots)?;
+        let cancel_signaller = cancel.mint(&root_cnode, slots, CapRights::RWG, Badge::from(1))?;
+        let (caller_cancel_slot, caller_slots) = caller_slots.alloc();
+        let caller_cancel = cancel.copy(&root_cnode, caller_cancel_slot, CapRights::RWG)?;
+
+        let (outcome_sender_slot, _caller_slots) = caller_slots.alloc();
+        let (_caller_fault_source, outcome_sender, outcome_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, outcome_sender_slot, slots)?;
+
+        let responder_fault_setup = FaultSinkSetup::new(&root_cnode, ut, slots, slots)?;
+        let (responder_fault_source_slot, _responder_slots) = responder_slots.alloc();
+        let responder_fault_source = responder_fault_setup.add_fault_source(
+            &root_cnode,
+            responder_fault_source_slot,
+            Badge::from(0),
+        )?;
+        let responder_fault_sink = responder_fault_setup.sink();
+
+        let (caller_region, responder_region) = local_mapped_region.split()?;
+
+        let mut caller_process = StandardProcess::new(
+            &mut caller_vspace,
+            caller_cnode,
+            caller_region,
+            root_cnode,
+            cancellable_caller_proc as extern "C" fn(_) -> (),
+            CancellableCallerParams {
+                caller,
+                cancel: caller_cancel,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        let mut responder_process = StandardProcess::new(
+            &mut responder_vspace,
+            responder_cnode,
+            responder_region,
+            root_cnode,
+            cancellable_responder_proc as extern "C" fn(_) -> (),
+            CancellableResponderParams { responder },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(responder_fault_source),
+        )?;
+    });
+    caller_process.bind_notification(&cancel)?;
+    caller_process.start()?;
+    responder_process.start()?;
+
+    // The responder only faults once it has taken the request, by which
+    // point the caller has sent it and gone on to wait for the reply.
+    let _ = responder_fault_sink.wait_for_fault();
+    cancel_signaller.signal();
+
+    match outcome_handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "The waiting call and the one after it should have been cancelled",
+        )),
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct CallerParams<Role: CNodeRole> {
+    pub caller: Caller<u32, u32, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+#[derive(RetypeForSetup)]
+pub struct ResponderParams<Role: CNodeRole> {
+    pub responder: Responder<u32, u32, Role>,
+}
+
+pub extern "C" fn caller_proc(p: CallerParams<role::Local>) {
+    let abandoned = match p.caller.blocking_call(&1) {
+        Err(IPCError::ResponderDead) => true,
+        _ => false,
+    };
+    let closed = match p.caller.blocking_call(&2) {
+        Err(IPCError::ResponderDead) => true,
+        _ => false,
+    };
+    p.outcome_sender
+        .blocking_send(&(abandoned && closed))
+        .expect("Failure sending test assertion outcome");
+}
+
+pub extern "C" fn responder_proc(p: ResponderParams<role::Local>) {
+    p.responder
+        .reply_recv(|request| {
+            unsafe { ptr::write_volatile(ptr::null_mut(), request) };
+            request
+        })
+        .expect("reply_recv");
+}
+
+#[derive(RetypeForSetup)]
+pub struct CancellableCallerParams<Role: CNodeRole> {
+    pub caller: CancellableCaller<u32, u32, Role>,
+    pub cancel: Cap<Notification, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+#[derive(RetypeForSetup)]
+pub struct CancellableResponderParams<Role: CNodeRole> {
+    pub responder: CancellableResponder<u32, u32, Role>,
+}
+
+pub extern "C" fn cancellable_caller_proc(p: CancellableCallerParams<role::Local>) {
+    let cancelled = match p.caller.call_with_cancel(&1, &p.cancel) {
+        Err(IPCError::ResponderDead) => true,
+        _ => false,
+    };
+    let stays_cancelled = match p.caller.call_with_cancel(&2, &p.cancel) {
+        Err(IPCError::ResponderDead) => true,
+        _ => false,
+    };
+    p.outcome_sender
+        .blocking_send(&(cancelled && stays_cancelled))
+        .expect("Failure sending test assertion outcome");
+}
+
+pub extern "C" fn cancellable_responder_proc(p: CancellableResponderParams<role::Local>) {
+    p.responder
+        .reply_recv(|request| {
+            unsafe { ptr::write_volatile(ptr::null_mut(), request) };
+            request
+        })
+        .expect("reply_recv");
+}
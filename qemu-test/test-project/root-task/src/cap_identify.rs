@@ -0,0 +1,105 @@
+use selfe_sys::{seL4_CapASIDControl, seL4_CapDomain};
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::cap::{ArchCapKind, LargePage, PageDirectory};
+#[cfg(target_arch = "aarch64")]
+use ferros::arch::cap::{PageGlobalDirectory, PageUpperDirectory};
+use ferros::cap::*;
+
+use super::TopLevelError;
+
+/// Each kind of cap the root task can get hold of is identified as
+/// that kind, and an empty slot as nothing. Reply, zombie and VCPU
+/// caps aren't covered, as there's no way to make one here.
+#[ferros_test::ferros_test]
+pub fn cap_identify(
+    local_slots: LocalCNodeSlots<U32>,
+    local_ut: LocalCap<Untyped<U22>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    mut irq_control: LocalCap<IRQControl>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let untyped: LocalCap<Untyped<U12>> = ut;
+        let endpoint: LocalCap<Endpoint> = retype(ut, slots)?;
+        let notification: LocalCap<Notification> = retype(ut, slots)?;
+        let (cnode, _cnode_slots) = retype_cnode::<U4>(ut, slots)?;
+        let tcb: LocalCap<ThreadControlBlock> = retype(ut, slots)?;
+        let page: LocalCap<Page<page_state::Unmapped>> = retype(ut, slots)?;
+        let large_page: LocalCap<LargePage<page_state::Unmapped>> = retype(ut, slots)?;
+        let page_table: LocalCap<PageTable> = retype(ut, slots)?;
+        let page_directory: LocalCap<PageDirectory> = retype(ut, slots)?;
+        let doomed: LocalCap<Endpoint> = retype(ut, slots)?;
+        let irq_handler = irq_control
+            .create_weak_handler(slots, MaxIRQCount::U16 - 1)
+            .map_err(|_| TopLevelError::TestAssertionFailure("The last IRQ should be free"))?;
+        let _upper_paging_slots: LocalCNodeSlots<U2> = slots;
+        let _upper_paging_ut: LocalCap<Untyped<U13>> = ut;
+    });
+
+    assert_eq_test!(untyped.identify(), Some(CapKind::Untyped));
+    assert_eq_test!(endpoint.identify(), Some(CapKind::Endpoint));
+    assert_eq_test!(notification.identify(), Some(CapKind::Notification));
+    assert_eq_test!(cnode.identify(), Some(CapKind::CNode));
+    assert_eq_test!(tcb.identify(), Some(CapKind::Thread));
+    assert_eq_test!(irq_control.identify(), Some(CapKind::IRQControl));
+    assert_eq_test!(irq_handler.identify(), Some(CapKind::IRQHandler));
+
+    // The root task's own caps to these are where the kernel put them
+    // at boot.
+    let domain: LocalCap<Endpoint> = unsafe { Cap::wrap_cptr(seL4_CapDomain as usize) };
+    assert_eq_test!(domain.identify(), Some(CapKind::Domain));
+    let asid_control: LocalCap<Endpoint> = unsafe { Cap::wrap_cptr(seL4_CapASIDControl as usize) };
+    assert_eq_test!(
+        asid_control.identify(),
+        Some(CapKind::Arch(ArchCapKind::ASIDControl))
+    );
+
+    assert_eq_test!(
+        asid_pool.identify(),
+        Some(CapKind::Arch(ArchCapKind::ASIDPool))
+    );
+    #[cfg(target_arch = "arm")]
+    assert_eq_test!(
+        page.identify(),
+        Some(CapKind::Arch(ArchCapKind::SmallFrame))
+    );
+    #[cfg(target_arch = "aarch64")]
+    assert_eq_test!(page.identify(), Some(CapKind::Arch(ArchCapKind::Frame)));
+    assert_eq_test!(
+        large_page.identify(),
+        Some(CapKind::Arch(ArchCapKind::Frame))
+    );
+    assert_eq_test!(
+        page_table.identify(),
+        Some(CapKind::Arch(ArchCapKind::PageTable))
+    );
+    assert_eq_test!(
+        page_directory.identify(),
+        Some(CapKind::Arch(ArchCapKind::PageDirectory))
+    );
+    #[cfg(target_arch = "aarch64")]
+    {
+        let uts = ut_buddy(_upper_paging_ut);
+        smart_alloc!(|slots: _upper_paging_slots, ut: uts| {
+            let page_upper_directory: LocalCap<PageUpperDirectory> = retype(ut, slots)?;
+            let page_global_directory: LocalCap<PageGlobalDirectory> = retype(ut, slots)?;
+        });
+        assert_eq_test!(
+            page_upper_directory.identify(),
+            Some(CapKind::Arch(ArchCapKind::PageUpperDirectory))
+        );
+        assert_eq_test!(
+            page_global_directory.identify(),
+            Some(CapKind::Arch(ArchCapKind::PageGlobalDirectory))
+        );
+    }
+
+    let emptied: LocalCap<Endpoint> = unsafe { Cap::wrap_cptr(doomed.cptr) };
+    doomed.delete(root_cnode)?;
+    assert_eq_test!(emptied.identify(), None);
+    Ok(())
+}
@@ -0,0 +1,129 @@
+use arrayvec::ArrayString;
+use selfe_sys::*;
+
+use ferros::bootstrap::{BootInfoCap, WellKnownCap};
+use ferros::capdl::{write_spec_for, CapRecord, SpecScratch};
+
+use super::TopLevelError;
+
+static mut SCRATCH: SpecScratch = SpecScratch::new();
+
+/// A spec written from a made-up log starts from the boot caps, follows
+/// copies, mints and moves back to the objects they're caps to, and
+/// leaves out slots that were emptied, while still listing the objects
+/// that were in them.
+#[ferros_test::ferros_test]
+pub fn capdl_spec() -> Result<(), TopLevelError> {
+    let boot_caps = [
+        BootInfoCap::WellKnown {
+            cptr: 1,
+            kind: WellKnownCap::InitThreadTCB,
+        },
+        BootInfoCap::WellKnown {
+            cptr: 2,
+            kind: WellKnownCap::InitThreadCNode,
+        },
+        BootInfoCap::WellKnown {
+            cptr: 4,
+            kind: WellKnownCap::IRQControl,
+        },
+        BootInfoCap::Untyped {
+            cptr: 20,
+            size_bits: 16,
+            paddr: 0x4000_0000,
+            is_device: false,
+        },
+    ];
+    let retype = |object_type: u32, size_bits, dest_offset, count| CapRecord::Retype {
+        untyped: 20,
+        object_type: object_type as usize,
+        size_bits,
+        dest_root: 2,
+        dest_offset,
+        count,
+    };
+    let log = [
+        retype(api_object_seL4_TCBObject, 0, 30, 1),
+        retype(api_object_seL4_EndpointObject, 0, 31, 2),
+        retype(api_object_seL4_CapTableObject, 4, 33, 1),
+        // A mint into the new CNode, found through the root CNode
+        CapRecord::Derive {
+            src_root: 2,
+            src_index: 31,
+            dest_root: 33,
+            dest_index: 0,
+            rights: 0b0011,
+            badge: 7,
+        },
+        CapRecord::Move {
+            src_root: 2,
+            src_index: 32,
+            dest_root: 33,
+            dest_index: 1,
+        },
+        CapRecord::Delete { root: 2, index: 30 },
+        // A copy of one of the root task's own caps
+        CapRecord::Derive {
+            src_root: 2,
+            src_index: 1,
+            dest_root: 33,
+            dest_index: 2,
+            rights: 0b1111,
+            badge: 0,
+        },
+        // A mint of the minted cap keeps its first badge and loses
+        // rights on the way
+        CapRecord::Derive {
+            src_root: 33,
+            src_index: 0,
+            dest_root: 2,
+            dest_index: 40,
+            rights: 0b0010,
+            badge: 9,
+        },
+        CapRecord::Move {
+            src_root: 2,
+            src_index: 4,
+            dest_root: 2,
+            dest_index: 41,
+        },
+    ];
+
+    let mut out = ArrayString::<[u8; 2048]>::new();
+    // Only this test uses it, and tests run one at a time.
+    let scratch = unsafe { &mut SCRATCH };
+    write_spec_for(&log, boot_caps.iter().cloned(), 12, scratch, &mut out)
+        .map_err(|_| TopLevelError::TestAssertionFailure("The spec should fit in the buffer"))?;
+    let expected = "
+objects {
+  root_tcb = tcb
+  root_cnode = cnode (12 bits)
+  ut_boot_20 = ut (16 bits, paddr: 0x40000000) { tcb_0_0, ep_1_0, ep_1_1, cnode_2_0 }
+  tcb_0_0 = tcb
+  ep_1_0 = ep
+  ep_1_1 = ep
+  cnode_2_0 = cnode (4 bits)
+}
+
+caps {
+  root_cnode {
+    0x1: root_tcb (RWGP)
+    0x2: root_cnode (RWGP)
+    0x14: ut_boot_20 (RWGP)
+    0x1f: ep_1_0 (RWGP)
+    0x21: cnode_2_0 (RWGP)
+    0x28: ep_1_0 (R, badge: 7)
+    0x29: irq_control
+  }
+  cnode_2_0 {
+    0x0: ep_1_0 (RW, badge: 7)
+    0x1: ep_1_1 (RWGP)
+    0x2: root_tcb (RWGP)
+  }
+}
+";
+    // The first line names the architecture, which depends on the build.
+    assert_test!(out.starts_with("arch "));
+    assert_eq_test!(out.find('\n').map(|i| &out[i + 1..]), Some(expected));
+    Ok(())
+}
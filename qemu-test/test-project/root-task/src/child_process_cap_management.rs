@@ -87,7 +87,7 @@ pub fn child_process_cap_management(
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, RetypeForSetup)]
 pub struct CapManagementParams<Role: CNodeRole> {
     pub my_cnode: Cap<CNode<Role>, Role>,
     pub my_cnode_slots: Cap<CNodeSlotsData<U42, Role>, Role>,
@@ -95,10 +95,6 @@ pub struct CapManagementParams<Role: CNodeRole> {
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for CapManagementParams<role::Local> {
-    type Output = CapManagementParams<role::Child>;
-}
-
 // 'extern' to force C calling conventions
 pub extern "C" fn proc_main(params: CapManagementParams<role::Local>) {
     let CapManagementParams {
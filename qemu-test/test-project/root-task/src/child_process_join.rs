@@ -0,0 +1,84 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{RetypeForSetup, StandardProcess};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn child_process_join(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (exit_notification_slot, _child_slots) = child_slots.alloc();
+        let exit_notification: LocalCap<Notification> = retype(ut, slots)?;
+        let params = ProcParams {
+            value: 42,
+            _role: core::marker::PhantomData,
+        };
+
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+
+        let child_root = retype(ut, slots)?;
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+
+        let mut child_vspace = VSpace::new(
+            child_root,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (mut child_process, join_handle) = StandardProcess::new_joinable(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> i32,
+            params,
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+            exit_notification,
+            exit_notification_slot,
+        )?;
+    });
+
+    child_process.start()?;
+
+    if join_handle.join() == 42 {
+        Ok(())
+    } else {
+        Err(TopLevelError::TestAssertionFailure(
+            "Child process should have exited with the value it was given",
+        ))
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub value: usize,
+    pub _role: core::marker::PhantomData<Role>,
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) -> i32 {
+    params.value as i32
+}
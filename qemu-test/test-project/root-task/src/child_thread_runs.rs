@@ -56,15 +56,12 @@ pub fn child_thread_runs(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams<Role: CNodeRole> {
     pub value: usize,
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ProcParams<role::Local> {
-    type Output = ProcParams<role::Child>;
-}
-
 pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
     params
         .outcome_sender
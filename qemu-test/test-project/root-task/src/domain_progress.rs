@@ -0,0 +1,133 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, RetypeForSetup, Sender, StandardProcessBuilder,
+};
+use ferros::vspace::*;
+
+/// A thread in domain 1 makes progress even while one in domain 0,
+/// at a higher priority, never gives up the CPU. Were they in the same
+/// domain, the lower priority thread would never run.
+#[ferros_test::ferros_test]
+pub fn domain_progress(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    domain_set: &LocalCap<DomainSet>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (spinner_asid, asid_pool) = asid_pool.alloc();
+        let (reporter_asid, _asid_pool) = asid_pool.alloc();
+
+        let (spinner_cnode, spinner_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (reporter_cnode, reporter_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let spinner_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let spinner_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut spinner_vspace = VSpace::new(
+            retype(ut, slots)?,
+            spinner_asid,
+            spinner_vspace_slots.weaken(),
+            spinner_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let reporter_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let reporter_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut reporter_vspace = VSpace::new(
+            retype(ut, slots)?,
+            reporter_asid,
+            reporter_vspace_slots.weaken(),
+            reporter_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (spinner_sender_slot, _spinner_slots) = spinner_slots.alloc();
+        let (_spinner_fault_source, spinner_sender, spinner_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, spinner_sender_slot, slots)?;
+        let (reporter_sender_slot, _reporter_slots) = reporter_slots.alloc();
+        let (_reporter_fault_source, reporter_sender, reporter_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, reporter_sender_slot, slots)?;
+
+        let (spinner_region, reporter_region) = local_mapped_region.split()?;
+
+        let mut spinner_process = StandardProcessBuilder::new(
+            proc_main as extern "C" fn(_) -> (),
+            ProcParams {
+                progress_sender: spinner_sender,
+                spin: true,
+            },
+        )
+        .stack_region(spinner_region)
+        .ipc_buffer_ut(ut)
+        .tcb_ut(ut)
+        .slots(slots)
+        .priority(tpa, 255)
+        .domain(domain_set, 0)
+        .build(&mut spinner_vspace, spinner_cnode, root_cnode)?;
+
+        let mut reporter_process = StandardProcessBuilder::new(
+            proc_main as extern "C" fn(_) -> (),
+            ProcParams {
+                progress_sender: reporter_sender,
+                spin: false,
+            },
+        )
+        .stack_region(reporter_region)
+        .ipc_buffer_ut(ut)
+        .tcb_ut(ut)
+        .slots(slots)
+        .priority(tpa, 254)
+        .domain(domain_set, 1)
+        .build(&mut reporter_vspace, reporter_cnode, root_cnode)?;
+
+        spinner_process.start()?;
+        reporter_process.start()?;
+    });
+
+    for handler in [spinner_handler, reporter_handler].iter() {
+        match handler.await_message()? {
+            FaultOrMessage::Message(true) => (),
+            _ => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "Threads in both domains should have made progress",
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub progress_sender: Sender<bool, Role>,
+    pub spin: bool,
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    params
+        .progress_sender
+        .blocking_send(&true)
+        .expect("Failure sending progress report");
+    if params.spin {
+        // Hog the CPU for as long as the test lasts.
+        loop {
+            core::sync::atomic::spin_loop_hint();
+        }
+    }
+}
@@ -126,30 +126,23 @@ pub fn dont_tread_on_me<'a, 'b, 'c>(
     }
 }
 
-/// The function that the proc2 child process will attempt to mutate
-#[allow(dead_code)]
-fn to_be_changed() -> bool {
-    true
-}
+/// The static that the proc2 child process will attempt to mutate. Only
+/// the image's data is writable in proc2's copy; its code never is.
+static mut TO_BE_CHANGED: bool = true;
 
-/// The substitue function that proc2 will attempt to put in the place of `to_be_changed`
-#[allow(dead_code)]
-fn substitute() -> bool {
-    false
+fn to_be_changed() -> bool {
+    unsafe { ptr::read_volatile(&TO_BE_CHANGED) }
 }
 
 pub mod proc1 {
     use super::*;
 
+    #[derive(RetypeForSetup)]
     pub struct Proc1Params<Role: CNodeRole> {
         pub rspdr: Responder<(), (), Role>,
         pub outcome_sender: Sender<bool, Role>,
     }
 
-    impl RetypeForSetup for Proc1Params<role::Local> {
-        type Output = Proc1Params<role::Child>;
-    }
-
     pub extern "C" fn run(params: Proc1Params<role::Local>) {
         let Proc1Params {
             rspdr,
@@ -168,19 +161,15 @@ pub mod proc1 {
 pub mod proc2 {
     use super::*;
 
+    #[derive(RetypeForSetup)]
     pub struct Proc2Params<Role: CNodeRole> {
         pub cllr: Caller<(), (), Role>,
     }
 
-    impl RetypeForSetup for Proc2Params<role::Local> {
-        type Output = Proc2Params<role::Child>;
-    }
-
     pub extern "C" fn run(params: Proc2Params<role::Local>) {
-        // Change the to_be_changed function to point to something different
+        // Change the to_be_changed static in this process' copy
         unsafe {
-            let tbc_ptr = to_be_changed as *mut usize;
-            ptr::write_volatile(tbc_ptr, substitute as usize);
+            ptr::write_volatile(&mut TO_BE_CHANGED, false);
         }
         params
             .cllr
@@ -238,39 +238,27 @@ pub struct Yttrium {
     padding: [u8; 1024],
 }
 
+#[derive(RetypeForSetup)]
 pub struct ConsumerParams<Role: CNodeRole> {
     pub consumer: Consumer2<Role, Xenon, Yttrium>,
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ConsumerParams<role::Local> {
-    type Output = ConsumerParams<role::Child>;
-}
-
+#[derive(RetypeForSetup)]
 pub struct ProducerXParams<Role: CNodeRole> {
     pub producer: Producer<Role, Xenon>,
 }
 
-impl RetypeForSetup for ProducerXParams<role::Local> {
-    type Output = ProducerXParams<role::Child>;
-}
-
+#[derive(RetypeForSetup)]
 pub struct ProducerYParams<Role: CNodeRole> {
     pub producer: Producer<Role, Yttrium>,
 }
 
-impl RetypeForSetup for ProducerYParams<role::Local> {
-    type Output = ProducerYParams<role::Child>;
-}
-
+#[derive(RetypeForSetup)]
 pub struct WakerParams<Role: CNodeRole> {
     pub waker: Waker<Role>,
 }
 
-impl RetypeForSetup for WakerParams<role::Local> {
-    type Output = WakerParams<role::Child>;
-}
-
 pub extern "C" fn consumer_proc(p: ConsumerParams<role::Local>) {
     #[derive(Debug)]
     struct State {
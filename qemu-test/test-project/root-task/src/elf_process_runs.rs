@@ -8,7 +8,6 @@ use ferros::bootstrap::UserImage;
 use ferros::cap::*;
 use ferros::userland::{fault_or_message_channel, FaultOrMessage, StandardProcess};
 use ferros::vspace::*;
-use selfe_arc;
 
 #[ferros_test::ferros_test]
 pub fn elf_process_runs(
@@ -23,18 +22,9 @@ pub fn elf_process_runs(
 ) -> Result<(), TopLevelError> {
     let uts = ut_buddy(local_ut);
 
-    let archive_slice: &[u8] = unsafe {
-        core::slice::from_raw_parts(
-            &crate::_selfe_arc_data_start,
-            &crate::_selfe_arc_data_end as *const _ as usize
-                - &crate::_selfe_arc_data_start as *const _ as usize,
-        )
-    };
-
-    let archive = selfe_arc::read::Archive::from_slice(archive_slice);
-    let elf_data = archive
-        .file(crate::resources::ElfProcess::IMAGE_NAME)
-        .expect("find elf-process in arc");
+    let elf_data = crate::resources::AllResources::load(user_image)
+        .elf_process
+        .data;
 
     smart_alloc!(|slots: local_slots, ut: uts| {
         let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
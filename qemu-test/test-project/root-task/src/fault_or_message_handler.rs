@@ -129,7 +129,7 @@ pub fn fault_or_message_handler(
     Ok(())
 }
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, AddressSpaceIndependent)]
 #[repr(C)]
 pub enum Command {
     ReportTrue,
@@ -137,15 +137,12 @@ pub enum Command {
     ThrowFault,
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams<Role: CNodeRole> {
     pub command: Command,
     pub sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ProcParams<role::Local> {
-    type Output = ProcParams<role::Child>;
-}
-
 pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
     let ProcParams { command, sender } = params;
     match command {
@@ -126,26 +126,18 @@ pub fn fault_pair(
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, RetypeForSetup)]
 pub struct MischiefMakerParams<Role: CNodeRole> {
     pub _role: PhantomData<Role>,
 }
 
-impl RetypeForSetup for MischiefMakerParams<role::Local> {
-    type Output = MischiefMakerParams<role::Child>;
-}
-
-#[derive(Debug)]
+#[derive(Debug, RetypeForSetup)]
 pub struct MischiefDetectorParams<Role: CNodeRole> {
     pub fault_sink: FaultSink<Role>,
     pub outcome_sender: Sender<bool, Role>,
     pub local_slots: Cap<CNodeSlotsData<U1, Role>, Role>,
 }
 
-impl RetypeForSetup for MischiefDetectorParams<role::Local> {
-    type Output = MischiefDetectorParams<role::Child>;
-}
-
 pub extern "C" fn mischief_maker_proc(_p: MischiefMakerParams<role::Local>) {
     unsafe { seL4_Send(314159, seL4_MessageInfo_new(0, 0, 0, 0)) }
 
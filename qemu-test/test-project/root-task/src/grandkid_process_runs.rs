@@ -111,6 +111,7 @@ pub fn grandkid_process_runs(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ChildParams<Role: CNodeRole> {
     cnode: Cap<CNode<Role>, Role>,
     cnode_slots: Cap<CNodeSlotsData<op!(CodePageTableCount + CodePageCount + U70), Role>, Role>,
@@ -122,10 +123,6 @@ pub struct ChildParams<Role: CNodeRole> {
     outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ChildParams<role::Local> {
-    type Output = ChildParams<role::Child>;
-}
-
 pub extern "C" fn child_main(params: ChildParams<role::Local>) {
     child_run(params).expect("Error in child process");
 }
@@ -208,14 +205,11 @@ fn child_run(params: ChildParams<role::Local>) -> Result<(), TopLevelError> {
     Ok(())
 }
 
+#[derive(RetypeForSetup)]
 pub struct GrandkidParams<Role: CNodeRole> {
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for GrandkidParams<role::Local> {
-    type Output = GrandkidParams<role::Child>;
-}
-
 pub extern "C" fn grandkid_main(params: GrandkidParams<role::Local>) {
     params
         .outcome_sender
@@ -0,0 +1,173 @@
+//! A test verifying that the user image is mapped into a child process
+//! by section: even in a writable copy of the image, code can't be
+//! written to, and data can't be executed.
+use core::ptr;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::arch::fault::Fault;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, RetypeForSetup, Sender, StandardProcess,
+};
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+type U42768 = Sum<U32768, U10000>;
+
+#[ferros_test::ferros_test]
+pub fn image_section_protection(
+    local_slots: LocalCNodeSlots<U42768>,
+    local_ut: LocalCap<Untyped<U27>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    mut local_vspace_scratch: &mut ScratchRegion,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (writer_cspace, writer_slots) = retype_cnode::<U8>(ut, slots)?;
+        let (executor_cspace, executor_slots) = retype_cnode::<U8>(ut, slots)?;
+    });
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (writer_asid, asid_pool) = asid_pool.alloc();
+        let (executor_asid, _asid_pool) = asid_pool.alloc();
+
+        // The writer gets a writable copy of the image, so that only
+        // the rights of the code's section stand in its way.
+        let writer_vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
+        let writer_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut writer_vspace = VSpace::new(
+            retype(ut, slots)?,
+            writer_asid,
+            writer_vspace_slots.weaken(),
+            writer_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadWritable {
+                parent_vspace_scratch: &mut local_vspace_scratch,
+                code_pages_ut: ut,
+                code_pages_slots: slots,
+            },
+            user_image,
+            root_cnode,
+        )?;
+
+        let executor_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let executor_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut executor_vspace = VSpace::new(
+            retype(ut, slots)?,
+            executor_asid,
+            executor_vspace_slots.weaken(),
+            executor_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (writer_sender_slot, _writer_slots) = writer_slots.alloc();
+        let (writer_fault_source, writer_sender, writer_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, writer_sender_slot, slots)?;
+        let (executor_sender_slot, _executor_slots) = executor_slots.alloc();
+        let (executor_fault_source, executor_sender, executor_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, executor_sender_slot, slots)?;
+
+        let (writer_region, executor_region) = local_mapped_region.split()?;
+
+        let mut writer_process = StandardProcess::new(
+            &mut writer_vspace,
+            writer_cspace,
+            writer_region,
+            root_cnode,
+            writer_main as extern "C" fn(_) -> (),
+            ProcParams {
+                survived_sender: writer_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(writer_fault_source),
+        )?;
+
+        let mut executor_process = StandardProcess::new(
+            &mut executor_vspace,
+            executor_cspace,
+            executor_region,
+            root_cnode,
+            executor_main as extern "C" fn(_) -> (),
+            ProcParams {
+                survived_sender: executor_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(executor_fault_source),
+        )?;
+    });
+    writer_process.start()?;
+    executor_process.start()?;
+
+    match writer_handler.await_message()? {
+        FaultOrMessage::Fault(Fault::VMFault(fault))
+            if fault.is_write && fault.address == code_addr() => {}
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "Writing to code should have faulted",
+            ))
+        }
+    }
+    match executor_handler.await_message()? {
+        FaultOrMessage::Fault(Fault::VMFault(fault))
+            if fault.is_instruction_fault && fault.address == data_addr() =>
+        {
+            Ok(())
+        }
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Executing data should have faulted",
+        )),
+    }
+}
+
+/// Data which the executor tries to run as code.
+static mut NOT_CODE: [usize; 4] = [1, 2, 3, 4];
+
+fn data_addr() -> usize {
+    unsafe { NOT_CODE.as_ptr() as usize }
+}
+
+/// Code which the writer tries to overwrite.
+fn code_addr() -> usize {
+    code_addr as usize
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub survived_sender: Sender<bool, Role>,
+}
+
+pub extern "C" fn writer_main(params: ProcParams<role::Local>) {
+    unsafe {
+        ptr::write_volatile(code_addr() as *mut usize, 0);
+    }
+    params
+        .survived_sender
+        .blocking_send(&true)
+        .expect("Failure sending survival report");
+}
+
+pub extern "C" fn executor_main(params: ProcParams<role::Local>) {
+    unsafe {
+        let not_code: extern "C" fn() = core::mem::transmute(data_addr());
+        not_code();
+    }
+    params
+        .survived_sender
+        .blocking_send(&true)
+        .expect("Failure sending survival report");
+}
@@ -0,0 +1,65 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::{self, cap::LargePage, LargePageBits};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// A large page is only mapped at an address aligned to its size, and
+/// once mapped can be flushed whole, which the kernel refuses for any
+/// range running past the end of the frame.
+#[ferros_test::ferros_test]
+pub fn large_page_flush(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U22>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let page: LocalCap<LargePage<page_state::Unmapped>> = retype(ut, slots)?;
+    });
+
+    let size = 1 << LargePageBits::USIZE;
+    let vaddr = (child_vspace.current_addr() + size - 1) & !(size - 1);
+
+    let page = match child_vspace.map_large_page(
+        page,
+        vaddr + arch::PageBytes::USIZE,
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT,
+    ) {
+        Err((VSpaceError::MappingError(MappingError::AddrNotPageAligned), page)) => page,
+        _ => {
+            return Err(TopLevelError::TestAssertionFailure(
+                "A misaligned large page should have been refused",
+            ))
+        }
+    };
+
+    let page = child_vspace
+        .map_large_page(page, vaddr, CapRights::RW, arch::vm_attributes::DEFAULT)
+        .map_err(|(e, _)| e)?;
+    assert_eq_test!(page.vaddr(), vaddr);
+    page.flush()?;
+    Ok(())
+}
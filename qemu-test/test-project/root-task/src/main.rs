@@ -12,30 +12,55 @@ extern crate selfe_sys;
 #[macro_use]
 extern crate typenum;
 
+mod array_vec_params;
+mod asid_pool_split;
+mod assertion_macros;
+mod barrier_at_capacity;
+mod cache_maintenance_ranges;
+mod call_cancelled_by_close;
 mod call_and_response_loop;
+mod cap_identify;
+mod capdl_spec;
 mod child_process_cap_management;
+mod child_process_join;
 mod child_process_runs;
 mod child_thread_runs;
+mod domain_progress;
 mod dont_tread_on_me;
 mod double_door_backpressure;
 mod elf_process_runs;
 mod fault_or_message_handler;
 mod fault_pair;
 mod grandkid_process_runs;
+mod image_section_protection;
 mod irq_control_manipulation;
+mod large_page_flush;
 mod memory_read_protection;
 mod memory_write_protection;
+mod message_word_counts;
+mod notification_badge_bits;
 mod over_register_size_params;
+mod panic_sender_at_spawn;
 mod polling_consumer;
+mod read_only_sharing;
+mod region_split_join;
+mod repopulate_page_tables;
+mod register_sized_call;
+mod retype_batch;
 mod reuse_slots;
 mod reuse_untyped;
 mod root_task_runs;
 mod self_hosted_mem_mgmt;
 mod shared_page_queue;
+mod spsc_queue_laps;
 mod stack_setup;
+mod test_timeout;
+mod thread_state_after_fault;
+mod tls_image_footprint;
 mod uart;
 mod wutbuddy;
 mod weak_elf;
+mod worker_pool;
 
 mod resources {
     include! {concat!(env!("OUT_DIR"), "/resources.rs")}
@@ -51,39 +76,74 @@ use ferros::alloc::ut_buddy::UTBuddyError;
 use ferros::cap::IRQError;
 use ferros::cap::RetypeError;
 use ferros::error::SeL4Error;
+use ferros::test_support::TestAssertionFailure;
 use ferros::userland::{
-    FaultManagementError, IPCError, MultiConsumerError, ProcessSetupError, ThreadSetupError,
+    BarrierError, FaultManagementError, IPCError, MultiConsumerError, ProcessSetupError,
+    ThreadSetupError,
 };
 use ferros::vspace::VSpaceError;
 
 #[cfg(not(test_case = "uart"))]
 use ferros_test::ferros_test_main;
 
-#[cfg(not(test_case = "uart"))]
+#[cfg(not(any(test_case = "uart", test_case = "test_timeout")))]
 ferros_test_main!(&[
+    &array_vec_params::array_vec_params,
+    &asid_pool_split::asid_pool_split_after_reclaim,
+    &assertion_macros::assertion_macros,
+    &barrier_at_capacity::barrier_at_capacity,
+    &cache_maintenance_ranges::cache_maintenance_ranges,
+    &call_cancelled_by_close::call_cancelled_by_close,
+    &call_cancelled_by_close::call_cancelled_while_waiting,
     &call_and_response_loop::call_and_response_loop,
+    &cap_identify::cap_identify,
+    &capdl_spec::capdl_spec,
     &child_process_cap_management::child_process_cap_management,
+    &child_process_join::child_process_join,
     &child_process_runs::child_process_runs,
     &child_thread_runs::child_thread_runs,
+    &domain_progress::domain_progress,
     &dont_tread_on_me::dont_tread_on_me,
     &double_door_backpressure::double_door_backpressure,
     &elf_process_runs::elf_process_runs,
     &fault_or_message_handler::fault_or_message_handler,
     &fault_pair::fault_pair,
     &grandkid_process_runs::grandkid_process_runs,
+    &image_section_protection::image_section_protection,
     &irq_control_manipulation::irq_control_manipulation,
+    &large_page_flush::large_page_flush,
     &memory_read_protection::memory_read_protection,
     &memory_write_protection::memory_write_protection,
+    &message_word_counts::message_word_counts,
+    &notification_badge_bits::notification_badge_bits,
     &over_register_size_params::over_register_size_params,
+    &panic_sender_at_spawn::panic_sender_at_spawn_read_only,
+    &panic_sender_at_spawn::panic_sender_at_spawn_read_writable,
     &polling_consumer::polling_consumer,
+    &read_only_sharing::read_only_sharing,
+    &region_split_join::region_split_join,
+    &repopulate_page_tables::repopulate_page_tables,
+    &register_sized_call::register_sized_call,
+    &retype_batch::retype_batch,
     &reuse_slots::reuse_slots,
     &reuse_untyped::reuse_untyped,
     &root_task_runs::root_task_runs,
     &self_hosted_mem_mgmt::self_hosted_mem_mgmt,
     &shared_page_queue::shared_page_queue,
+    &spsc_queue_laps::spsc_queue_laps,
     &stack_setup::stack_setup,
+    &thread_state_after_fault::thread_state_after_fault,
+    &tls_image_footprint::tls_image_footprint,
     &wutbuddy::wutbuddy,
     &weak_elf::weak_elf_process_runs,
+    &worker_pool::worker_pool,
+]);
+
+#[cfg(test_case = "test_timeout")]
+ferros_test_main!(&[
+    &test_timeout::hangs_on_a_thread,
+    &test_timeout::hangs_in_a_process,
+    &test_timeout::runs_after_hung_tests,
 ]);
 
 #[cfg(test_case = "uart")]
@@ -116,6 +176,7 @@ pub enum TopLevelError {
     ThreadSetupError(ThreadSetupError),
     UTBuddyError(UTBuddyError),
     RetypeError(RetypeError),
+    BarrierError(BarrierError),
     TestAssertionFailure(&'static str),
 }
 
@@ -179,8 +240,20 @@ impl From<UTBuddyError> for TopLevelError {
     }
 }
 
+impl From<TestAssertionFailure> for TopLevelError {
+    fn from(e: TestAssertionFailure) -> Self {
+        TopLevelError::TestAssertionFailure(e.0)
+    }
+}
+
 impl From<RetypeError> for TopLevelError {
     fn from(e: RetypeError) -> Self {
         TopLevelError::RetypeError(e)
     }
 }
+
+impl From<BarrierError> for TopLevelError {
+    fn from(e: BarrierError) -> Self {
+        TopLevelError::BarrierError(e)
+    }
+}
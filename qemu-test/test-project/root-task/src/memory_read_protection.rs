@@ -72,12 +72,9 @@ pub fn memory_read_protection(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams {}
 
-impl RetypeForSetup for ProcParams {
-    type Output = ProcParams;
-}
-
 pub extern "C" fn proc_main(_params: ProcParams) {
     unsafe {
         let x: *const usize = 0x88888888usize as _;
@@ -70,12 +70,9 @@ pub fn memory_write_protection<'a, 'b, 'c>(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams {}
 
-impl RetypeForSetup for ProcParams {
-    type Output = ProcParams;
-}
-
 pub extern "C" fn proc_main(_params: ProcParams) {
     unsafe {
         let x: *mut usize = proc_main as _;
@@ -0,0 +1,26 @@
+use core::mem::size_of;
+
+use ferros::arch::Word;
+use ferros::test_support::message_length_in_words;
+
+use super::TopLevelError;
+
+/// Messages are measured in kernel words, four bytes on arm and eight
+/// on aarch64, and anything with a partial word left over takes up a
+/// whole one.
+#[ferros_test::ferros_test]
+pub fn message_word_counts() -> Result<(), TopLevelError> {
+    #[cfg(target_arch = "aarch64")]
+    let (word_bytes, u64_words, twelve_byte_words) = (8, 1, 2);
+    #[cfg(any(target_arch = "arm", target_arch = "aarch32"))]
+    let (word_bytes, u64_words, twelve_byte_words) = (4, 2, 3);
+
+    assert_eq_test!(size_of::<Word>(), word_bytes);
+    assert_eq_test!(message_length_in_words::<()>(), 0);
+    assert_eq_test!(message_length_in_words::<u8>(), 1);
+    assert_eq_test!(message_length_in_words::<Word>(), 1);
+    assert_eq_test!(message_length_in_words::<u64>(), u64_words);
+    assert_eq_test!(message_length_in_words::<[u8; 12]>(), twelve_byte_words);
+    assert_eq_test!(message_length_in_words::<[Word; 3]>(), 3);
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::cap::{retype, Badge, LocalCNode, LocalCNodeSlots, LocalCap, Notification, Untyped};
+use ferros::userland::CapRights;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn notification_badge_bits(
+    local_slots: LocalCNodeSlots<U8>,
+    local_ut: LocalCap<Untyped<U10>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let notification: LocalCap<Notification> = retype(ut, slots)?;
+        let low = notification.mint(root_cnode, slots, CapRights::RWG, Badge::from(0b001))?;
+        let high = notification.mint(root_cnode, slots, CapRights::RWG, Badge::from(0b100))?;
+    });
+
+    assert_eq_test!(notification.poll(), None);
+
+    // Neither source is waited on between signals, so they arrive
+    // together; signalling the same bit twice is no different from once.
+    low.signal();
+    high.signal();
+    low.signal();
+    let received = notification.poll();
+    assert_eq_test!(received, Some(Badge::from(0b101)));
+
+    let mut bits = received.unwrap_or(Badge::from(0)).bits();
+    assert_eq_test!(bits.next(), Some(Badge::from(0b001)));
+    assert_eq_test!(bits.next(), Some(Badge::from(0b100)));
+    assert_eq_test!(bits.next(), None);
+
+    assert_eq_test!(
+        notification.poll(),
+        None,
+        "polling should have cleared the badge"
+    );
+    Ok(())
+}
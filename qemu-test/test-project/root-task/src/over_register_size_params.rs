@@ -75,23 +75,17 @@ pub fn over_register_size_params<'a, 'b, 'c>(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams {
     pub value: usize,
 }
 
-impl RetypeForSetup for ProcParams {
-    type Output = ProcParams;
-}
-
+#[derive(RetypeForSetup)]
 pub struct OverRegisterSizeParams<Role: CNodeRole> {
     pub nums: [usize; 10],
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for OverRegisterSizeParams<role::Local> {
-    type Output = OverRegisterSizeParams<role::Child>;
-}
-
 pub extern "C" fn proc_main(params: OverRegisterSizeParams<role::Local>) {
     let OverRegisterSizeParams {
         nums,
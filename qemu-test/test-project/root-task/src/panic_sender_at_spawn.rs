@@ -0,0 +1,167 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, PanicReport, RetypeForSetup, Sender,
+    StandardProcessBuilder,
+};
+use ferros::vspace::*;
+
+type U42768 = Sum<U32768, U10000>;
+
+/// A process given a panic sender at spawn time still starts at its
+/// own entry point, with its parameter intact, even though its image
+/// is mapped read-only.
+#[ferros_test::ferros_test]
+pub fn panic_sender_at_spawn_read_only(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let process_slots: LocalCNodeSlots<U1024> = slots;
+        let process_ut: LocalCap<Untyped<U19>> = ut;
+    });
+
+    spawn_and_check(
+        &mut child_vspace,
+        process_slots,
+        process_ut,
+        local_mapped_region,
+        root_cnode,
+        tpa,
+    )
+}
+
+/// As `panic_sender_at_spawn_read_only`, with a writable copy of the
+/// image.
+#[ferros_test::ferros_test]
+pub fn panic_sender_at_spawn_read_writable(
+    local_slots: LocalCNodeSlots<U42768>,
+    local_ut: LocalCap<Untyped<U27>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    mut local_vspace_scratch: &mut ScratchRegion,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<ferros::arch::CodePageCount> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadWritable {
+                parent_vspace_scratch: &mut local_vspace_scratch,
+                code_pages_ut: ut,
+                code_pages_slots: slots,
+            },
+            user_image,
+            root_cnode,
+        )?;
+        let process_slots: LocalCNodeSlots<U1024> = slots;
+        let process_ut: LocalCap<Untyped<U19>> = ut;
+    });
+
+    spawn_and_check(
+        &mut child_vspace,
+        process_slots,
+        process_ut,
+        local_mapped_region,
+        root_cnode,
+        tpa,
+    )
+}
+
+fn spawn_and_check(
+    child_vspace: &mut VSpace,
+    local_slots: LocalCNodeSlots<U1024>,
+    local_ut: LocalCap<Untyped<U19>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let (panic_sender_slot, child_slots) = child_slots.alloc();
+        let (panic_fault_source, panic_sender, _panic_handler) =
+            fault_or_message_channel::<PanicReport, _>(
+                &root_cnode,
+                ut,
+                slots,
+                panic_sender_slot,
+                slots,
+            )?;
+        let (outcome_sender_slot, _child_slots) = child_slots.alloc();
+        let (_outcome_fault_source, outcome_sender, outcome_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, outcome_sender_slot, slots)?;
+
+        let mut child_process = StandardProcessBuilder::new(
+            proc_main as extern "C" fn(_) -> (),
+            ProcParams {
+                outcome_sender,
+                value: 0xdead_beef,
+            },
+        )
+        .stack_region(local_mapped_region)
+        .ipc_buffer_ut(ut)
+        .tcb_ut(ut)
+        .slots(slots)
+        .priority(tpa, 255)
+        .fault_source(panic_fault_source)
+        .panic_sender(panic_sender)
+        .build(child_vspace, child_cnode, root_cnode)?;
+        child_process.start()?;
+    });
+
+    match outcome_handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "The process should have been handed its parameter",
+        )),
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams<Role: CNodeRole> {
+    pub outcome_sender: Sender<bool, Role>,
+    pub value: usize,
+}
+
+pub extern "C" fn proc_main(params: ProcParams<role::Local>) {
+    params
+        .outcome_sender
+        .blocking_send(&(params.value == 0xdead_beef))
+        .expect("Failure sending test assertion outcome");
+}
@@ -143,23 +143,17 @@ pub struct Data {
     a: u64,
 }
 
+#[derive(RetypeForSetup)]
 pub struct ConsumerParams<Role: CNodeRole> {
     pub consumer: Consumer1<Role, Data>,
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ConsumerParams<role::Local> {
-    type Output = ConsumerParams<role::Child>;
-}
-
+#[derive(RetypeForSetup)]
 pub struct ProducerParams<Role: CNodeRole> {
     pub producer: Producer<Role, Data>,
 }
 
-impl RetypeForSetup for ProducerParams<role::Local> {
-    type Output = ProducerParams<role::Child>;
-}
-
 pub extern "C" fn consumer_proc(p: ConsumerParams<role::Local>) {
     #[derive(Debug)]
     struct State {
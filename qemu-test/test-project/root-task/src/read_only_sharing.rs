@@ -0,0 +1,73 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::rights::rights;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn read_only_sharing(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let region: UnmappedMemoryRegion<U12, _> = UnmappedMemoryRegion::new(ut, slots)?;
+        let region = region.to_shared_with_rights::<rights::R>();
+
+        // Mapping it with `rights::RW` instead doesn't compile, as
+        // `RW` isn't `SubsetOf<rights::R>`.
+        let mapped = child_vspace.map_shared_region(
+            &region,
+            rights::R,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            root_cnode,
+        )?;
+        assert_eq_test!(mapped.rights(), CapRights::R);
+
+        // Rights only known at runtime are checked when mapping.
+        let refused = child_vspace.map_shared_region(
+            &region,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            root_cnode,
+        );
+        assert_test!(matches!(
+            refused,
+            Err(VSpaceError::RightsExceedSharedRegion)
+        ));
+        let remapped = child_vspace.map_shared_region(
+            &region,
+            CapRights::R,
+            arch::vm_attributes::DEFAULT,
+            slots,
+            root_cnode,
+        )?;
+        assert_eq_test!(remapped.rights(), CapRights::R);
+    });
+    Ok(())
+}
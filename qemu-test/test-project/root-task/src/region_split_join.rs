@@ -0,0 +1,31 @@
+use typenum::*;
+
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn region_split_join(
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+) -> Result<(), TopLevelError> {
+    let vaddr = local_mapped_region.vaddr();
+    let size_bytes = local_mapped_region.size_bytes();
+
+    let (low, mut high) = local_mapped_region.split()?;
+    high.as_mut_slice()[0] = 0xAB;
+
+    // Out of order, the halves aren't adjacent.
+    let (high, low) = match high.join(low) {
+        Ok(_) => return Err(TopLevelError::TestAssertionFailure("joined halves out of order")),
+        Err(halves) => halves,
+    };
+
+    let joined = match low.join(high) {
+        Ok(joined) => joined,
+        Err(_) => return Err(TopLevelError::TestAssertionFailure("could not rejoin halves")),
+    };
+    assert_eq_test!(joined.vaddr(), vaddr);
+    assert_eq_test!(joined.size_bytes(), size_bytes);
+    assert_eq_test!(joined.as_slice()[size_bytes / 2], 0xAB);
+    Ok(())
+}
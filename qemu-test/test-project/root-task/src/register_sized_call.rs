@@ -0,0 +1,182 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch::Stopwatch;
+use ferros::cap::*;
+use ferros::userland::{call_channel, Responder, RetypeForSetup, Thread, VariableMessage};
+use ferros::vspace::*;
+
+const CALLS: usize = 100;
+
+/// A word which `blocking_call_variable` will always send through the
+/// IPC buffer, to time against the same word sent in a register.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(transparent)]
+pub struct BufferedWord(usize);
+
+unsafe impl VariableMessage for BufferedWord {
+    fn length_in_words(&self) -> usize {
+        1
+    }
+}
+
+#[ferros_test::ferros_test]
+pub fn register_sized_call(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    word_stack_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    word_ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    pair_stack_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    pair_ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    buffered_stack_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    buffered_ipc_buffer_region: MappedMemoryRegion<U12, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+    vspace_paging_root: &LocalCap<ferros::arch::PagingRoot>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (word_cnode, word_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (word_responder_slot, _word_slots) = word_slots.alloc();
+        let (word_setup, word_responder) =
+            call_channel(ut, &root_cnode, slots, word_responder_slot)?;
+        let word_caller = word_setup.create_caller(slots)?;
+
+        let (pair_cnode, pair_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (pair_responder_slot, _pair_slots) = pair_slots.alloc();
+        let (pair_setup, pair_responder) =
+            call_channel(ut, &root_cnode, slots, pair_responder_slot)?;
+        let pair_caller = pair_setup.create_caller(slots)?;
+
+        let (buffered_cnode, buffered_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (buffered_responder_slot, _buffered_slots) = buffered_slots.alloc();
+        let (buffered_setup, buffered_responder) =
+            call_channel(ut, &root_cnode, slots, buffered_responder_slot)?;
+        let buffered_caller = buffered_setup.create_caller(slots)?;
+
+        let word_thread = Thread::new(
+            vspace_paging_root,
+            word_cnode,
+            word_stack_region,
+            word_proc,
+            WordParams {
+                responder: word_responder,
+            },
+            word_ipc_buffer_region,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        let pair_thread = Thread::new(
+            vspace_paging_root,
+            pair_cnode,
+            pair_stack_region,
+            pair_proc,
+            PairParams {
+                responder: pair_responder,
+            },
+            pair_ipc_buffer_region,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        let buffered_thread = Thread::new(
+            vspace_paging_root,
+            buffered_cnode,
+            buffered_stack_region,
+            buffered_proc,
+            BufferedParams {
+                responder: buffered_responder,
+            },
+            buffered_ipc_buffer_region,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+    });
+
+    word_thread.start()?;
+    pair_thread.start()?;
+    buffered_thread.start()?;
+
+    // A usize request and response ride in a register; a pair of them
+    // is too big for that and goes through the IPC buffer.
+    let stopwatch = Stopwatch::start();
+    for i in 0..CALLS {
+        assert_eq_test!(word_caller.blocking_call(&i)?, i + 1);
+    }
+    let word_ticks = stopwatch.map(|s| s.elapsed());
+
+    // The same word again, but through the IPC buffer, which is what
+    // the register path saves.
+    let stopwatch = Stopwatch::start();
+    for i in 0..CALLS {
+        assert_eq_test!(
+            buffered_caller.blocking_call_variable(&BufferedWord(i))?,
+            BufferedWord(i + 1)
+        );
+    }
+    let buffered_ticks = stopwatch.map(|s| s.elapsed());
+
+    let stopwatch = Stopwatch::start();
+    for i in 0..CALLS {
+        assert_eq_test!(pair_caller.blocking_call(&[i, i])?, [i + 1, i + 1]);
+    }
+    let pair_ticks = stopwatch.map(|s| s.elapsed());
+
+    match (word_ticks, buffered_ticks, pair_ticks) {
+        (Ok(word_ticks), Ok(buffered_ticks), Ok(pair_ticks)) => debug_println!(
+            "{} calls: {} ticks for a word in a register, {} for a word through the IPC buffer, {} for a pair through the IPC buffer",
+            CALLS,
+            word_ticks,
+            buffered_ticks,
+            pair_ticks
+        ),
+        _ => debug_println!("No cycle counter at user level; not timing the calls"),
+    }
+    Ok(())
+}
+
+#[derive(RetypeForSetup)]
+pub struct WordParams<Role: CNodeRole> {
+    pub responder: Responder<usize, usize, Role>,
+}
+
+#[derive(RetypeForSetup)]
+pub struct PairParams<Role: CNodeRole> {
+    pub responder: Responder<[usize; 2], [usize; 2], Role>,
+}
+
+#[derive(RetypeForSetup)]
+pub struct BufferedParams<Role: CNodeRole> {
+    pub responder: Responder<BufferedWord, BufferedWord, Role>,
+}
+
+pub extern "C" fn word_proc(params: WordParams<role::Local>) {
+    params
+        .responder
+        .reply_recv(|i| i + 1)
+        .expect("word responder failed");
+}
+
+pub extern "C" fn pair_proc(params: PairParams<role::Local>) {
+    params
+        .responder
+        .reply_recv(|[a, b]| [a + 1, b + 1])
+        .expect("pair responder failed");
+}
+
+pub extern "C" fn buffered_proc(params: BufferedParams<role::Local>) {
+    params
+        .responder
+        .reply_recv_variable(|BufferedWord(i)| BufferedWord(i + 1))
+        .expect("buffered responder failed");
+}
@@ -0,0 +1,57 @@
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::arch;
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::CapRights;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// More times than the address space has slots, so this only passes if
+/// populating over an existing page table hands back what it used.
+const REPOPULATE_COUNT: usize = 2048;
+
+#[ferros_test::ferros_test]
+pub fn repopulate_page_tables(
+    local_slots: LocalCNodeSlots<U2048>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let region: UnmappedMemoryRegion<U12, _> = UnmappedMemoryRegion::new(ut, slots)?;
+    });
+
+    let reservation = child_vspace.reserve_address_range::<U12>()?;
+    for _ in 0..REPOPULATE_COUNT {
+        child_vspace.populate_page_tables::<U12>(reservation.vaddr(), root_cnode)?;
+    }
+
+    child_vspace
+        .map_region_at_reservation(
+            region,
+            reservation,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )
+        .map_err(|(e, _, _)| e)?;
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::cap::{
+    role, Badge, Endpoint, LocalCNode, LocalCNodeSlots, LocalCap, Notification, RetypeError,
+    Untyped,
+};
+use ferros::userland::CapRights;
+
+use super::TopLevelError;
+
+#[ferros_test::ferros_test]
+pub fn retype_batch(
+    local_slots: LocalCNodeSlots<U16>,
+    local_ut: LocalCap<Untyped<U12>>,
+    root_cnode: &LocalCap<LocalCNode>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let big: LocalCap<Untyped<U10>> = ut;
+        let small: LocalCap<Untyped<U6>> = ut;
+        let first_endpoint_slots: LocalCNodeSlots<U2> = slots;
+        let second_endpoint_slots: LocalCNodeSlots<U1> = slots;
+        let notification_slots: LocalCNodeSlots<U2> = slots;
+        let too_many_slots: LocalCNodeSlots<U5> = slots;
+        let badged_slots: LocalCNodeSlots<U5> = slots;
+    });
+
+    // The two endpoint requests go in adjacent slots, so they share a
+    // call; the notifications need one of their own.
+    let (first_endpoints, second_endpoints, notifications) = big
+        .retype_batch()
+        .retype::<Endpoint, role::Local, U2>(first_endpoint_slots)
+        .retype::<Endpoint, role::Local, U1>(second_endpoint_slots)
+        .retype::<Notification, role::Local, U2>(notification_slots)
+        .finish()
+        .map_err(|(e, _)| e)?;
+
+    // Minting from an empty slot fails, so these show that every
+    // object was made where its range says.
+    let mut badged_slots = badged_slots.weaken();
+    for (_, endpoint) in first_endpoints
+        .iter_indexed()
+        .chain(second_endpoints.iter_indexed())
+    {
+        endpoint.mint(
+            root_cnode,
+            badged_slots.alloc_strong().map_err(RetypeError::from)?,
+            CapRights::RWG,
+            Badge::from(1),
+        )?;
+    }
+    for (_, notification) in notifications.iter_indexed() {
+        let badged = notification.mint(
+            root_cnode,
+            badged_slots.alloc_strong().map_err(RetypeError::from)?,
+            CapRights::RWG,
+            Badge::from(1),
+        )?;
+        badged.signal();
+        assert_eq_test!(notification.poll(), Some(Badge::from(1)));
+    }
+
+    // Four endpoints fill 64 bytes on their own, leaving no room for a
+    // notification after them.
+    let (endpoint_slots, notification_slot) = too_many_slots.alloc::<U4>();
+    match small
+        .retype_batch()
+        .retype::<Endpoint, role::Local, U4>(endpoint_slots)
+        .retype::<Notification, role::Local, U1>(notification_slot)
+        .finish()
+    {
+        // The untyped comes back untouched, so the notification alone
+        // can still be made from it.
+        Err((RetypeError::NotBigEnough, small)) => {
+            small
+                .retype_batch()
+                .retype::<Notification, role::Local, U1>(notification_slot)
+                .finish()
+                .map_err(|(e, _)| e)?;
+            Ok(())
+        }
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "an overfull batch should be rejected before anything is made",
+        )),
+    }
+}
@@ -96,6 +96,7 @@ pub fn self_hosted_mem_mgmt(
     }
 }
 
+#[derive(RetypeForSetup)]
 pub struct ProcParams<Role: CNodeRole> {
     pub value: usize,
     pub child_slots: Cap<CNodeSlotsData<U1, Role>, Role>,
@@ -103,10 +104,6 @@ pub struct ProcParams<Role: CNodeRole> {
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ProcParams<role::Local> {
-    type Output = ProcParams<role::Child>;
-}
-
 pub extern "C" fn sh_main(mut vspace: VSpace, params: ProcParams<role::Local>) {
     let ProcParams {
         value,
@@ -135,23 +135,17 @@ pub struct Xenon {
     a: u64,
 }
 
+#[derive(RetypeForSetup)]
 pub struct ConsumerParams<Role: CNodeRole> {
     pub consumer: Consumer1<Role, Xenon>,
     pub outcome_sender: Sender<bool, Role>,
 }
 
-impl RetypeForSetup for ConsumerParams<role::Local> {
-    type Output = ConsumerParams<role::Child>;
-}
-
+#[derive(RetypeForSetup)]
 pub struct ProducerParams<Role: CNodeRole> {
     pub producer: Producer<Role, Xenon>,
 }
 
-impl RetypeForSetup for ProducerParams<role::Local> {
-    type Output = ProducerParams<role::Child>;
-}
-
 pub extern "C" fn consumer_run(p: ConsumerParams<role::Local>) {
     let ConsumerParams {
         consumer,
@@ -0,0 +1,159 @@
+use super::TopLevelError;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, spsc_queue_with_wakeups, FaultOrMessage, RetypeForSetup, Sender,
+    SpscConsumer, SpscProducer, StandardProcess,
+};
+use ferros::vspace::*;
+
+/// Enough elements to go around a four slot queue several times,
+/// ending part way through a lap.
+const ELEMENT_COUNT: usize = 4 * 8 + 3;
+
+#[ferros_test::ferros_test]
+pub fn spsc_queue_laps(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U2>>,
+    local_mapped_region: MappedMemoryRegion<U18, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (consumer_asid, asid_pool) = asid_pool.alloc();
+        let (producer_asid, _asid_pool) = asid_pool.alloc();
+
+        let (consumer_cnode, consumer_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (producer_cnode, producer_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let consumer_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let consumer_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut consumer_vspace = VSpace::new(
+            retype(ut, slots)?,
+            consumer_asid,
+            consumer_vspace_slots.weaken(),
+            consumer_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let producer_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let producer_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut producer_vspace = VSpace::new(
+            retype(ut, slots)?,
+            producer_asid,
+            producer_vspace_slots.weaken(),
+            producer_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+
+        let (producer_cap_slots, _producer_slots) = producer_slots.alloc();
+        let (consumer_cap_slots, consumer_slots) = consumer_slots.alloc();
+        let (producer, consumer) = spsc_queue_with_wakeups::<usize, U4, U12, _, _>(
+            root_cnode,
+            ut,
+            slots,
+            &mut producer_vspace,
+            slots,
+            &mut consumer_vspace,
+            ut,
+            ut,
+            slots,
+            producer_cap_slots,
+            consumer_cap_slots,
+        )?;
+
+        let (outcome_sender_slots, _consumer_slots) = consumer_slots.alloc();
+        let (_fault_source, outcome_sender, handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, outcome_sender_slots, slots)?;
+
+        let (consumer_region, producer_region) = local_mapped_region.split()?;
+
+        let mut consumer_process = StandardProcess::new(
+            &mut consumer_vspace,
+            consumer_cnode,
+            consumer_region,
+            root_cnode,
+            consumer_proc as extern "C" fn(_) -> (),
+            ConsumerParams {
+                consumer,
+                outcome_sender,
+            },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        let mut producer_process = StandardProcess::new(
+            &mut producer_vspace,
+            producer_cnode,
+            producer_region,
+            root_cnode,
+            producer_proc as extern "C" fn(_) -> (),
+            ProducerParams { producer },
+            ut,
+            ut,
+            slots,
+            tpa,
+            None, // fault
+        )?;
+
+        consumer_process.start()?;
+        producer_process.start()?;
+    });
+
+    match handler.await_message()? {
+        FaultOrMessage::Message(true) => Ok(()),
+        _ => Err(TopLevelError::TestAssertionFailure(
+            "Every element should have come out once, in order",
+        )),
+    }
+}
+
+#[derive(RetypeForSetup)]
+pub struct ConsumerParams<Role: CNodeRole> {
+    pub consumer: SpscConsumer<usize, Role>,
+    pub outcome_sender: Sender<bool, Role>,
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProducerParams<Role: CNodeRole> {
+    pub producer: SpscProducer<usize, Role>,
+}
+
+pub extern "C" fn consumer_proc(p: ConsumerParams<role::Local>) {
+    let ConsumerParams {
+        mut consumer,
+        outcome_sender,
+    } = p;
+
+    let mut in_order = consumer.capacity() == 4;
+    for expected in 0..ELEMENT_COUNT {
+        in_order &= consumer.pop_blocking() == expected;
+    }
+    in_order &= consumer.is_empty();
+
+    outcome_sender
+        .blocking_send(&in_order)
+        .expect("Failure sending test assertion outcome");
+}
+
+pub extern "C" fn producer_proc(p: ProducerParams<role::Local>) {
+    let ProducerParams { mut producer } = p;
+    for i in 0..ELEMENT_COUNT {
+        producer.push_blocking(i);
+    }
+}
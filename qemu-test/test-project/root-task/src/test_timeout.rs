@@ -0,0 +1,25 @@
+use super::TopLevelError;
+
+/// Never finishes, so the runner has to time it out and tear its
+/// thread down before it can go on.
+#[ferros_test::ferros_test]
+pub fn hangs_on_a_thread() {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// As `hangs_on_a_thread`, in a process of its own.
+#[ferros_test::ferros_test(process)]
+pub fn hangs_in_a_process() {
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Runs after the two above, to show that the runner carries on once
+/// it has given up on them.
+#[ferros_test::ferros_test]
+pub fn runs_after_hung_tests() -> Result<(), TopLevelError> {
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use core::ptr;
+
+use typenum::*;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::*;
+use ferros::vspace::*;
+
+use super::TopLevelError;
+
+/// A process is reported as not started until it is, and once its
+/// fault handler has killed it after its one fault, as killed.
+#[ferros_test::ferros_test]
+pub fn thread_state_after_fault(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U1>>,
+    local_mapped_region: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        let (child_asid, _asid_pool) = asid_pool.alloc();
+        let child_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let child_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut child_vspace = VSpace::new(
+            retype(ut, slots)?,
+            child_asid,
+            child_vspace_slots.weaken(),
+            child_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let (child_cnode, child_slots) = retype_cnode::<U12>(ut, slots)?;
+
+        let badge = Badge::from(7);
+        let fault_setup = FaultSinkSetup::new(&root_cnode, ut, slots, slots)?;
+        let (fault_source_slot, _child_slots) = child_slots.alloc();
+        let fault_source = fault_setup.add_fault_source(&root_cnode, fault_source_slot, badge)?;
+        let mut handler = FaultHandler::new(fault_setup.sink(), slots);
+
+        let mut child_process = StandardProcess::new(
+            &mut child_vspace,
+            child_cnode,
+            local_mapped_region,
+            root_cnode,
+            proc_main as extern "C" fn(_) -> (),
+            ProcParams { value: 42 },
+            ut,
+            ut,
+            slots,
+            tpa,
+            Some(fault_source),
+        )?;
+    });
+    child_process.watch_stack_guards(&mut handler, badge)?;
+
+    assert_eq_test!(
+        child_process.thread_state(Some(&handler)),
+        ThreadState::NotStarted
+    );
+    assert_eq_test!(child_process.fault_count(&handler), Some(0));
+
+    child_process.start()?;
+    assert_eq_test!(child_process.thread_state(None), ThreadState::Started);
+
+    handler.handle_next(|_| FaultDecision::Kill)?;
+    assert_eq_test!(child_process.fault_count(&handler), Some(1));
+    assert_eq_test!(
+        child_process.thread_state(Some(&handler)),
+        ThreadState::Killed
+    );
+    Ok(())
+}
+
+#[derive(RetypeForSetup)]
+pub struct ProcParams {
+    pub value: usize,
+}
+
+pub extern "C" fn proc_main(params: ProcParams) {
+    unsafe { ptr::write_volatile(ptr::null_mut(), params.value) };
+}
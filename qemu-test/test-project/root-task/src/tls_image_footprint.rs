@@ -0,0 +1,49 @@
+use core::mem::size_of;
+
+use ferros::userland::{ProcessSetupError, TlsImage};
+
+use super::TopLevelError;
+
+/// A TLS image's block starts after the two-word thread control block,
+/// rounded up to its alignment, and an image that couldn't have come
+/// from a well-formed ELF is refused rather than laid out.
+#[ferros_test::ferros_test(process)]
+pub fn tls_image_footprint() -> Result<(), TopLevelError> {
+    let tcb_size = 2 * size_of::<usize>();
+    let tdata = [1u8; 8];
+
+    let image = TlsImage {
+        initialized: &tdata,
+        size: 24,
+        align: 64,
+    };
+    assert_eq_test!(image.footprint().ok(), Some(64 + 24));
+
+    let image = TlsImage {
+        initialized: &tdata,
+        size: 24,
+        align: 1,
+    };
+    assert_eq_test!(image.footprint().ok(), Some(tcb_size + 24));
+
+    let oversized = TlsImage {
+        initialized: &tdata,
+        size: 4,
+        align: 8,
+    };
+    assert_test!(matches!(
+        oversized.footprint(),
+        Err(ProcessSetupError::MalformedTlsImage)
+    ));
+
+    let misaligned = TlsImage {
+        initialized: &tdata,
+        size: 24,
+        align: 24,
+    };
+    assert_test!(matches!(
+        misaligned.footprint(),
+        Err(ProcessSetupError::MalformedTlsImage)
+    ));
+    Ok(())
+}
@@ -151,6 +151,7 @@ pub mod uart {
     use bounded_registers::Field;
     use bounded_registers::*;
 
+    #[derive(RetypeForSetup)]
     pub struct UartParams<IRQ: Unsigned + Sync + Send, Role: CNodeRole>
     where
         IRQ: IsLess<MaxIRQCount, Output = True>,
@@ -159,13 +160,6 @@ pub mod uart {
         pub consumer: Consumer1<Role, u32, IRQ>,
     }
 
-    impl<IRQ: Unsigned + Sync + Send> RetypeForSetup for UartParams<IRQ, role::Local>
-    where
-        IRQ: IsLess<MaxIRQCount, Output = True>,
-    {
-        type Output = UartParams<IRQ, role::Child>;
-    }
-
     register! {
         UartRX,
         u32,
@@ -0,0 +1,168 @@
+use super::TopLevelError;
+
+use ferros::alloc::{smart_alloc, ut_buddy};
+use typenum::*;
+
+use ferros::bootstrap::UserImage;
+use ferros::cap::*;
+use ferros::userland::{
+    fault_or_message_channel, FaultOrMessage, PoolWorker, RetypeForSetup, Sender, WorkerPool,
+    WorkerSetup,
+};
+use ferros::vspace::*;
+
+#[ferros_test::ferros_test]
+pub fn worker_pool(
+    local_slots: LocalCNodeSlots<U32768>,
+    local_ut: LocalCap<Untyped<U20>>,
+    asid_pool: LocalCap<ASIDPool<U3>>,
+    first_stack: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    second_stack: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    third_stack: MappedMemoryRegion<U17, shared_status::Exclusive>,
+    root_cnode: &LocalCap<LocalCNode>,
+    user_image: &UserImage<role::Local>,
+    tpa: &LocalCap<ThreadPriorityAuthority>,
+) -> Result<(), TopLevelError> {
+    let uts = ut_buddy(local_ut);
+
+    smart_alloc!(|slots: local_slots, ut: uts| {
+        // Each worker is its own process, so each gets its own
+        // address space, CSpace and channel back to the parent.
+        let (first_asid, asid_pool) = asid_pool.alloc();
+        let first_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let first_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut first_vspace = VSpace::new(
+            retype(ut, slots)?,
+            first_asid,
+            first_vspace_slots.weaken(),
+            first_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let (first_cnode, first_child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (first_fault_slot, _first_child_slots) = first_child_slots.alloc();
+        let (first_fault_source, first_sender, first_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, first_fault_slot, slots)?;
+
+        let (second_asid, asid_pool) = asid_pool.alloc();
+        let second_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let second_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut second_vspace = VSpace::new(
+            retype(ut, slots)?,
+            second_asid,
+            second_vspace_slots.weaken(),
+            second_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let (second_cnode, second_child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (second_fault_slot, _second_child_slots) = second_child_slots.alloc();
+        let (second_fault_source, second_sender, second_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, second_fault_slot, slots)?;
+
+        let (third_asid, _asid_pool) = asid_pool.alloc();
+        let third_vspace_slots: LocalCNodeSlots<U1024> = slots;
+        let third_vspace_ut: LocalCap<Untyped<U15>> = ut;
+        let mut third_vspace = VSpace::new(
+            retype(ut, slots)?,
+            third_asid,
+            third_vspace_slots.weaken(),
+            third_vspace_ut.weaken(),
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            root_cnode,
+        )?;
+        let (third_cnode, third_child_slots) = retype_cnode::<U12>(ut, slots)?;
+        let (third_fault_slot, _third_child_slots) = third_child_slots.alloc();
+        let (third_fault_source, third_sender, third_handler) =
+            fault_or_message_channel(&root_cnode, ut, slots, third_fault_slot, slots)?;
+
+        let first_stack_slots: LocalCNodeSlots<U32> = slots;
+        let second_stack_slots: LocalCNodeSlots<U32> = slots;
+        let third_stack_slots: LocalCNodeSlots<U32> = slots;
+
+        let pool_slots: LocalCNodeSlots<U8> = slots;
+        let pool_ut: LocalCap<Untyped<U15>> = ut;
+    });
+
+    let mut pool_slots = pool_slots.weaken();
+    let mut pool: WorkerPool<U3, U17> = WorkerPool::new(
+        [
+            WorkerSetup {
+                vspace: &mut first_vspace,
+                cspace: first_cnode,
+                stack: first_stack,
+                stack_slots: first_stack_slots,
+                fault_source: Some(first_fault_source),
+            },
+            WorkerSetup {
+                vspace: &mut second_vspace,
+                cspace: second_cnode,
+                stack: second_stack,
+                stack_slots: second_stack_slots,
+                fault_source: Some(second_fault_source),
+            },
+            WorkerSetup {
+                vspace: &mut third_vspace,
+                cspace: third_cnode,
+                stack: third_stack,
+                stack_slots: third_stack_slots,
+                fault_source: Some(third_fault_source),
+            },
+        ],
+        root_cnode,
+        worker_main,
+        [
+            WorkerParams {
+                index_sender: first_sender,
+            },
+            WorkerParams {
+                index_sender: second_sender,
+            },
+            WorkerParams {
+                index_sender: third_sender,
+            },
+        ],
+        pool_ut,
+        &mut pool_slots,
+        tpa,
+    )?;
+
+    pool.start()?;
+
+    // Every worker reports the index it was handed, right or wrong,
+    // and a worker that faults instead is reported as such, so none
+    // of these waits can hang.
+    for (expected_index, handler) in [first_handler, second_handler, third_handler]
+        .iter()
+        .enumerate()
+    {
+        match handler.await_message()? {
+            FaultOrMessage::Message(index) if index == expected_index => (),
+            FaultOrMessage::Message(_) => {
+                return Err(TopLevelError::TestAssertionFailure(
+                    "A worker was handed the wrong index",
+                ))
+            }
+            FaultOrMessage::Fault(_) => {
+                return Err(TopLevelError::TestAssertionFailure("A worker faulted"))
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(RetypeForSetup)]
+pub struct WorkerParams<Role: CNodeRole> {
+    pub index_sender: Sender<usize, Role>,
+}
+
+pub extern "C" fn worker_main(worker: PoolWorker<WorkerParams<role::Local>>) {
+    worker
+        .params
+        .index_sender
+        .blocking_send(&worker.index)
+        .expect("Failure sending worker index");
+}
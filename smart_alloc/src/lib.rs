@@ -30,7 +30,11 @@ it should be in one of the following forms:
 * `request_id: resource_id`
 * `request_id: resource_id<ResourceKind>`
 
-where ResourceKind is one of CNodeSlots or UntypedBuddy.";
+where ResourceKind is one of CNodeSlots or UntypedBuddy.
+
+More than one UntypedBuddy resource may be declared, in which case
+each request id draws from its own resource. The CNodeSlots resource
+is then the one marked as such, or else the first.";
 
 #[proc_macro]
 pub fn smart_alloc(tokens: TokenStream) -> TokenStream {
@@ -45,7 +49,111 @@ fn smart_alloc_impl(tokens: TokenStream2) -> Result<TokenStream2, Error> {
     Ok(output_tokens)
 }
 
+/// Like `smart_alloc!`, but the block is a scope whose value is a
+/// `Result`, and which can be undone. Everything the scope allocated is
+/// revoked and freed if it fails, so that it can be retried from a
+/// clean slate.
+///
+/// ```ignore
+/// let outcome = smart_alloc_scoped!(|slots: local_slots, ut: uts| {
+///     let endpoint: LocalCap<Endpoint> = ut.retype(slots)?;
+///     let probed = probe_hardware(&endpoint)?;
+///     Ok::<_, ProbeError>(probed)
+/// });
+/// match outcome {
+///     Ok((probed, local_slots, uts)) => { /* carry on with what's left */ }
+///     Err((e, Ok((local_slots, uts)))) => { /* all of it is back, untouched */ }
+///     Err((e, Err(rollback_error))) => { /* the resources are lost */ }
+/// }
+/// ```
+///
+/// On success it evaluates to `Ok` of the scope's value and the
+/// remaining resources; on failure, to `Err` of the error and the
+/// outcome of rolling back, which is either the resources as they were
+/// before the scope or the first error met while restoring them. The
+/// resources must have `checkpoint` methods whose results can
+/// `rollback` to a `Result`, as ferros' `LocalCNodeSlots` and `UTBuddy`
+/// do, all with the same error type. As with any closure using `?`,
+/// the error type may need spelling out, as above.
+#[proc_macro]
+pub fn smart_alloc_scoped(tokens: TokenStream) -> TokenStream {
+    smart_alloc_scoped_impl(TokenStream2::from(tokens))
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn smart_alloc_scoped_impl(tokens: TokenStream2) -> Result<TokenStream2, Error> {
+    let Expansion {
+        resource_ids,
+        alloc_stmts,
+        user_stmts,
+    } = expand(tokens)?;
+    let ResolvedResourceIds {
+        cslots_resource,
+        untyped_resources,
+    } = resource_ids;
+    let cslots_checkpoint = gensym("cslots_checkpoint");
+    let untyped_checkpoints: Vec<Ident> = untyped_resources
+        .iter()
+        .map(|_| gensym("untyped_checkpoint"))
+        .collect();
+    let ut_resources = &untyped_resources;
+    let ut_checkpoints = &untyped_checkpoints;
+    let rollback_slots = std::iter::repeat(&cslots_checkpoint);
+
+    let scoped: Expr = parse_quote! { {
+        let #cslots_checkpoint = unsafe { #cslots_resource.checkpoint() };
+        #(let #ut_checkpoints = unsafe { #ut_resources.checkpoint() };)*
+        let scope_result = (|| {
+            #(#alloc_stmts)*
+            match { #(#user_stmts)* } {
+                Ok(value) => Ok((value, #cslots_resource #(, #ut_resources)*)),
+                Err(e) => Err(e),
+            }
+        })();
+        match scope_result {
+            Ok(success) => Ok(success),
+            Err(e) => {
+                let rolled_back = (|| {
+                    #(let #ut_resources = match #ut_checkpoints.rollback(&#rollback_slots) {
+                        Ok(resource) => resource,
+                        Err(rollback_error) => return Err(rollback_error),
+                    };)*
+                    let #cslots_resource = match #cslots_checkpoint.rollback() {
+                        Ok(resource) => resource,
+                        Err(rollback_error) => return Err(rollback_error),
+                    };
+                    Ok((#cslots_resource #(, #ut_resources)*))
+                })();
+                Err((e, rolled_back))
+            }
+        }
+    } };
+    let mut output_tokens = TokenStream2::new();
+    output_tokens.append_all(Some(scoped));
+    Ok(output_tokens)
+}
+
 fn smart_alloc_structured(tokens: TokenStream2) -> Result<Vec<Stmt>, Error> {
+    let Expansion {
+        alloc_stmts,
+        user_stmts,
+        ..
+    } = expand(tokens)?;
+    let mut output_stmts = alloc_stmts;
+    output_stmts.extend(user_stmts);
+    Ok(output_stmts)
+}
+
+/// The pieces of a smart_alloc invocation, with its requests replaced
+/// by the ids of the allocations planned for them.
+struct Expansion {
+    resource_ids: ResolvedResourceIds,
+    alloc_stmts: Vec<Stmt>,
+    user_stmts: Vec<Stmt>,
+}
+
+fn expand(tokens: TokenStream2) -> Result<Expansion, Error> {
     let closure: ExprClosure = syn_parse(tokens.into())?;
     if closure.output != ReturnType::Default {
         return Err(Error::NoReturnTypeAllowed {
@@ -68,20 +176,19 @@ fn smart_alloc_structured(tokens: TokenStream2) -> Result<Vec<Stmt>, Error> {
     block = id_tracker.fold_block(block);
 
     let resource_ids = ResolvedResourceIds::resolve(&header, &id_tracker.planned_allocs).unwrap();
-    let mut output_stmts = materialize_alloc_statements(&id_tracker.planned_allocs, resource_ids);
-    let user_statements = block.stmts;
-    output_stmts.extend(user_statements);
-    Ok(output_stmts)
+    let alloc_stmts = materialize_alloc_statements(&id_tracker.planned_allocs, &resource_ids);
+    Ok(Expansion {
+        resource_ids,
+        alloc_stmts,
+        user_stmts: block.stmts,
+    })
 }
 
 fn materialize_alloc_statements(
     planned_allocs: &[PlannedAlloc],
-    resource_ids: ResolvedResourceIds,
+    resource_ids: &ResolvedResourceIds,
 ) -> Vec<Stmt> {
-    let ResolvedResourceIds {
-        cslots_resource,
-        untyped_resource,
-    } = resource_ids;
+    let cslots_resource = &resource_ids.cslots_resource;
     let mut output_stmts = Vec::new();
     for plan in planned_allocs {
         match plan {
@@ -91,7 +198,11 @@ fn materialize_alloc_statements(
                 };
                 output_stmts.push(alloc_cslot);
             }
-            PlannedAlloc::Untyped { ut, cslot } => {
+            PlannedAlloc::Untyped {
+                ut,
+                cslot,
+                resource: untyped_resource,
+            } => {
                 let alloc_both: Block = parse_quote! { {
                     let (#cslot, #cslots_resource) = #cslots_resource.alloc();
                     let (#ut, #untyped_resource) = #untyped_resource.alloc(#cslot)?;
@@ -114,7 +225,7 @@ enum Error {
     AmbiguousRequestId { id: String, span: Span },
     InvalidRequestId { id: String, span: Span },
     MissingResourceId { request_id: String, span: Span },
-    TooManyResources { span: Span },
+    TooManyCNodeSlotsResources { span: Span },
     SynParse(SynError),
 }
 impl Error {
@@ -132,7 +243,7 @@ impl Error {
                 request_id: _,
                 span,
             } => *span,
-            Error::TooManyResources { span } => *span,
+            Error::TooManyCNodeSlotsResources { span } => *span,
             Error::SynParse(e) => e.span(),
         }
     }
@@ -180,9 +291,9 @@ impl Display for Error {
                 "{}\nbut no resource was supplied for request id {}",
                 EXPECTED_LAYOUT_MESSAGE, request_id
             ),
-            Error::TooManyResources { .. } => format!(
-                "{}\nbut more than two resources without explicit resource kinds were requested",
-                EXPECTED_LAYOUT_MESSAGE
+            Error::TooManyCNodeSlotsResources { .. } => format!(
+                "{}\nbut more than one resource was marked as {}",
+                EXPECTED_LAYOUT_MESSAGE, RESOURCE_TYPE_HINT_CSLOTS
             ),
             Error::SynParse(se) => se.to_compile_error().to_string(),
         };
@@ -325,9 +436,7 @@ fn header_from_intermediate_resources(
         }),
         1 => Header::from_single_resource(&resources[0]),
         2 => Header::from_resource_pair(&resources[0], &resources[1]),
-        _ => Err(Error::TooManyResources {
-            span: all_resources_span,
-        }),
+        _ => Header::from_many_resources(resources, all_resources_span),
     }
 }
 
@@ -374,12 +483,12 @@ enum ResKind {
 #[derive(Debug, PartialEq)]
 struct Header {
     pub(crate) cnode_slots: ResourceRequest,
-    pub(crate) untypeds: Option<ResourceRequest>,
+    pub(crate) untypeds: Vec<ResourceRequest>,
 }
 
 struct ResolvedResourceIds {
     cslots_resource: Ident,
-    untyped_resource: Ident,
+    untyped_resources: Vec<Ident>,
 }
 
 impl ResolvedResourceIds {
@@ -394,20 +503,18 @@ impl ResolvedResourceIds {
         if planned_allocs.iter().any(|p| match p {
             PlannedAlloc::Untyped { .. } => true,
             _ => false,
-        }) && header.untypeds == None
+        }) && header.untypeds.is_empty()
         {
             return Err(format!("{}\nbut untyped allocations were requested and the {} resource not provided to smart_alloc", EXPECTED_LAYOUT_MESSAGE, RESOURCE_TYPE_HINT_UNTYPED));
         }
-        let untyped_resource = header
+        let untyped_resources = header
             .untypeds
-            .as_ref()
+            .iter()
             .map(|ut_rr| Ident::new(&ut_rr.resource_id.to_string(), ut_rr.resource_id.span()))
-            .unwrap_or_else(|| {
-                Ident::new("untyped_buddy_not_provided_to_macro", Span::call_site())
-            });
+            .collect();
         Ok(ResolvedResourceIds {
             cslots_resource,
-            untyped_resource,
+            untyped_resources,
         })
     }
 }
@@ -433,7 +540,7 @@ impl Header {
                 resource_id: first.resource_id.clone(),
                 request_id: first.request_id.clone(),
             },
-            untypeds: None,
+            untypeds: Vec::new(),
         })
     }
 
@@ -494,10 +601,64 @@ impl Header {
                 resource_id: cnode_slots.resource_id.clone(),
                 request_id: cnode_slots.request_id.clone(),
             },
-            untypeds: Some(ResourceRequest {
+            untypeds: vec![ResourceRequest {
                 resource_id: untypeds.resource_id.clone(),
                 request_id: untypeds.request_id.clone(),
-            }),
+            }],
+        })
+    }
+
+    /// With three or more resources, all but one are untyped pools.
+    /// The CNodeSlots resource is the one marked as such, or else the
+    /// first.
+    fn from_many_resources(
+        resources: &[IntermediateResource],
+        all_resources_span: Span,
+    ) -> Result<Header, Error> {
+        let mut marked_cslots = resources
+            .iter()
+            .enumerate()
+            .filter(|(_, r)| r.kind == Some(ResKind::CNodeSlots));
+        let cslots_index = match (marked_cslots.next(), marked_cslots.next()) {
+            (Some((i, _)), None) => i,
+            (None, _) => 0,
+            (Some(_), Some(_)) => {
+                return Err(Error::TooManyCNodeSlotsResources {
+                    span: all_resources_span,
+                })
+            }
+        };
+        let cnode_slots = &resources[cslots_index];
+        let mut untypeds = Vec::new();
+        for (i, untyped) in resources.iter().enumerate() {
+            if i == cslots_index {
+                continue;
+            }
+            // Reuse the pairwise checks for kinds and duplicates
+            let pair = Header::from_known_kinds_resource_pair(cnode_slots, untyped)?;
+            for other in untypeds.iter() {
+                let other: &ResourceRequest = other;
+                if other.resource_id == untyped.resource_id {
+                    return Err(Error::AmbiguousResourceId {
+                        id: untyped.resource_id.to_string(),
+                        span: untyped.resource_id.span(),
+                    });
+                }
+                if other.request_id == untyped.request_id {
+                    return Err(Error::AmbiguousRequestId {
+                        id: untyped.request_id.to_string(),
+                        span: untyped.request_id.span(),
+                    });
+                }
+            }
+            untypeds.extend(pair.untypeds);
+        }
+        Ok(Header {
+            cnode_slots: ResourceRequest {
+                resource_id: cnode_slots.resource_id.clone(),
+                request_id: cnode_slots.request_id.clone(),
+            },
+            untypeds,
         })
     }
 }
@@ -510,13 +671,18 @@ struct ResourceRequest {
 
 struct IdTracker {
     cslot_request_id: Ident,
-    untyped_request_id: Option<Ident>,
+    /// Each untyped request id, along with the pool it draws from
+    untyped_request_ids: Vec<(Ident, Ident)>,
     planned_allocs: Vec<PlannedAlloc>,
 }
 
 enum PlannedAlloc {
     CSlot(Ident),
-    Untyped { ut: Ident, cslot: Ident },
+    Untyped {
+        ut: Ident,
+        cslot: Ident,
+        resource: Ident,
+    },
 }
 
 impl From<&Header> for IdTracker {
@@ -524,17 +690,23 @@ impl From<&Header> for IdTracker {
         IdTracker::new(
             Ident::new(&h.cnode_slots.request_id.to_string(), Span::call_site()),
             h.untypeds
-                .as_ref()
-                .map(|rr| Ident::new(&rr.request_id.to_string(), Span::call_site())),
+                .iter()
+                .map(|rr| {
+                    (
+                        Ident::new(&rr.request_id.to_string(), Span::call_site()),
+                        Ident::new(&rr.resource_id.to_string(), rr.resource_id.span()),
+                    )
+                })
+                .collect(),
         )
     }
 }
 
 impl IdTracker {
-    fn new(cslot_request_id: Ident, untyped_request_id: Option<Ident>) -> Self {
+    fn new(cslot_request_id: Ident, untyped_request_ids: Vec<(Ident, Ident)>) -> Self {
         IdTracker {
             cslot_request_id,
-            untyped_request_id,
+            untyped_request_ids,
             planned_allocs: vec![],
         }
     }
@@ -596,15 +768,18 @@ impl Fold for IdTracker {
             return fresh_id;
         }
 
-        if let Some(ut_request_id) = &self.untyped_request_id {
-            if node == *ut_request_id {
-                let fresh_id = gensym("untyped");
-                self.planned_allocs.push(PlannedAlloc::Untyped {
-                    ut: fresh_id.clone(),
-                    cslot: gensym("cslots_for_untyped"),
-                });
-                return fresh_id;
-            }
+        if let Some((_, resource)) = self
+            .untyped_request_ids
+            .iter()
+            .find(|(request_id, _)| node == *request_id)
+        {
+            let fresh_id = gensym("untyped");
+            self.planned_allocs.push(PlannedAlloc::Untyped {
+                ut: fresh_id.clone(),
+                cslot: gensym("cslots_for_untyped"),
+                resource: resource.clone(),
+            });
+            return fresh_id;
         }
         node
     }
@@ -1,23 +1,61 @@
 #![feature(proc_macro_hygiene)]
 
-use smart_alloc::smart_alloc;
+use smart_alloc::{smart_alloc, smart_alloc_scoped};
 
 struct CNodeSlots {
     capacity: usize,
+    stuck: bool,
 }
 
 impl CNodeSlots {
     fn alloc(self) -> (CNodeSlots, CNodeSlots) {
         (
-            CNodeSlots { capacity: 1 },
+            CNodeSlots {
+                capacity: 1,
+                stuck: self.stuck,
+            },
             CNodeSlots {
                 capacity: self.capacity - 1,
+                stuck: self.stuck,
             },
         )
     }
 
     fn new(capacity: usize) -> Self {
-        CNodeSlots { capacity }
+        CNodeSlots {
+            capacity,
+            stuck: false,
+        }
+    }
+
+    /// Slots whose contents can't be cleaned out on rollback.
+    fn stuck(capacity: usize) -> Self {
+        CNodeSlots {
+            capacity,
+            stuck: true,
+        }
+    }
+
+    unsafe fn checkpoint(&self) -> CNodeSlotsCheckpoint {
+        CNodeSlotsCheckpoint {
+            capacity: self.capacity,
+            stuck: self.stuck,
+        }
+    }
+}
+
+struct CNodeSlotsCheckpoint {
+    capacity: usize,
+    stuck: bool,
+}
+
+impl CNodeSlotsCheckpoint {
+    fn rollback(self) -> Result<CNodeSlots, &'static str> {
+        if self.stuck {
+            Err("stuck")
+        } else {
+            Ok(CNodeSlots::new(self.capacity))
+        }
     }
 }
 
@@ -39,6 +77,22 @@ impl UntypedBuddy {
     fn new(capacity: usize) -> Self {
         UntypedBuddy { capacity }
     }
+
+    unsafe fn checkpoint(&self) -> UntypedBuddyCheckpoint {
+        UntypedBuddyCheckpoint {
+            capacity: self.capacity,
+        }
+    }
+}
+
+struct UntypedBuddyCheckpoint {
+    capacity: usize,
+}
+
+impl UntypedBuddyCheckpoint {
+    fn rollback(self, _slots: &CNodeSlotsCheckpoint) -> Result<UntypedBuddy, &'static str> {
+        Ok(UntypedBuddy::new(self.capacity))
+    }
 }
 
 #[test]
@@ -245,6 +299,152 @@ fn single_resources_deeply_nested() -> Result<(), ()> {
     Ok(())
 }
 
+#[test]
+fn multiple_untyped_pools() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(10);
+    let untypeds = UntypedBuddy::new(5);
+    let device_untypeds = UntypedBuddy::new(3);
+
+    smart_alloc!(|c: cslots, u: untypeds, d: device_untypeds| {
+        let gamma = consume_slot(c);
+        let eta = consume_untyped(u);
+        let theta = consume_untyped(d);
+        let iota = consume_untyped(d);
+    });
+
+    assert_eq!(1, gamma);
+    assert_eq!(1, eta);
+    assert_eq!(1, theta);
+    assert_eq!(1, iota);
+    assert_eq!(6, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    assert_eq!(1, device_untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn multiple_untyped_pools_kinded_cslots_last() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(10);
+    let untypeds = UntypedBuddy::new(5);
+    let device_untypeds = UntypedBuddy::new(3);
+
+    smart_alloc!(|u: untypeds, d: device_untypeds, c: cslots<CNodeSlots>| {
+        let eta = consume_untyped(u);
+        let theta = consume_untyped(d);
+    });
+
+    assert_eq!(1, eta);
+    assert_eq!(1, theta);
+    assert_eq!(8, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    assert_eq!(2, device_untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn scoped_success_keeps_allocations() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+    let untypeds = UntypedBuddy::new(5);
+
+    let outcome = smart_alloc_scoped!(|c: cslots, u: untypeds| {
+        let gamma = consume_slot(c);
+        let eta = consume_untyped(u);
+        Ok::<_, ()>(gamma + eta)
+    });
+
+    let (sum, cslots, untypeds) = outcome.map_err(|_| ())?;
+    assert_eq!(2, sum);
+    assert_eq!(3, cslots.capacity);
+    assert_eq!(4, untypeds.capacity);
+    Ok(())
+}
+
+#[test]
+fn scoped_failure_rolls_back() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+    let untypeds = UntypedBuddy::new(5);
+
+    let outcome = smart_alloc_scoped!(|c: cslots, u: untypeds| {
+        consume_slot(c);
+        consume_untyped(u);
+        let fallible: Result<usize, ()> = Err(());
+        let never = fallible?;
+        Ok::<_, ()>(never)
+    });
+
+    match outcome {
+        Ok(_) => Err(()),
+        Err((e, Ok((cslots, untypeds)))) => {
+            assert_eq!((), e);
+            assert_eq!(5, cslots.capacity);
+            assert_eq!(5, untypeds.capacity);
+            Ok(())
+        }
+        Err((_, Err(_))) => Err(()),
+    }
+}
+
+#[test]
+fn scoped_multiple_untyped_pools_roll_back() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+    let untypeds = UntypedBuddy::new(5);
+    let device_untypeds = UntypedBuddy::new(3);
+
+    let outcome = smart_alloc_scoped!(|c: cslots, u: untypeds, d: device_untypeds| {
+        consume_untyped(u);
+        consume_untyped(d);
+        Err::<(), _>(())
+    });
+
+    match outcome {
+        Ok(_) => Err(()),
+        Err((_, Ok((cslots, untypeds, device_untypeds)))) => {
+            assert_eq!(5, cslots.capacity);
+            assert_eq!(5, untypeds.capacity);
+            assert_eq!(3, device_untypeds.capacity);
+            Ok(())
+        }
+        Err((_, Err(_))) => Err(()),
+    }
+}
+
+#[test]
+fn scoped_single_resource_failure_rolls_back() -> Result<(), ()> {
+    let cslots = CNodeSlots::new(5);
+
+    let outcome = smart_alloc_scoped!(|c: cslots| {
+        consume_slot(c);
+        consume_slot(c);
+        Err::<(), _>("nope")
+    });
+
+    match outcome {
+        Ok(_) => Err(()),
+        Err((_, Ok(cslots))) => {
+            assert_eq!(5, cslots.capacity);
+            Ok(())
+        }
+        Err(_) => Err(()),
+    }
+}
+
+#[test]
+fn scoped_failed_rollback_hands_back_its_error() -> Result<(), ()> {
+    let cslots = CNodeSlots::stuck(5);
+    let untypeds = UntypedBuddy::new(5);
+
+    let outcome = smart_alloc_scoped!(|c: cslots, u: untypeds| {
+        consume_slot(c);
+        consume_untyped(u);
+        Err::<(), _>(())
+    });
+
+    match outcome {
+        Err(((), Err("stuck"))) => Ok(()),
+        _ => Err(()),
+    }
+}
+
 fn consume_slot(cslots: CNodeSlots) -> usize {
     cslots.capacity
 }
@@ -1,6 +1,16 @@
 //! A tiny first-chance allocator for the untyped capabilities sel4's BOOTINFO.
-//! This one doesn't split anything; it just hands out the smallest untyped item
-//! that's big enough for the request.
+//! By default this one doesn't split anything; it just hands out an untyped item
+//! of exactly the requested size. `Allocator::get_untyped_splitting` is available
+//! for when splitting a larger item is acceptable.
+//!
+//! Items are handed out in the order BOOTINFO lists them, which shifts
+//! whenever the kernel or the root task image changes size. With the
+//! `deterministic_alloc` feature they're instead sorted by size and
+//! then physical address, so two builds of the same source pick the
+//! same untypeds, in the same order, as long as the memory they come
+//! from hasn't moved. The `alloc_seed` metadata property in sel4.toml
+//! reorders items of the same size among themselves; each seed still
+//! gives the same order every run.
 use core::fmt::{Debug, Error as FmtError, Formatter};
 use core::marker::PhantomData;
 
@@ -32,11 +42,21 @@ pub enum Error {
     TooManyGeneralUntypeds,
 }
 
+#[derive(Debug)]
+pub enum SplittingAllocError {
+    NoItemLargeEnough,
+    NotEnoughCNodeSlots,
+    TooManyGeneralUntypeds,
+    SplitError(WUntypedSplitError),
+}
+
 /// Use `BootInfo` to bootstrap both the device and general allocators.
 pub fn bootstrap_allocators(
     bootinfo: &'static seL4_BootInfo,
 ) -> Result<(Allocator, DeviceAllocator), Error> {
-    let mut general_uts = ArrayVec::new();
+    let mut general_uts: ArrayVec<
+        [LocalCap<WUntyped<memory_kind::General>>; MAX_INIT_UNTYPED_ITEMS],
+    > = ArrayVec::new();
     let mut device_uts: ArrayVec<[LocalCap<WUntyped<memory_kind::Device>>; MAX_DEVICE_UTS]> =
         ArrayVec::new();
 
@@ -69,19 +89,61 @@ pub fn bootstrap_allocators(
             }
         }
     }
+    #[cfg(feature = "deterministic_alloc")]
+    pdqsort::sort_by_key(&mut general_uts, |wut| {
+        let ut = &bootinfo.untypedList[wut.cptr - bootinfo.untyped.start as usize];
+        deterministic_order_key(ut.sizeBits, ut.paddr)
+    });
     // N.B. could cut the pdqsort dependency by doing this sorting during the initial insertion
     pdqsort::sort_by_key(&mut device_uts, |wut| wut.cap_data.kind.paddr);
     Ok((
-        Allocator { items: general_uts },
+        Allocator {
+            items: general_uts,
+            bootinfo,
+        },
         DeviceAllocator {
             untypeds: device_uts,
         },
     ))
 }
 
+#[cfg(feature = "deterministic_alloc")]
+include!(concat!(env!("OUT_DIR"), "/ALLOC_SEED"));
+
+/// Where a general untyped falls in `deterministic_alloc` order. With
+/// a seed of zero this is plain address order within each size;
+/// otherwise the address is scrambled with the seed first.
+#[cfg(feature = "deterministic_alloc")]
+fn deterministic_order_key(size_bits: u8, paddr: usize) -> (u8, u64) {
+    if ALLOC_SEED == 0 {
+        return (size_bits, paddr as u64);
+    }
+    // splitmix64's finalizer; it's a bijection, so distinct addresses
+    // never tie.
+    let mut z = paddr as u64 ^ ALLOC_SEED;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    (size_bits, z ^ (z >> 31))
+}
+
 /// An allocator for general purpose memory.
 pub struct Allocator {
     pub(super) items: ArrayVec<[LocalCap<WUntyped<memory_kind::General>>; MAX_INIT_UNTYPED_ITEMS]>,
+    bootinfo: &'static seL4_BootInfo,
+}
+
+/// A description of one of the untyped items provided by BOOTINFO,
+/// as seen by an `Allocator`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UntypedReport {
+    pub cptr: usize,
+    pub size_bits: u8,
+    pub is_device: bool,
+    pub paddr: usize,
+    /// Whether the allocator can still hand out this item whole.
+    /// Device untypeds are never free from the perspective of the
+    /// general allocator.
+    pub is_free: bool,
 }
 
 impl Debug for Allocator {
@@ -118,6 +180,82 @@ impl Allocator {
         self.items.remove(position);
         return Some(ut);
     }
+
+    /// Find an untyped of the given size, splitting the smallest
+    /// larger item down to size if no exact match is available. The
+    /// unused halves produced along the way remain in the allocator.
+    ///
+    /// Splitting consumes two slots per halving.
+    pub fn get_untyped_splitting<BitSize: Unsigned>(
+        &mut self,
+        slots: &mut LocalCap<WCNodeSlotsData<role::Local>>,
+    ) -> Result<LocalCap<Untyped<BitSize, memory_kind::General>>, SplittingAllocError> {
+        if let Some(ut) = self.get_untyped::<BitSize>() {
+            return Ok(ut);
+        }
+
+        let position = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, ut)| ut.size_bits() > BitSize::U8)
+            .min_by_key(|(_, ut)| ut.size_bits())
+            .map(|(pos, _)| pos)
+            .ok_or_else(|| SplittingAllocError::NoItemLargeEnough)?;
+
+        let num_splits = usize::from(self.items[position].size_bits() - BitSize::U8);
+        if 2 * num_splits > slots.size() {
+            return Err(SplittingAllocError::NotEnoughCNodeSlots);
+        }
+        // Each split leaves one half behind, but the item being split
+        // is removed first.
+        if self.items.len() - 1 + num_splits > self.items.capacity() {
+            return Err(SplittingAllocError::TooManyGeneralUntypeds);
+        }
+
+        let mut ut = self.items.remove(position);
+        while ut.size_bits() > BitSize::U8 {
+            let slot_pair = slots
+                .alloc_strong::<U2>()
+                .map_err(|_| SplittingAllocError::NotEnoughCNodeSlots)?;
+            let (ut_left, ut_right) = ut
+                .split(slot_pair)
+                .map_err(|e| SplittingAllocError::SplitError(e))?;
+            // Capacity was checked above. Pushing keeps any
+            // `deterministic_alloc` ordering intact, since only the
+            // order among items of the same size decides which is
+            // picked, and the new half lands after all of those.
+            self.items.push(ut_right);
+            ut = ut_left;
+        }
+
+        Ok(Cap {
+            cptr: ut.cptr,
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+
+    /// Report on every untyped item BOOTINFO provided, whether or not
+    /// it is still available. This does not disturb the allocator.
+    ///
+    /// An item which has been split by `get_untyped_splitting` is
+    /// reported as not free; its remaining halves are not reported
+    /// individually.
+    pub fn describe<'a>(&'a self) -> impl Iterator<Item = UntypedReport> + 'a {
+        let bootinfo = self.bootinfo;
+        (0..(bootinfo.untyped.end - bootinfo.untyped.start) as usize).map(move |i| {
+            let cptr = bootinfo.untyped.start as usize + i;
+            let ut = &bootinfo.untypedList[i];
+            UntypedReport {
+                cptr,
+                size_bits: ut.sizeBits,
+                is_device: ut.isDevice == 1,
+                paddr: ut.paddr,
+                is_free: self.items.iter().any(|item| item.cptr == cptr),
+            }
+        })
+    }
 }
 
 // TODO(dan@auxon.io): I have no idea what to put here.
@@ -264,7 +402,7 @@ impl DeviceAllocator {
         let requested_size_bits = requested_size_bytes.trailing_zeros() as usize;
 
         let mut ut = self
-            .get_device_untyped_containing(address_range.start.0)
+            .take_device_untyped_containing(address_range.start.0)
             .ok_or_else(|| DeviceRangeAllocError::AddressStartNotFound)?;
         let first_found_size = ut.size_bytes();
 
@@ -330,10 +468,38 @@ impl DeviceAllocator {
         }
         Ok(ut)
     }
+
+    /// Extract whichever device untyped wholly contains the range
+    /// `[paddr, paddr + len)`, without splitting anything. Along with
+    /// the untyped, returns the offset of `paddr` within it.
+    ///
+    /// This is useful when all that's known is the address of a
+    /// device's register block, rather than the base of the untyped
+    /// that covers it.
+    pub fn get_device_untyped_containing(
+        &mut self,
+        paddr: usize,
+        len: usize,
+    ) -> Result<(LocalCap<WUntyped<memory_kind::Device>>, usize), DeviceRangeAllocError> {
+        let ut = self
+            .take_device_untyped_containing(paddr)
+            .ok_or_else(|| DeviceRangeAllocError::AddressStartNotFound)?;
+        let offset = paddr - ut.paddr();
+        let fits = offset
+            .checked_add(len)
+            .map_or(false, |end| end <= ut.size_bytes());
+        if !fits {
+            self.insert_sorted(ut)
+                .map_err(|_| DeviceRangeAllocError::TooManyDeviceUntypeds)?;
+            return Err(DeviceRangeAllocError::AddressFoundButSizeDoesNotFitInASingleUntyped);
+        }
+        Ok((ut, offset))
+    }
+
     /// Get the device untyped which contains the given physical
     /// address. If it's present in the list, remove it from the list
     /// and return it.
-    fn get_device_untyped_containing(
+    fn take_device_untyped_containing(
         &mut self,
         paddr: usize,
     ) -> Option<LocalCap<WUntyped<memory_kind::Device>>> {
@@ -2,4 +2,4 @@ pub mod micro_alloc;
 pub mod ut_buddy;
 
 pub use self::ut_buddy::{ut_buddy, UTBuddy, WUTBuddy};
-pub use crate::smart_alloc::smart_alloc;
+pub use crate::smart_alloc::{smart_alloc, smart_alloc_scoped};
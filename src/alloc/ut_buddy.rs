@@ -10,13 +10,31 @@ use typenum::*;
 
 use crate::arch::{MaxUntypedSize, MinUntypedSize};
 use crate::cap::{
-    memory_kind, role, CNodeRole, Cap, LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap,
-    PhantomCap, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
+    memory_kind, role, CNodeRole, CNodeSlotsCheckpoint, Cap, LocalCNode, LocalCNodeSlot,
+    LocalCNodeSlots, LocalCap, PhantomCap, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
 };
 use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::AddressSpaceIndependent;
 
 type UTPoolSlotsPerSize = U4;
 
+/// How many splits a buddy remembers for the purposes of coalescing
+/// freed untypeds back together.
+type UTSplitHistorySize = U128;
+
+/// A record of one untyped having been split into two halves. The
+/// halves always occupy adjacent slots, so `first_child` and
+/// `first_child + 1` are buddies.
+#[derive(Clone, Copy, Debug)]
+struct SplitRecord {
+    parent: usize,
+    parent_cnode: usize,
+    first_child: usize,
+    child_size_bits: u8,
+}
+
+type SplitHistory = ArrayVec<[SplitRecord; UTSplitHistorySize::USIZE]>;
+
 /// A type-level linked list of typenum::Unsigned.
 pub trait UList {
     type Length: Unsigned;
@@ -160,15 +178,100 @@ impl<PoolSizes: UList> UTBuddy<PoolSizes> {
         PoolSizes: _TakeUntyped<Diff<BitSize, MinUntypedSize>, NumSplits = NumSplits>,
         TakeUntyped_ResultPoolSizes<PoolSizes, Diff<BitSize, MinUntypedSize>>: UList,
     {
-        let weak_ut = alloc(&mut self.pool, slots.iter(), BitSize::U8, NumSplits::U8)?;
+        let weak_ut = alloc(
+            &mut self.pool,
+            None,
+            slots.iter(),
+            BitSize::U8,
+            NumSplits::U8,
+        )?;
         Ok((
-            Cap::wrap_cptr(weak_ut.cptr),
+            unsafe { Cap::wrap_cptr(weak_ut.cptr) },
             UTBuddy {
                 pool: self.pool,
                 _pool_sizes: PhantomData,
             },
         ))
     }
+
+    /// Remember the state of the pool so that it can be restored,
+    /// along with `slots`, if the allocations that follow need to be
+    /// undone. Used by `smart_alloc_scoped!`.
+    ///
+    /// This is unsafe because `rollback` revokes every untyped the pool
+    /// held at this point, destroying anything made from them since.
+    pub unsafe fn checkpoint(&self) -> UTBuddyCheckpoint<PoolSizes> {
+        let mut pool = make_pool();
+        for (snapshot, current) in pool.iter_mut().zip(self.pool.iter()) {
+            *snapshot = current.clone();
+        }
+        UTBuddyCheckpoint {
+            pool,
+            _pool_sizes: PhantomData,
+        }
+    }
+
+    /// Give up on tracking the pool's state in the types. This is
+    /// required in order to `free` untypeds back into the pool,
+    /// since the coalescing that may follow depends on runtime state.
+    ///
+    /// Only the weak buddy records its splits, so untypeds split off
+    /// before weakening can be freed but are never coalesced.
+    pub fn weaken(self) -> WUTBuddy<role::Local> {
+        WUTBuddy {
+            pool: self.pool,
+            splits: ArrayVec::new(),
+            _role: PhantomData,
+        }
+    }
+
+    /// The bit-size of the largest untyped that this allocator could
+    /// presently hand out, or `None` if it is entirely exhausted.
+    pub fn capacity_bits(&self) -> Option<u8> {
+        capacity_bits(&self.pool)
+    }
+
+    /// The sum of the sizes of all untypeds held by this allocator, in
+    /// bytes.
+    pub fn total_free_bytes(&self) -> usize {
+        total_free_bytes(&self.pool)
+    }
+}
+
+/// The state of a `UTBuddy`, as recorded by `checkpoint`.
+pub struct UTBuddyCheckpoint<PoolSizes: UList> {
+    pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    _pool_sizes: PhantomData<PoolSizes>,
+}
+
+impl<PoolSizes: UList> UTBuddyCheckpoint<PoolSizes> {
+    /// Revoke each untyped the pool held when checkpointed, so that
+    /// every one is free again, and hand back the pool as it was.
+    ///
+    /// The untypeds live in the same CNode as the slots the buddy
+    /// splits them into, which `slots` records. If any can't be
+    /// revoked the pool isn't handed back, as that untyped may still
+    /// have objects made from it.
+    pub fn rollback<Size: Unsigned>(
+        self,
+        slots: &CNodeSlotsCheckpoint<Size>,
+    ) -> Result<UTBuddy<PoolSizes>, SeL4Error> {
+        for cptr in self.pool.iter().flat_map(|p| p.iter()) {
+            unsafe {
+                seL4_CNode_Revoke(
+                    slots.cptr,          // _service
+                    *cptr,               // index
+                    seL4_WordBits as u8, // depth
+                )
+            }
+            .as_result()
+            .map_err(|e| SeL4Error::CNodeRevoke(e))?;
+        }
+        Ok(UTBuddy {
+            pool: self.pool,
+            _pool_sizes: PhantomData,
+        })
+    }
 }
 
 /// Make a weak ut buddy around a weak untyped.
@@ -179,6 +282,7 @@ pub fn weak_ut_buddy<Role: CNodeRole>(
     pool[usize::from(ut.cap_data.size_bits) - MinUntypedSize::USIZE].push(ut.cptr);
     WUTBuddy {
         pool,
+        splits: ArrayVec::new(),
         _role: PhantomData,
     }
 }
@@ -196,11 +300,28 @@ pub enum UTBuddyError {
     /// The wrapped untyped lacks the sufficient size to do this
     /// allocation request.
     CannotAllocateRequestedSize(u8),
+    /// The size is below the smallest untyped the pool keeps.
+    RequestedSizeBelowMin(u8),
+    /// The pool has no room left for untypeds of this size.
+    PoolFull(u8),
+    /// The allocation would need more splits than the buddy has room
+    /// left to remember, and an unremembered split could never be
+    /// coalesced on `free`.
+    SplitHistoryFull,
     /// We got an error from an seL4 syscall, namely the
-    /// `seL4_Untyped_Retype` call.
+    /// `seL4_Untyped_Retype` or `seL4_CNode_Revoke` calls.
     SeL4Error(SeL4Error),
 }
 
+/// The error returned when an untyped can't be freed to a buddy. The
+/// untyped is handed back rather than dropped; after coalescing, that
+/// may be a larger one than was freed.
+#[derive(Debug)]
+pub struct FreeError {
+    pub error: UTBuddyError,
+    pub untyped: LocalCap<WUntyped<memory_kind::General>>,
+}
+
 impl From<SeL4Error> for UTBuddyError {
     fn from(e: SeL4Error) -> Self {
         UTBuddyError::SeL4Error(e)
@@ -213,9 +334,12 @@ impl From<SeL4Error> for UTBuddyError {
 /// Presently restricted to provide memory_kind::General untyped
 pub struct WUTBuddy<Role: CNodeRole = role::Local> {
     pool: [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    splits: SplitHistory,
     _role: PhantomData<Role>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for WUTBuddy<Role> {}
+
 impl WUTBuddy<role::Local> {
     /// Allocate a strong untyped from the pool.
     pub fn alloc_strong<Size: Unsigned>(
@@ -268,6 +392,12 @@ impl WUTBuddy<role::Local> {
             return Err(UTBuddyError::NotEnoughSlots);
         }
 
+        // Every split is recorded so that `free` can coalesce its
+        // halves again, so refuse up front rather than forget one.
+        if usize::from(split_count) > self.splits.capacity() - self.splits.len() {
+            return Err(UTBuddyError::SplitHistoryFull);
+        }
+
         let slots_for_alloc_to_consume = Cap {
             cptr: slots.cptr,
             cap_data: WCNodeSlotsData {
@@ -285,6 +415,7 @@ impl WUTBuddy<role::Local> {
 
         let ut = alloc(
             &mut self.pool,
+            Some(&mut self.splits),
             slots_for_alloc_to_consume.into_strong_iter(),
             size,
             split_count,
@@ -292,6 +423,45 @@ impl WUTBuddy<role::Local> {
         Ok(ut)
     }
 
+    /// Return an untyped to the pool. If its buddy is also free, the
+    /// two are coalesced back into their parent, and so on up the
+    /// tree.
+    ///
+    /// The caller must have already revoked anything retyped from
+    /// `ut`; coalescing revokes the parent, which would otherwise
+    /// silently delete those derived capabilities.
+    pub fn free<BitSize: Unsigned>(
+        &mut self,
+        ut: LocalCap<Untyped<BitSize>>,
+    ) -> Result<(), FreeError> {
+        self.free_weak(ut.weaken())
+    }
+
+    /// Return a weak untyped to the pool. See `free`.
+    pub fn free_weak(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
+    ) -> Result<(), FreeError> {
+        let size_bits = ut.cap_data.size_bits;
+        if size_bits > MaxUntypedSize::U8 {
+            return Err(FreeError {
+                error: UTBuddyError::RequestedSizeExceedsMax(size_bits),
+                untyped: ut,
+            });
+        }
+        if size_bits < MinUntypedSize::U8 {
+            return Err(FreeError {
+                error: UTBuddyError::RequestedSizeBelowMin(size_bits),
+                untyped: ut,
+            });
+        }
+        debug_assert!(
+            !self.pool[usize::from(size_bits - MinUntypedSize::U8)].contains(&ut.cptr),
+            "untyped freed to the buddy twice"
+        );
+        free(&mut self.pool, &mut self.splits, ut.cptr, size_bits)
+    }
+
     fn total_occupied_slots(&self) -> usize {
         self.pool.iter().map(|sub_pool| sub_pool.len()).sum()
     }
@@ -328,19 +498,35 @@ impl WUTBuddy<role::Local> {
                 child_bucket.push(child_wut.cptr);
             }
         }
+        // The split history refers to parents in the local CSpace, so
+        // the child's buddy starts out without any.
         Ok(WUTBuddy {
             pool: child_pool,
+            splits: ArrayVec::new(),
             _role: PhantomData,
         })
     }
 }
 
 impl<Role: CNodeRole> WUTBuddy<Role> {
+    /// The bit-size of the largest untyped that this allocator could
+    /// presently hand out, or `None` if it is entirely exhausted.
+    pub fn capacity_bits(&self) -> Option<u8> {
+        capacity_bits(&self.pool)
+    }
+
+    /// The sum of the sizes of all untypeds held by this allocator, in
+    /// bytes.
+    pub fn total_free_bytes(&self) -> usize {
+        total_free_bytes(&self.pool)
+    }
+
     // This might be brought back to life later on
     #[allow(dead_code)]
     pub(crate) fn empty() -> WUTBuddy<Role> {
         WUTBuddy {
             pool: make_pool(),
+            splits: ArrayVec::new(),
             _role: PhantomData,
         }
     }
@@ -348,6 +534,7 @@ impl<Role: CNodeRole> WUTBuddy<Role> {
 
 fn alloc(
     pool: &mut [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    mut splits: Option<&mut SplitHistory>,
     slots_iter: impl Iterator<Item = LocalCNodeSlot>,
     size_bits: u8,
     split_count: u8,
@@ -367,7 +554,7 @@ fn alloc(
             let (slot_cptr, slot_offset, _) = slot.elim();
 
             unsafe {
-                seL4_Untyped_Retype(
+                crate::cap::logged::untyped_retype(
                     cptr,                                   // _service
                     api_object_seL4_UntypedObject as usize, // type
                     usize::from(cptr_bitsize - 1),          // size_bits
@@ -383,6 +570,17 @@ fn alloc(
 
             pool[usize::from(i) - 1].push(slot_offset);
             pool[usize::from(i) - 1].push(slot_offset + 1);
+
+            // `WUTBuddy::alloc` has already checked there's room for
+            // every split this allocation makes.
+            if let Some(splits) = splits.as_mut() {
+                splits.push(SplitRecord {
+                    parent: cptr,
+                    parent_cnode: slot_cptr,
+                    first_child: slot_offset,
+                    child_size_bits: cptr_bitsize - 1,
+                });
+            }
         }
     }
 
@@ -398,6 +596,96 @@ fn alloc(
     })
 }
 
+fn free(
+    pool: &mut [ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+    splits: &mut SplitHistory,
+    mut cptr: usize,
+    mut size_bits: u8,
+) -> Result<(), FreeError> {
+    let unfreed = |error, cptr, size_bits| FreeError {
+        error,
+        untyped: Cap {
+            cptr,
+            cap_data: WUntyped {
+                size_bits,
+                kind: memory_kind::General,
+            },
+            _role: PhantomData,
+        },
+    };
+    loop {
+        let index = usize::from(size_bits - MinUntypedSize::U8);
+
+        let split_pos = splits.iter().position(|s| {
+            s.child_size_bits == size_bits && (s.first_child == cptr || s.first_child + 1 == cptr)
+        });
+        if let Some(split_pos) = split_pos {
+            let split = splits[split_pos];
+            let buddy = if split.first_child == cptr {
+                cptr + 1
+            } else {
+                split.first_child
+            };
+            if let Some(buddy_pos) = pool[index].iter().position(|c| *c == buddy) {
+                // Both halves are free: revoking the parent deletes
+                // them and makes its full extent retypeable once more.
+                if let Err(e) = unsafe {
+                    seL4_CNode_Revoke(
+                        split.parent_cnode,  // _service
+                        split.parent,        // index
+                        seL4_WordBits as u8, // depth
+                    )
+                }
+                .as_result()
+                {
+                    return Err(unfreed(
+                        UTBuddyError::SeL4Error(SeL4Error::CNodeRevoke(e)),
+                        cptr,
+                        size_bits,
+                    ));
+                }
+
+                // N.B. The slots which held the halves are now empty,
+                // but as elsewhere we do not reclaim them.
+                pool[index].remove(buddy_pos);
+                splits.swap_remove(split_pos);
+                cptr = split.parent;
+                size_bits += 1;
+                continue;
+            }
+        }
+
+        return pool[index]
+            .try_push(cptr)
+            .map_err(|_| unfreed(UTBuddyError::PoolFull(size_bits), cptr, size_bits));
+    }
+}
+
+/// Because every untyped in the pool is a whole block (splitting
+/// only ever happens on the way out), the largest available block is
+/// simply the largest non-empty sub-pool.
+fn capacity_bits(
+    pool: &[ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+) -> Option<u8> {
+    pool.iter()
+        .rposition(|sub_pool| !sub_pool.is_empty())
+        .map(|i| i as u8 + MinUntypedSize::U8)
+}
+
+fn total_free_bytes(
+    pool: &[ArrayVec<[usize; UTPoolSlotsPerSize::USIZE]>; MaxUntypedSize::USIZE],
+) -> usize {
+    pool.iter()
+        .enumerate()
+        .map(|(i, sub_pool)| {
+            let size_bits = i as u32 + MinUntypedSize::U32;
+            sub_pool
+                .len()
+                .saturating_mul(2usize.saturating_pow(size_bits))
+        })
+        .fold(0, |acc, bytes| acc.saturating_add(bytes))
+}
+
 impl From<super::micro_alloc::Allocator> for WUTBuddy<role::Local> {
     fn from(alloc: super::micro_alloc::Allocator) -> Self {
         let mut pool = make_pool();
@@ -409,6 +697,7 @@ impl From<super::micro_alloc::Allocator> for WUTBuddy<role::Local> {
 
         WUTBuddy {
             pool,
+            splits: ArrayVec::new(),
             _role: PhantomData,
         }
     }
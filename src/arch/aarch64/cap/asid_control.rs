@@ -8,6 +8,7 @@ use selfe_sys::*;
 use crate::arch;
 use crate::cap::{
     memory_kind, ASIDControl, ASIDPool, CNodeRole, CNodeSlot, Cap, LocalCap, Untyped,
+    NO_RECLAIMED_ASIDS,
 };
 use crate::error::{ErrorExt, SeL4Error};
 
@@ -38,6 +39,7 @@ impl<FreePools: Unsigned> LocalCap<ASIDControl<FreePools>> {
             cap_data: ASIDPool {
                 id: (arch::ASIDPoolCount::USIZE - FreePools::USIZE),
                 next_free_slot: 0,
+                reclaimed: NO_RECLAIMED_ASIDS,
                 _free_slots: PhantomData,
             },
             _role: PhantomData,
@@ -0,0 +1,72 @@
+use selfe_sys::*;
+
+use typenum::Unsigned;
+
+use crate::cap::{page_state, DirectRetype, Frame, LocalCap};
+use crate::userland::CapRights;
+use crate::vspace::{MappingError, Maps};
+
+use super::super::{map_frame, HugePageBits, LargePageBits, PagingRoot};
+use super::{PageDirectory, PageUpperDirectory};
+
+/// A 2MB frame, mapped directly into a page directory in place of a
+/// page table.
+pub type LargePage<State> = Frame<LargePageBits, State>;
+
+/// A 1GB frame, mapped directly into a page upper directory in place
+/// of a page directory.
+pub type HugePage<State> = Frame<HugePageBits, State>;
+
+impl Maps<LargePage<page_state::Unmapped>> for PageDirectory {
+    fn map_granule(
+        &mut self,
+        page: &LocalCap<LargePage<page_state::Unmapped>>,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        rights: CapRights,
+        vm_attributes: seL4_ARM_VMAttributes,
+    ) -> Result<(), MappingError> {
+        map_frame(
+            page.cptr,
+            LargePageBits::USIZE,
+            addr,
+            root,
+            rights,
+            vm_attributes,
+        )
+    }
+}
+
+impl Maps<HugePage<page_state::Unmapped>> for PageUpperDirectory {
+    fn map_granule(
+        &mut self,
+        page: &LocalCap<HugePage<page_state::Unmapped>>,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        rights: CapRights,
+        vm_attributes: seL4_ARM_VMAttributes,
+    ) -> Result<(), MappingError> {
+        map_frame(
+            page.cptr,
+            HugePageBits::USIZE,
+            addr,
+            root,
+            rights,
+            vm_attributes,
+        )
+    }
+}
+
+impl DirectRetype for LargePage<page_state::Unmapped> {
+    type SizeBits = LargePageBits;
+    fn sel4_type_id() -> usize {
+        _object_seL4_ARM_LargePageObject as usize
+    }
+}
+
+impl DirectRetype for HugePage<page_state::Unmapped> {
+    type SizeBits = HugePageBits;
+    fn sel4_type_id() -> usize {
+        _object_seL4_ARM_HugePageObject as usize
+    }
+}
@@ -1,5 +1,6 @@
 mod asid;
 mod asid_control;
+mod frame;
 mod page;
 mod page_directory;
 mod page_global_directory;
@@ -10,6 +11,7 @@ mod vcpu;
 
 pub use asid::*;
 pub use asid_control::*;
+pub use frame::*;
 pub use page::*;
 pub use page_directory::*;
 pub use page_global_directory::*;
@@ -17,3 +19,60 @@ pub use page_table::*;
 pub use page_upper_directory::*;
 #[cfg(KernelArmHypervisorSupport)]
 pub use vcpu::*;
+
+/// The kernel's capability tags, from its `structures_64.bf`.
+/// libsel4 doesn't export these, so neither does `selfe_sys`.
+#[cfg(KernelDebugBuild)]
+pub(crate) mod cap_tag {
+    pub const NULL_CAP: u32 = 0;
+    pub const UNTYPED_CAP: u32 = 2;
+    pub const ENDPOINT_CAP: u32 = 4;
+    pub const NOTIFICATION_CAP: u32 = 6;
+    pub const REPLY_CAP: u32 = 8;
+    pub const CNODE_CAP: u32 = 10;
+    pub const THREAD_CAP: u32 = 12;
+    pub const IRQ_CONTROL_CAP: u32 = 14;
+    pub const IRQ_HANDLER_CAP: u32 = 16;
+    pub const ZOMBIE_CAP: u32 = 18;
+    pub const DOMAIN_CAP: u32 = 20;
+
+    pub const FRAME_CAP: u32 = 1;
+    pub const PAGE_TABLE_CAP: u32 = 3;
+    pub const PAGE_DIRECTORY_CAP: u32 = 5;
+    pub const PAGE_UPPER_DIRECTORY_CAP: u32 = 7;
+    pub const PAGE_GLOBAL_DIRECTORY_CAP: u32 = 9;
+    pub const ASID_CONTROL_CAP: u32 = 11;
+    pub const ASID_POOL_CAP: u32 = 13;
+    pub const VCPU_CAP: u32 = 15;
+}
+
+/// The architecture-specific kinds of capability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchCapKind {
+    Frame,
+    PageTable,
+    PageDirectory,
+    PageUpperDirectory,
+    PageGlobalDirectory,
+    ASIDControl,
+    ASIDPool,
+    VCPU,
+}
+
+#[cfg(KernelDebugBuild)]
+impl ArchCapKind {
+    pub(crate) fn from_tag(tag: u32) -> Option<ArchCapKind> {
+        use cap_tag::*;
+        match tag {
+            FRAME_CAP => Some(ArchCapKind::Frame),
+            PAGE_TABLE_CAP => Some(ArchCapKind::PageTable),
+            PAGE_DIRECTORY_CAP => Some(ArchCapKind::PageDirectory),
+            PAGE_UPPER_DIRECTORY_CAP => Some(ArchCapKind::PageUpperDirectory),
+            PAGE_GLOBAL_DIRECTORY_CAP => Some(ArchCapKind::PageGlobalDirectory),
+            ASID_CONTROL_CAP => Some(ArchCapKind::ASIDControl),
+            ASID_POOL_CAP => Some(ArchCapKind::ASIDPool),
+            VCPU_CAP => Some(ArchCapKind::VCPU),
+            _ => None,
+        }
+    }
+}
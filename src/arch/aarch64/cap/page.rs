@@ -1,20 +1,10 @@
 use selfe_sys::*;
 
-use crate::cap::{page_state, DirectRetype, LocalCap, Page, PageState, PhantomCap};
+use crate::cap::{page_state, DirectRetype, LocalCap, Page, PhantomCap};
 use crate::error::{ErrorExt, SeL4Error};
 use crate::userland::CapRights;
 use typenum::Unsigned;
 
-impl<T: PageState> LocalCap<Page<T>> {
-    pub(crate) fn paddr(&self) -> Result<usize, SeL4Error> {
-        let res = unsafe { seL4_ARM_Page_GetAddress(self.cptr) };
-        match (res.error as seL4_Error).as_result() {
-            Ok(_) => Ok(res.paddr),
-            Err(e) => Err(SeL4Error::PageGetAddress(e)),
-        }
-    }
-}
-
 impl LocalCap<Page<page_state::Unmapped>> {
     pub(crate) unsafe fn unchecked_page_map(
         &self,
@@ -36,6 +26,41 @@ impl LocalCap<Page<page_state::Unmapped>> {
 }
 
 impl LocalCap<Page<page_state::Mapped>> {
+    /// Map this page again at the same address, with new rights and
+    /// attributes. The kernel allows this in place of an unmap and map.
+    pub(crate) fn remap(
+        &mut self,
+        root: &mut LocalCap<crate::arch::PagingRoot>,
+        rights: CapRights,
+        vm_attributes: seL4_ARM_VMAttributes,
+    ) -> Result<(), SeL4Error> {
+        if self.rights().is_writable() {
+            unsafe {
+                seL4_ARM_Page_CleanInvalidate_Data(
+                    self.cptr,
+                    0x0000,
+                    super::super::PageBytes::USIZE,
+                )
+            }
+            .as_result()
+            .map_err(|e| SeL4Error::PageCleanInvalidateData(e))?;
+        }
+
+        unsafe {
+            seL4_ARM_Page_Map(
+                self.cptr,
+                root.cptr,
+                self.vaddr(),
+                seL4_CapRights_t::from(rights),
+                vm_attributes,
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::PageMap(e))?;
+        self.cap_data.state.rights = rights;
+        Ok(())
+    }
+
     /// Keeping this non-public in order to restrict mapping operations to owners
     /// of a VSpace-related object
     pub(crate) fn unmap(self) -> Result<LocalCap<Page<page_state::Unmapped>>, SeL4Error> {
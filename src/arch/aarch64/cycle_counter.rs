@@ -0,0 +1,62 @@
+use core::arch::asm;
+
+use super::super::CycleCounterError;
+
+/// Turn on the PMU's cycle counter (PMCCNTR_EL0) for the current core.
+///
+/// The kernel only lets user level near the PMU when it's built with
+/// `KernelExportPMCUser`; it doesn't start the counter itself. Without
+/// that, this does nothing and `read_cycle_counter` falls back to the
+/// generic timer's virtual count, provided the kernel exports that
+/// instead (`KernelArmExportVCNTUser`).
+pub fn enable_cycle_counter() -> Result<(), CycleCounterError> {
+    #[cfg(KernelExportPMCUser)]
+    unsafe {
+        // PMCR_EL0.E, then PMCNTENSET_EL0.C.
+        asm!(
+            "mrs {tmp}, pmcr_el0",
+            "orr {tmp}, {tmp}, #1",
+            "msr pmcr_el0, {tmp}",
+            "msr pmcntenset_el0, {c}",
+            "isb",
+            tmp = out(reg) _,
+            c = in(reg) 1u64 << 31,
+            options(nostack, preserves_flags),
+        );
+    }
+    read_cycle_counter().map(|_| ())
+}
+
+/// Read the current core's cycle counter.
+///
+/// This is PMCCNTR_EL0 when the kernel exports the PMU to user level,
+/// which counts once per CPU cycle after `enable_cycle_counter`. The
+/// fallback, CNTVCT_EL0, ticks at the generic timer's frequency
+/// (CNTFRQ_EL0), which is usually far lower than the CPU clock.
+pub fn read_cycle_counter() -> Result<u64, CycleCounterError> {
+    #[cfg(KernelExportPMCUser)]
+    {
+        let cycles: u64;
+        unsafe {
+            asm!("mrs {}, pmccntr_el0", out(reg) cycles, options(nomem, nostack, preserves_flags));
+        }
+        Ok(cycles)
+    }
+    #[cfg(all(not(KernelExportPMCUser), KernelArmExportVCNTUser))]
+    {
+        let ticks: u64;
+        unsafe {
+            asm!("mrs {}, cntvct_el0", out(reg) ticks, options(nomem, nostack, preserves_flags));
+        }
+        Ok(ticks)
+    }
+    #[cfg(not(any(KernelExportPMCUser, KernelArmExportVCNTUser)))]
+    {
+        Err(CycleCounterError::NotExported)
+    }
+}
+
+/// Both counters are 64 bits wide here.
+pub(crate) fn cycles_between(start: u64, end: u64) -> u64 {
+    end.wrapping_sub(start)
+}
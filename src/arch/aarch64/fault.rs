@@ -2,12 +2,18 @@ use crate::cap::Badge;
 use crate::userland::MessageInfo;
 use selfe_sys::*;
 
+/// The ESR bit set when a data abort was caused by a write.
+const FSR_WRITE_NOT_READ: usize = 1 << 6;
+
 #[derive(Debug)]
 pub struct VMFault {
     pub sender: Badge,
     pub program_counter: usize,
     pub address: usize,
     pub is_instruction_fault: bool,
+    /// Whether a data fault was caused by a write, as reported by the
+    /// WnR bit of the ESR. Always false for instruction faults.
+    pub is_write: bool,
     pub fault_status_register: usize,
 }
 #[derive(Debug)]
@@ -43,8 +49,14 @@ pub struct NullFault {
 #[derive(Debug)]
 pub struct CapFault {
     pub sender: Badge,
+    pub program_counter: usize,
     pub in_receive_phase: bool,
     pub cap_address: usize,
+    /// Why the lookup of `cap_address` failed, as one of the
+    /// `seL4_LookupFailureType` values.
+    pub lookup_failure_type: usize,
+    /// How many bits of `cap_address` were left unresolved.
+    pub bits_left: usize,
 }
 /// Grab bag for faults that don't fit the regular classification
 #[derive(Debug)]
@@ -95,6 +107,18 @@ impl Fault {
             Fault::VCPUFault(f) => f.sender,
         }
     }
+
+    /// The address of the instruction that faulted, for those faults
+    /// which report one.
+    pub fn program_counter(&self) -> Option<usize> {
+        match self {
+            Fault::VMFault(f) => Some(f.program_counter),
+            Fault::UnknownSyscall(f) => Some(f.program_counter),
+            Fault::UserException(f) => Some(f.program_counter),
+            Fault::CapFault(f) => Some(f.program_counter),
+            _ => None,
+        }
+    }
 }
 
 impl From<(MessageInfo, Badge)> for Fault {
@@ -112,13 +136,19 @@ impl From<(MessageInfo, Badge)> for Fault {
         const VCPU_FAULT: usize = seL4_Fault_tag_seL4_Fault_VCPUFault as usize;
         match info.label() {
             NULL_FAULT => Fault::NullFault(NullFault { sender }),
-            VM_FAULT => Fault::VMFault(VMFault {
-                sender,
-                program_counter: buffer.msg[seL4_VMFault_IP as usize],
-                address: buffer.msg[seL4_VMFault_Addr as usize],
-                is_instruction_fault: 1 == buffer.msg[seL4_VMFault_PrefetchFault as usize],
-                fault_status_register: buffer.msg[seL4_VMFault_FSR as usize],
-            }),
+            VM_FAULT => {
+                let is_instruction_fault = 1 == buffer.msg[seL4_VMFault_PrefetchFault as usize];
+                let fault_status_register = buffer.msg[seL4_VMFault_FSR as usize];
+                Fault::VMFault(VMFault {
+                    sender,
+                    program_counter: buffer.msg[seL4_VMFault_IP as usize],
+                    address: buffer.msg[seL4_VMFault_Addr as usize],
+                    is_instruction_fault,
+                    is_write: !is_instruction_fault
+                        && fault_status_register & FSR_WRITE_NOT_READ != 0,
+                    fault_status_register,
+                })
+            }
             UNKNOWN_SYSCALL => Fault::UnknownSyscall(UnknownSyscall {
                 sender,
                 x0: buffer.msg[seL4_UnknownSyscall_X0 as usize],
@@ -145,8 +175,11 @@ impl From<(MessageInfo, Badge)> for Fault {
             }),
             CAP_FAULT => Fault::CapFault(CapFault {
                 sender,
+                program_counter: buffer.msg[seL4_CapFault_IP as usize],
                 cap_address: buffer.msg[seL4_CapFault_Addr as usize],
                 in_receive_phase: 1 == buffer.msg[seL4_CapFault_InRecvPhase as usize],
+                lookup_failure_type: buffer.msg[seL4_CapFault_LookupFailureType as usize],
+                bits_left: buffer.msg[seL4_CapFault_BitsLeft as usize],
             }),
             #[cfg(KernelArmHypervisorSupport)]
             VGIC_MAINTENANCE_FAULT => Fault::VGICMaintenanceFault(VGICMaintenanceFault {
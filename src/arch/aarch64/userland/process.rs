@@ -1,3 +1,4 @@
+use core::arch::global_asm;
 use core::mem;
 use core::ptr;
 
@@ -90,6 +91,35 @@ pub(crate) fn set_thread_link_register(
     registers.x30 = (post_return_fn as *const fn() -> !) as usize;
 }
 
+/// Point the link register at `ferros_joinable_exit`, so a joinable
+/// process' entry point returns straight into reporting its exit. Where
+/// to report it is kept in x19 and x20, which the calling convention
+/// has the entry point preserve.
+pub(crate) fn set_thread_exit_stub(
+    registers: &mut selfe_sys::seL4_UserContext,
+    exit_status: usize,
+    exit_notification: usize,
+) {
+    registers.x19 = exit_status;
+    registers.x20 = exit_notification;
+    registers.x30 = ferros_joinable_exit as usize;
+}
+
+extern "C" {
+    fn ferros_joinable_exit();
+}
+
+// The entry point's return value is still in x0, so this only has to
+// put the saved exit details next to it before handing over.
+global_asm!(
+    ".text",
+    ".global ferros_joinable_exit",
+    "ferros_joinable_exit:",
+    "mov x1, x19",
+    "mov x2, x20",
+    "b ferros_report_exit",
+);
+
 #[doc(hidden)]
 #[allow(dead_code)]
 #[cfg(feature = "test_support")]
@@ -0,0 +1,40 @@
+use selfe_sys::*;
+
+use typenum::Unsigned;
+
+use crate::cap::{page_state, DirectRetype, Frame, LocalCap, PageTable};
+use crate::userland::CapRights;
+use crate::vspace::{MappingError, Maps};
+
+use super::super::{map_frame, LargePageBits, PagingRoot};
+
+/// A 64KB frame, mapped as sixteen consecutive entries of a page
+/// table.
+pub type LargePage<State> = Frame<LargePageBits, State>;
+
+impl Maps<LargePage<page_state::Unmapped>> for PageTable {
+    fn map_granule(
+        &mut self,
+        page: &LocalCap<LargePage<page_state::Unmapped>>,
+        addr: usize,
+        root: &mut LocalCap<PagingRoot>,
+        rights: CapRights,
+        vm_attributes: seL4_ARM_VMAttributes,
+    ) -> Result<(), MappingError> {
+        map_frame(
+            page.cptr,
+            LargePageBits::USIZE,
+            addr,
+            root,
+            rights,
+            vm_attributes,
+        )
+    }
+}
+
+impl DirectRetype for LargePage<page_state::Unmapped> {
+    type SizeBits = LargePageBits;
+    fn sel4_type_id() -> usize {
+        _object_seL4_ARM_LargePageObject as usize
+    }
+}
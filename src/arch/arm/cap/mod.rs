@@ -1,11 +1,73 @@
 mod asid;
 mod asid_control;
+mod frame;
 mod page;
 mod page_directory;
 mod page_table;
 
 pub use asid::*;
 pub use asid_control::*;
+pub use frame::*;
 pub use page::*;
 pub use page_directory::*;
 pub use page_table::*;
+
+/// The kernel's capability tags, from its `structures_32.bf`.
+/// libsel4 doesn't export these, so neither does `selfe_sys`.
+///
+/// Tags whose low four bits are `0xe` or `0xf` are eight bits wide,
+/// which is why the ones for IRQ and domain caps differ from 64-bit
+/// ARM's.
+#[cfg(KernelDebugBuild)]
+pub(crate) mod cap_tag {
+    pub const NULL_CAP: u32 = 0;
+    pub const UNTYPED_CAP: u32 = 2;
+    pub const ENDPOINT_CAP: u32 = 4;
+    pub const NOTIFICATION_CAP: u32 = 6;
+    pub const REPLY_CAP: u32 = 8;
+    pub const CNODE_CAP: u32 = 10;
+    pub const THREAD_CAP: u32 = 12;
+    pub const IRQ_CONTROL_CAP: u32 = 0x0e;
+    pub const IRQ_HANDLER_CAP: u32 = 0x1e;
+    pub const ZOMBIE_CAP: u32 = 0x2e;
+    pub const DOMAIN_CAP: u32 = 0x3e;
+
+    pub const SMALL_FRAME_CAP: u32 = 1;
+    pub const FRAME_CAP: u32 = 3;
+    pub const ASID_POOL_CAP: u32 = 5;
+    pub const PAGE_TABLE_CAP: u32 = 7;
+    pub const PAGE_DIRECTORY_CAP: u32 = 9;
+    pub const ASID_CONTROL_CAP: u32 = 11;
+    pub const VCPU_CAP: u32 = 0x0f;
+}
+
+/// The architecture-specific kinds of capability.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchCapKind {
+    /// A large page, section or supersection.
+    Frame,
+    /// A 4K page.
+    SmallFrame,
+    PageTable,
+    PageDirectory,
+    ASIDControl,
+    ASIDPool,
+    VCPU,
+}
+
+#[cfg(KernelDebugBuild)]
+impl ArchCapKind {
+    pub(crate) fn from_tag(tag: u32) -> Option<ArchCapKind> {
+        use cap_tag::*;
+        match tag {
+            FRAME_CAP => Some(ArchCapKind::Frame),
+            SMALL_FRAME_CAP => Some(ArchCapKind::SmallFrame),
+            PAGE_TABLE_CAP => Some(ArchCapKind::PageTable),
+            PAGE_DIRECTORY_CAP => Some(ArchCapKind::PageDirectory),
+            ASID_CONTROL_CAP => Some(ArchCapKind::ASIDControl),
+            ASID_POOL_CAP => Some(ArchCapKind::ASIDPool),
+            VCPU_CAP => Some(ArchCapKind::VCPU),
+            _ => None,
+        }
+    }
+}
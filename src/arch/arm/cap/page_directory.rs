@@ -3,7 +3,7 @@ use selfe_sys::*;
 use typenum::Unsigned;
 
 use crate::arch;
-use crate::cap::{CapType, DirectRetype, LocalCap, Movable, PageTable, PhantomCap};
+use crate::cap::{CapType, Delible, DirectRetype, LocalCap, Movable, PageTable, PhantomCap};
 use crate::error::{ErrorExt, KernelError, SeL4Error};
 use crate::userland::CapRights;
 use crate::vspace::{MappingError, Maps};
@@ -43,6 +43,8 @@ impl CapType for PageDirectory {}
 
 impl Movable for PageDirectory {}
 
+impl Delible for PageDirectory {}
+
 impl PhantomCap for PageDirectory {
     fn phantom_instance() -> Self {
         PageDirectory {}
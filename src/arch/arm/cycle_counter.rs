@@ -0,0 +1,74 @@
+use core::arch::asm;
+
+use super::super::CycleCounterError;
+
+/// Turn on the PMU's cycle counter (CCNT) for the current core.
+///
+/// The kernel only lets user level near the PMU when it's built with
+/// `KernelExportPMCUser`; it doesn't start the counter itself. Without
+/// that, this does nothing and `read_cycle_counter` falls back to the
+/// generic timer's virtual count, provided the kernel exports that
+/// instead (`KernelArmExportVCNTUser`).
+pub fn enable_cycle_counter() -> Result<(), CycleCounterError> {
+    #[cfg(KernelExportPMCUser)]
+    unsafe {
+        // PMCR.E, then PMCNTENSET.C.
+        asm!(
+            "mrc p15, 0, {tmp}, c9, c12, 0",
+            "orr {tmp}, {tmp}, #1",
+            "mcr p15, 0, {tmp}, c9, c12, 0",
+            "mcr p15, 0, {c}, c9, c12, 1",
+            "isb",
+            tmp = out(reg) _,
+            c = in(reg) 1u32 << 31,
+            options(nostack, preserves_flags),
+        );
+    }
+    read_cycle_counter().map(|_| ())
+}
+
+/// Read the current core's cycle counter.
+///
+/// This is CCNT when the kernel exports the PMU to user level, which
+/// counts once per CPU cycle after `enable_cycle_counter`. CCNT is only
+/// 32 bits wide, so it wraps every few seconds; `Stopwatch` accounts
+/// for a single wrap. The fallback, CNTVCT, is 64 bits but ticks at
+/// the generic timer's frequency (CNTFRQ), which is usually far lower
+/// than the CPU clock.
+pub fn read_cycle_counter() -> Result<u64, CycleCounterError> {
+    #[cfg(KernelExportPMCUser)]
+    {
+        let cycles: u32;
+        unsafe {
+            asm!("mrc p15, 0, {}, c9, c13, 0", out(reg) cycles, options(nomem, nostack, preserves_flags));
+        }
+        Ok(u64::from(cycles))
+    }
+    #[cfg(all(not(KernelExportPMCUser), KernelArmExportVCNTUser))]
+    {
+        let (low, high): (u32, u32);
+        unsafe {
+            asm!(
+                "mrrc p15, 1, {}, {}, c14",
+                out(reg) low,
+                out(reg) high,
+                options(nomem, nostack, preserves_flags),
+            );
+        }
+        Ok(u64::from(high) << 32 | u64::from(low))
+    }
+    #[cfg(not(any(KernelExportPMCUser, KernelArmExportVCNTUser)))]
+    {
+        Err(CycleCounterError::NotExported)
+    }
+}
+
+#[cfg(KernelExportPMCUser)]
+pub(crate) fn cycles_between(start: u64, end: u64) -> u64 {
+    u64::from((end as u32).wrapping_sub(start as u32))
+}
+
+#[cfg(not(KernelExportPMCUser))]
+pub(crate) fn cycles_between(start: u64, end: u64) -> u64 {
+    end.wrapping_sub(start)
+}
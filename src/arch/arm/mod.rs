@@ -1,10 +1,16 @@
-use crate::error::{ErrorExt, SeL4Error};
+use crate::cap::LocalCap;
+use crate::error::{ErrorExt, KernelError, SeL4Error};
+use crate::userland::CapRights;
+use crate::vspace::MappingError;
 use typenum::*;
 
 pub mod cap;
+mod cycle_counter;
 pub mod fault;
 pub mod userland;
 
+pub use cycle_counter::*;
+
 pub type WordSize = U32;
 pub type MinUntypedSize = U4;
 // MaxUntypedSize is half the address space and/or word size.
@@ -26,6 +32,31 @@ pub type ASIDPoolSize = op!(U1 << ASIDLowBits);
 pub type TCBBits = U10;
 pub type NotificationBits = U4;
 
+/// The `arch` line of a capDL spec for this architecture.
+#[cfg(feature = "capdl")]
+pub(crate) const CAPDL_ARCH: &str = "arm11";
+
+/// The capDL name for one of this architecture's object types.
+#[cfg(feature = "capdl")]
+pub(crate) fn capdl_object_name(object_type: usize) -> Option<&'static str> {
+    use selfe_sys::*;
+    if object_type == _object_seL4_ARM_SmallPageObject as usize {
+        Some("frame (4k)")
+    } else if object_type == _object_seL4_ARM_LargePageObject as usize {
+        Some("frame (64k)")
+    } else if object_type == _object_seL4_ARM_SectionObject as usize {
+        Some("frame (1M)")
+    } else if object_type == _object_seL4_ARM_SuperSectionObject as usize {
+        Some("frame (16M)")
+    } else if object_type == _object_seL4_ARM_PageTableObject as usize {
+        Some("pt")
+    } else if object_type == _object_seL4_ARM_PageDirectoryObject as usize {
+        Some("pd")
+    } else {
+        None
+    }
+}
+
 #[cfg(KernelHypervisorSupport)]
 mod hyp_dependent_constants {
     use typenum::*;
@@ -58,6 +89,11 @@ mod hyp_dependent_constants {
 
     pub type AddressSpace = PagingRec<Page<page_state::Unmapped>, PageTable, PagingTop>;
 
+    /// The paging structures a large page is mapped through: a large
+    /// page takes up several entries of a page table.
+    pub type LargePageAddressSpace =
+        PagingRec<cap::LargePage<page_state::Unmapped>, PageTable, PagingTop>;
+
     pub type PagingRoot = cap::PageDirectory;
     /// The level directly underneath the PagingRoot
     pub type PagingRootLowerLevel = PageTable;
@@ -74,6 +110,19 @@ mod hyp_dependent_constants {
             }
         }
     }
+
+    impl LargePageAddressSpace {
+        pub fn new() -> Self {
+            PagingRec {
+                layer: PageTable {},
+                next: PagingTop {
+                    layer: cap::PageDirectory {},
+                    _item: PhantomData,
+                },
+                _item: PhantomData,
+            }
+        }
+    }
 }
 
 pub use hyp_dependent_constants::*;
@@ -84,6 +133,15 @@ pub type PageIndexBits = U12;
 pub type PageBytes = op!(U1 << U12);
 pub type LargePageBits = U16;
 
+/// The intermediate paging structures a VSpace creates as mappings
+/// need them, from the bottom up, each as (bits of address space one
+/// object spans, size bits of the object). The page directory is the
+/// root, and always exists.
+pub(crate) const INTERMEDIATE_PAGING_LAYERS: [(usize, usize); 1] = [(
+    PageBits::USIZE + PageTableIndexBits::USIZE,
+    PageTableBits::USIZE,
+)];
+
 pub type BasePageDirFreeSlots = op!((U1 << PageDirIndexBits) - (U1 << U9));
 pub type BasePageTableFreeSlots = op!(U1 << PageTableIndexBits);
 
@@ -135,12 +193,97 @@ pub mod vm_attributes {
     pub const PROGRAM_CODE: VMAttributes = DEFAULT;
 
     pub const PROGRAM_DATA: VMAttributes = PAGE_CACHEABLE | PARITY_ENABLED | EXECUTE_NEVER;
+
+    /// For memory shared with devices, e.g. DMA buffers, where the
+    /// cache would hide writes from the other side.
+    pub const UNCACHED: VMAttributes = PARITY_ENABLED | EXECUTE_NEVER;
+}
+
+/// Map the frame `cptr`, which is `1 << size_bits` bytes long, at
+/// `addr`, sorting the kernel's refusals the way a `Maps`
+/// implementation reports them.
+pub(crate) fn map_frame(
+    cptr: usize,
+    size_bits: usize,
+    addr: usize,
+    root: &mut LocalCap<PagingRoot>,
+    rights: CapRights,
+    vm_attributes: VMAttributes,
+) -> Result<(), MappingError> {
+    if addr % (1 << size_bits) != 0 {
+        return Err(MappingError::AddrNotPageAligned);
+    }
+    match unsafe {
+        selfe_sys::seL4_ARM_Page_Map(
+            cptr,
+            root.cptr,
+            addr,
+            selfe_sys::seL4_CapRights_t::from(rights),
+            vm_attributes,
+        )
+    }
+    .as_result()
+    {
+        Ok(_) => Ok(()),
+        Err(KernelError::FailedLookup) => Err(MappingError::Overflow),
+        // As with a page; see `MappingError::PageMaybeAlreadyMapped`.
+        Err(e @ KernelError::InvalidArgument) | Err(e @ KernelError::InvalidCapability) => {
+            Err(MappingError::PageMaybeAlreadyMapped(SeL4Error::PageMap(e)))
+        }
+        Err(e) => Err(MappingError::PageMapFailure(SeL4Error::PageMap(e))),
+    }
 }
 
 pub(crate) unsafe fn flush_page(cptr: usize) -> Result<(), SeL4Error> {
-    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, 0x0000, PageBytes::USIZE)
+    flush_granule(cptr, PageBytes::USIZE)
+}
+
+/// Clean and invalidate the whole of a frame mapped with `cptr`, which
+/// is `size_bytes` long. A large page has to be flushed with its own
+/// size; using `PageBytes` would leave everything past its first 4K
+/// stale.
+pub(crate) unsafe fn flush_granule(cptr: usize, size_bytes: usize) -> Result<(), SeL4Error> {
+    flush_page_range(cptr, 0x0000, size_bytes)
+}
+
+/// Clean and invalidate the bytes from `start` up to `end` within a
+/// page, given as offsets from its start. The kernel rounds these out
+/// to whole cache lines.
+pub(crate) unsafe fn flush_page_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_CleanInvalidate_Data(cptr, start, end)
         .as_result()
         .map_err(|e| SeL4Error::PageCleanInvalidateData(e))?;
 
     Ok(())
 }
+
+/// Like `flush_page_range`, but only writes dirty lines back to memory.
+pub(crate) unsafe fn clean_page_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Clean_Data(cptr, start, end)
+        .as_result()
+        .map_err(|e| SeL4Error::PageCleanData(e))?;
+
+    Ok(())
+}
+
+/// Like `flush_page_range`, but discards cached lines without writing
+/// them back.
+pub(crate) unsafe fn invalidate_page_range(
+    cptr: usize,
+    start: usize,
+    end: usize,
+) -> Result<(), SeL4Error> {
+    selfe_sys::seL4_ARM_Page_Invalidate_Data(cptr, start, end)
+        .as_result()
+        .map_err(|e| SeL4Error::PageInvalidateData(e))?;
+
+    Ok(())
+}
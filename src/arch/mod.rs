@@ -8,16 +8,15 @@ mod aarch64;
 #[cfg(target_arch = "aarch64")]
 pub use aarch64::*;
 
-/// For use in places where code is generated from bitfield DSL files and
-/// hard-codes the output integer size rather than referring to seL4Word
-/// or equivalent.
-///
-/// Removing this helper would involve adapting around many or all of the
-/// bitfield-DSL-derived methods or replacing the generation of such.
+use crate::error::{ErrorExt, SeL4Error};
+
+/// A machine word as the kernel sees it: the size of a message
+/// register, a capability pointer or a badge. `WordSize` is its width
+/// in bits, at the type level.
 #[cfg(target_pointer_width = "64")]
-pub(crate) unsafe fn to_sel4_word(n: usize) -> u64 {
-    n as u64
-}
+pub type Word = u64;
+#[cfg(target_pointer_width = "32")]
+pub type Word = u32;
 
 /// For use in places where code is generated from bitfield DSL files and
 /// hard-codes the output integer size rather than referring to seL4Word
@@ -25,12 +24,76 @@ pub(crate) unsafe fn to_sel4_word(n: usize) -> u64 {
 ///
 /// Removing this helper would involve adapting around many or all of the
 /// bitfield-DSL-derived methods or replacing the generation of such.
-#[cfg(target_pointer_width = "32")]
-pub(crate) unsafe fn to_sel4_word(n: usize) -> u32 {
-    n as u32
+pub(crate) fn to_sel4_word(n: usize) -> Word {
+    n as Word
+}
+
+/// The inverse of `to_sel4_word`, for values read back out of
+/// bitfield-DSL-derived accessors.
+pub(crate) fn from_sel4_word(w: Word) -> usize {
+    w as usize
+}
+
+/// The physical address of the frame behind the page cap at `cptr`,
+/// whatever its size.
+pub(crate) fn frame_paddr(cptr: usize) -> Result<usize, SeL4Error> {
+    let res = unsafe { selfe_sys::seL4_ARM_Page_GetAddress(cptr) };
+    match (res.error as selfe_sys::seL4_Error).as_result() {
+        Ok(_) => Ok(res.paddr),
+        Err(e) => Err(SeL4Error::PageGetAddress(e)),
+    }
 }
 
 #[cfg(target_pointer_width = "64")]
 pub type CNodeSlotBits = typenum::U5;
 #[cfg(target_pointer_width = "32")]
 pub type CNodeSlotBits = typenum::U4;
+
+/// Why `read_cycle_counter` can't give an answer.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CycleCounterError {
+    /// The kernel exports neither the PMU (`KernelExportPMCUser`) nor
+    /// the generic timer's virtual count (`KernelArmExportVCNTUser`) to
+    /// user level.
+    NotExported,
+}
+
+/// Measures elapsed time in cycle counter ticks, for putting numbers
+/// on things like IPC round trips.
+///
+/// The counters are per core, so a thread that migrates between
+/// `start` and `elapsed` gets a meaningless answer; pin it first if
+/// the kernel is SMP.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    started_at: u64,
+}
+
+impl Stopwatch {
+    /// Enable the counter, if need be, and start timing.
+    pub fn start() -> Result<Stopwatch, CycleCounterError> {
+        enable_cycle_counter()?;
+        Ok(Stopwatch {
+            started_at: read_cycle_counter()?,
+        })
+    }
+
+    /// Ticks since `start`.
+    pub fn elapsed(&self) -> u64 {
+        match read_cycle_counter() {
+            Ok(now) => cycles_between(self.started_at, now),
+            Err(_) => unreachable!("the counter was readable when the stopwatch started"),
+        }
+    }
+
+    /// Ticks since `start`, starting the stopwatch over.
+    pub fn lap(&mut self) -> u64 {
+        let now = match read_cycle_counter() {
+            Ok(now) => now,
+            Err(_) => unreachable!("the counter was readable when the stopwatch started"),
+        };
+        let ticks = cycles_between(self.started_at, now);
+        self.started_at = now;
+        ticks
+    }
+}
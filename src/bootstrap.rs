@@ -1,5 +1,7 @@
 use core::marker::PhantomData;
 
+use arrayvec::ArrayVec;
+
 use selfe_sys::*;
 
 use typenum::operator_aliases::Diff;
@@ -14,34 +16,78 @@ use crate::cap::{
 use crate::error::SeL4Error;
 use crate::pow::Pow;
 use crate::userland::process::NeitherSendNorSync;
-use crate::userland::CapRights;
-use crate::vspace::VSpace;
+use crate::userland::{AddressSpaceIndependent, CapRights};
+use crate::vspace::{VSpace, VSpaceError};
 
 // The root CNode radix is 19. Conservatively set aside 2^12 (the default root
-// cnode size) for system use. TODO: verify at build time that this is enough /
-// compute a better number
-type RootCNodeSize = Pow<U19>;
+// cnode size) for system use. `root_cnode` checks both the radix and that
+// this really is enough against the bootinfo.
+type RootCNodeRadix = U19;
+type RootCNodeSize = Pow<RootCNodeRadix>;
 type SystemProvidedCapCount = Pow<U12>;
 type RootCNodeAvailableSlots = Diff<RootCNodeSize, SystemProvidedCapCount>;
 
+/// Where the kernel has left room in the root CNode, as reported by
+/// the bootinfo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RootCNodeLayout {
+    pub radix: u8,
+    /// The first slot the kernel left empty. Everything before it is
+    /// occupied by initial caps: the root thread's own, the user
+    /// image's frames and paging structures, the untypeds and so on.
+    pub first_free_slot: usize,
+    /// How many empty slots follow `first_free_slot`.
+    pub free_slot_count: usize,
+}
+
+impl RootCNodeLayout {
+    pub fn from_bootinfo(bootinfo: &seL4_BootInfo) -> Self {
+        RootCNodeLayout {
+            radix: bootinfo.initThreadCNodeSizeBits as u8,
+            first_free_slot: bootinfo.empty.start,
+            free_slot_count: bootinfo.empty.end - bootinfo.empty.start,
+        }
+    }
+}
+
 // of random things in the bootinfo.
 // TODO: ideally, this should only be callable once in the process. Is that possible?
+/// The root CNode, and the slots in it that the kernel has left empty.
+///
+/// Panics if the kernel's root CNode isn't the size assumed by
+/// `RootCNodeSize`, which would be the case for a kernel built with a
+/// different `KernelRootCNodeSizeBits`, or if the kernel filled in more
+/// than `SystemProvidedCapCount` slots. Either way the slots handed out
+/// would run past the end of the CNode.
 pub fn root_cnode(
     bootinfo: &'static seL4_BootInfo,
 ) -> (
     LocalCap<LocalCNode>,
     LocalCNodeSlots<RootCNodeAvailableSlots>,
 ) {
+    let layout = RootCNodeLayout::from_bootinfo(bootinfo);
+    assert_eq!(
+        layout.radix,
+        RootCNodeRadix::U8,
+        "the kernel's root CNode radix is not the {} assumed",
+        RootCNodeRadix::U8
+    );
+    assert!(
+        layout.free_slot_count >= RootCNodeAvailableSlots::USIZE,
+        "the kernel left only {} free root CNode slots, fewer than the {} assumed",
+        layout.free_slot_count,
+        RootCNodeAvailableSlots::USIZE
+    );
     (
         Cap {
             cptr: seL4_CapInitThreadCNode as usize,
             _role: PhantomData,
             cap_data: CNode {
-                radix: 19,
+                radix: layout.radix,
                 _role: PhantomData,
             },
         },
-        CNodeSlots::internal_new(seL4_CapInitThreadCNode as usize, bootinfo.empty.start),
+        CNodeSlots::internal_new(seL4_CapInitThreadCNode as usize, layout.first_free_slot),
     )
 }
 
@@ -61,6 +107,8 @@ pub struct UserImage<Role: CNodeRole> {
     _role: PhantomData<Role>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for UserImage<Role> {}
+
 /// A BootInfo cannot be handed to child processes and thus its related
 /// structures always operate in a "Local" role.
 pub struct BootInfo<ASIDControlFreePools: Unsigned> {
@@ -69,12 +117,202 @@ pub struct BootInfo<ASIDControlFreePools: Unsigned> {
 
     pub asid_control: LocalCap<ASIDControl<ASIDControlFreePools>>,
     pub irq_control: LocalCap<IRQControl>,
+    #[cfg(KernelMultipleDomains)]
+    pub domain_set: LocalCap<crate::cap::DomainSet>,
+    #[cfg(KernelIsMCS)]
+    pub sched_control: LocalCap<crate::cap::SchedControl>,
     pub user_image: UserImage<role::Local>,
 
+    raw: &'static seL4_BootInfo,
+    extra_frames_taken: bool,
+
     #[allow(dead_code)]
     neither_send_nor_sync: NeitherSendNorSync,
 }
 
+/// The caps seL4 places in the same slot of every root task's CNode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WellKnownCap {
+    InitThreadTCB,
+    InitThreadCNode,
+    InitThreadVSpace,
+    IRQControl,
+    ASIDControl,
+    InitThreadASIDPool,
+    BootInfoFrame,
+    InitThreadIPCBuffer,
+    Domain,
+}
+
+static WELL_KNOWN_CAPS: [(usize, WellKnownCap); 9] = [
+    (seL4_CapInitThreadTCB as usize, WellKnownCap::InitThreadTCB),
+    (
+        seL4_CapInitThreadCNode as usize,
+        WellKnownCap::InitThreadCNode,
+    ),
+    (
+        seL4_CapInitThreadVSpace as usize,
+        WellKnownCap::InitThreadVSpace,
+    ),
+    (seL4_CapIRQControl as usize, WellKnownCap::IRQControl),
+    (seL4_CapASIDControl as usize, WellKnownCap::ASIDControl),
+    (
+        seL4_CapInitThreadASIDPool as usize,
+        WellKnownCap::InitThreadASIDPool,
+    ),
+    (seL4_CapBootInfoFrame as usize, WellKnownCap::BootInfoFrame),
+    (
+        seL4_CapInitThreadIPCBuffer as usize,
+        WellKnownCap::InitThreadIPCBuffer,
+    ),
+    (seL4_CapDomain as usize, WellKnownCap::Domain),
+];
+
+/// One of the capabilities the kernel handed the root task, and what
+/// it is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BootInfoCap {
+    WellKnown {
+        cptr: usize,
+        kind: WellKnownCap,
+    },
+    UserImageFrame {
+        cptr: usize,
+    },
+    UserImagePaging {
+        cptr: usize,
+    },
+    /// A frame holding extra boot information, e.g. a device tree.
+    ExtraBootInfoFrame {
+        cptr: usize,
+    },
+    Untyped {
+        cptr: usize,
+        size_bits: u8,
+        paddr: usize,
+        is_device: bool,
+    },
+    #[cfg(KernelIsMCS)]
+    SchedControl {
+        cptr: usize,
+    },
+}
+
+impl BootInfoCap {
+    /// The root CNode slot the kernel put this cap in.
+    pub fn cptr(&self) -> usize {
+        match *self {
+            BootInfoCap::WellKnown { cptr, .. }
+            | BootInfoCap::UserImageFrame { cptr }
+            | BootInfoCap::UserImagePaging { cptr }
+            | BootInfoCap::ExtraBootInfoFrame { cptr }
+            | BootInfoCap::Untyped { cptr, .. } => cptr,
+            #[cfg(KernelIsMCS)]
+            BootInfoCap::SchedControl { cptr } => cptr,
+        }
+    }
+}
+
+/// The caps `BootInfo::describe` lists, read straight from the raw
+/// bootinfo.
+pub(crate) fn describe_boot_caps(
+    raw: &'static seL4_BootInfo,
+) -> impl Iterator<Item = BootInfoCap> + Clone {
+    let slots = |region: seL4_SlotRegion| region.start..region.end;
+
+    let well_known = WELL_KNOWN_CAPS
+        .iter()
+        .map(|&(cptr, kind)| BootInfoCap::WellKnown { cptr, kind });
+    let frames = slots(raw.userImageFrames).map(|cptr| BootInfoCap::UserImageFrame { cptr });
+    let paging = slots(raw.userImagePaging).map(|cptr| BootInfoCap::UserImagePaging { cptr });
+    let extra = slots(raw.extraBIPages).map(|cptr| BootInfoCap::ExtraBootInfoFrame { cptr });
+    let untyped = slots(raw.untyped).map(move |cptr| {
+        let desc = &raw.untypedList[cptr - raw.untyped.start];
+        BootInfoCap::Untyped {
+            cptr,
+            size_bits: desc.sizeBits,
+            paddr: desc.paddr,
+            is_device: desc.isDevice == 1,
+        }
+    });
+    #[cfg(KernelIsMCS)]
+    let untyped =
+        untyped.chain(slots(raw.schedcontrol).map(|cptr| BootInfoCap::SchedControl { cptr }));
+
+    well_known
+        .chain(frames)
+        .chain(paging)
+        .chain(extra)
+        .chain(untyped)
+}
+
+impl<ASIDControlFreePools: Unsigned> BootInfo<ASIDControlFreePools> {
+    /// Every capability the kernel put in the root CNode, grouped by
+    /// kind, whether or not ferros has wrapped it. This reads only
+    /// the bootinfo, so says nothing about what has been done with
+    /// the caps since.
+    pub fn describe(&self) -> impl Iterator<Item = BootInfoCap> + Clone {
+        describe_boot_caps(self.raw)
+    }
+
+    /// The frames holding the extra boot information (boot modules,
+    /// the device tree), in order.
+    ///
+    /// The kernel has already mapped these into the root task's
+    /// VSpace, in the page after the bootinfo frame, so they come out
+    /// mapped. To map them anywhere else, `copy` each one, which gives
+    /// an unmapped page.
+    ///
+    /// The frames are only handed out once; later calls return
+    /// `None`. Once they have been, `extra_bytes` no longer reads them,
+    /// as they may since have been unmapped.
+    pub fn extra_frames(
+        &mut self,
+    ) -> Option<impl Iterator<Item = LocalCap<Page<page_state::Mapped>>>> {
+        if self.extra_frames_taken {
+            return None;
+        }
+        self.extra_frames_taken = true;
+        let region = self.raw.extraBIPages;
+        let start_vaddr = self.extra_vaddr();
+        Some(
+            (region.start..region.end)
+                .enumerate()
+                .map(move |(i, cptr)| Cap {
+                    cptr,
+                    cap_data: Page {
+                        state: page_state::Mapped {
+                            vaddr: start_vaddr + (i << PageBits::USIZE),
+                            // Valid for the root task only, as for the user image.
+                            asid: InternalASID { asid: 0 },
+                            rights: CapRights::RW,
+                        },
+                    },
+                    _role: PhantomData,
+                }),
+        )
+    }
+
+    /// The extra boot information, read in place. Empty if the
+    /// bootloader passed none, and `None` once `extra_frames` has
+    /// handed out the frames it's read from.
+    pub fn extra_bytes(&self) -> Option<&[u8]> {
+        if self.extra_frames_taken {
+            return None;
+        }
+        if self.raw.extraLen == 0 {
+            return Some(&[]);
+        }
+        Some(unsafe {
+            core::slice::from_raw_parts(self.extra_vaddr() as *const u8, self.raw.extraLen as usize)
+        })
+    }
+
+    fn extra_vaddr(&self) -> usize {
+        self.raw as *const seL4_BootInfo as usize + (1 << PageBits::USIZE)
+    }
+}
+
 impl BootInfo<op!(ASIDPoolCount - U1)> {
     /* NB(dan@auxon.io): Just wanted to leave a quick note here: Now
      * that the vspace needs some resources to do its job, we need the
@@ -96,7 +334,7 @@ impl BootInfo<op!(ASIDPoolCount - U1)> {
         root_vspace_ut: LocalCap<Untyped<VSpaceUntypedSize>>,
         root_vspace_cslots: LocalCNodeSlots<VSpaceSlotCount>,
     ) -> Self {
-        let asid_control = Cap::wrap_cptr(seL4_CapASIDControl as usize);
+        let asid_control = unsafe { Cap::wrap_cptr(seL4_CapASIDControl as usize) };
 
         let user_image = UserImage {
             frames_start_cptr: bootinfo.userImageFrames.start,
@@ -122,7 +360,7 @@ impl BootInfo<op!(ASIDPoolCount - U1)> {
                 },
                 root_vspace_ut.weaken(),
             ),
-            root_tcb: Cap::wrap_cptr(seL4_CapInitThreadTCB as usize),
+            root_tcb: unsafe { Cap::wrap_cptr(seL4_CapInitThreadTCB as usize) },
             asid_control,
             irq_control: Cap {
                 cptr: seL4_CapIRQControl as usize,
@@ -131,17 +369,102 @@ impl BootInfo<op!(ASIDPoolCount - U1)> {
                 },
                 _role: PhantomData,
             },
+            #[cfg(KernelMultipleDomains)]
+            domain_set: unsafe { Cap::wrap_cptr(seL4_CapDomain as usize) },
+            #[cfg(KernelIsMCS)]
+            sched_control: unsafe { Cap::wrap_cptr(bootinfo.schedcontrol.start) },
             user_image,
+            raw: bootinfo,
+            extra_frames_taken: false,
             neither_send_nor_sync: Default::default(),
         }
     }
 }
 
+/// The most loadable segments `UserImage::sections` will report.
+pub const MAX_USER_IMAGE_SECTIONS: usize = 8;
+
+/// One of the loadable segments of the root task's image, and how it
+/// is meant to be mapped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UserImageSection {
+    /// The first byte of the section. Not necessarily page-aligned.
+    pub vaddr: usize,
+    /// How many pages the section touches.
+    pub page_count: usize,
+    /// `CapRights::R` or `CapRights::RW`.
+    pub rights: CapRights,
+    pub executable: bool,
+}
+
+impl UserImageSection {
+    /// Whether any of the section falls within the page at
+    /// `page_vaddr`.
+    pub fn touches_page(&self, page_vaddr: usize) -> bool {
+        let first_page = self.vaddr & !((1 << PageBits::USIZE) - 1);
+        page_vaddr >= first_page && (page_vaddr - first_page) >> PageBits::USIZE < self.page_count
+    }
+}
+
 impl UserImage<role::Local> {
     pub fn page_table_count(&self) -> usize {
         self.page_table_count
     }
 
+    /// Another handle on the same frames, for a process whose CSpace
+    /// is the root CNode itself.
+    pub(crate) fn alias(&self) -> Self {
+        UserImage {
+            frames_start_cptr: self.frames_start_cptr,
+            frames_count: self.frames_count,
+            page_table_count: self.page_table_count,
+            _role: PhantomData,
+        }
+    }
+
+    /// The loadable segments of the root task's image, read from its
+    /// own program headers.
+    ///
+    /// The standard linker scripts put the ELF and program headers in
+    /// the first loaded page, at `ProgramStart`; an image linked
+    /// otherwise gets `VSpaceError::ElfParseError`.
+    pub fn sections(
+        &self,
+    ) -> Result<ArrayVec<[UserImageSection; MAX_USER_IMAGE_SECTIONS]>, VSpaceError> {
+        let image = unsafe {
+            core::slice::from_raw_parts(
+                ProgramStart::USIZE as *const u8,
+                self.pages_count() << PageBits::USIZE,
+            )
+        };
+        let elf = xmas_elf::ElfFile::new(image).map_err(VSpaceError::ElfParseError)?;
+
+        let mut sections = ArrayVec::new();
+        for header in elf
+            .program_iter()
+            .filter(|h| h.get_type() == Ok(xmas_elf::program::Type::Load))
+        {
+            let vaddr = header.virtual_addr() as usize;
+            let end = vaddr + header.mem_size() as usize;
+            let first_page = vaddr >> PageBits::USIZE;
+            let end_page = (end + (1 << PageBits::USIZE) - 1) >> PageBits::USIZE;
+            let flags = header.flags();
+            sections
+                .try_push(UserImageSection {
+                    vaddr,
+                    page_count: end_page - first_page,
+                    rights: if flags.is_write() {
+                        CapRights::RW
+                    } else {
+                        CapRights::R
+                    },
+                    executable: flags.is_execute(),
+                })
+                .map_err(|_| VSpaceError::ElfParseError("too many loadable segments"))?;
+        }
+        Ok(sections)
+    }
+
     // TODO this doesn't enforce the aliasing constraints we want at the type
     // level. This can be modeled as an array (or other sized thing) once we
     // know how big the user image is.
@@ -177,6 +500,13 @@ impl UserImage<role::Local> {
         core::cmp::min(self.frames_count, vaddr_count)
     }
 
+    /// Whether the given address (in the root task's address space)
+    /// falls within the user image.
+    pub fn contains_vaddr(&self, vaddr: usize) -> bool {
+        vaddr >= ProgramStart::USIZE
+            && (vaddr - ProgramStart::USIZE) >> PageBits::USIZE < self.pages_count()
+    }
+
     pub fn copy<TargetRole: CNodeRole>(
         &self,
         src_cnode: &LocalCap<LocalCNode>,
@@ -13,3 +13,11 @@ pub struct AssignedASID {
 }
 
 impl CapType for AssignedASID {}
+
+/// The ASID of an address space which has been torn down by
+/// `VSpace::destroy`, ready to be handed back to its pool with
+/// `ASIDPool::reclaim`.
+#[derive(Debug)]
+pub struct FreedASID {
+    pub(crate) asid: InternalASID,
+}
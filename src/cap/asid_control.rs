@@ -24,6 +24,13 @@ impl<FreePools: Unsigned> PhantomCap for ASIDControl<FreePools> {
 }
 
 impl<FreePools: Unsigned> LocalCap<ASIDControl<FreePools>> {
+    /// Make a new ASID pool from a 4K untyped.
+    ///
+    /// The control cap is consumed and handed back with one fewer free
+    /// pool, so that each pool's id (the high bits of the ASIDs it
+    /// hands out) is known statically. Calling this again on the
+    /// returned control cap makes another pool, up to
+    /// `arch::ASIDPoolCount` in total.
     pub fn allocate_asid_pool(
         mut self,
         ut12: LocalCap<Untyped<U12, memory_kind::General>>,
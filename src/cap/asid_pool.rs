@@ -1,17 +1,23 @@
 use core::marker::PhantomData;
-use core::ops::Sub;
+use core::ops::{Add, Sub};
 
 use typenum::*;
 
 use crate::arch;
-use crate::cap::{Cap, CapType, LocalCap, UnassignedASID};
+use crate::cap::{Cap, CapType, FreedASID, LocalCap, UnassignedASID};
 use crate::error::SeL4Error;
 use crate::userland::CapRights;
 
+/// A bit for each of a pool's ASIDs which has been handed back with
+/// `reclaim`, and so can be allocated again.
+pub(crate) type ReclaimedASIDs = [u32; arch::ASIDPoolSize::USIZE / 32];
+pub(crate) const NO_RECLAIMED_ASIDS: ReclaimedASIDs = [0; arch::ASIDPoolSize::USIZE / 32];
+
 #[derive(Debug)]
 pub struct ASIDPool<FreeSlots: Unsigned> {
     pub(crate) id: usize,
     pub(crate) next_free_slot: usize,
+    pub(crate) reclaimed: ReclaimedASIDs,
     pub(crate) _free_slots: PhantomData<FreeSlots>,
 }
 
@@ -28,14 +34,28 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
         FreeSlots: Sub<U1>,
         op!(FreeSlots - U1): Unsigned,
     {
+        // Reuse a reclaimed ASID if there is one, before moving on to
+        // ones never yet handed out.
+        let mut reclaimed = self.cap_data.reclaimed;
+        let mut next_free_slot = self.cap_data.next_free_slot;
+        let slot = match reclaimed.iter().position(|word| *word != 0) {
+            Some(word_index) => {
+                let bit = reclaimed[word_index].trailing_zeros() as usize;
+                reclaimed[word_index] &= !(1 << bit);
+                word_index * 32 + bit
+            }
+            None => {
+                next_free_slot += 1;
+                self.cap_data.next_free_slot
+            }
+        };
         (
             Cap {
                 cptr: self.cptr,
                 _role: PhantomData,
                 cap_data: UnassignedASID {
                     asid: InternalASID {
-                        asid: (self.cap_data.id << arch::ASIDLowBits::USIZE)
-                            | self.cap_data.next_free_slot,
+                        asid: (self.cap_data.id << arch::ASIDLowBits::USIZE) | slot,
                     },
                 },
             },
@@ -44,13 +64,43 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
                 _role: PhantomData,
                 cap_data: ASIDPool {
                     id: self.cap_data.id,
-                    next_free_slot: self.cap_data.next_free_slot + 1,
+                    next_free_slot,
+                    reclaimed,
                     _free_slots: PhantomData,
                 },
             },
         )
     }
 
+    /// Return an ASID freed by `VSpace::destroy` to this pool, so that
+    /// it can be allocated again. If the ASID came from a different
+    /// pool, both are handed back untouched.
+    pub fn reclaim(
+        self,
+        freed: FreedASID,
+    ) -> Result<LocalCap<ASIDPool<op!(FreeSlots + U1)>>, (Self, FreedASID)>
+    where
+        FreeSlots: Add<U1>,
+        op!(FreeSlots + U1): Unsigned,
+    {
+        if freed.asid.asid >> arch::ASIDLowBits::USIZE != self.cap_data.id {
+            return Err((self, freed));
+        }
+        let slot = freed.asid.asid & ((1 << arch::ASIDLowBits::USIZE) - 1);
+        let mut reclaimed = self.cap_data.reclaimed;
+        reclaimed[slot / 32] |= 1 << (slot % 32);
+        Ok(Cap {
+            cptr: self.cptr,
+            _role: PhantomData,
+            cap_data: ASIDPool {
+                id: self.cap_data.id,
+                next_free_slot: self.cap_data.next_free_slot,
+                reclaimed,
+                _free_slots: PhantomData,
+            },
+        })
+    }
+
     pub fn split<
         LeftRole: crate::cap::CNodeRole,
         RightRole: crate::cap::CNodeRole,
@@ -76,6 +126,28 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
         op!(LeftSlots + RightSlots): Unsigned,
         op!(LeftSlots + RightSlots): IsLessOrEqual<FreeSlots, Output = True>,
     {
+        // The left half takes reclaimed ASIDs first, lowest slot first,
+        // and makes up any shortfall from the ones never handed out. The
+        // right half gets the rest of each, starting where the left's
+        // unallocated run ends.
+        let mut left_reclaimed = NO_RECLAIMED_ASIDS;
+        let mut right_reclaimed = NO_RECLAIMED_ASIDS;
+        let mut left_wanted = LeftSlots::USIZE;
+        for (word_index, word) in self.cap_data.reclaimed.iter().enumerate() {
+            for bit in 0..32 {
+                if word & (1 << bit) == 0 {
+                    continue;
+                }
+                if left_wanted > 0 {
+                    left_reclaimed[word_index] |= 1 << bit;
+                    left_wanted -= 1;
+                } else {
+                    right_reclaimed[word_index] |= 1 << bit;
+                }
+            }
+        }
+        let split_point = self.cap_data.next_free_slot + left_wanted;
+
         let left_offset = self.unchecked_copy(src_cnode, left_slot, CapRights::RWG)?;
         let right_offset = self.unchecked_copy(src_cnode, right_slot, CapRights::RWG)?;
         Ok((
@@ -85,6 +157,7 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
                 cap_data: ASIDPool {
                     id: self.cap_data.id,
                     next_free_slot: self.cap_data.next_free_slot,
+                    reclaimed: left_reclaimed,
                     _free_slots: PhantomData,
                 },
             },
@@ -93,7 +166,8 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
                 _role: PhantomData,
                 cap_data: ASIDPool {
                     id: self.cap_data.id,
-                    next_free_slot: self.cap_data.next_free_slot + LeftSlots::USIZE,
+                    next_free_slot: split_point,
+                    reclaimed: right_reclaimed,
                     _free_slots: PhantomData,
                 },
             },
@@ -111,6 +185,7 @@ impl<FreeSlots: Unsigned> LocalCap<ASIDPool<FreeSlots>> {
                 id: self.cap_data.id,
                 next_free_slot: self.cap_data.next_free_slot
                     + (FreeSlots::USIZE - OutFreeSlots::USIZE),
+                reclaimed: self.cap_data.reclaimed,
                 _free_slots: PhantomData,
             },
         }
@@ -1,3 +1,5 @@
+use crate::userland::AddressSpaceIndependent;
+
 /// Wrapper for an Endpoint or Notification badge.
 /// Note that the kernel will ignore any use of the high 4 bits
 #[derive(Clone, Copy, PartialEq, PartialOrd, Eq, Ord, Debug, Hash)]
@@ -5,7 +7,73 @@ pub struct Badge {
     pub(crate) inner: usize,
 }
 
+unsafe impl AddressSpaceIndependent for Badge {}
+
+/// The number of low bits of a badge the kernel pays attention to.
+///
+/// Of these, the shared memory IPC channels reserve the value 2 on the
+/// notifications they set up for waking a blocked responder, so
+/// packed badges minted onto those should leave bit 1 clear.
+pub const BADGE_BITS: usize = core::mem::size_of::<usize>() * 8 - 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BadgeError {
+    /// A field reaches past `BADGE_BITS`.
+    FieldOutOfRange { shift: usize, width: usize },
+    /// A value doesn't fit in the width of its field.
+    ValueTooWide {
+        shift: usize,
+        width: usize,
+        value: usize,
+    },
+    /// Two fields claim some of the same bits.
+    OverlappingFields { shift: usize, width: usize },
+}
+
 impl Badge {
+    /// Build a badge out of `(shift, width, value)` fields, e.g. a
+    /// client id in the low 12 bits and some flags above it. Values
+    /// too wide for their field are rejected rather than truncated.
+    pub fn pack(fields: &[(usize, usize, usize)]) -> Result<Badge, BadgeError> {
+        let mut inner = 0;
+        let mut claimed = 0;
+        for &(shift, width, value) in fields {
+            let mask = field_mask(shift, width)?;
+            if value & !(mask >> shift) != 0 {
+                return Err(BadgeError::ValueTooWide {
+                    shift,
+                    width,
+                    value,
+                });
+            }
+            if claimed & mask != 0 {
+                return Err(BadgeError::OverlappingFields { shift, width });
+            }
+            claimed |= mask;
+            inner |= value << shift;
+        }
+        Ok(Badge { inner })
+    }
+
+    /// Read back a field packed with `Badge::pack`.
+    pub fn field(&self, shift: usize, width: usize) -> Result<usize, BadgeError> {
+        let mask = field_mask(shift, width)?;
+        Ok((self.inner & mask) >> shift)
+    }
+
+    /// Each set bit of this badge, lowest first, as a badge of its
+    /// own.
+    ///
+    /// Signals which reach a notification before anyone waits on it
+    /// are ORed together into the one badge the next wait returns, so
+    /// with each source badged with a distinct bit, this gives back
+    /// every source which signalled.
+    pub fn bits(self) -> BadgeBits {
+        BadgeBits {
+            remaining: self.inner,
+        }
+    }
+
     pub fn are_all_overlapping_bits_set(self, other: Badge) -> bool {
         if self.inner == 0 && other.inner == 0 {
             return true;
@@ -15,6 +83,25 @@ impl Badge {
     }
 }
 
+/// The iterator returned by `Badge::bits`.
+#[derive(Clone, Debug)]
+pub struct BadgeBits {
+    remaining: usize,
+}
+
+impl Iterator for BadgeBits {
+    type Item = Badge;
+
+    fn next(&mut self) -> Option<Badge> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let lowest = self.remaining & self.remaining.wrapping_neg();
+        self.remaining &= !lowest;
+        Some(Badge { inner: lowest })
+    }
+}
+
 impl From<usize> for Badge {
     fn from(u: usize) -> Self {
         let shifted_left = u << 4;
@@ -29,3 +116,10 @@ impl From<Badge> for usize {
         b.inner
     }
 }
+
+fn field_mask(shift: usize, width: usize) -> Result<usize, BadgeError> {
+    if width == 0 || shift >= BADGE_BITS || width > BADGE_BITS - shift {
+        return Err(BadgeError::FieldOutOfRange { shift, width });
+    }
+    Ok((!0usize >> (core::mem::size_of::<usize>() * 8 - width)) << shift)
+}
@@ -3,10 +3,12 @@ use core::ops::{Add, Sub};
 
 use selfe_sys::*;
 
+use arrayvec::ArrayVec;
+
 use typenum::operator_aliases::Diff;
 use typenum::*;
 
-use crate::cap::{role, CNodeRole, Cap, CapType, ChildCap, LocalCap};
+use crate::cap::{role, CNodeRole, Cap, CapType, ChildCap, Delible, LocalCap, Movable};
 use crate::error::{ErrorExt, SeL4Error};
 use crate::userland::CapRights;
 
@@ -124,6 +126,26 @@ impl<Size: Unsigned, Role: CNodeRole> CNodeSlots<Size, Role> {
     }
 }
 
+/// Where a `LocalCNodeSlots` was, as recorded by `checkpoint`.
+pub struct CNodeSlotsCheckpoint<Size: Unsigned> {
+    pub(crate) cptr: usize,
+    offset: usize,
+    _size: PhantomData<Size>,
+}
+
+impl<Size: Unsigned> CNodeSlotsCheckpoint<Size> {
+    /// Revoke and delete whatever now occupies the checkpointed slots
+    /// and hand them back, as good as new.
+    ///
+    /// If any slot can't be emptied, none are handed back, since the
+    /// range as a whole could no longer be trusted to be free.
+    pub fn rollback(self) -> Result<LocalCNodeSlots<Size>, SeL4Error> {
+        let slots: LocalCNodeSlots<Size> = Cap::internal_new(self.cptr, self.offset);
+        unsafe { slots.try_revoke_in_reverse() }?;
+        Ok(slots)
+    }
+}
+
 impl<Size: Unsigned> LocalCNodeSlots<Size> {
     /// Gain temporary access to some slots for use in a function context.
     /// When the passed function call is complete, all capabilities
@@ -138,6 +160,44 @@ impl<Size: Unsigned> LocalCNodeSlots<Size> {
         Ok(r)
     }
 
+    /// Remember where these slots are so that they can be recovered,
+    /// emptied out, after they have been handed out. This is how
+    /// `smart_alloc_scoped!` undoes a failed scope.
+    ///
+    /// This is unsafe because `rollback` mints slots which may
+    /// still be in use elsewhere; the caller must ensure that anything
+    /// made from these slots is abandoned by then.
+    pub unsafe fn checkpoint(&self) -> CNodeSlotsCheckpoint<Size> {
+        CNodeSlotsCheckpoint {
+            cptr: self.cptr,
+            offset: self.cap_data.offset,
+            _size: PhantomData,
+        }
+    }
+
+    /// Revoke and delete the contents of the slots in reverse order,
+    /// stopping at the first that fails. Empty slots are no error.
+    unsafe fn try_revoke_in_reverse(&self) -> Result<(), SeL4Error> {
+        for offset in (self.cap_data.offset..self.cap_data.offset + Size::USIZE).rev() {
+            seL4_CNode_Revoke(
+                self.cptr,           // _service
+                offset,              // index
+                seL4_WordBits as u8, // depth
+            )
+            .as_result()
+            .map_err(|e| SeL4Error::CNodeRevoke(e))?;
+
+            crate::cap::logged::cnode_delete(
+                self.cptr,           // _service
+                offset,              // index
+                seL4_WordBits as u8, // depth
+            )
+            .as_result()
+            .map_err(|e| SeL4Error::CNodeDelete(e))?;
+        }
+        Ok(())
+    }
+
     /// Blindly attempt to revoke and delete the contents of the slots,
     /// (in reverse order) ignoring errors related to empty slots.
     pub(crate) unsafe fn revoke_in_reverse(&self) {
@@ -150,7 +210,7 @@ impl<Size: Unsigned> LocalCNodeSlots<Size> {
             );
 
             // Clean out the slot itself
-            let _err = seL4_CNode_Delete(
+            let _err = crate::cap::logged::cnode_delete(
                 self.cptr,           // _service
                 offset,              // index
                 seL4_WordBits as u8, // depth
@@ -178,7 +238,7 @@ impl LocalCap<ChildCNode> {
         let (dest_cptr, dest_offset, _) = dest_slots.elim();
 
         unsafe {
-            seL4_CNode_Copy(
+            crate::cap::logged::cnode_copy(
                 dest_cptr,            // _service
                 dest_offset,          // index
                 seL4_WordBits as u8,  // depth
@@ -235,6 +295,18 @@ impl<Role: CNodeRole> LocalCap<WCNodeSlotsData<Role>> {
             _role: PhantomData,
         })
     }
+    /// Take back `slot`, which must be empty, if it is the one most
+    /// recently allocated from here. Any other slot is handed back in
+    /// the error, since these slots can only be returned in order.
+    pub(crate) fn free_last(&mut self, slot: CNodeSlot<Role>) -> Result<(), CNodeSlot<Role>> {
+        if slot.cptr != self.cptr || slot.cap_data.offset + 1 != self.cap_data.offset {
+            return Err(slot);
+        }
+        self.cap_data.offset -= 1;
+        self.cap_data.size += 1;
+        Ok(())
+    }
+
     /// Allocate `Count` and return them as strengthened cnode slots.
     pub fn alloc_strong<Count: Unsigned>(
         &mut self,
@@ -291,3 +363,108 @@ impl<Role: CNodeRole> LocalCap<WCNodeSlotsData<Role>> {
         })
     }
 }
+
+/// How many freed slots a `RecyclingCNode` can hold on to.
+const RECYCLED_SLOTS_CAPACITY: usize = 256;
+
+/// The error returned when a `RecyclingCNode` can't take a slot back.
+#[derive(Debug)]
+pub enum RecycleError<T> {
+    /// There's no room left to hold on to another freed slot. What
+    /// was to be freed is handed back untouched.
+    RecycledSlotsFull(T),
+    SeL4Error(SeL4Error),
+}
+
+/// A runtime-checked slot allocator for the local CNode which, unlike
+/// `WCNodeSlots`, takes slots back once the caps in them are deleted
+/// or moved away, and hands those out again before fresh ones.
+pub struct RecyclingCNode<'a> {
+    cnode: &'a LocalCap<LocalCNode>,
+    fresh_slots: WCNodeSlots,
+    recycled_offsets: ArrayVec<[usize; RECYCLED_SLOTS_CAPACITY]>,
+}
+
+impl<'a> RecyclingCNode<'a> {
+    /// `fresh_slots` must be slots of `cnode`.
+    pub fn new(cnode: &'a LocalCap<LocalCNode>, fresh_slots: WCNodeSlots) -> Self {
+        debug_assert_eq!(cnode.cptr, fresh_slots.cptr);
+        RecyclingCNode {
+            cnode,
+            fresh_slots,
+            recycled_offsets: ArrayVec::new(),
+        }
+    }
+
+    pub fn cnode(&self) -> &LocalCap<LocalCNode> {
+        self.cnode
+    }
+
+    /// The number of slots that can be allocated without any more
+    /// being freed.
+    pub fn available(&self) -> usize {
+        self.recycled_offsets.len() + self.fresh_slots.size()
+    }
+
+    pub fn alloc(&mut self) -> Result<LocalCNodeSlot, CNodeSlotsError> {
+        match self.recycled_offsets.pop() {
+            Some(offset) => Ok(Cap {
+                cptr: self.cnode.cptr,
+                cap_data: CNodeSlotsData {
+                    offset,
+                    _size: PhantomData,
+                    _role: PhantomData,
+                },
+                _role: PhantomData,
+            }),
+            None => self.fresh_slots.alloc_strong::<U1>(),
+        }
+    }
+
+    /// Return an empty slot to the pool, or hand it back if the pool
+    /// of recycled slots is full.
+    pub fn free(&mut self, slot: LocalCNodeSlot) -> Result<(), RecycleError<LocalCNodeSlot>> {
+        debug_assert_eq!(slot.cptr, self.cnode.cptr);
+        if self.recycled_offsets.is_full() {
+            return Err(RecycleError::RecycledSlotsFull(slot));
+        }
+        self.recycled_offsets.push(slot.cap_data.offset);
+        Ok(())
+    }
+
+    /// Delete a cap in this CNode and recycle its slot. If there's no
+    /// room to recycle it, the cap is handed back without having been
+    /// deleted.
+    pub fn delete<CT: CapType + Delible>(
+        &mut self,
+        cap: LocalCap<CT>,
+    ) -> Result<(), RecycleError<LocalCap<CT>>> {
+        if self.recycled_offsets.is_full() {
+            return Err(RecycleError::RecycledSlotsFull(cap));
+        }
+        let slot = cap
+            .delete_reclaiming_slot(self.cnode)
+            .map_err(RecycleError::SeL4Error)?;
+        self.recycled_offsets.push(slot.cap_data.offset);
+        Ok(())
+    }
+
+    /// Move a cap out of this CNode and recycle the slot it was in.
+    /// If there's no room to recycle it, the cap and destination slot
+    /// are handed back without the move having been made.
+    pub fn move_out<CT: CapType + Movable, DestRole: CNodeRole>(
+        &mut self,
+        cap: LocalCap<CT>,
+        dest_slot: CNodeSlot<DestRole>,
+    ) -> Result<Cap<CT, DestRole>, RecycleError<(LocalCap<CT>, CNodeSlot<DestRole>)>> {
+        if self.recycled_offsets.is_full() {
+            return Err(RecycleError::RecycledSlotsFull((cap, dest_slot)));
+        }
+        let src_offset = cap.cptr;
+        let moved = cap
+            .move_to_slot(self.cnode, dest_slot)
+            .map_err(RecycleError::SeL4Error)?;
+        self.recycled_offsets.push(src_offset);
+        Ok(moved)
+    }
+}
@@ -0,0 +1,36 @@
+use selfe_sys::*;
+
+use crate::cap::{CapType, LocalCap, PhantomCap, ThreadControlBlock};
+use crate::error::{ErrorExt, SeL4Error};
+
+/// The authority to move threads between scheduling domains. Only the
+/// root task is handed one, as `BootInfo::domain_set`.
+///
+/// Domains are scheduled on a fixed, kernel-compiled timetable, so
+/// threads in one domain can't starve those in another no matter
+/// their priorities. Kernels built with `KernelNumDomains = 1` only
+/// have domain 0, so this is only there when the kernel has more,
+/// under the `KernelMultipleDomains` cfg.
+#[derive(Debug)]
+pub struct DomainSet {}
+
+impl CapType for DomainSet {}
+
+impl PhantomCap for DomainSet {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+impl LocalCap<DomainSet> {
+    /// Move `tcb` into scheduling domain `domain`.
+    pub fn set_domain(
+        &self,
+        tcb: &mut LocalCap<ThreadControlBlock>,
+        domain: u8,
+    ) -> Result<(), SeL4Error> {
+        unsafe { seL4_DomainSet_Set(self.cptr, domain, tcb.cptr) }
+            .as_result()
+            .map_err(|e| SeL4Error::DomainSetSet(e))
+    }
+}
@@ -0,0 +1,42 @@
+use selfe_sys::*;
+
+use crate::cap::{
+    CapType, CopyAliasable, Delible, DirectRetype, Endpoint, Mintable, Movable, PhantomCap,
+};
+
+/// An endpoint set aside for faults. It's the same kernel object as an
+/// `Endpoint`, but nothing which sends or receives ordinary messages
+/// accepts one, so a fault source can't be wired to a channel which
+/// also carries application IPC by mistake.
+#[derive(Debug)]
+pub struct FaultEndpoint {}
+
+impl CapType for FaultEndpoint {}
+
+impl PhantomCap for FaultEndpoint {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+impl CopyAliasable for FaultEndpoint {
+    type CopyOutput = Self;
+}
+impl<'a> From<&'a FaultEndpoint> for FaultEndpoint {
+    fn from(_val: &'a FaultEndpoint) -> Self {
+        PhantomCap::phantom_instance()
+    }
+}
+
+impl Mintable for FaultEndpoint {}
+
+impl Delible for FaultEndpoint {}
+
+impl Movable for FaultEndpoint {}
+
+impl DirectRetype for FaultEndpoint {
+    type SizeBits = <Endpoint as DirectRetype>::SizeBits;
+    fn sel4_type_id() -> usize {
+        api_object_seL4_EndpointObject as usize
+    }
+}
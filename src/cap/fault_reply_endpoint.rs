@@ -1,5 +1,5 @@
 use crate::arch;
-use crate::cap::{CNodeSlotsData, Cap, CapType, LocalCNodeSlot, LocalCap};
+use crate::cap::{CNodeSlotsData, Cap, CapType, LocalCNodeSlot, LocalCap, ThreadControlBlock};
 use crate::error::{ErrorExt, SeL4Error};
 use core::marker::PhantomData;
 use selfe_sys::*;
@@ -19,6 +19,11 @@ impl CapType for FaultReplyEndpoint {}
 impl LocalCap<FaultReplyEndpoint> {
     /// Save the TCB reply capability into the given CNode slot. This expects to
     /// be Used only in response to a Fault.
+    ///
+    /// MCS kernels have no implicit reply capability to save; replies
+    /// go through reply objects instead, so this is only on non-MCS
+    /// kernels.
+    #[cfg(not(KernelIsMCS))]
     pub fn save_caller_and_create(
         slot: LocalCNodeSlot,
     ) -> Result<LocalCap<FaultReplyEndpoint>, SeL4Error> {
@@ -66,4 +71,37 @@ impl LocalCap<FaultReplyEndpoint> {
         // send, so we should be able to reuse its slot.
         self.to_slot()
     }
+
+    /// Move the faulted thread's program counter to `ip` and then
+    /// resume it there, consuming the cap and returning the slot it
+    /// was in. `tcb` must be the thread that faulted.
+    ///
+    /// The thread is only unblocked by the reply, so the registers are
+    /// written first, while it is still waiting on its fault.
+    pub fn restart_at(
+        self,
+        tcb: &mut LocalCap<ThreadControlBlock>,
+        ip: usize,
+    ) -> Result<LocalCNodeSlot, SeL4Error> {
+        let mut registers = tcb.read_registers()?;
+        registers.pc = ip;
+        tcb.write_registers(&registers)?;
+        Ok(self.resume_faulted_thread())
+    }
+
+    /// Throw away the reply capability without resuming the faulted
+    /// thread, which is then left blocked for good, and return the slot
+    /// it was in.
+    pub fn abandon(self) -> Result<LocalCNodeSlot, SeL4Error> {
+        unsafe {
+            crate::cap::logged::cnode_delete(
+                self.cap_data.original_slot_cptr, // _service
+                self.cptr,                        // index
+                arch::WordSize::U8,               // depth
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::CNodeDelete(e))?;
+        Ok(self.to_slot())
+    }
 }
@@ -0,0 +1,58 @@
+use core::marker::PhantomData;
+
+use typenum::Unsigned;
+
+use crate::cap::{page_state, CapType, CopyAliasable, LocalCap, Movable, PageState, PhantomCap};
+use crate::error::SeL4Error;
+
+/// A frame of `1 << SizeBits` bytes, bigger than a `Page`, which is
+/// mapped directly into the paging structure a page of its size would
+/// otherwise have in its place. Each architecture names the sizes it
+/// has, e.g. `LargePage`.
+#[derive(Clone, Debug)]
+pub struct Frame<SizeBits: Unsigned, State: PageState> {
+    pub(crate) state: State,
+    pub(crate) _size_bits: PhantomData<SizeBits>,
+}
+
+impl<SizeBits: Unsigned, State: PageState> CapType for Frame<SizeBits, State> {}
+
+impl<SizeBits: Unsigned, State: PageState> CopyAliasable for Frame<SizeBits, State> {
+    type CopyOutput = Frame<SizeBits, page_state::Unmapped>;
+}
+impl<SizeBits: Unsigned, State: PageState> Movable for Frame<SizeBits, State> {}
+
+impl<'a, SizeBits: Unsigned, State: PageState> From<&'a Frame<SizeBits, State>>
+    for Frame<SizeBits, page_state::Unmapped>
+{
+    fn from(_val: &'a Frame<SizeBits, State>) -> Self {
+        Frame::phantom_instance()
+    }
+}
+
+impl<SizeBits: Unsigned> PhantomCap for Frame<SizeBits, page_state::Unmapped> {
+    fn phantom_instance() -> Self {
+        Frame {
+            state: page_state::Unmapped {},
+            _size_bits: PhantomData,
+        }
+    }
+}
+
+impl<SizeBits: Unsigned, State: PageState> LocalCap<Frame<SizeBits, State>> {
+    /// The physical address of the frame's first byte.
+    pub fn paddr(&self) -> Result<usize, SeL4Error> {
+        crate::arch::frame_paddr(self.cptr)
+    }
+}
+
+impl<SizeBits: Unsigned> LocalCap<Frame<SizeBits, page_state::Mapped>> {
+    pub fn vaddr(&self) -> usize {
+        self.cap_data.state.vaddr
+    }
+
+    /// Clean and invalidate the data cache for the whole frame.
+    pub fn flush(&self) -> Result<(), SeL4Error> {
+        unsafe { crate::arch::flush_granule(self.cptr, 1 << SizeBits::USIZE) }
+    }
+}
@@ -0,0 +1,56 @@
+use crate::arch::cap::ArchCapKind;
+#[cfg(KernelDebugBuild)]
+use crate::cap::{CapType, LocalCap};
+
+/// The kind of capability the kernel reports as occupying a slot.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CapKind {
+    Untyped,
+    Endpoint,
+    Notification,
+    Reply,
+    CNode,
+    Thread,
+    IRQControl,
+    IRQHandler,
+    Zombie,
+    Domain,
+    Arch(ArchCapKind),
+    /// A tag this library doesn't know about.
+    Unknown(u32),
+}
+
+#[cfg(KernelDebugBuild)]
+impl CapKind {
+    /// Interpret one of the kernel's capability tags, as listed in
+    /// `arch::cap::cap_tag`.
+    fn from_tag(tag: u32) -> Option<CapKind> {
+        use crate::arch::cap::cap_tag::*;
+        Some(match tag {
+            NULL_CAP => return None,
+            UNTYPED_CAP => CapKind::Untyped,
+            ENDPOINT_CAP => CapKind::Endpoint,
+            NOTIFICATION_CAP => CapKind::Notification,
+            REPLY_CAP => CapKind::Reply,
+            CNODE_CAP => CapKind::CNode,
+            THREAD_CAP => CapKind::Thread,
+            IRQ_CONTROL_CAP => CapKind::IRQControl,
+            IRQ_HANDLER_CAP => CapKind::IRQHandler,
+            ZOMBIE_CAP => CapKind::Zombie,
+            DOMAIN_CAP => CapKind::Domain,
+            _ => ArchCapKind::from_tag(tag)
+                .map(CapKind::Arch)
+                .unwrap_or(CapKind::Unknown(tag)),
+        })
+    }
+}
+
+/// Only available when the kernel is a debug build.
+#[cfg(KernelDebugBuild)]
+impl<CT: CapType> LocalCap<CT> {
+    /// Ask the kernel what is actually in this cap's slot. Returns
+    /// `None` if the slot is empty.
+    pub fn identify(&self) -> Option<CapKind> {
+        CapKind::from_tag(unsafe { selfe_sys::seL4_DebugCapIdentify(self.cptr) })
+    }
+}
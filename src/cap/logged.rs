@@ -0,0 +1,178 @@
+//! The kernel calls that make, copy, move and delete caps. With the
+//! `capdl` feature, each one that succeeds is also appended to the log
+//! `capdl::write_spec` describes; without it they're plain forwarding
+//! calls.
+
+use selfe_sys::*;
+
+#[cfg(feature = "capdl")]
+use crate::capdl::{record, CapRecord};
+
+/// `seL4_Untyped_Retype`, logged.
+pub(crate) unsafe fn untyped_retype(
+    service: seL4_Untyped,
+    type_: seL4_Word,
+    size_bits: seL4_Word,
+    root: seL4_CNode,
+    node_index: seL4_Word,
+    node_depth: seL4_Word,
+    node_offset: seL4_Word,
+    num_objects: seL4_Word,
+) -> seL4_Error {
+    let err = seL4_Untyped_Retype(
+        service,
+        type_,
+        size_bits,
+        root,
+        node_index,
+        node_depth,
+        node_offset,
+        num_objects,
+    );
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Retype {
+                untyped: service as usize,
+                object_type: type_ as usize,
+                size_bits: size_bits as usize,
+                dest_root: root as usize,
+                dest_offset: node_offset as usize,
+                count: num_objects as usize,
+            });
+        }
+    }
+    err
+}
+
+/// `seL4_CNode_Copy`, logged.
+pub(crate) unsafe fn cnode_copy(
+    service: seL4_CNode,
+    dest_index: seL4_Word,
+    dest_depth: u8,
+    src_root: seL4_CNode,
+    src_index: seL4_Word,
+    src_depth: u8,
+    rights: seL4_CapRights_t,
+) -> seL4_Error {
+    #[cfg(feature = "capdl")]
+    let rights_word = rights.words[0] as usize;
+    let err = seL4_CNode_Copy(
+        service, dest_index, dest_depth, src_root, src_index, src_depth, rights,
+    );
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Derive {
+                src_root: src_root as usize,
+                src_index: src_index as usize,
+                dest_root: service as usize,
+                dest_index: dest_index as usize,
+                rights: rights_word,
+                badge: 0,
+            });
+        }
+    }
+    err
+}
+
+/// `seL4_CNode_Mint`, logged.
+pub(crate) unsafe fn cnode_mint(
+    service: seL4_CNode,
+    dest_index: seL4_Word,
+    dest_depth: u8,
+    src_root: seL4_CNode,
+    src_index: seL4_Word,
+    src_depth: u8,
+    rights: seL4_CapRights_t,
+    badge: seL4_Word,
+) -> seL4_Error {
+    #[cfg(feature = "capdl")]
+    let rights_word = rights.words[0] as usize;
+    let err = seL4_CNode_Mint(
+        service, dest_index, dest_depth, src_root, src_index, src_depth, rights, badge,
+    );
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Derive {
+                src_root: src_root as usize,
+                src_index: src_index as usize,
+                dest_root: service as usize,
+                dest_index: dest_index as usize,
+                rights: rights_word,
+                badge: badge as usize,
+            });
+        }
+    }
+    err
+}
+
+/// `seL4_CNode_Move`, logged.
+pub(crate) unsafe fn cnode_move(
+    service: seL4_CNode,
+    dest_index: seL4_Word,
+    dest_depth: u8,
+    src_root: seL4_CNode,
+    src_index: seL4_Word,
+    src_depth: u8,
+) -> seL4_Error {
+    let err = seL4_CNode_Move(
+        service, dest_index, dest_depth, src_root, src_index, src_depth,
+    );
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Move {
+                src_root: src_root as usize,
+                src_index: src_index as usize,
+                dest_root: service as usize,
+                dest_index: dest_index as usize,
+            });
+        }
+    }
+    err
+}
+
+/// `seL4_CNode_Mutate`, logged as the move it is. The new guard isn't
+/// recorded.
+pub(crate) unsafe fn cnode_mutate(
+    service: seL4_CNode,
+    dest_index: seL4_Word,
+    dest_depth: u8,
+    src_root: seL4_CNode,
+    src_index: seL4_Word,
+    src_depth: u8,
+    badge: seL4_Word,
+) -> seL4_Error {
+    let err = seL4_CNode_Mutate(
+        service, dest_index, dest_depth, src_root, src_index, src_depth, badge,
+    );
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Move {
+                src_root: src_root as usize,
+                src_index: src_index as usize,
+                dest_root: service as usize,
+                dest_index: dest_index as usize,
+            });
+        }
+    }
+    err
+}
+
+/// `seL4_CNode_Delete`, logged.
+pub(crate) unsafe fn cnode_delete(service: seL4_CNode, index: seL4_Word, depth: u8) -> seL4_Error {
+    let err = seL4_CNode_Delete(service, index, depth);
+    #[cfg(feature = "capdl")]
+    {
+        if err == seL4_Error_seL4_NoError {
+            record(CapRecord::Delete {
+                root: service as usize,
+                index: index as usize,
+            });
+        }
+    }
+    err
+}
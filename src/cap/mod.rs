@@ -1,9 +1,11 @@
 use core::marker::PhantomData;
+use core::ops::Deref;
 
 use selfe_sys::*;
 use typenum::*;
 
 use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::AddressSpaceIndependent;
 use crate::userland::CapRights;
 
 mod asid;
@@ -11,13 +13,21 @@ mod asid_control;
 mod asid_pool;
 mod badge;
 mod cnode;
+#[cfg(KernelMultipleDomains)]
+mod domain;
 mod endpoint;
+mod fault_endpoint;
 mod fault_reply_endpoint;
+mod frame;
+mod identify;
 mod irq_control;
 pub mod irq_handler;
+pub(crate) mod logged;
 mod notification;
 mod page;
 mod page_table;
+#[cfg(KernelIsMCS)]
+mod sched_context;
 mod tcb;
 mod untyped;
 
@@ -26,13 +36,20 @@ pub use asid_control::*;
 pub use asid_pool::*;
 pub use badge::*;
 pub use cnode::*;
+#[cfg(KernelMultipleDomains)]
+pub use domain::*;
 pub use endpoint::*;
+pub use fault_endpoint::*;
 pub use fault_reply_endpoint::*;
+pub use frame::*;
+pub use identify::*;
 pub use irq_control::*;
 pub use irq_handler::*;
 pub use notification::*;
 pub use page::*;
 pub use page_table::*;
+#[cfg(KernelIsMCS)]
+pub use sched_context::*;
 pub use tcb::*;
 pub use untyped::*;
 
@@ -56,6 +73,10 @@ pub mod role {
 /// this type of capability can be generated directly
 /// from retyping an Untyped
 pub trait DirectRetype {
+    /// The size of the object, as a power of two in bytes.
+    /// `Untyped::retype` requires the untyped to be at least this
+    /// big, so retyping from one that is too small is a compile
+    /// error rather than a kernel one.
     type SizeBits: Unsigned;
     fn sel4_type_id() -> usize;
 }
@@ -101,6 +122,9 @@ pub struct Cap<CT: CapType, Role: CNodeRole> {
     pub(crate) _role: PhantomData<Role>,
 }
 
+// A cap is its cptr, and which CSpace that's good in is up to `Role`.
+unsafe impl<CT: CapType, Role: CNodeRole> AddressSpaceIndependent for Cap<CT, Role> {}
+
 pub trait CapType {}
 
 pub type LocalCap<T> = Cap<T, role::Local>;
@@ -110,9 +134,18 @@ impl<CT: CapType, Role: CNodeRole> Cap<CT, Role>
 where
     CT: PhantomCap,
 {
-    // TODO most of this should only happen in the bootstrap adapter
-    // TODO - Make even more private!
-    pub(crate) fn wrap_cptr(cptr: usize) -> Cap<CT, Role> {
+    /// Take on a cap which ferros didn't make, e.g. one placed at a
+    /// known cptr by a capDL loader or some other non-ferros setup.
+    ///
+    /// Only cap types which carry no data of their own can be wrapped
+    /// this way; the likes of a `CNode`'s radix or a mapped page's
+    /// address can't be recovered from a cptr.
+    ///
+    /// This is unsafe because nothing checks that `cptr` is good in
+    /// `Role`'s CSpace, that it holds a cap of type `CT`, or that
+    /// nothing else claims it, and all of ferros's type-level
+    /// bookkeeping rests on those.
+    pub unsafe fn wrap_cptr(cptr: usize) -> Cap<CT, Role> {
         Cap {
             cptr: cptr,
             cap_data: PhantomCap::phantom_instance(),
@@ -182,6 +215,22 @@ impl<CT: CapType, Role: CNodeRole, Slots: Unsigned> CapRange<CT, Role, Slots> {
         Slots::USIZE
     }
 
+    /// The `index`th cap in the range, if the range is that long.
+    pub fn get(&self, index: usize) -> Option<CapRangeEntry<'_, CT, Role>>
+    where
+        CT: CapRangeDataReconstruction,
+    {
+        range_entry(self.start_cptr, &self.start_cap_data, self.len(), index)
+    }
+
+    /// Every cap in the range along with its index, in slot order.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, CapRangeEntry<'_, CT, Role>)> + '_
+    where
+        CT: CapRangeDataReconstruction,
+    {
+        range_entries(self.start_cptr, &self.start_cap_data, self.len())
+    }
+
     pub fn weaken(self) -> WeakCapRange<CT, Role> {
         let len = self.len();
         WeakCapRange::new(self.start_cptr, self.start_cap_data, len)
@@ -199,13 +248,7 @@ impl<CT: CapType + CopyAliasable, Role: CNodeRole, Slots: Unsigned> CapRange<CT,
         CT: CapRangeDataReconstruction,
     {
         let copied_to_start_cptr = slots.cap_data.offset;
-        // N.B. Conside replacing with a general purpose CapRange::iter(&self) that returns references to constructed caps
-        for (offset, slot) in (0..Slots::USIZE).zip(slots.iter()) {
-            let cap: Cap<CT, Role> = Cap {
-                cptr: self.start_cptr + offset,
-                _role: PhantomData,
-                cap_data: CapRangeDataReconstruction::reconstruct(offset, &self.start_cap_data),
-            };
+        for ((_, cap), slot) in self.iter_indexed().zip(slots.iter()) {
             cap.copy(cnode, slot, rights)?;
         }
         Ok(CapRange {
@@ -217,6 +260,109 @@ impl<CT: CapType + CopyAliasable, Role: CNodeRole, Slots: Unsigned> CapRange<CT,
     }
 }
 
+impl<CT: CapType, Role: CNodeRole, Slots: Unsigned> CapRange<CT, Role, Slots> {
+    /// Mint every capability in the range into `slots`, all with the
+    /// same rights and badge. If any mint fails, the capabilities
+    /// minted so far are deleted before the error is returned.
+    pub fn mint_range<DestRole: CNodeRole>(
+        &self,
+        cnode: &LocalCap<CNode<Role>>,
+        slots: CNodeSlots<Slots, DestRole>,
+        rights: CapRights,
+        badge: Badge,
+    ) -> Result<CapRange<CT::CopyOutput, DestRole, Slots>, SeL4Error>
+    where
+        CT: Mintable,
+    {
+        let (dest_cptr, dest_start_offset, _) = slots.elim();
+        for index in 0..Slots::USIZE {
+            let result = unsafe {
+                crate::cap::logged::cnode_mint(
+                    dest_cptr,                 // _service
+                    dest_start_offset + index, // dest index
+                    seL4_WordBits as u8,       // dest depth
+                    // Since cnode is restricted to Root, the cptr must
+                    // actually be the slot index
+                    cnode.cptr,              // src_root
+                    self.start_cptr + index, // src_index
+                    seL4_WordBits as u8,     // src_depth
+                    rights.into(),           // rights
+                    badge.into(),            // badge
+                )
+            }
+            .as_result();
+            if let Err(e) = result {
+                for minted_index in (0..index).rev() {
+                    let _err = unsafe {
+                        crate::cap::logged::cnode_delete(
+                            dest_cptr,                        // _service
+                            dest_start_offset + minted_index, // index
+                            seL4_WordBits as u8,              // depth
+                        )
+                    };
+                }
+                return Err(SeL4Error::CNodeMint(e));
+            }
+        }
+        Ok(CapRange {
+            start_cptr: dest_start_offset,
+            start_cap_data: From::from(&self.start_cap_data),
+            _role: PhantomData,
+            _slots: PhantomData,
+        })
+    }
+
+    /// Move every capability in the range into `slots`. If any move
+    /// fails, the capabilities moved so far are moved back to where
+    /// they came from before the error is returned.
+    pub fn move_range<DestRole: CNodeRole>(
+        self,
+        cnode: &LocalCap<CNode<Role>>,
+        slots: CNodeSlots<Slots, DestRole>,
+    ) -> Result<CapRange<CT, DestRole, Slots>, SeL4Error>
+    where
+        CT: Movable,
+    {
+        let (dest_cptr, dest_start_offset, _) = slots.elim();
+        for index in 0..Slots::USIZE {
+            let result = unsafe {
+                crate::cap::logged::cnode_move(
+                    dest_cptr,                 // _service
+                    dest_start_offset + index, // index
+                    seL4_WordBits as u8,       // depth
+                    // Since cnode is restricted to Root, the cptr must
+                    // actually be the slot index
+                    cnode.cptr,              // src_root
+                    self.start_cptr + index, // src_index
+                    seL4_WordBits as u8,     // src_depth
+                )
+            }
+            .as_result();
+            if let Err(e) = result {
+                for moved_index in (0..index).rev() {
+                    let _err = unsafe {
+                        crate::cap::logged::cnode_move(
+                            cnode.cptr,                      // _service
+                            self.start_cptr + moved_index,   // index
+                            seL4_WordBits as u8,             // depth
+                            dest_cptr,                       // src_root
+                            dest_start_offset + moved_index, // src_index
+                            seL4_WordBits as u8,             // src_depth
+                        )
+                    };
+                }
+                return Err(SeL4Error::CNodeMove(e));
+            }
+        }
+        Ok(CapRange {
+            start_cptr: dest_start_offset,
+            start_cap_data: self.start_cap_data,
+            _role: PhantomData,
+            _slots: PhantomData,
+        })
+    }
+}
+
 pub struct WeakCapRange<CT: CapType, Role: CNodeRole> {
     pub(crate) start_cptr: usize,
     pub(crate) start_cap_data: CT,
@@ -290,6 +436,66 @@ impl<CT: CapType, Role: CNodeRole> WeakCapRange<CT, Role> {
     pub(crate) fn len(&self) -> usize {
         self.len
     }
+
+    /// The `index`th cap in the range, if the range is that long.
+    pub fn get(&self, index: usize) -> Option<CapRangeEntry<'_, CT, Role>>
+    where
+        CT: CapRangeDataReconstruction,
+    {
+        range_entry(self.start_cptr, &self.start_cap_data, self.len(), index)
+    }
+
+    /// Every cap in the range along with its index, in slot order.
+    pub fn iter_indexed(&self) -> impl Iterator<Item = (usize, CapRangeEntry<'_, CT, Role>)> + '_
+    where
+        CT: CapRangeDataReconstruction,
+    {
+        range_entries(self.start_cptr, &self.start_cap_data, self.len())
+    }
+}
+
+/// One of the caps in a `CapRange` or `WeakCapRange`. It derefs to
+/// the cap, which remains owned by the range.
+pub struct CapRangeEntry<'a, CT: CapType, Role: CNodeRole> {
+    cap: Cap<CT, Role>,
+    _range: PhantomData<&'a ()>,
+}
+
+impl<'a, CT: CapType, Role: CNodeRole> Deref for CapRangeEntry<'a, CT, Role> {
+    type Target = Cap<CT, Role>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cap
+    }
+}
+
+fn range_entry<'a, CT: CapType + CapRangeDataReconstruction, Role: CNodeRole>(
+    start_cptr: usize,
+    start_cap_data: &'a CT,
+    len: usize,
+    index: usize,
+) -> Option<CapRangeEntry<'a, CT, Role>> {
+    if index >= len {
+        return None;
+    }
+    Some(CapRangeEntry {
+        cap: Cap {
+            cptr: start_cptr + index,
+            _role: PhantomData,
+            cap_data: CT::reconstruct(index, start_cap_data),
+        },
+        _range: PhantomData,
+    })
+}
+
+fn range_entries<'a, CT: CapType + CapRangeDataReconstruction, Role: CNodeRole>(
+    start_cptr: usize,
+    start_cap_data: &'a CT,
+    len: usize,
+) -> impl Iterator<Item = (usize, CapRangeEntry<'a, CT, Role>)> + 'a {
+    (0..len).filter_map(move |index| {
+        range_entry(start_cptr, start_cap_data, len, index).map(|entry| (index, entry))
+    })
 }
 
 /// A helper trait for CapRange and WeakCapRange to assist in iteration.
@@ -331,7 +537,7 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
     ) -> Result<usize, SeL4Error> {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
         match unsafe {
-            seL4_CNode_Copy(
+            crate::cap::logged::cnode_copy(
                 dest_cptr,           // _service
                 dest_offset,         // index
                 seL4_WordBits as u8, // depth
@@ -366,7 +572,7 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
     {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
         unsafe {
-            seL4_CNode_Mint(
+            crate::cap::logged::cnode_mint(
                 dest_cptr,           // _service
                 dest_offset,         // dest index
                 seL4_WordBits as u8, // dest depth
@@ -404,7 +610,7 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
     {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
         unsafe {
-            seL4_CNode_Mint(
+            crate::cap::logged::cnode_mint(
                 dest_cptr,           // _service
                 dest_offset,         // dest index
                 seL4_WordBits as u8, // dest depth
@@ -440,7 +646,7 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
     {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
         unsafe {
-            seL4_CNode_Mint(
+            crate::cap::logged::cnode_mint(
                 dest_cptr,           // _service
                 dest_offset,         // index
                 seL4_WordBits as u8, // depth
@@ -462,7 +668,12 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
         })
     }
 
-    /// Migrate a capability from one CNode slot to another.
+    /// Migrate a capability from one CNode slot to another, which may
+    /// be in a different CNode.
+    ///
+    /// Unlike a copy followed by a delete, this is a single syscall
+    /// and leaves no derived capability behind, so a later revoke of
+    /// the original's parent still reaches the moved capability.
     pub fn move_to_slot<DestRole: CNodeRole>(
         self,
         src_cnode: &LocalCap<LocalCNode>,
@@ -473,7 +684,7 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
     {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
         unsafe {
-            seL4_CNode_Move(
+            crate::cap::logged::cnode_move(
                 dest_cptr,           // _service
                 dest_offset,         // index
                 seL4_WordBits as u8, // depth
@@ -495,18 +706,53 @@ impl<Role: CNodeRole, CT: CapType> Cap<CT, Role> {
 
     /// Delete a capability
     pub fn delete(self, parent_cnode: &LocalCap<LocalCNode>) -> Result<(), SeL4Error>
+    where
+        CT: Delible,
+    {
+        self.delete_reclaiming_slot(parent_cnode).map(|_slot| ())
+    }
+
+    /// Delete a capability, handing back the slot it occupied so that
+    /// it may be reused.
+    pub fn delete_reclaiming_slot(
+        self,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<LocalCNodeSlot, SeL4Error>
     where
         CT: Delible,
     {
         unsafe {
-            seL4_CNode_Delete(
+            crate::cap::logged::cnode_delete(
                 parent_cnode.cptr,   // _service
                 self.cptr,           // index
                 seL4_WordBits as u8, // depth
             )
         }
         .as_result()
-        .map_err(|e| SeL4Error::CNodeDelete(e))
+        .map_err(|e| SeL4Error::CNodeDelete(e))?;
+        Ok(Cap {
+            cptr: parent_cnode.cptr,
+            cap_data: CNodeSlotsData {
+                offset: self.cptr,
+                _size: PhantomData,
+                _role: PhantomData,
+            },
+            _role: PhantomData,
+        })
+    }
+
+    /// Delete every capability derived from this one, leaving this one
+    /// in place.
+    pub fn revoke(&self, parent_cnode: &LocalCap<LocalCNode>) -> Result<(), SeL4Error> {
+        unsafe {
+            seL4_CNode_Revoke(
+                parent_cnode.cptr,   // _service
+                self.cptr,           // index
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::CNodeRevoke(e))
     }
 }
 
@@ -524,6 +770,7 @@ mod private {
     impl SealedCapType for ThreadControlBlock {}
     impl SealedCapType for ThreadPriorityAuthority {}
     impl SealedCapType for Endpoint {}
+    impl SealedCapType for FaultEndpoint {}
     impl SealedCapType for FaultReplyEndpoint {}
     impl SealedCapType for Notification {}
     impl<FreeSlots: Unsigned> SealedCapType for ASIDPool<FreeSlots> {}
@@ -533,6 +780,7 @@ mod private {
     {
     }
     impl<State: PageState> SealedCapType for Page<State> {}
+    impl<SizeBits: Unsigned, State: PageState> SealedCapType for Frame<SizeBits, State> {}
 
     /*
     Cross Arch things:
@@ -577,6 +825,5 @@ mod private {
         impl<FreePools: Unsigned> super::SealedCapType for ASIDControl<FreePools> {}
         impl super::SealedCapType for UnassignedASID {}
         impl super::SealedCapType for AssignedASID {}
-
     }
 }
@@ -1,6 +1,8 @@
 use selfe_sys::*;
 
-use crate::cap::{Badge, CapType, CopyAliasable, DirectRetype, LocalCap, Mintable, PhantomCap};
+use crate::cap::{
+    Badge, CapType, CopyAliasable, Delible, DirectRetype, LocalCap, Mintable, Movable, PhantomCap,
+};
 
 #[derive(Debug)]
 pub struct Notification {}
@@ -24,6 +26,10 @@ impl<'a> From<&'a Notification> for Notification {
 
 impl Mintable for Notification {}
 
+impl Delible for Notification {}
+
+impl Movable for Notification {}
+
 impl DirectRetype for Notification {
     type SizeBits = crate::arch::NotificationBits;
     fn sel4_type_id() -> usize {
@@ -37,6 +43,10 @@ impl LocalCap<Notification> {
     }
 
     /// Blocking wait on a notification
+    ///
+    /// The badge returned is the OR of the badges of every signal
+    /// since the last wait or poll, not just the latest; see
+    /// `Badge::bits` for pulling the sources apart.
     pub fn wait(&self) -> Badge {
         let mut sender_badge: usize = 0;
         unsafe {
@@ -44,4 +54,19 @@ impl LocalCap<Notification> {
         };
         Badge::from(sender_badge)
     }
+
+    /// Like `wait`, but returns straight away, with `None` if nothing
+    /// has signalled. A signal through an unbadged cap carries badge
+    /// zero, so it looks the same as no signal at all here.
+    pub fn poll(&self) -> Option<Badge> {
+        let mut sender_badge: usize = 0;
+        unsafe {
+            seL4_Poll(self.cptr, &mut sender_badge as *mut usize);
+        };
+        if sender_badge == 0 {
+            None
+        } else {
+            Some(Badge::from(sender_badge))
+        }
+    }
 }
@@ -1,7 +1,9 @@
 use crate::arch::PageBytes;
 use crate::cap::{
-    CNodeRole, Cap, CapRangeDataReconstruction, CapType, CopyAliasable, InternalASID, Movable,
+    CNodeRole, Cap, CapRangeDataReconstruction, CapType, CopyAliasable, InternalASID, LocalCap,
+    Movable,
 };
+use crate::error::SeL4Error;
 use crate::userland::CapRights;
 use typenum::Unsigned;
 
@@ -74,6 +76,13 @@ impl<State: PageState> CapRangeDataReconstruction for Page<State> {
     }
 }
 
+impl<T: PageState> LocalCap<Page<T>> {
+    /// The physical address of the frame behind this page.
+    pub fn paddr(&self) -> Result<usize, SeL4Error> {
+        crate::arch::frame_paddr(self.cptr)
+    }
+}
+
 impl<CapRole: CNodeRole> Cap<Page<page_state::Mapped>, CapRole> {
     pub fn vaddr(&self) -> usize {
         self.cap_data.state.vaddr
@@ -1,7 +1,7 @@
 use selfe_sys::*;
 
 use crate::arch::PagingRoot;
-use crate::cap::{page_state, CapType, LocalCap, Page, PhantomCap};
+use crate::cap::{page_state, CapType, Delible, LocalCap, Page, PhantomCap};
 use crate::error::{KernelError, SeL4Error};
 use crate::userland::CapRights;
 use crate::vspace::{MappingError, Maps};
@@ -15,6 +15,8 @@ impl PhantomCap for PageTable {
         PageTable {}
     }
 }
+impl Delible for PageTable {}
+
 impl Maps<Page<page_state::Unmapped>> for PageTable {
     fn map_granule(
         &mut self,
@@ -28,6 +30,15 @@ impl Maps<Page<page_state::Unmapped>> for PageTable {
             match unsafe { page.unchecked_page_map(addr, root, rights, vm_attributes) } {
                 Ok(_) => Ok(()),
                 Err(SeL4Error::PageMap(KernelError::FailedLookup)) => Err(MappingError::Overflow),
+                // What the kernel says of a frame cap which is already
+                // mapped: at another address in this address space, or
+                // in another one altogether. Neither is conclusive, as
+                // an address outside user space or a paging root with
+                // no ASID are turned away the same ways.
+                Err(e @ SeL4Error::PageMap(KernelError::InvalidArgument))
+                | Err(e @ SeL4Error::PageMap(KernelError::InvalidCapability)) => {
+                    Err(MappingError::PageMaybeAlreadyMapped(e))
+                }
                 Err(e) => Err(MappingError::PageMapFailure(e)),
             }
         } else {
@@ -0,0 +1,146 @@
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::cap::{
+    memory_kind, role, CNodeSlot, Cap, CapType, FaultEndpoint, LocalCap, PhantomCap,
+    ThreadControlBlock, ThreadPriorityAuthority, Untyped,
+};
+use crate::error::{ErrorExt, SeL4Error};
+
+/// The size of a scheduling context with no extra refills; the
+/// kernel's `seL4_MinSchedContextBits`.
+pub type SchedContextBits = U8;
+
+/// A budget of CPU time, replenished every period, which a thread on
+/// an MCS kernel needs bound to it before it will run.
+#[derive(Debug)]
+pub struct SchedContext {}
+
+impl CapType for SchedContext {}
+
+impl PhantomCap for SchedContext {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+/// The authority to set the budget and period of scheduling contexts
+/// on one core. The root task gets the one for the boot core as
+/// `BootInfo::sched_control`.
+#[derive(Debug)]
+pub struct SchedControl {}
+
+impl CapType for SchedControl {}
+
+impl PhantomCap for SchedControl {
+    fn phantom_instance() -> Self {
+        Self {}
+    }
+}
+
+impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
+    /// Scheduling contexts are sized objects, so they can't go through
+    /// `retype` and its `DirectRetype` bound.
+    pub fn retype_sched_context(
+        self,
+        dest_slot: CNodeSlot<role::Local>,
+    ) -> Result<LocalCap<SchedContext>, SeL4Error>
+    where
+        BitSize: IsGreaterOrEqual<SchedContextBits, Output = True>,
+    {
+        let (dest_cptr, dest_offset, _) = dest_slot.elim();
+
+        unsafe {
+            crate::cap::logged::untyped_retype(
+                self.cptr,                                   // _service
+                api_object_seL4_SchedContextObject as usize, // type
+                SchedContextBits::USIZE,                     // size_bits
+                dest_cptr,                                   // root
+                0,                                           // index
+                0,                                           // depth
+                dest_offset,                                 // offset
+                1,                                           // num_objects
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::UntypedRetype(e))?;
+
+        Ok(Cap {
+            cptr: dest_offset,
+            cap_data: PhantomCap::phantom_instance(),
+            _role: PhantomData,
+        })
+    }
+}
+
+impl LocalCap<SchedControl> {
+    /// Give `sched_context` a budget of `budget_us` microseconds of CPU
+    /// time every `period_us` microseconds. A budget equal to the
+    /// period makes for a round-robin thread with no temporal limit.
+    pub fn configure(
+        &self,
+        sched_context: &mut LocalCap<SchedContext>,
+        budget_us: u64,
+        period_us: u64,
+    ) -> Result<(), SeL4Error> {
+        unsafe {
+            seL4_SchedControl_Configure(
+                self.cptr,
+                sched_context.cptr,
+                budget_us,
+                period_us,
+                0, // extra_refills
+                0, // badge
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::SchedControlConfigure(e))
+    }
+}
+
+impl LocalCap<ThreadControlBlock> {
+    /// On an MCS kernel, the priority, maximum controlled priority and
+    /// scheduling context are set together here rather than through
+    /// `configure` and `set_priority`.
+    ///
+    /// The kernel copies a fault endpoint from the caller's CSpace, so
+    /// it's given as a local cap, badged as the handler expects. With
+    /// `None`, whatever fault endpoint the thread already has is left
+    /// in place.
+    pub fn set_sched_params(
+        &mut self,
+        tpa: &LocalCap<ThreadPriorityAuthority>,
+        mcp: usize,
+        priority: usize,
+        sched_context: &LocalCap<SchedContext>,
+        fault_endpoint: Option<&LocalCap<FaultEndpoint>>,
+    ) -> Result<(), SeL4Error> {
+        match fault_endpoint {
+            Some(fault_endpoint) => unsafe {
+                seL4_TCB_SetSchedParams(
+                    self.cptr,
+                    tpa.cptr,
+                    mcp,
+                    priority,
+                    sched_context.cptr,
+                    fault_endpoint.cptr,
+                )
+            }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBSetSchedParams(e)),
+            // `SetSchedParams` would replace the fault endpoint with a
+            // null one, so the parts are set one at a time instead.
+            None => {
+                unsafe { seL4_TCB_SetMCPriority(self.cptr, tpa.cptr, mcp) }
+                    .as_result()
+                    .map_err(|e| SeL4Error::TCBSetMCPriority(e))?;
+                self.set_priority(tpa, priority)?;
+                unsafe { seL4_SchedContext_Bind(sched_context.cptr, self.cptr) }
+                    .as_result()
+                    .map_err(|e| SeL4Error::SchedContextBind(e))
+            }
+        }
+    }
+}
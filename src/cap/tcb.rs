@@ -1,8 +1,11 @@
 use selfe_sys::*;
 
 use crate::cap::{
-    page_state, role, CapType, ChildCNode, CopyAliasable, DirectRetype, LocalCap, Page, PhantomCap,
+    page_state, role, CapType, ChildCNode, CopyAliasable, Delible, DirectRetype, LocalCap, Page,
+    PhantomCap,
 };
+#[cfg(KernelIsMCS)]
+use crate::error::KernelError;
 use crate::error::{ErrorExt, SeL4Error};
 use crate::userland::FaultSource;
 
@@ -27,6 +30,8 @@ impl DirectRetype for ThreadControlBlock {
 impl CopyAliasable for ThreadControlBlock {
     type CopyOutput = Self;
 }
+
+impl Delible for ThreadControlBlock {}
 impl<'a> From<&'a ThreadControlBlock> for ThreadControlBlock {
     fn from(_val: &'a ThreadControlBlock) -> Self {
         PhantomCap::phantom_instance()
@@ -67,6 +72,14 @@ impl LocalCap<ThreadControlBlock> {
         unsafe { core::mem::transmute(self) }
     }
 
+    /// Set this TCB's CSpace, VSpace, IPC buffer and, on non-MCS
+    /// kernels, fault endpoint.
+    ///
+    /// An MCS kernel looks a thread's fault endpoint up in the
+    /// caller's CSpace, where a `FaultSource<role::Child>` doesn't
+    /// live, so there one has to be installed from a local cap with
+    /// `set_sched_params` instead; passing `Some` here fails with
+    /// `IllegalOperation` rather than picking up the wrong cap.
     pub fn configure(
         &mut self,
         cspace_root: LocalCap<ChildCNode>,
@@ -90,7 +103,8 @@ impl LocalCap<ThreadControlBlock> {
             (seL4_CapNull as usize, 0)
         };
 
-        unsafe {
+        #[cfg(not(KernelIsMCS))]
+        let result = unsafe {
             seL4_TCB_Configure(
                 self.cptr,
                 fault_source.map_or(seL4_CapNull as usize, |source| source.endpoint.cptr), // fault_ep.cptr,
@@ -101,9 +115,27 @@ impl LocalCap<ThreadControlBlock> {
                 buffer_vaddr,          // buffer address
                 buffer_cap,            // bufferFrame capability
             )
-        }
-        .as_result()
-        .map_err(|e| SeL4Error::TCBConfigure(e))
+        };
+
+        #[cfg(KernelIsMCS)]
+        let result = {
+            if fault_source.is_some() {
+                return Err(SeL4Error::TCBConfigure(KernelError::IllegalOperation));
+            }
+            unsafe {
+                seL4_TCB_Configure(
+                    self.cptr,
+                    cspace_root.cptr,
+                    cspace_root_data,
+                    virtual_address_space_root.cptr,
+                    seL4_NilData as usize, // vspace_root_data, always 0, reserved by kernel?
+                    buffer_vaddr,          // buffer address
+                    buffer_cap,            // bufferFrame capability
+                )
+            }
+        };
+
+        result.as_result().map_err(|e| SeL4Error::TCBConfigure(e))
     }
 
     /// Set this TCB's priority.
@@ -116,4 +148,56 @@ impl LocalCap<ThreadControlBlock> {
             .as_result()
             .map_err(|e| SeL4Error::TCBSetPriority(e))
     }
+
+    /// Pin this thread to the core numbered `affinity`.
+    #[cfg(KernelSMP)]
+    pub fn set_affinity(&mut self, affinity: usize) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_SetAffinity(self.cptr, affinity) }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBSetAffinity(e))
+    }
+
+    /// Point this thread's TLS register (`TPIDRURW` on ARM,
+    /// `TPIDR_EL0` on aarch64) at `tls_base`.
+    pub fn set_tls_base(&mut self, tls_base: usize) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_SetTLSBase(self.cptr, tls_base) }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBSetTLSBase(e))
+    }
+
+    /// Read all of this thread's user-visible registers.
+    pub fn read_registers(&self) -> Result<seL4_UserContext, SeL4Error> {
+        let mut registers: seL4_UserContext = unsafe { core::mem::zeroed() };
+        unsafe {
+            seL4_TCB_ReadRegisters(
+                self.cptr,
+                0, // suspend_source
+                0, // arch_flags
+                // all the regs
+                core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
+                &mut registers,
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::TCBReadRegisters(e))?;
+        Ok(registers)
+    }
+
+    /// Overwrite all of this thread's user-visible registers. The
+    /// thread is not resumed.
+    pub fn write_registers(&mut self, registers: &seL4_UserContext) -> Result<(), SeL4Error> {
+        let mut registers = *registers;
+        unsafe {
+            seL4_TCB_WriteRegisters(
+                self.cptr,
+                0, // resume_target
+                0, // arch_flags
+                // all the regs
+                core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
+                &mut registers,
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::TCBWriteRegisters(e))
+    }
 }
@@ -1,6 +1,8 @@
 use core::marker::PhantomData;
 use core::ops::{Add, Mul, Sub};
 
+use arrayvec::ArrayVec;
+
 use selfe_sys::*;
 
 use typenum::operator_aliases::{Diff, Prod, Sum};
@@ -75,7 +77,7 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
         let (dest_cptr, dest_offset, _) = dest_slots.elim();
 
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                              // _service
                 api_object_seL4_UntypedObject as usize, // type
                 usize::from(output_size_bits),          // size_bits
@@ -114,6 +116,29 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
         ))
     }
 
+    /// Split this untyped in two, keeping the lower half in place of
+    /// `self` and returning the upper half, e.g. to top up a
+    /// `VSpace`'s paging untyped from a spare one.
+    ///
+    /// Both halves end up in `dest_slots`; the slot `self` was
+    /// originally in is left holding the now fully-used parent.
+    pub fn split_off(
+        &mut self,
+        dest_slots: LocalCNodeSlots<U2>,
+    ) -> Result<LocalCap<WUntyped<Kind>>, WUntypedSplitError> {
+        let whole = Cap {
+            cptr: self.cptr,
+            cap_data: WUntyped {
+                kind: self.cap_data.kind,
+                size_bits: self.cap_data.size_bits,
+            },
+            _role: PhantomData,
+        };
+        let (lower, upper) = whole.split(dest_slots)?;
+        *self = lower;
+        Ok(upper)
+    }
+
     pub fn retype_pages<CRole: CNodeRole>(
         self,
         slots: &mut Cap<WCNodeSlotsData<CRole>, role::Local>,
@@ -130,7 +155,7 @@ impl<Kind: MemoryKind> LocalCap<WUntyped<Kind>> {
             .alloc(num_pages)
             .map_err(|e| RetypeError::CNodeSlotsError(e))?;
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                  // _service
                 Page::sel4_type_id(),       // type
                 0,                          // size_bits
@@ -167,7 +192,7 @@ impl LocalCap<WUntyped<memory_kind::General>> {
 
         let slot = slots.alloc(1)?;
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,            // _service
                 D::sel4_type_id(),    // type
                 0,                    // size_bits
@@ -181,7 +206,26 @@ impl LocalCap<WUntyped<memory_kind::General>> {
         .as_result()
         .map_err(|err| RetypeError::SeL4RetypeError(SeL4Error::UntypedRetype(err)))?;
 
-        Ok(Cap::wrap_cptr(slot.cap_data.offset))
+        Ok(unsafe { Cap::wrap_cptr(slot.cap_data.offset) })
+    }
+
+    /// Retype this untyped into a single object, which must start on a
+    /// `2^align_bits` byte boundary.
+    ///
+    /// This never skips padding to reach the boundary. The object lands
+    /// at the start of the untyped, which is aligned to the untyped's
+    /// own size. Where the physical address isn't known, no stronger
+    /// alignment can be promised, so asking for one is refused with
+    /// `RetypeError::PaddingRequired` and the untyped is left as it was.
+    pub fn retype_aligned<D: CapType + PhantomCap + DirectRetype>(
+        self,
+        slots: &mut WCNodeSlots,
+        align_bits: u8,
+    ) -> Result<LocalCap<D>, RetypeError> {
+        if align_bits > self.cap_data.size_bits {
+            return Err(RetypeError::PaddingRequired);
+        }
+        self.retype(slots)
     }
 }
 
@@ -196,6 +240,29 @@ impl LocalCap<WUntyped<memory_kind::Device>> {
     pub fn paddr(&self) -> usize {
         self.cap_data.kind.paddr
     }
+
+    /// Retype this untyped into pages like `retype_pages`, so long as
+    /// the first of them starts at a physical address on a
+    /// `2^align_bits` byte boundary, e.g. for a DMA buffer that a
+    /// device needs naturally aligned.
+    ///
+    /// As with `retype_aligned`, no padding is skipped: the pages start
+    /// at the very beginning of the untyped. If the untyped's physical
+    /// address isn't suitably aligned, the retype is refused with
+    /// `RetypeError::PaddingRequired`.
+    pub fn retype_pages_aligned<CRole: CNodeRole>(
+        self,
+        slots: &mut Cap<WCNodeSlotsData<CRole>, role::Local>,
+        align_bits: u8,
+    ) -> Result<WeakCapRange<Page<page_state::Unmapped>, CRole>, RetypeError> {
+        let aligned = 1usize
+            .checked_shl(u32::from(align_bits))
+            .map_or(false, |align| self.paddr() % align == 0);
+        if !aligned {
+            return Err(RetypeError::PaddingRequired);
+        }
+        self.retype_pages(slots)
+    }
 }
 
 impl<BitSize: Unsigned> PhantomCap for Untyped<BitSize, memory_kind::General> {
@@ -227,6 +294,19 @@ pub enum WeakMemoryKind {
     General,
     Device { paddr: usize },
 }
+
+impl WeakMemoryKind {
+    /// The kind of the memory `offset` bytes further on, as for the
+    /// upper part of a split region.
+    pub(crate) fn offset_by(self, offset: usize) -> WeakMemoryKind {
+        match self {
+            WeakMemoryKind::General => WeakMemoryKind::General,
+            WeakMemoryKind::Device { paddr } => WeakMemoryKind::Device {
+                paddr: paddr + offset,
+            },
+        }
+    }
+}
 pub mod memory_kind {
     use super::MemoryKind;
 
@@ -308,6 +388,9 @@ pub enum RetypeError {
     BitSizeOverflow,
     KernelRetypeFanOutLimit,
     NotBigEnough,
+    /// The requested alignment could only be met by skipping padding
+    /// at the start of the untyped, which aligned retypes don't do.
+    PaddingRequired,
     SeL4RetypeError(SeL4Error),
     CNodeSlotsError(CNodeSlotsError),
 }
@@ -325,6 +408,16 @@ impl From<CNodeSlotsError> for RetypeError {
 }
 
 impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
+    /// The size of this untyped, as a power of two. The same as
+    /// `BitSize`, for when only a value will do.
+    pub fn size_bits(&self) -> u8 {
+        BitSize::U8
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        2_usize.pow(BitSize::U32)
+    }
+
     /// Gain temporary access to an untyped capability for use in a function context.
     /// When the passed function call is complete, all capabilities derived
     /// from this untyped will be revoked (and thus destroyed).
@@ -389,7 +482,7 @@ impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
         let (dest_cptr, dest_offset, _) = dest_slots.elim();
 
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                              // _service
                 api_object_seL4_UntypedObject as usize, // type
                 BitSize::to_usize() - 1,                // size_bits
@@ -443,7 +536,7 @@ impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
     {
         let (dest_cptr, dest_offset, _) = dest_slots.elim();
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                              // _service
                 api_object_seL4_UntypedObject as usize, // type
                 BitSize::to_usize() - 2,                // size_bits
@@ -511,7 +604,7 @@ impl<BitSize: Unsigned, Kind: MemoryKind> LocalCap<Untyped<BitSize, Kind>> {
     {
         let (dest_cptr, dest_offset, _) = dest_slots.elim();
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                               // _service
                 Page::sel4_type_id(),                    // type
                 0,                                       // size_bits
@@ -577,6 +670,10 @@ where
 }
 
 impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
+    /// Retype this untyped into a single object in `dest_slot`.
+    ///
+    /// The slot may belong to a child's CNode, in which case the object
+    /// is created there directly, with no local copy to move across.
     pub fn retype<TargetCapType: CapType, TargetRole: CNodeRole>(
         self,
         dest_slot: CNodeSlot<TargetRole>,
@@ -589,7 +686,7 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
 
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                     // _service
                 TargetCapType::sel4_type_id(), // type
                 0,                             // size_bits
@@ -610,10 +707,16 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
         })
     }
 
-    pub fn retype_multi<TargetCapType: CapType, Count: Unsigned>(
+    /// Retype this untyped into `Count` objects of the same type with a
+    /// single `seL4_Untyped_Retype`, filling the contiguous
+    /// `dest_slots`, e.g. a pool of endpoints. The untyped must be
+    /// large enough to hold all of them, and `Count` must be within
+    /// the kernel's fan-out limit. As with `retype`, the slots may be
+    /// in a child's CNode.
+    pub fn retype_multi<TargetCapType: CapType, TargetRole: CNodeRole, Count: Unsigned>(
         self,
-        dest_slots: LocalCNodeSlots<Count>,
-    ) -> Result<CapRange<TargetCapType, role::Local, Count>, SeL4Error>
+        dest_slots: CNodeSlots<Count, TargetRole>,
+    ) -> Result<CapRange<TargetCapType, TargetRole, Count>, SeL4Error>
     where
         Count: IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
 
@@ -645,6 +748,16 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
         Ok(CapRange::new_phantom(dest_offset))
     }
 
+    /// Start a `RetypeBatch`, for making objects of several types out
+    /// of this untyped in as few system calls as possible.
+    pub fn retype_batch(self) -> RetypeBatch<BitSize, ()> {
+        RetypeBatch {
+            untyped: self,
+            requests: ArrayVec::new(),
+            out: (),
+        }
+    }
+
     unsafe fn retype_multi_internal(
         self_cptr: usize,
         count: usize,
@@ -652,7 +765,7 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
         dest_cptr: usize,
         dest_offset: usize,
     ) -> Result<(), SeL4Error> {
-        seL4_Untyped_Retype(
+        crate::cap::logged::untyped_retype(
             self_cptr,   // _service
             type_id,     // type
             0,           // size_bits
@@ -695,7 +808,7 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
 
         unsafe {
             // Retype to fill the scratch slot with a fresh CNode
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,                               // _service
                 api_object_seL4_CapTableObject as usize, // type
                 ChildRadix::to_usize(),                  // size_bits
@@ -716,7 +829,7 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
             )
             .words[0];
 
-            seL4_CNode_Mutate(
+            crate::cap::logged::cnode_mutate(
                 dest_cptr,           // _service: seL4_CNode,
                 dest_offset,         // dest_index: seL4_Word,
                 seL4_WordBits as u8, // dest_depth: seL4_Uint8,
@@ -748,6 +861,171 @@ impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::General>> {
     }
 }
 
+/// Several retypes out of one untyped, of differing object types and
+/// into differing slots, gathered up to be made together. Each `retype`
+/// adds a request; `finish` makes them all and returns their cap
+/// ranges as a tuple, in the order they were added.
+///
+/// A single `seL4_Untyped_Retype` can only make objects of one type,
+/// so `finish` needs at least one call per change of type. Requests
+/// for the same type going into adjacent slots of the same CNode,
+/// added one after the other, share a call.
+///
+/// Before making anything, `finish` checks that everything fits in
+/// the untyped, padding as the kernel does to align each call's
+/// objects to their size. Requests are made in the order they were
+/// added, so adding the largest objects first wastes the least.
+pub struct RetypeBatch<BitSize: Unsigned, Out> {
+    untyped: LocalCap<Untyped<BitSize, memory_kind::General>>,
+    requests: ArrayVec<[BatchedRetype; MAX_BATCHED_RETYPES]>,
+    out: Out,
+}
+
+/// How many requests one `RetypeBatch` can hold; as many as there are
+/// `AppendCapRange` impls.
+const MAX_BATCHED_RETYPES: usize = 6;
+
+#[derive(Clone, Copy)]
+struct BatchedRetype {
+    type_id: usize,
+    size_bits: usize,
+    dest_cptr: usize,
+    dest_offset: usize,
+    count: usize,
+}
+
+impl<BitSize: Unsigned, Out> RetypeBatch<BitSize, Out> {
+    /// Add a request for `Count` objects of `TargetCapType`, to go in
+    /// `dest_slots`. As with `retype_multi`, the slots may be in a
+    /// child's CNode.
+    pub fn retype<TargetCapType: CapType, TargetRole: CNodeRole, Count: Unsigned>(
+        mut self,
+        dest_slots: CNodeSlots<Count, TargetRole>,
+    ) -> RetypeBatch<BitSize, Out::Output>
+    where
+        Out: AppendCapRange<CapRange<TargetCapType, TargetRole, Count>>,
+        Count: IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+        TargetCapType: DirectRetype,
+        TargetCapType: PhantomCap,
+        BitSize: IsGreaterOrEqual<TargetCapType::SizeBits, Output = True>,
+    {
+        let (dest_cptr, dest_offset, _) = dest_slots.elim();
+        // AppendCapRange stops at MAX_BATCHED_RETYPES elements, so
+        // there's always room.
+        self.requests.push(BatchedRetype {
+            type_id: TargetCapType::sel4_type_id(),
+            size_bits: TargetCapType::SizeBits::USIZE,
+            dest_cptr,
+            dest_offset,
+            count: Count::USIZE,
+        });
+        RetypeBatch {
+            untyped: self.untyped,
+            requests: self.requests,
+            out: self.out.append(CapRange::new_phantom(dest_offset)),
+        }
+    }
+
+    /// Make every requested object, returning their cap ranges.
+    ///
+    /// Fails with `RetypeError::NotBigEnough`, having made nothing, if
+    /// they won't all fit in the untyped. A kernel error partway
+    /// through leaves the objects from earlier calls in their slots.
+    /// Either way the untyped is handed back with the error, so that
+    /// revoking it reaches whatever was made.
+    pub fn finish(
+        self,
+    ) -> Result<
+        Out,
+        (
+            RetypeError,
+            LocalCap<Untyped<BitSize, memory_kind::General>>,
+        ),
+    > {
+        if let Err(e) = self.check_fits() {
+            return Err((e, self.untyped));
+        }
+
+        let mut remaining = &self.requests[..];
+        while let Some((first, rest)) = remaining.split_first() {
+            let mut call = *first;
+            let mut merged = 0;
+            for next in rest {
+                let joins = next.type_id == call.type_id
+                    && next.dest_cptr == call.dest_cptr
+                    && next.dest_offset == call.dest_offset + call.count
+                    && call.count + next.count <= KernelRetypeFanOutLimit::USIZE;
+                if !joins {
+                    break;
+                }
+                call.count += next.count;
+                merged += 1;
+            }
+            let made = unsafe {
+                LocalCap::<Untyped<BitSize>>::retype_multi_internal(
+                    self.untyped.cptr,
+                    call.count,
+                    call.type_id,
+                    call.dest_cptr,
+                    call.dest_offset,
+                )
+            };
+            if let Err(e) = made {
+                return Err((e.into(), self.untyped));
+            }
+            remaining = &rest[merged..];
+        }
+        Ok(self.out)
+    }
+
+    /// Whether every request fits in the untyped, padding as the
+    /// kernel does.
+    fn check_fits(&self) -> Result<(), RetypeError> {
+        let mut used_bytes: usize = 0;
+        for r in self.requests.iter() {
+            let object_bytes = 1usize
+                .checked_shl(r.size_bits as u32)
+                .ok_or_else(|| RetypeError::BitSizeOverflow)?;
+            let aligned = (used_bytes + object_bytes - 1) & !(object_bytes - 1);
+            used_bytes = object_bytes
+                .checked_mul(r.count)
+                .and_then(|bytes| aligned.checked_add(bytes))
+                .ok_or_else(|| RetypeError::CapSizeOverflow)?;
+        }
+        if used_bytes > self.untyped.size_bytes() {
+            return Err(RetypeError::NotBigEnough);
+        }
+        Ok(())
+    }
+}
+
+/// Tuples that `RetypeBatch` can add one more cap range to the end
+/// of. Implemented for up to six elements.
+pub trait AppendCapRange<R> {
+    type Output;
+    fn append(self, range: R) -> Self::Output;
+}
+
+macro_rules! append_cap_range {
+    ($($t:ident),*) => {
+        impl<$($t,)* R> AppendCapRange<R> for ($($t,)*) {
+            type Output = ($($t,)* R,);
+            #[allow(non_snake_case)]
+            fn append(self, range: R) -> Self::Output {
+                let ($($t,)*) = self;
+                ($($t,)* range,)
+            }
+        }
+    };
+}
+
+append_cap_range!();
+append_cap_range!(A);
+append_cap_range!(A, B);
+append_cap_range!(A, B, C);
+append_cap_range!(A, B, C, D);
+append_cap_range!(A, B, C, D, E);
+
 impl LocalCap<Untyped<PageBits, memory_kind::Device>> {
     /// The only thing memory_kind::Device memory can be used to make
     /// is a page/frame.
@@ -763,7 +1041,7 @@ impl LocalCap<Untyped<PageBits, memory_kind::Device>> {
         let (dest_cptr, dest_offset, _) = dest_slot.elim();
 
         unsafe {
-            seL4_Untyped_Retype(
+            crate::cap::logged::untyped_retype(
                 self.cptr,            // _service
                 Page::sel4_type_id(), // type
                 0,                    // size_bits
@@ -790,10 +1068,20 @@ impl LocalCap<Untyped<PageBits, memory_kind::Device>> {
 }
 
 impl<BitSize: Unsigned> LocalCap<Untyped<BitSize, memory_kind::Device>> {
-    /// Physical address at the start of the memory this untyped represents
+    /// Physical address at the start of the memory this untyped
+    /// represents, as reported by the boot info, and carried along
+    /// through any splitting since.
+    ///
+    /// General untypeds don't track their physical address; only
+    /// device memory is placed by address.
     pub fn paddr(&self) -> usize {
         self.cap_data.kind.paddr
     }
+
+    /// Whether `paddr` falls within this untyped's memory.
+    pub fn contains_paddr(&self, paddr: usize) -> bool {
+        paddr >= self.paddr() && paddr - self.paddr() < self.size_bytes()
+    }
 }
 
 mod private {
@@ -0,0 +1,816 @@
+//! A record of the capabilities ferros creates, written out as a capDL
+//! spec. Only built with the `capdl` feature.
+//!
+//! Every retype, copy, mint, move, mutate and delete goes through the
+//! wrappers in `cap::logged`, and each one that succeeds is appended to
+//! a fixed-size log. `write_spec` replays that log over the caps the
+//! kernel handed the root task at boot to describe every object and
+//! the slots that hold caps to them. `write_spec_for` does the same for
+//! any log and set of boot caps.
+//!
+//! Two things are still left out. Revokes aren't logged, so a cap
+//! taken away by revoking its parent is still listed. And the paging
+//! structures the kernel made for the root task's own image aren't
+//! described, since the bootinfo doesn't say what type each is.
+
+use core::cmp::Ordering as CmpOrdering;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use selfe_sys::*;
+use typenum::Unsigned;
+
+use crate::bootstrap::{BootInfoCap, WellKnownCap};
+use crate::cap::{page_state, DirectRetype, Page};
+
+/// How many operations the log holds, and so the most `write_spec_for`
+/// takes. Anything past this is counted in `dropped_records` and
+/// otherwise forgotten.
+pub const MAX_CAPDL_RECORDS: usize = 4096;
+
+/// One logged operation. All cptrs and indices are as the calling
+/// thread saw them, so a root is a cptr into that thread's CSpace.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CapRecord {
+    /// `count` objects of the kernel's `object_type` made from
+    /// `untyped`, with caps in consecutive slots of `dest_root`
+    /// starting at `dest_offset`.
+    Retype {
+        untyped: usize,
+        object_type: usize,
+        size_bits: usize,
+        dest_root: usize,
+        dest_offset: usize,
+        count: usize,
+    },
+    /// A copy (`badge` of zero) or mint of one cap into another slot.
+    /// `rights` is the raw `seL4_CapRights_t` word.
+    Derive {
+        src_root: usize,
+        src_index: usize,
+        dest_root: usize,
+        dest_index: usize,
+        rights: usize,
+        badge: usize,
+    },
+    /// A move or mutate of one cap into another slot, leaving the one
+    /// it came from empty.
+    Move {
+        src_root: usize,
+        src_index: usize,
+        dest_root: usize,
+        dest_index: usize,
+    },
+    /// A slot emptied.
+    Delete { root: usize, index: usize },
+}
+
+impl CapRecord {
+    /// The runs of slots this record changes, as `(root, start, count,
+    /// filled)`: filled with a cap, or emptied.
+    fn slot_writes(&self) -> [Option<(usize, usize, usize, bool)>; 2] {
+        match *self {
+            CapRecord::Retype {
+                dest_root,
+                dest_offset,
+                count,
+                ..
+            } => [Some((dest_root, dest_offset, count, true)), None],
+            CapRecord::Derive {
+                dest_root,
+                dest_index,
+                ..
+            } => [Some((dest_root, dest_index, 1, true)), None],
+            CapRecord::Move {
+                src_root,
+                src_index,
+                dest_root,
+                dest_index,
+            } => [
+                Some((src_root, src_index, 1, false)),
+                Some((dest_root, dest_index, 1, true)),
+            ],
+            CapRecord::Delete { root, index } => [Some((root, index, 1, false)), None],
+        }
+    }
+}
+
+// Only the first RECORD_COUNT entries mean anything; the rest are
+// filler until they're written.
+const UNUSED_RECORD: CapRecord = CapRecord::Retype {
+    untyped: 0,
+    object_type: 0,
+    size_bits: 0,
+    dest_root: 0,
+    dest_offset: 0,
+    count: 0,
+};
+
+static mut RECORDS: [CapRecord; MAX_CAPDL_RECORDS] = [UNUSED_RECORD; MAX_CAPDL_RECORDS];
+static RECORD_COUNT: AtomicUsize = AtomicUsize::new(0);
+static DROPPED_RECORDS: AtomicUsize = AtomicUsize::new(0);
+
+pub(crate) fn record(r: CapRecord) {
+    let index = RECORD_COUNT.fetch_add(1, Ordering::SeqCst);
+    if index < MAX_CAPDL_RECORDS {
+        unsafe { RECORDS[index] = r };
+    } else {
+        RECORD_COUNT.store(MAX_CAPDL_RECORDS, Ordering::SeqCst);
+        DROPPED_RECORDS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn log() -> &'static [CapRecord] {
+    let count = core::cmp::min(RECORD_COUNT.load(Ordering::SeqCst), MAX_CAPDL_RECORDS);
+    unsafe { &RECORDS[..count] }
+}
+
+/// The operations logged so far, oldest first.
+pub fn records() -> impl Iterator<Item = CapRecord> {
+    log().iter().cloned()
+}
+
+/// How many operations didn't fit in the log. A spec written while
+/// this is non-zero is missing objects.
+pub fn dropped_records() -> usize {
+    DROPPED_RECORDS.load(Ordering::SeqCst)
+}
+
+/// Write the log out as a capDL spec, starting from the caps the
+/// kernel listed in `bootinfo`. See `write_spec_for`.
+pub fn write_spec<W: fmt::Write>(
+    bootinfo: &'static seL4_BootInfo,
+    scratch: &mut SpecScratch,
+    out: &mut W,
+) -> fmt::Result {
+    write_spec_for(
+        log(),
+        crate::bootstrap::describe_boot_caps(bootinfo),
+        bootinfo.initThreadCNodeSizeBits as u8,
+        scratch,
+        out,
+    )
+}
+
+/// Write `log` out as a capDL spec. `boot_caps` are what the root
+/// CNode, `root_cnode_size_bits` in size, held before the first
+/// record, as `BootInfo::describe` lists them.
+///
+/// The root task's own objects are named `root_tcb`, `root_cnode`,
+/// `root_vspace`, `root_asid_pool`, `root_bootinfo_frame` and
+/// `root_ipc_buffer`, the other boot-time ones after their type and
+/// slot, e.g. `ut_boot_20`, and the rest after their type and the
+/// retype that made them, e.g. `tcb_12_0`. Untyped objects list the
+/// objects later retyped from them as children.
+///
+/// The spec is worked out in `scratch`. A log longer than
+/// `MAX_CAPDL_RECORDS` gets an error.
+pub fn write_spec_for<I, W>(
+    log: &[CapRecord],
+    boot_caps: I,
+    root_cnode_size_bits: u8,
+    scratch: &mut SpecScratch,
+    out: &mut W,
+) -> fmt::Result
+where
+    I: Iterator<Item = BootInfoCap> + Clone,
+    W: fmt::Write,
+{
+    if log.len() > MAX_CAPDL_RECORDS {
+        return Err(fmt::Error);
+    }
+    Replay::new(log, boot_caps, root_cnode_size_bits, scratch).write(out)
+}
+
+/// An object a cap can refer to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Object {
+    /// The `index`th object made by the retype at `record` in the log.
+    Logged { record: u32, index: u32 },
+    /// Whatever the kernel put in `slot` of the root CNode at boot.
+    Boot { slot: u32 },
+}
+
+const ROOT_CNODE: Object = Object::Boot {
+    slot: seL4_CapInitThreadCNode as u32,
+};
+
+/// A run of slots in one CNode that the log record at `record` filled
+/// with caps, or emptied.
+#[derive(Clone, Copy)]
+struct SlotWrite {
+    cnode: Object,
+    start: u32,
+    end: u32,
+    record: u32,
+    filled: bool,
+}
+
+impl SlotWrite {
+    fn key(&self) -> (Object, u32, u32) {
+        (self.cnode, self.start, self.record)
+    }
+}
+
+// All zeroes, so a scratch space in a static costs nothing in the
+// image.
+const UNUSED_OBJECT: Object = Object::Logged {
+    record: 0,
+    index: 0,
+};
+
+const UNUSED_SLOT_WRITE: SlotWrite = SlotWrite {
+    cnode: UNUSED_OBJECT,
+    start: 0,
+    end: 0,
+    record: 0,
+    filled: false,
+};
+
+/// Where a spec is worked out. Too big for most stacks, so it's
+/// usually kept in a static or in mapped memory.
+pub struct SpecScratch {
+    // A move writes two runs, anything else one.
+    writes: [SlotWrite; 2 * MAX_CAPDL_RECORDS],
+    // Each retype's untyped and position in the log.
+    children: [(Object, u32); MAX_CAPDL_RECORDS],
+}
+
+impl SpecScratch {
+    pub const fn new() -> Self {
+        SpecScratch {
+            writes: [UNUSED_SLOT_WRITE; 2 * MAX_CAPDL_RECORDS],
+            children: [(UNUSED_OBJECT, 0); MAX_CAPDL_RECORDS],
+        }
+    }
+}
+
+// seL4_CapRights_t: GrantReply, Grant, Read, Write from the top.
+const ALL_RIGHTS: usize = 0b1111;
+
+/// A cap found in a slot: the object it's to, its rights and its
+/// badge.
+#[derive(Clone, Copy)]
+struct FoundCap {
+    object: Object,
+    rights: usize,
+    badge: usize,
+}
+
+/// What the kernel put in one of the root CNode's slots at boot.
+#[derive(Clone, Copy)]
+enum BootObject {
+    /// A cap to an object, which is named `name` if it's one of the
+    /// root task's own.
+    Object {
+        name: Option<&'static str>,
+        object_type: ObjectType,
+    },
+    /// A cap to no object, only some authority, e.g. `irq_control`.
+    Special(&'static str),
+}
+
+/// The capDL type of an object, e.g. `tcb` or `ut (12 bits)`.
+#[derive(Clone, Copy)]
+enum ObjectType {
+    /// One the kernel can make by retyping, by its `seL4_ObjectType`.
+    Retyped {
+        object_type: usize,
+        size_bits: usize,
+    },
+    /// One of the boot-time untypeds, whose address is known.
+    BootUntyped {
+        size_bits: usize,
+        paddr: usize,
+    },
+    ASIDPool,
+}
+
+impl ObjectType {
+    /// The type as capDL spells it, sized or not, e.g. `frame (4k)`.
+    fn spec(&self) -> &'static str {
+        let t = match *self {
+            ObjectType::Retyped { object_type, .. } => object_type,
+            ObjectType::BootUntyped { .. } => return "ut",
+            ObjectType::ASIDPool => return "asid_pool",
+        };
+        if t == api_object_seL4_UntypedObject as usize {
+            "ut"
+        } else if t == api_object_seL4_TCBObject as usize {
+            "tcb"
+        } else if t == api_object_seL4_EndpointObject as usize {
+            "ep"
+        } else if t == api_object_seL4_NotificationObject as usize {
+            "notification"
+        } else if t == api_object_seL4_CapTableObject as usize {
+            "cnode"
+        } else {
+            #[cfg(KernelIsMCS)]
+            {
+                if t == api_object_seL4_SchedContextObject as usize {
+                    return "sc";
+                }
+            }
+            crate::arch::capdl_object_name(t).unwrap_or("unknown")
+        }
+    }
+
+    /// The type's bare name, for naming objects, e.g. `frame`.
+    fn name(&self) -> &'static str {
+        self.spec().split(' ').next().unwrap_or("unknown")
+    }
+
+    fn size_bits(&self) -> Option<usize> {
+        match *self {
+            ObjectType::Retyped { size_bits, .. } => match self.name() {
+                "ut" | "cnode" | "sc" => Some(size_bits),
+                _ => None,
+            },
+            ObjectType::BootUntyped { size_bits, .. } => Some(size_bits),
+            ObjectType::ASIDPool => None,
+        }
+    }
+}
+
+impl fmt::Display for ObjectType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.size_bits(), *self) {
+            (Some(size_bits), ObjectType::BootUntyped { paddr, .. }) => write!(
+                f,
+                "{} ({} bits, paddr: 0x{:x})",
+                self.name(),
+                size_bits,
+                paddr
+            ),
+            (Some(size_bits), _) => write!(f, "{} ({} bits)", self.name(), size_bits),
+            (None, _) => f.write_str(self.spec()),
+        }
+    }
+}
+
+fn small_frame() -> ObjectType {
+    ObjectType::Retyped {
+        object_type: Page::<page_state::Unmapped>::sel4_type_id(),
+        size_bits: crate::arch::PageBits::USIZE,
+    }
+}
+
+/// What a boot cap is to, or `None` for the image's paging structures.
+fn boot_object(cap: BootInfoCap, root_cnode_size_bits: u8) -> Option<BootObject> {
+    let named = |name, object_type| {
+        Some(BootObject::Object {
+            name: Some(name),
+            object_type,
+        })
+    };
+    match cap {
+        BootInfoCap::WellKnown { kind, .. } => match kind {
+            WellKnownCap::InitThreadTCB => named(
+                "root_tcb",
+                ObjectType::Retyped {
+                    object_type: api_object_seL4_TCBObject as usize,
+                    size_bits: 0,
+                },
+            ),
+            WellKnownCap::InitThreadCNode => named(
+                "root_cnode",
+                ObjectType::Retyped {
+                    object_type: api_object_seL4_CapTableObject as usize,
+                    size_bits: root_cnode_size_bits as usize,
+                },
+            ),
+            WellKnownCap::InitThreadVSpace => named(
+                "root_vspace",
+                ObjectType::Retyped {
+                    object_type: crate::arch::PagingRoot::sel4_type_id(),
+                    size_bits: 0,
+                },
+            ),
+            WellKnownCap::InitThreadASIDPool => named("root_asid_pool", ObjectType::ASIDPool),
+            WellKnownCap::BootInfoFrame => named("root_bootinfo_frame", small_frame()),
+            WellKnownCap::InitThreadIPCBuffer => named("root_ipc_buffer", small_frame()),
+            WellKnownCap::IRQControl => Some(BootObject::Special("irq_control")),
+            WellKnownCap::ASIDControl => Some(BootObject::Special("asid_control")),
+            WellKnownCap::Domain => Some(BootObject::Special("domain")),
+        },
+        BootInfoCap::UserImageFrame { .. } | BootInfoCap::ExtraBootInfoFrame { .. } => {
+            Some(BootObject::Object {
+                name: None,
+                object_type: small_frame(),
+            })
+        }
+        BootInfoCap::UserImagePaging { .. } => None,
+        BootInfoCap::Untyped {
+            size_bits, paddr, ..
+        } => Some(BootObject::Object {
+            name: None,
+            object_type: ObjectType::BootUntyped {
+                size_bits: size_bits as usize,
+                paddr,
+            },
+        }),
+        #[cfg(KernelIsMCS)]
+        BootInfoCap::SchedControl { .. } => Some(BootObject::Special("sched_control")),
+    }
+}
+
+/// The log laid out for looking up what's in a slot at any point.
+struct Replay<'a, I> {
+    log: &'a [CapRecord],
+    boot_caps: I,
+    root_cnode_size_bits: u8,
+    /// Every run of slots a record changed, by CNode, first slot and
+    /// then record.
+    writes: &'a [SlotWrite],
+    /// The most slots any one run covers, so that any run covering a
+    /// slot starts no further back than this from it.
+    longest_write: u32,
+    /// Each retype whose untyped is known, by untyped and then record.
+    children: &'a [(Object, u32)],
+}
+
+impl<'a, I> Replay<'a, I>
+where
+    I: Iterator<Item = BootInfoCap> + Clone,
+{
+    fn new(
+        log: &'a [CapRecord],
+        boot_caps: I,
+        root_cnode_size_bits: u8,
+        scratch: &'a mut SpecScratch,
+    ) -> Self {
+        let SpecScratch { writes, children } = scratch;
+        let writes = &mut writes[..];
+
+        // Writes to the root CNode first, as every other CNode is found
+        // through it.
+        let mut root_count = 0;
+        let mut longest_write = 0;
+        for (k, r) in log.iter().enumerate() {
+            for &(root, start, len, filled) in r.slot_writes().iter().flatten() {
+                if root == seL4_CapInitThreadCNode as usize {
+                    writes[root_count] = SlotWrite {
+                        cnode: ROOT_CNODE,
+                        start: start as u32,
+                        end: (start + len) as u32,
+                        record: k as u32,
+                        filled,
+                    };
+                    longest_write = core::cmp::max(longest_write, len as u32);
+                    root_count += 1;
+                }
+            }
+        }
+        pdqsort::sort_by_key(&mut writes[..root_count], SlotWrite::key);
+
+        let (root_writes, other_writes) = writes.split_at_mut(root_count);
+        let roots_only = Replay {
+            log,
+            boot_caps: boot_caps.clone(),
+            root_cnode_size_bits,
+            writes: root_writes,
+            longest_write,
+            children: &[],
+        };
+        let mut other_count = 0;
+        for (k, r) in log.iter().enumerate() {
+            for &(root, start, len, filled) in r.slot_writes().iter().flatten() {
+                if root == seL4_CapInitThreadCNode as usize {
+                    continue;
+                }
+                if let Some(cnode) = roots_only.cnode_at(root, k) {
+                    other_writes[other_count] = SlotWrite {
+                        cnode,
+                        start: start as u32,
+                        end: (start + len) as u32,
+                        record: k as u32,
+                        filled,
+                    };
+                    longest_write = core::cmp::max(longest_write, len as u32);
+                    other_count += 1;
+                }
+            }
+        }
+        let count = root_count + other_count;
+        pdqsort::sort_by_key(&mut writes[..count], SlotWrite::key);
+
+        let mut replay = Replay {
+            log,
+            boot_caps,
+            root_cnode_size_bits,
+            writes: &writes[..count],
+            longest_write,
+            children: &[],
+        };
+        let mut count = 0;
+        for (k, r) in log.iter().enumerate() {
+            if let CapRecord::Retype { untyped, .. } = *r {
+                if let Some(cap) = replay.cap_at(ROOT_CNODE, untyped, k) {
+                    children[count] = (cap.object, k as u32);
+                    count += 1;
+                }
+            }
+        }
+        pdqsort::sort_by_key(&mut children[..count], |&c| c);
+        replay.children = &children[..count];
+        replay
+    }
+
+    /// The latest run of slots to cover `index` in `cnode` that was
+    /// written before the `before`th record.
+    fn latest_write(&self, cnode: Object, index: usize, before: usize) -> Option<SlotWrite> {
+        if index > u32::max_value() as usize {
+            return None;
+        }
+        let index = index as u32;
+        // Everything from here back may start at or before `index`.
+        let end = self
+            .writes
+            .binary_search_by(|w| {
+                if (w.cnode, w.start) <= (cnode, index) {
+                    CmpOrdering::Less
+                } else {
+                    CmpOrdering::Greater
+                }
+            })
+            .unwrap_or_else(|e| e);
+        self.writes[..end]
+            .iter()
+            .rev()
+            .take_while(|w| w.cnode == cnode && index - w.start < self.longest_write)
+            .filter(|w| index < w.end && (w.record as usize) < before)
+            .max_by_key(|w| w.record)
+            .cloned()
+    }
+
+    /// The cap in slot `index` of `cnode` just before the `before`th
+    /// record, following copies, mints and moves back to the retype or
+    /// boot cap it came from.
+    fn cap_at(&self, cnode: Object, index: usize, before: usize) -> Option<FoundCap> {
+        let (mut cnode, mut index, mut before) = (cnode, index, before);
+        let (mut rights, mut badge) = (ALL_RIGHTS, 0);
+        loop {
+            let write = match self.latest_write(cnode, index, before) {
+                Some(write) => write,
+                None if cnode == ROOT_CNODE && self.boot_cap(index).is_some() => {
+                    return Some(FoundCap {
+                        object: Object::Boot { slot: index as u32 },
+                        rights,
+                        badge,
+                    });
+                }
+                None => return None,
+            };
+            if !write.filled {
+                return None;
+            }
+            let (src_root, src_index) = match self.log[write.record as usize] {
+                CapRecord::Retype { .. } => {
+                    return Some(FoundCap {
+                        object: Object::Logged {
+                            record: write.record,
+                            index: index as u32 - write.start,
+                        },
+                        rights,
+                        badge,
+                    });
+                }
+                CapRecord::Derive {
+                    src_root,
+                    src_index,
+                    rights: derived_rights,
+                    badge: derived_badge,
+                    ..
+                } => {
+                    rights &= derived_rights;
+                    // A badged cap can't be rebadged, so the first
+                    // badge given it is the one it has.
+                    if derived_badge != 0 {
+                        badge = derived_badge;
+                    }
+                    (src_root, src_index)
+                }
+                CapRecord::Move {
+                    src_root,
+                    src_index,
+                    ..
+                } => (src_root, src_index),
+                CapRecord::Delete { .. } => return None,
+            };
+            before = write.record as usize;
+            cnode = self.cnode_at(src_root, before)?;
+            index = src_index;
+        }
+    }
+
+    /// The CNode a root cptr refers to just before the `before`th
+    /// record.
+    fn cnode_at(&self, root: usize, before: usize) -> Option<Object> {
+        if root == seL4_CapInitThreadCNode as usize {
+            Some(ROOT_CNODE)
+        } else {
+            self.cap_at(ROOT_CNODE, root, before).map(|cap| cap.object)
+        }
+    }
+
+    fn boot_cap(&self, slot: usize) -> Option<BootObject> {
+        self.boot_caps
+            .clone()
+            .find(|cap| cap.cptr() == slot)
+            .and_then(|cap| boot_object(cap, self.root_cnode_size_bits))
+    }
+
+    fn object_type(&self, object: Object) -> Option<ObjectType> {
+        match object {
+            Object::Logged { record, .. } => match self.log[record as usize] {
+                CapRecord::Retype {
+                    object_type,
+                    size_bits,
+                    ..
+                } => Some(ObjectType::Retyped {
+                    object_type,
+                    size_bits,
+                }),
+                _ => None,
+            },
+            Object::Boot { slot } => match self.boot_cap(slot as usize) {
+                Some(BootObject::Object { object_type, .. }) => Some(object_type),
+                _ => None,
+            },
+        }
+    }
+
+    fn write<W: fmt::Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "arch {}", crate::arch::CAPDL_ARCH)?;
+        writeln!(out)?;
+
+        writeln!(out, "objects {{")?;
+        for cap in self.boot_caps.clone() {
+            let object = Object::Boot {
+                slot: cap.cptr() as u32,
+            };
+            if let Some(BootObject::Object { .. }) = boot_object(cap, self.root_cnode_size_bits) {
+                self.write_object(out, object)?;
+            }
+        }
+        for (k, r) in self.log.iter().enumerate() {
+            if let CapRecord::Retype { count, .. } = *r {
+                for index in 0..count {
+                    let object = Object::Logged {
+                        record: k as u32,
+                        index: index as u32,
+                    };
+                    self.write_object(out, object)?;
+                }
+            }
+        }
+        writeln!(out, "}}")?;
+        writeln!(out)?;
+
+        writeln!(out, "caps {{")?;
+        writeln!(out, "  {} {{", self.name(ROOT_CNODE))?;
+        for cap in self.boot_caps.clone() {
+            if self
+                .latest_write(ROOT_CNODE, cap.cptr(), self.log.len())
+                .is_none()
+            {
+                self.write_cap(out, ROOT_CNODE, cap.cptr())?;
+            }
+        }
+        self.write_slots(out, ROOT_CNODE)?;
+        writeln!(out, "  }}")?;
+        let mut last = ROOT_CNODE;
+        for w in self.writes.iter().filter(|w| w.cnode != ROOT_CNODE) {
+            if w.cnode == last {
+                continue;
+            }
+            last = w.cnode;
+            writeln!(out, "  {} {{", self.name(w.cnode))?;
+            self.write_slots(out, w.cnode)?;
+            writeln!(out, "  }}")?;
+        }
+        writeln!(out, "}}")
+    }
+
+    fn write_object<W: fmt::Write>(&self, out: &mut W, object: Object) -> fmt::Result {
+        let object_type = match self.object_type(object) {
+            Some(object_type) => object_type,
+            None => return Ok(()),
+        };
+        write!(out, "  {} = {}", self.name(object), object_type)?;
+        if object_type.name() == "ut" {
+            self.write_children(out, object)?;
+        }
+        writeln!(out)
+    }
+
+    fn write_children<W: fmt::Write>(&self, out: &mut W, parent: Object) -> fmt::Result {
+        let start = self
+            .children
+            .binary_search_by(|&(untyped, _)| {
+                if untyped < parent {
+                    CmpOrdering::Less
+                } else {
+                    CmpOrdering::Greater
+                }
+            })
+            .unwrap_or_else(|e| e);
+        let mut any = false;
+        for &(_, record) in self.children[start..]
+            .iter()
+            .take_while(|&&(untyped, _)| untyped == parent)
+        {
+            if let CapRecord::Retype { count, .. } = self.log[record as usize] {
+                for index in 0..count as u32 {
+                    out.write_str(if any { ", " } else { " { " })?;
+                    write!(out, "{}", self.name(Object::Logged { record, index }))?;
+                    any = true;
+                }
+            }
+        }
+        if any {
+            out.write_str(" }")?;
+        }
+        Ok(())
+    }
+
+    /// The filled slots of `cnode` whose latest write is one in the
+    /// log.
+    fn write_slots<W: fmt::Write>(&self, out: &mut W, cnode: Object) -> fmt::Result {
+        let end = self.log.len();
+        for w in self.writes.iter().filter(|w| w.cnode == cnode && w.filled) {
+            for index in w.start as usize..w.end as usize {
+                let latest = self.latest_write(cnode, index, end).map(|l| l.record);
+                if latest == Some(w.record) {
+                    self.write_cap(out, cnode, index)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn write_cap<W: fmt::Write>(&self, out: &mut W, cnode: Object, index: usize) -> fmt::Result {
+        let cap = match self.cap_at(cnode, index, self.log.len()) {
+            Some(cap) => cap,
+            None => return Ok(()),
+        };
+        match cap.object {
+            Object::Boot { slot } => match self.boot_cap(slot as usize) {
+                Some(BootObject::Special(name)) => {
+                    return writeln!(out, "    0x{:x}: {}", index, name)
+                }
+                Some(BootObject::Object { .. }) => (),
+                None => return Ok(()),
+            },
+            Object::Logged { .. } => (),
+        }
+        write!(out, "    0x{:x}: {} (", index, self.name(cap.object))?;
+        write_rights(out, cap.rights)?;
+        if cap.badge != 0 {
+            write!(out, ", badge: {}", cap.badge)?;
+        }
+        writeln!(out, ")")
+    }
+
+    fn name(&self, object: Object) -> ObjectName<'_, 'a, I> {
+        ObjectName(self, object)
+    }
+}
+
+struct ObjectName<'r, 'a, I>(&'r Replay<'a, I>, Object);
+
+impl<'r, 'a, I> fmt::Display for ObjectName<'r, 'a, I>
+where
+    I: Iterator<Item = BootInfoCap> + Clone,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let ObjectName(replay, object) = *self;
+        let type_name = replay.object_type(object).map_or("unknown", |t| t.name());
+        match object {
+            Object::Logged { record, index } => write!(f, "{}_{}_{}", type_name, record, index),
+            Object::Boot { slot } => match replay.boot_cap(slot as usize) {
+                Some(BootObject::Object {
+                    name: Some(name), ..
+                })
+                | Some(BootObject::Special(name)) => f.write_str(name),
+                _ => write!(f, "{}_boot_{}", type_name, slot),
+            },
+        }
+    }
+}
+
+fn write_rights<W: fmt::Write>(out: &mut W, rights: usize) -> fmt::Result {
+    if rights & 0b0010 != 0 {
+        out.write_char('R')?;
+    }
+    if rights & 0b0001 != 0 {
+        out.write_char('W')?;
+    }
+    if rights & 0b0100 != 0 {
+        out.write_char('G')?;
+    }
+    if rights & 0b1000 != 0 {
+        out.write_char('P')?;
+    }
+    Ok(())
+}
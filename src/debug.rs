@@ -17,6 +17,22 @@ impl fmt::Write for DebugOutHandle {
     }
 }
 
+/// Have the kernel print the state of every thread it knows about,
+/// including each one's name (cf. `StandardProcess::set_name`), thread
+/// state and priority. The kernel offers no way to query a single
+/// thread's state programmatically, so this is the tool for figuring
+/// out why some thread isn't running. `StandardProcess::thread_state`
+/// and `StandardProcess::fault_count` say what ferros itself has seen
+/// of one.
+///
+/// Does nothing unless the kernel is a debug build with printing.
+pub fn dump_scheduler() {
+    #[cfg(all(KernelDebugBuild, KernelPrinting))]
+    unsafe {
+        selfe_sys::seL4_DebugDumpScheduler()
+    };
+}
+
 #[macro_export]
 macro_rules! debug_print {
     ($($arg:tt)*) => ({
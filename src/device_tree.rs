@@ -0,0 +1,232 @@
+//! A minimal, read-only parser for flattened device tree (FDT) blobs.
+//! It borrows the blob handed over by the bootloader and knows just
+//! enough to find a device's MMIO region and interrupts, for handing
+//! to `DeviceAllocator::get_device_untyped_containing` and friends.
+use arrayvec::ArrayVec;
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 0x1;
+const FDT_END_NODE: u32 = 0x2;
+const FDT_PROP: u32 = 0x3;
+const FDT_NOP: u32 = 0x4;
+const FDT_END: u32 = 0x9;
+
+const HEADER_BYTES: usize = 40;
+/// The version whose structure block layout we understand; later
+/// versions remain backwards compatible with it.
+const SUPPORTED_VERSION: u32 = 16;
+
+/// Nodes nested deeper than this are not searched.
+const MAX_DEPTH: usize = 16;
+pub const MAX_INTERRUPT_CELLS: usize = 16;
+
+/// The raw cells of a node's `interrupts` property. Their meaning
+/// depends on the `#interrupt-cells` of the node's interrupt parent.
+pub type Interrupts = ArrayVec<[u32; MAX_INTERRUPT_CELLS]>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceTreeError {
+    BadMagic,
+    Truncated,
+    UnsupportedVersion(u32),
+}
+
+pub struct DeviceTree<'a> {
+    structure: &'a [u8],
+    strings: &'a [u8],
+}
+
+impl<'a> DeviceTree<'a> {
+    pub fn new(blob: &'a [u8]) -> Result<DeviceTree<'a>, DeviceTreeError> {
+        let header = |field: usize| be_u32(blob, field * 4).ok_or(DeviceTreeError::Truncated);
+        if header(0)? != FDT_MAGIC {
+            return Err(DeviceTreeError::BadMagic);
+        }
+        let total_size = header(1)? as usize;
+        let structure_offset = header(2)? as usize;
+        let strings_offset = header(3)? as usize;
+        let last_compatible_version = header(6)?;
+        let strings_size = header(8)? as usize;
+        let structure_size = header(9)? as usize;
+
+        if last_compatible_version > SUPPORTED_VERSION {
+            return Err(DeviceTreeError::UnsupportedVersion(last_compatible_version));
+        }
+        let blob = blob.get(..total_size).ok_or(DeviceTreeError::Truncated)?;
+        Ok(DeviceTree {
+            structure: sub_slice(blob, structure_offset, structure_size)
+                .ok_or(DeviceTreeError::Truncated)?,
+            strings: sub_slice(blob, strings_offset, strings_size)
+                .ok_or(DeviceTreeError::Truncated)?,
+        })
+    }
+
+    /// Wrap a blob whose size is known only from its own header, as
+    /// is the case for one passed along by a bootloader.
+    ///
+    /// The memory at `blob` must be readable and remain unchanged for
+    /// the rest of the program.
+    pub unsafe fn from_raw(blob: *const u8) -> Result<DeviceTree<'static>, DeviceTreeError> {
+        let header = core::slice::from_raw_parts(blob, HEADER_BYTES);
+        if be_u32(header, 0) != Some(FDT_MAGIC) {
+            return Err(DeviceTreeError::BadMagic);
+        }
+        let total_size = be_u32(header, 4).ok_or(DeviceTreeError::Truncated)? as usize;
+        if total_size < HEADER_BYTES {
+            return Err(DeviceTreeError::Truncated);
+        }
+        DeviceTree::new(core::slice::from_raw_parts(blob, total_size))
+    }
+
+    /// Find the first node which lists `compat` among its
+    /// `compatible` strings and has a `reg` property. Returns the
+    /// physical address and length of its first register block along
+    /// with its interrupts, if any.
+    pub fn find_compatible(&self, compat: &str) -> Option<(usize, usize, Interrupts)> {
+        let mut nodes: ArrayVec<[Node; MAX_DEPTH]> = ArrayVec::new();
+        let mut offset = 0;
+        loop {
+            let token = be_u32(self.structure, offset)?;
+            offset += 4;
+            match token {
+                FDT_BEGIN_NODE => {
+                    // A node's properties all precede its children,
+                    // so by now the parent is fully described.
+                    if let Some(found) = nodes.last_mut().and_then(Node::take_match) {
+                        return Some(found);
+                    }
+                    let name_len = self.structure.get(offset..)?.iter().position(|b| *b == 0)?;
+                    offset = align4(offset + name_len + 1);
+                    let (address_cells, size_cells) = nodes
+                        .last()
+                        .map(|parent| (parent.address_cells, parent.size_cells))
+                        .unwrap_or((DEFAULT_ADDRESS_CELLS, DEFAULT_SIZE_CELLS));
+                    nodes.try_push(Node::new(address_cells, size_cells)).ok()?;
+                }
+                FDT_END_NODE => {
+                    if let Some(found) = nodes.pop().as_mut().and_then(Node::take_match) {
+                        return Some(found);
+                    }
+                }
+                FDT_PROP => {
+                    let len = be_u32(self.structure, offset)? as usize;
+                    let name_offset = be_u32(self.structure, offset + 4)? as usize;
+                    let value = sub_slice(self.structure, offset + 8, len)?;
+                    offset = align4(offset + 8 + len);
+
+                    let name = self.strings.get(name_offset..)?;
+                    let name = &name[..name.iter().position(|b| *b == 0)?];
+                    nodes.last_mut()?.observe_property(name, value, compat);
+                }
+                FDT_NOP => (),
+                FDT_END => return None,
+                _ => return None,
+            }
+        }
+    }
+}
+
+const DEFAULT_ADDRESS_CELLS: u32 = 2;
+const DEFAULT_SIZE_CELLS: u32 = 1;
+
+/// What we've learned so far about a node whose properties are being
+/// walked.
+struct Node {
+    /// The parent's `#address-cells` and `#size-cells`, which govern
+    /// how this node's `reg` is laid out.
+    reg_address_cells: u32,
+    reg_size_cells: u32,
+    /// This node's own `#address-cells` and `#size-cells`, for its
+    /// children.
+    address_cells: u32,
+    size_cells: u32,
+    is_compatible: bool,
+    reg: Option<(usize, usize)>,
+    interrupts: Interrupts,
+    checked: bool,
+}
+
+impl Node {
+    fn new(reg_address_cells: u32, reg_size_cells: u32) -> Node {
+        Node {
+            reg_address_cells,
+            reg_size_cells,
+            address_cells: DEFAULT_ADDRESS_CELLS,
+            size_cells: DEFAULT_SIZE_CELLS,
+            is_compatible: false,
+            reg: None,
+            interrupts: ArrayVec::new(),
+            checked: false,
+        }
+    }
+
+    fn observe_property(&mut self, name: &[u8], value: &[u8], compat: &str) {
+        match name {
+            b"compatible" => {
+                self.is_compatible = value.split(|b| *b == 0).any(|s| s == compat.as_bytes())
+            }
+            b"reg" => {
+                let address_bytes = self.reg_address_cells as usize * 4;
+                let size_bytes = self.reg_size_cells as usize * 4;
+                self.reg =
+                    read_cells(value.get(..address_bytes).unwrap_or(&[])).and_then(|paddr| {
+                        read_cells(value.get(address_bytes..address_bytes + size_bytes)?)
+                            .map(|len| (paddr, len))
+                    });
+            }
+            b"interrupts" => {
+                self.interrupts = value
+                    .chunks(4)
+                    .filter_map(|cell| be_u32(cell, 0))
+                    .take(MAX_INTERRUPT_CELLS)
+                    .collect()
+            }
+            b"#address-cells" => {
+                self.address_cells = be_u32(value, 0).unwrap_or(DEFAULT_ADDRESS_CELLS)
+            }
+            b"#size-cells" => self.size_cells = be_u32(value, 0).unwrap_or(DEFAULT_SIZE_CELLS),
+            _ => (),
+        }
+    }
+
+    /// Yield this node's details if it's the one we're looking for,
+    /// but only the first time we're asked.
+    fn take_match(&mut self) -> Option<(usize, usize, Interrupts)> {
+        if self.checked {
+            return None;
+        }
+        self.checked = true;
+        if !self.is_compatible {
+            return None;
+        }
+        let (paddr, len) = self.reg?;
+        Some((paddr, len, self.interrupts.clone()))
+    }
+}
+
+/// Combine big-endian cells into a single number. Values wider than
+/// a word are rejected.
+fn read_cells(bytes: &[u8]) -> Option<usize> {
+    if bytes.is_empty() || bytes.len() % 4 != 0 {
+        return None;
+    }
+    bytes.chunks(4).try_fold(0usize, |acc, cell| {
+        if acc.leading_zeros() < 32 {
+            return None;
+        }
+        Some(((acc as u64) << 32 | u64::from(be_u32(cell, 0)?)) as usize)
+    })
+}
+
+fn be_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    let b = sub_slice(bytes, offset, 4)?;
+    Some(u32::from(b[0]) << 24 | u32::from(b[1]) << 16 | u32::from(b[2]) << 8 | u32::from(b[3]))
+}
+
+fn sub_slice(bytes: &[u8], offset: usize, len: usize) -> Option<&[u8]> {
+    bytes.get(offset..offset.checked_add(len)?)
+}
+
+fn align4(offset: usize) -> usize {
+    (offset + 3) & !3
+}
@@ -16,7 +16,13 @@ pub enum SeL4Error {
     TCBWriteRegisters(KernelError),
     TCBReadRegisters(KernelError),
     TCBSetPriority(KernelError),
+    DomainSetSet(KernelError),
+    SchedControlConfigure(KernelError),
+    TCBSetSchedParams(KernelError),
+    TCBSetMCPriority(KernelError),
+    SchedContextBind(KernelError),
     TCBResume(KernelError),
+    TCBSuspend(KernelError),
     CNodeMutate(KernelError),
     CNodeMove(KernelError),
     CNodeDelete(KernelError),
@@ -25,12 +31,16 @@ pub enum SeL4Error {
     IRQHandlerAck(KernelError),
     GetPageAddr(KernelError),
     PageCleanInvalidateData(KernelError),
+    PageCleanData(KernelError),
+    PageInvalidateData(KernelError),
     CNodeRevoke(KernelError),
     VCPUInjectIRQ(KernelError),
     VCPUReadRegisters(KernelError),
     VCPUWriteRegisters(KernelError),
     VCPUBindTcb(KernelError),
     TCBBindNotification(KernelError),
+    TCBSetTLSBase(KernelError),
+    TCBSetAffinity(KernelError),
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
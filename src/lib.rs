@@ -8,6 +8,7 @@ extern crate selfe_sys;
 extern crate typenum;
 
 extern crate cross_queue;
+extern crate ferros_derive;
 extern crate smart_alloc;
 
 #[macro_use]
@@ -17,7 +18,11 @@ pub mod alloc;
 pub mod arch;
 pub mod bootstrap;
 pub mod cap;
+#[cfg(feature = "capdl")]
+pub mod capdl;
+pub mod device_tree;
 pub mod error;
+pub mod lz4;
 pub mod pow;
 #[cfg(feature = "test_support")]
 pub mod test_support;
@@ -0,0 +1,78 @@
+//! Decompression of LZ4 blocks, as produced by `ferros-build` for
+//! `ElfResource`s with `compress` set. Only the raw block format is
+//! handled; there is no frame header, and the decompressed size must
+//! be known up front (see `ElfProc::DECOMPRESSED_SIZE`).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Lz4Error {
+    /// The input ended partway through a sequence.
+    Truncated,
+    /// The decompressed data would not fit in the destination.
+    DestinationTooSmall,
+    /// A match referred back to before the start of the output.
+    InvalidOffset,
+}
+
+/// Decompress the LZ4 block `src` into the start of `dest`, returning
+/// the number of bytes written.
+pub fn decompress(src: &[u8], dest: &mut [u8]) -> Result<usize, Lz4Error> {
+    let mut in_pos = 0;
+    let mut out_pos = 0;
+
+    while in_pos < src.len() {
+        let token = src[in_pos];
+        in_pos += 1;
+
+        let literal_len = read_length(src, &mut in_pos, usize::from(token >> 4))?;
+        let literals = src
+            .get(in_pos..in_pos + literal_len)
+            .ok_or(Lz4Error::Truncated)?;
+        dest.get_mut(out_pos..out_pos + literal_len)
+            .ok_or(Lz4Error::DestinationTooSmall)?
+            .copy_from_slice(literals);
+        in_pos += literal_len;
+        out_pos += literal_len;
+
+        // The last sequence is literals only.
+        if in_pos == src.len() {
+            break;
+        }
+
+        let offset_bytes = src.get(in_pos..in_pos + 2).ok_or(Lz4Error::Truncated)?;
+        let offset = usize::from(offset_bytes[0]) | usize::from(offset_bytes[1]) << 8;
+        in_pos += 2;
+        if offset == 0 || offset > out_pos {
+            return Err(Lz4Error::InvalidOffset);
+        }
+
+        let match_len = read_length(src, &mut in_pos, usize::from(token & 0xf))? + 4;
+        if out_pos + match_len > dest.len() {
+            return Err(Lz4Error::DestinationTooSmall);
+        }
+        // Matches may overlap the bytes they produce, so this has to go
+        // a byte at a time.
+        for i in out_pos..out_pos + match_len {
+            dest[i] = dest[i - offset];
+        }
+        out_pos += match_len;
+    }
+
+    Ok(out_pos)
+}
+
+/// A length nibble of 15 means more length follows, in bytes which
+/// are summed until one is less than 255.
+fn read_length(src: &[u8], in_pos: &mut usize, nibble: usize) -> Result<usize, Lz4Error> {
+    let mut len = nibble;
+    if nibble == 0xf {
+        loop {
+            let byte = *src.get(*in_pos).ok_or(Lz4Error::Truncated)?;
+            *in_pos += 1;
+            len += usize::from(byte);
+            if byte != 0xff {
+                break;
+            }
+        }
+    }
+    Ok(len)
+}
@@ -0,0 +1,83 @@
+use super::types::TestOutcome;
+
+/// Carried back out of a test whose `assert_test!` or
+/// `assert_eq_test!` failed, when that test returns a `Result`. Holds
+/// the text of the failed condition.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TestAssertionFailure(pub &'static str);
+
+/// The return types a test may be short-circuited from by the
+/// assertion macros.
+pub trait FromTestAssertionFailure {
+    fn from_test_assertion_failure(failure: TestAssertionFailure) -> Self;
+}
+
+impl FromTestAssertionFailure for TestOutcome {
+    fn from_test_assertion_failure(_failure: TestAssertionFailure) -> Self {
+        TestOutcome::Failure
+    }
+}
+
+impl<T, E: From<TestAssertionFailure>> FromTestAssertionFailure for Result<T, E> {
+    fn from_test_assertion_failure(failure: TestAssertionFailure) -> Self {
+        Err(E::from(failure))
+    }
+}
+
+/// Fail the enclosing test unless `cond` holds, printing where and
+/// why. The test must return either `TestOutcome` or a `Result` whose
+/// error type implements `From<TestAssertionFailure>`.
+///
+/// ```ignore
+/// assert_test!(slots.size() == 4, "expected 4 slots, got {}", slots.size());
+/// ```
+#[macro_export]
+macro_rules! assert_test {
+    ($cond:expr) => {
+        $crate::assert_test!($cond, "{}", stringify!($cond))
+    };
+    ($cond:expr, $($arg:tt)+) => {
+        if !$cond {
+            $crate::debug_println!(
+                "assertion failed at {}:{}: {}",
+                file!(),
+                line!(),
+                format_args!($($arg)+)
+            );
+            return $crate::test_support::FromTestAssertionFailure::from_test_assertion_failure(
+                $crate::test_support::TestAssertionFailure(stringify!($cond)),
+            );
+        }
+    };
+}
+
+/// Fail the enclosing test unless `left == right`, printing both
+/// values. Has the same requirements on the test's return type as
+/// `assert_test!`.
+#[macro_export]
+macro_rules! assert_eq_test {
+    ($left:expr, $right:expr) => {
+        $crate::assert_eq_test!($left, $right, "")
+    };
+    ($left:expr, $right:expr, $($arg:tt)+) => {
+        match (&$left, &$right) {
+            (left_val, right_val) => {
+                if !(*left_val == *right_val) {
+                    $crate::debug_println!(
+                        "assertion failed at {}:{}: `(left == right)`\n  left: `{:?}`,\n right: `{:?}` {}",
+                        file!(),
+                        line!(),
+                        left_val,
+                        right_val,
+                        format_args!($($arg)+)
+                    );
+                    return $crate::test_support::FromTestAssertionFailure::from_test_assertion_failure(
+                        $crate::test_support::TestAssertionFailure(
+                            stringify!($left == $right),
+                        ),
+                    );
+                }
+            }
+        }
+    };
+}
@@ -9,12 +9,25 @@ use crate::cap::*;
 use crate::error::{ErrorExt, SeL4Error};
 use crate::pow::{Pow, _Pow};
 
+mod assert;
 mod resources;
 mod types;
+mod watchdog;
 
 use crate::vspace::MappedMemoryRegion;
+pub use assert::{FromTestAssertionFailure, TestAssertionFailure};
 pub use resources::*;
 pub use types::*;
+pub use watchdog::{
+    TestProcessSlotCount, TestProcessUntypedBitSize, TestStackBitSize, DEFAULT_TEST_TIMEOUT_TICKS,
+    TEST_TICK_CYCLES,
+};
+
+/// How many message registers a `T` takes up when sent over IPC, so
+/// that tests can pin the count on each architecture.
+pub fn message_length_in_words<T>() -> usize {
+    crate::userland::type_length_in_words::<T>()
+}
 
 impl TestReporter for crate::debug::DebugOutHandle {
     fn report(&mut self, test_name: &'static str, outcome: TestOutcome) {
@@ -31,6 +44,30 @@ impl TestReporter for crate::debug::DebugOutHandle {
         );
     }
 
+    fn report_timeout(&mut self, test_name: &'static str) {
+        use core::fmt::Write;
+        let _ = writeln!(self, "test {} ... FAILED (timed out)", test_name);
+    }
+
+    fn report_fault(&mut self, test_name: &'static str, fault: &crate::arch::fault::Fault) {
+        use core::fmt::Write;
+        let _ = writeln!(self, "test {} ... FAILED (faulted)\n{:?}", test_name, fault);
+    }
+
+    fn report_not_stopped(
+        &mut self,
+        test_name: &'static str,
+        suspend_error: &SeL4Error,
+        revoke_error: &SeL4Error,
+    ) {
+        use core::fmt::Write;
+        let _ = writeln!(
+            self,
+            "test {} ... FAILED (couldn't be stopped)\nsuspending: {:?}\ndeleting: {:?}",
+            test_name, suspend_error, revoke_error
+        );
+    }
+
     fn summary(&mut self, passed: u32, failed: u32) {
         use core::fmt::Write;
         let _ = writeln!(
@@ -50,56 +87,83 @@ impl TestReporter for crate::debug::DebugOutHandle {
 ///
 /// The &RunTest instances are expected to be references
 /// to functions annotated with `#[ferros_test]`, which
-/// transforms said tests to conform with the RunTest signature
+/// transforms said tests to conform with the RunTest signature.
+/// Each is paired with its name, as `#[ferros_test]` reports it,
+/// by which it's reported if it never finishes.
+///
+/// Each test runs on a thread of its own, or, if its `TestContext`
+/// is `Process`, as a process with its own address space, so that one
+/// which corrupts memory can't take the runner with it. One which
+/// faults, or which hasn't finished after the number of ticks of
+/// `Resources::test_timer` set with `Resources::set_test_timeout_ticks`,
+/// is torn down and counted as a failure, as is one which can't be
+/// torn down afterwards.
 pub fn execute_tests<'t, R: types::TestReporter>(
     mut reporter: R,
     resources: resources::TestResourceRefs<'t>,
-    tests: &[&types::RunTest],
-) -> Result<types::TestOutcome, SeL4Error> {
+    tests: &[(&'static str, &types::RunTest, types::TestContext)],
+) -> Result<types::TestOutcome, types::TestSetupError> {
     let resources::TestResourceRefs {
         slots,
         untyped,
         asid_pool,
-        mut scratch,
-        mapped_memory_region,
+        test_stack,
         cnode,
         thread_authority,
-        vspace_paging_root,
         user_image,
         irq_control,
+        domain_set,
+        watchdog,
     } = resources;
     let mut successes = 0;
     let mut failures = 0;
-    for t in tests {
+    for (name, t, context) in tests.iter() {
         with_temporary_resources(
             slots,
             untyped,
             asid_pool,
-            mapped_memory_region,
+            test_stack,
             irq_control,
             |inner_slots,
              inner_untyped,
              inner_asid_pool,
-             inner_mapped_memory_region,
+             inner_test_stack,
              inner_irq_control|
-             -> Result<(), SeL4Error> {
-                let (name, outcome) = t(
-                    inner_slots,
-                    inner_untyped,
-                    inner_asid_pool,
-                    &mut scratch,
-                    inner_mapped_memory_region,
-                    cnode,
-                    thread_authority,
-                    vspace_paging_root,
-                    user_image,
-                    inner_irq_control,
-                );
-                reporter.report(name, outcome);
-                if outcome == types::TestOutcome::Success {
-                    successes += 1;
-                } else {
-                    failures += 1;
+             -> Result<(), types::TestSetupError> {
+                let test_resources = watchdog::TestResources {
+                    test: *t,
+                    context: *context,
+                    slots: inner_slots,
+                    untyped: inner_untyped,
+                    asid_pool: inner_asid_pool,
+                    stack: inner_test_stack,
+                    irq_control: inner_irq_control,
+                    domain_set,
+                };
+                match watchdog.run(test_resources, cnode, thread_authority, user_image)? {
+                    watchdog::TestRun::Finished(name, outcome) => {
+                        reporter.report(name, outcome);
+                        if outcome == types::TestOutcome::Success {
+                            successes += 1;
+                        } else {
+                            failures += 1;
+                        }
+                    }
+                    watchdog::TestRun::TimedOut => {
+                        reporter.report_timeout(*name);
+                        failures += 1;
+                    }
+                    watchdog::TestRun::Faulted(fault) => {
+                        reporter.report_fault(*name, &fault);
+                        failures += 1;
+                    }
+                    watchdog::TestRun::NotStopped {
+                        suspend_error,
+                        revoke_error,
+                    } => {
+                        reporter.report_not_stopped(*name, &suspend_error, &revoke_error);
+                        failures += 1;
+                    }
                 }
                 Ok(())
             },
@@ -166,6 +230,7 @@ where
             cap_data: ASIDPool {
                 id: asid_pool.cap_data.id,
                 next_free_slot: asid_pool.cap_data.next_free_slot,
+                reclaimed: asid_pool.cap_data.reclaimed,
                 _free_slots: PhantomData,
             },
         },
@@ -4,7 +4,8 @@ use typenum::*;
 use crate::arch;
 use crate::bootstrap::*;
 use crate::cap::*;
-use crate::test_support::MaxMappedMemoryRegionBitSize;
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::{CapRights, Thread};
 use crate::vspace::*;
 
 pub struct Resources {
@@ -12,36 +13,37 @@ pub struct Resources {
     pub(super) untyped: LocalCap<Untyped<super::types::MaxTestUntypedSize>>,
     pub(super) asid_pool: LocalCap<ASIDPool<super::types::MaxTestASIDPoolSize>>,
     pub(super) vspace: VSpace<vspace_state::Imaged, role::Local>,
-    pub(super) scratch: ScratchRegion,
-    pub(super) mapped_memory_region: MappedMemoryRegion<
-        super::types::MaxMappedMemoryRegionBitSize,
-        crate::vspace::shared_status::Exclusive,
-    >,
+    /// Each test's thread or process runs on this stack, which stays
+    /// mapped here.
+    pub(super) test_stack:
+        MappedMemoryRegion<super::TestStackBitSize, crate::vspace::shared_status::Exclusive>,
     pub(super) cnode: LocalCap<LocalCNode>,
     pub(super) thread_authority: LocalCap<ThreadPriorityAuthority>,
-    pub(super) vspace_paging_root: LocalCap<crate::arch::PagingRoot>,
     pub(super) user_image: UserImage<role::Local>,
     pub(super) irq_control: LocalCap<IRQControl>,
+    pub(super) domain_set: super::types::TestDomainSet,
+    pub(super) watchdog: super::watchdog::Watchdog,
+    /// A badged copy of the test timer, for whatever ticks it.
+    pub(super) test_timer: LocalCap<Notification>,
 }
 
 pub struct TestResourceRefs<'t> {
     pub(super) slots: &'t mut LocalCNodeSlots<super::types::MaxTestCNodeSlots>,
     pub(super) untyped: &'t mut LocalCap<Untyped<super::types::MaxTestUntypedSize>>,
     pub(super) asid_pool: &'t mut LocalCap<ASIDPool<super::types::MaxTestASIDPoolSize>>,
-    pub(super) scratch: &'t mut ScratchRegion<crate::userland::process::DefaultStackPageCount>,
-    pub(super) mapped_memory_region: &'t mut MappedMemoryRegion<
-        super::types::MaxMappedMemoryRegionBitSize,
+    pub(super) test_stack: &'t mut MappedMemoryRegion<
+        super::TestStackBitSize,
         crate::vspace::shared_status::Exclusive,
     >,
     pub(super) cnode: &'t LocalCap<LocalCNode>,
     pub(super) thread_authority: &'t LocalCap<ThreadPriorityAuthority>,
-    pub(super) vspace_paging_root: &'t LocalCap<crate::arch::PagingRoot>,
     pub(super) user_image: &'t UserImage<role::Local>,
     pub(super) irq_control: &'t mut LocalCap<IRQControl>,
+    pub(super) domain_set: &'t super::types::TestDomainSet,
+    pub(super) watchdog: &'t mut super::watchdog::Watchdog,
 }
 
-type PageFallbackNextSize = Sum<U1, <Page<page_state::Unmapped> as DirectRetype>::SizeBits>;
-type MappedMemoryRegionFallbackNextSize = Sum<U1, MaxMappedMemoryRegionBitSize>;
+type TestStackFallbackNextSize = Sum<U1, super::TestStackBitSize>;
 
 impl Resources {
     pub fn with_debug_reporting(
@@ -58,6 +60,8 @@ impl Resources {
             user_image,
             root_tcb,
             irq_control,
+            #[cfg(KernelMultipleDomains)]
+            domain_set,
             ..
         } = BootInfo::wrap(
             &raw_boot_info,
@@ -66,28 +70,6 @@ impl Resources {
                 .ok_or_else(|| super::TestSetupError::InitialUntypedNotFound { bit_size: 14 })?,
             vspace_slots,
         );
-        let (extra_scratch_slots, local_slots) = local_slots.alloc();
-        let ut_for_scratch = {
-            match allocator.get_untyped::<<Page<page_state::Unmapped> as DirectRetype>::SizeBits>()
-            {
-                Some(v) => v,
-                None => {
-                    let ut_plus_one =
-                        allocator
-                            .get_untyped::<PageFallbackNextSize>()
-                            .ok_or_else(|| super::TestSetupError::InitialUntypedNotFound {
-                                bit_size: PageFallbackNextSize::USIZE,
-                            })?;
-                    let (ut_page, _) = ut_plus_one.split(extra_scratch_slots)?;
-                    ut_page
-                }
-            }
-        };
-        let (scratch_slots, local_slots) = local_slots.alloc();
-        let sacrificial_page = ut_for_scratch.retype(scratch_slots)?;
-        let scratch = root_vspace
-            .reserve(sacrificial_page)?
-            .as_scratch(&root_vspace)?;
         let (asid_pool_slots, local_slots) = local_slots.alloc();
         let (extra_pool_slots, local_slots) = local_slots.alloc();
         let ut_for_asid_pool = {
@@ -102,55 +84,189 @@ impl Resources {
                 }
             }
         };
-        let (asid_pool, _asid_control) =
+        let (asid_pool, asid_control) =
             asid_control.allocate_asid_pool(ut_for_asid_pool, asid_pool_slots)?;
 
-        let (extra_memory_slots, local_slots) = local_slots.alloc();
-        let memory_region_ut = match allocator.get_untyped::<MaxMappedMemoryRegionBitSize>() {
+        let (extra_stack_slots, local_slots) = local_slots.alloc();
+        let test_stack_ut = match allocator.get_untyped::<super::TestStackBitSize>() {
             Some(v) => v,
             None => {
                 let ut_fallback = allocator
-                    .get_untyped::<MappedMemoryRegionFallbackNextSize>()
+                    .get_untyped::<TestStackFallbackNextSize>()
                     .ok_or_else(|| super::TestSetupError::InitialUntypedNotFound {
-                        bit_size: MappedMemoryRegionFallbackNextSize::USIZE,
+                        bit_size: TestStackFallbackNextSize::USIZE,
                     })?;
-                let (ut_target, _) = ut_fallback.split(extra_memory_slots)?;
+                let (ut_target, _) = ut_fallback.split(extra_stack_slots)?;
                 ut_target
             }
         };
 
+        let (test_stack_slots, local_slots) = local_slots.alloc();
+        let test_stack = root_vspace.map_region(
+            UnmappedMemoryRegion::new(test_stack_ut, test_stack_slots)?,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )?;
+        // Claim the test untyped before splitting anything else for
+        // the watchdog, which might otherwise carve it up.
+        let untyped = allocator
+            .get_untyped::<super::types::MaxTestUntypedSize>()
+            .ok_or_else(|| super::TestSetupError::InitialUntypedNotFound {
+                bit_size: super::types::MaxTestUntypedSize::USIZE,
+            })?;
+
+        let (watchdog_split_slots, local_slots): (LocalCNodeSlots<U128>, _) = local_slots.alloc();
+        let mut watchdog_split_slots = watchdog_split_slots.weaken();
+        let process_untyped = allocator
+            .get_untyped_splitting::<super::TestProcessUntypedBitSize>(&mut watchdog_split_slots)?;
+        let (process_slots, local_slots) = local_slots.alloc();
+        let process_asid_pool_ut =
+            allocator.get_untyped_splitting::<U12>(&mut watchdog_split_slots)?;
+        let (process_asid_pool_slots, local_slots) = local_slots.alloc();
+        let (process_asid_pool, _asid_control) =
+            asid_control.allocate_asid_pool(process_asid_pool_ut, process_asid_pool_slots)?;
+        let timer_ut = allocator
+            .get_untyped_splitting::<<Notification as DirectRetype>::SizeBits>(
+                &mut watchdog_split_slots,
+            )?;
+        let (timer_slot, local_slots) = local_slots.alloc();
+        let timer: LocalCap<Notification> = timer_ut.retype(timer_slot)?;
+        let (test_timer_slot, local_slots) = local_slots.alloc();
+        let test_timer = timer.mint(
+            &cnode,
+            test_timer_slot,
+            CapRights::RWG,
+            Badge::from(super::watchdog::TEST_TIMER_BADGE),
+        )?;
+        unsafe { selfe_sys::seL4_TCB_BindNotification(root_tcb.cptr, timer.cptr) }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBBindNotification(e))?;
+        let thread_authority = root_tcb.downgrade_to_thread_priority_authority();
+
+        // Tick the test timer by the cycle counter, where the kernel
+        // lets user level read one.
+        let (ticker_slots, local_slots): (LocalCNodeSlots<U4>, _) = local_slots.alloc();
+        if crate::arch::Stopwatch::start().is_ok() {
+            let (ticker_timer_slot, ticker_slots) = ticker_slots.alloc();
+            let ticker_timer = timer.mint(
+                &cnode,
+                ticker_timer_slot,
+                CapRights::RWG,
+                Badge::from(super::watchdog::TEST_TIMER_BADGE),
+            )?;
+            let ticker_stack_ut = allocator
+                .get_untyped_splitting::<super::watchdog::TestTickerStackBitSize>(
+                    &mut watchdog_split_slots,
+                )?;
+            let (ticker_stack_slots, ticker_slots) = ticker_slots.alloc();
+            let ticker_stack = root_vspace.map_region(
+                UnmappedMemoryRegion::new(ticker_stack_ut, ticker_stack_slots)?,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+            )?;
+            let ticker_ipc_buffer_ut =
+                allocator.get_untyped_splitting::<arch::PageBits>(&mut watchdog_split_slots)?;
+            let (ticker_ipc_buffer_slot, ticker_slots) = ticker_slots.alloc();
+            let ticker_ipc_buffer = root_vspace.map_region(
+                UnmappedMemoryRegion::new(ticker_ipc_buffer_ut, ticker_ipc_buffer_slot)?,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+            )?;
+            let ticker_tcb_ut = allocator
+                .get_untyped_splitting::<<ThreadControlBlock as DirectRetype>::SizeBits>(
+                    &mut watchdog_split_slots,
+                )?;
+            let (ticker_tcb_slot, _ticker_slots) = ticker_slots.alloc();
+            let ticker = Thread::<super::watchdog::TestTickerStackBitSize>::new(
+                root_vspace.root(),
+                Cap {
+                    cptr: cnode.cptr,
+                    _role: core::marker::PhantomData,
+                    cap_data: CNode {
+                        radix: cnode.cap_data.radix,
+                        _role: core::marker::PhantomData,
+                    },
+                },
+                ticker_stack,
+                super::watchdog::run_test_ticker as extern "C" fn(_) -> (),
+                super::watchdog::TestTicker {
+                    timer: ticker_timer,
+                    cycles_per_tick: super::TEST_TICK_CYCLES,
+                },
+                ticker_ipc_buffer,
+                ticker_tcb_ut,
+                ticker_tcb_slot,
+                &thread_authority,
+                None,
+            )?;
+            ticker.start()?;
+        }
+
+        // What a test run on a thread of its own is handed, in place
+        // of the scratch region and memory region a process gets.
+        let scratch_ut = allocator
+            .get_untyped_splitting::<<Page<page_state::Unmapped> as DirectRetype>::SizeBits>(
+                &mut watchdog_split_slots,
+            )?;
+        let (scratch_slot, local_slots) = local_slots.alloc();
+        let sacrificial_page = scratch_ut.retype(scratch_slot)?;
+        let scratch = root_vspace
+            .reserve(sacrificial_page)?
+            .as_scratch(&root_vspace)?;
+        let memory_region_ut = allocator
+            .get_untyped_splitting::<super::types::MaxMappedMemoryRegionBitSize>(
+                &mut watchdog_split_slots,
+            )?;
         let (memory_region_slots, local_slots) = local_slots.alloc();
-        let unmapped_region: UnmappedMemoryRegion<
-            MaxMappedMemoryRegionBitSize,
-            shared_status::Exclusive,
-        > = UnmappedMemoryRegion::new(memory_region_ut, memory_region_slots)?;
         let mapped_memory_region = root_vspace.map_region(
-            unmapped_region,
-            crate::userland::CapRights::RW,
+            UnmappedMemoryRegion::new(memory_region_ut, memory_region_slots)?,
+            CapRights::RW,
             arch::vm_attributes::DEFAULT,
         )?;
+        let watchdog = super::watchdog::Watchdog {
+            process_untyped,
+            process_slots,
+            asid_pool: process_asid_pool,
+            timer,
+            timeout_ticks: super::DEFAULT_TEST_TIMEOUT_TICKS,
+            scratch,
+            mapped_memory_region,
+            paging_root: Cap {
+                cptr: root_vspace.root().cptr,
+                _role: core::marker::PhantomData,
+                cap_data: arch::PagingRoot {},
+            },
+            ipc_buffer: Cap {
+                cptr: selfe_sys::seL4_CapInitThreadIPCBuffer as usize,
+                _role: core::marker::PhantomData,
+                cap_data: Page {
+                    state: page_state::Mapped {
+                        vaddr: raw_boot_info.ipcBuffer as usize,
+                        asid: InternalASID { asid: 0 },
+                        rights: CapRights::RW,
+                    },
+                },
+            },
+        };
+
         let (slots, _local_slots) = local_slots.alloc();
         Ok((
             Resources {
                 slots,
-                untyped: allocator
-                    .get_untyped::<super::types::MaxTestUntypedSize>()
-                    .ok_or_else(|| super::TestSetupError::InitialUntypedNotFound {
-                        bit_size: super::types::MaxTestUntypedSize::USIZE,
-                    })?,
+                untyped,
                 asid_pool,
                 vspace: root_vspace,
-                scratch,
-                mapped_memory_region,
+                test_stack,
                 cnode,
-                thread_authority: root_tcb.downgrade_to_thread_priority_authority(),
-                vspace_paging_root: Cap {
-                    cptr: selfe_sys::seL4_CapInitThreadVSpace as usize,
-                    cap_data: crate::arch::PagingRoot {},
-                    _role: core::marker::PhantomData,
-                },
+                thread_authority,
                 user_image,
                 irq_control,
+                #[cfg(KernelMultipleDomains)]
+                domain_set,
+                #[cfg(not(KernelMultipleDomains))]
+                domain_set: (),
+                watchdog,
+                test_timer,
             },
             crate::debug::DebugOutHandle,
         ))
@@ -161,13 +277,33 @@ impl Resources {
             slots: &mut self.slots,
             untyped: &mut self.untyped,
             asid_pool: &mut self.asid_pool,
-            scratch: &mut self.scratch,
-            mapped_memory_region: &mut self.mapped_memory_region,
+            test_stack: &mut self.test_stack,
             cnode: &self.cnode,
             thread_authority: &self.thread_authority,
-            vspace_paging_root: &self.vspace_paging_root,
             user_image: &self.user_image,
             irq_control: &mut self.irq_control,
+            domain_set: &self.domain_set,
+            watchdog: &mut self.watchdog,
         }
     }
+
+    /// The test timer, which is signalled once per tick. A test which
+    /// hasn't finished after the number of ticks set with
+    /// `set_test_timeout_ticks` is given up on.
+    ///
+    /// The runner ticks it every `TEST_TICK_CYCLES` cycles itself when
+    /// the kernel lets user level read a cycle counter
+    /// (`KernelExportPMCUser` or `KernelArmExportVCNTUser`). Otherwise
+    /// something else has to, e.g. a driver thread which handles a
+    /// periodic timer's IRQ, and until something does, no test is ever
+    /// timed out.
+    pub fn test_timer(&self) -> &LocalCap<Notification> {
+        &self.test_timer
+    }
+
+    /// Set how many ticks of the test timer a test may run for.
+    /// Defaults to `DEFAULT_TEST_TIMEOUT_TICKS`.
+    pub fn set_test_timeout_ticks(&mut self, ticks: usize) {
+        self.watchdog.timeout_ticks = ticks;
+    }
 }
@@ -1,10 +1,12 @@
 use typenum::*;
 
-use crate::alloc::micro_alloc::Error as AllocError;
+use crate::alloc::micro_alloc::{Error as AllocError, SplittingAllocError};
+use crate::alloc::ut_buddy::UTBuddyError;
 use crate::bootstrap::*;
 use crate::cap::*;
 use crate::error::SeL4Error;
 use crate::pow::Pow;
+use crate::userland::{ProcessSetupError, ThreadSetupError};
 use crate::vspace::*;
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -17,6 +19,36 @@ pub type MaxTestUntypedSize = U27;
 pub type MaxTestCNodeSlots = Pow<U17>;
 pub type MaxTestASIDPoolSize = crate::arch::ASIDPoolSize;
 pub type MaxMappedMemoryRegionBitSize = U20;
+
+/// What a test asking for a `&LocalCap<DomainSet>` is handed. Kernels
+/// with a single scheduling domain have no `DomainSet`, and there
+/// tests are handed nothing in its place.
+#[cfg(KernelMultipleDomains)]
+pub type TestDomainSet = LocalCap<DomainSet>;
+#[cfg(not(KernelMultipleDomains))]
+pub type TestDomainSet = ();
+
+/// Where a test runs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TestContext {
+    /// On a thread of the runner's own, in the root task's address
+    /// space, where the code image is writable and statics work as
+    /// they would in the root task. The default.
+    Local,
+    /// In a process of its own, whose address space holds a read-only
+    /// copy of the code image, so that a test which scribbles over
+    /// memory can only damage its own. Asked for with
+    /// `#[ferros_test(process)]`.
+    Process,
+}
+
+/// Implemented by `#[ferros_test]` for a type of the same name as each
+/// test, so that `ferros_test_main!` can tell where to run it.
+#[doc(hidden)]
+pub trait TestCase {
+    const CONTEXT: TestContext;
+}
+
 pub type RunTest = Fn(
     LocalCNodeSlots<MaxTestCNodeSlots>,
     LocalCap<Untyped<MaxTestUntypedSize>>,
@@ -31,11 +63,35 @@ pub type RunTest = Fn(
     &LocalCap<crate::arch::PagingRoot>,
     &UserImage<role::Local>,
     LocalCap<IRQControl>,
+    &TestDomainSet,
 ) -> (&'static str, TestOutcome);
 
 pub trait TestReporter {
     fn report(&mut self, test_name: &'static str, outcome: TestOutcome);
 
+    /// Called in place of `report` for a test which didn't finish
+    /// within its time budget.
+    fn report_timeout(&mut self, test_name: &'static str) {
+        self.report(test_name, TestOutcome::Failure)
+    }
+
+    /// Called in place of `report` for a test whose thread or process
+    /// faulted.
+    fn report_fault(&mut self, test_name: &'static str, _fault: &crate::arch::fault::Fault) {
+        self.report(test_name, TestOutcome::Failure)
+    }
+
+    /// Called in place of `report` for a test whose thread or process
+    /// couldn't be stopped afterwards, and so may still be running.
+    fn report_not_stopped(
+        &mut self,
+        test_name: &'static str,
+        _suspend_error: &SeL4Error,
+        _revoke_error: &SeL4Error,
+    ) {
+        self.report(test_name, TestOutcome::Failure)
+    }
+
     fn summary(&mut self, passed: u32, failed: u32);
 }
 
@@ -43,8 +99,13 @@ pub trait TestReporter {
 pub enum TestSetupError {
     InitialUntypedNotFound { bit_size: usize },
     AllocError(AllocError),
+    SplittingAllocError(SplittingAllocError),
     SeL4Error(SeL4Error),
     VSpaceError(VSpaceError),
+    RetypeError(RetypeError),
+    UTBuddyError(UTBuddyError),
+    ProcessSetupError(ProcessSetupError),
+    ThreadSetupError(ThreadSetupError),
 }
 
 impl From<AllocError> for TestSetupError {
@@ -53,6 +114,12 @@ impl From<AllocError> for TestSetupError {
     }
 }
 
+impl From<SplittingAllocError> for TestSetupError {
+    fn from(e: SplittingAllocError) -> Self {
+        TestSetupError::SplittingAllocError(e)
+    }
+}
+
 impl From<SeL4Error> for TestSetupError {
     fn from(e: SeL4Error) -> Self {
         TestSetupError::SeL4Error(e)
@@ -64,3 +131,27 @@ impl From<VSpaceError> for TestSetupError {
         TestSetupError::VSpaceError(e)
     }
 }
+
+impl From<RetypeError> for TestSetupError {
+    fn from(e: RetypeError) -> Self {
+        TestSetupError::RetypeError(e)
+    }
+}
+
+impl From<UTBuddyError> for TestSetupError {
+    fn from(e: UTBuddyError) -> Self {
+        TestSetupError::UTBuddyError(e)
+    }
+}
+
+impl From<ProcessSetupError> for TestSetupError {
+    fn from(e: ProcessSetupError) -> Self {
+        TestSetupError::ProcessSetupError(e)
+    }
+}
+
+impl From<ThreadSetupError> for TestSetupError {
+    fn from(e: ThreadSetupError) -> Self {
+        TestSetupError::ThreadSetupError(e)
+    }
+}
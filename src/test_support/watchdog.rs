@@ -0,0 +1,658 @@
+//! Each test runs on a thread of its own, or in a process of its own
+//! if it asks for one with `#[ferros_test(process)]`, while the root
+//! thread keeps watch. Either way, it reports its outcome over an
+//! endpoint which is also its fault endpoint, so one which faults is
+//! counted as a failure rather than taking the runner down, and one
+//! which never reports back can be abandoned rather than stalling the
+//! rest of the suite.
+//!
+//! A test thread shares the root task's address space, code image
+//! and all, so it can write to statics as the root task can; a test
+//! which scribbles over the runner's memory can't be caught this way.
+//! A test process has a fresh address space, into which the code
+//! image is mapped read only, and its own stack, IPC buffer and TCB,
+//! so such a test can only damage its own memory.
+//!
+//! Both run in the root CNode itself, since that's where the
+//! capabilities a `RunTest` is handed live. Only memory is isolated,
+//! not capabilities.
+//!
+//! Time is kept by the test timer, a notification which the runner's
+//! ticker thread signals once per tick, going by the cycle counter.
+//! Where the kernel doesn't let user level read a cycle counter, the
+//! ticker isn't started, and something else, such as a timer driver,
+//! has to signal it instead; see `Resources::test_timer`. If nothing
+//! does, tests are never timed out.
+use core::marker::PhantomData;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::alloc::ut_buddy::{weak_ut_buddy, WUTBuddy};
+use crate::arch::fault::Fault;
+use crate::arch::userland::process::{set_thread_link_register, setup_initial_stack_and_regs};
+use crate::arch::{self, PageBits, PagingRoot, Stopwatch};
+use crate::bootstrap::UserImage;
+use crate::cap::*;
+use crate::error::{ErrorExt, SeL4Error};
+use crate::userland::process::{yield_forever, yield_now};
+use crate::userland::{
+    type_length_in_words, AddressSpaceIndependent, CapRights, FaultSource, IPCBuffer, MessageInfo,
+    RetypeForSetup, Sender, StandardProcess,
+};
+use crate::vspace::*;
+
+use super::types::{
+    MaxMappedMemoryRegionBitSize, MaxTestASIDPoolSize, MaxTestCNodeSlots, MaxTestUntypedSize,
+    RunTest, TestContext, TestDomainSet, TestOutcome, TestSetupError,
+};
+
+pub type TestStackBitSize = U20;
+
+/// What each test's thread or process is made of, apart from its
+/// stack: its TCB and endpoint, and for a process its paging
+/// structures, IPC buffer, a scratch page, and the
+/// `MaxMappedMemoryRegionBitSize` region the test is handed.
+pub type TestProcessUntypedBitSize = U21;
+pub type TestProcessSlotCount = U4096;
+/// Enough for the code image and the paging structures beneath it.
+type TestProcessVSpaceSlotCount = U2048;
+type TestProcessPagingBitSize = U16;
+
+/// How many ticks of the test timer a test may run for before it's
+/// given up on: ten seconds, for a 100Hz timer.
+pub const DEFAULT_TEST_TIMEOUT_TICKS: usize = 1000;
+
+/// How many cycle counter ticks the ticker counts as one tick of the
+/// test timer: a hundredth of a second, for a 1GHz counter such as
+/// QEMU's.
+pub const TEST_TICK_CYCLES: u64 = 10_000_000;
+
+pub(super) type TestTickerStackBitSize = U14;
+
+/// The badge on the test's end of its endpoint.
+const TEST_PROCESS_BADGE: usize = 1;
+
+/// The badge on the copy of the test timer handed out to whatever
+/// ticks it. The timer is bound to the root thread, so its signals
+/// arrive through the same receive as the test's messages, and are
+/// told apart from them by this.
+pub(super) const TEST_TIMER_BADGE: usize = 1 << 1;
+
+/// What a test's thread or process sends back when the test finishes.
+type TestReport = (&'static str, TestOutcome);
+
+pub struct Watchdog {
+    /// Everything a test's thread or process is made of, apart from
+    /// its stack, comes out of this untyped and these slots. Both are
+    /// revoked once the test is over.
+    pub(super) process_untyped: LocalCap<Untyped<TestProcessUntypedBitSize>>,
+    pub(super) process_slots: LocalCNodeSlots<TestProcessSlotCount>,
+    /// The runner's own pool, so that the ASID of a test's process
+    /// can't clash with one the test hands out from its pool.
+    pub(super) asid_pool: LocalCap<ASIDPool<arch::ASIDPoolSize>>,
+    /// The test timer, bound to the root thread.
+    pub(super) timer: LocalCap<Notification>,
+    pub(super) timeout_ticks: usize,
+    /// The scratch region and memory region a test thread is handed,
+    /// both in the root task's address space. A test process is given
+    /// its own.
+    pub(super) scratch: ScratchRegion,
+    pub(super) mapped_memory_region:
+        MappedMemoryRegion<MaxMappedMemoryRegionBitSize, shared_status::Exclusive>,
+    /// The root task's paging root, in which a test thread runs.
+    pub(super) paging_root: LocalCap<PagingRoot>,
+    /// The root thread's own IPC buffer, which a test thread is handed
+    /// too. The root thread is blocked on the test's endpoint for as
+    /// long as the test runs, so the two never use it at once.
+    pub(super) ipc_buffer: LocalCap<Page<page_state::Mapped>>,
+}
+
+/// How a test run under the watchdog came to an end.
+pub(super) enum TestRun {
+    Finished(&'static str, TestOutcome),
+    TimedOut,
+    Faulted(Fault),
+    /// The test's thread or process could be neither suspended nor
+    /// deleted afterwards, so it may still be running.
+    NotStopped {
+        suspend_error: SeL4Error,
+        revoke_error: SeL4Error,
+    },
+}
+
+/// What of a test's thread or process has been made so far, for
+/// `tear_down` to undo.
+#[derive(Default)]
+struct Started {
+    tcb: Option<usize>,
+    /// The ASID of a test's process, taken from the runner's pool.
+    asid: Option<InternalASID>,
+}
+
+enum TearDownError {
+    /// The test was stopped, but what it was made of couldn't all be
+    /// deleted, so there's nothing to run the next test with.
+    NotDeleted(SeL4Error),
+    NotStopped {
+        suspend_error: SeL4Error,
+        revoke_error: SeL4Error,
+    },
+}
+
+/// The parameter of a test's thread or process: the test, along with
+/// what it's to be called with.
+struct TestProcess {
+    /// Points into the code image, which is mapped at the same
+    /// addresses in a test's process as in the root task's.
+    /// `#[ferros_test]` functions capture nothing, so that's all the
+    /// test refers to.
+    test: *const RunTest,
+    slots: LocalCNodeSlots<MaxTestCNodeSlots>,
+    untyped: LocalCap<Untyped<MaxTestUntypedSize>>,
+    asid_pool: LocalCap<ASIDPool<MaxTestASIDPoolSize>>,
+    scratch: ScratchRegion,
+    mapped_memory_region:
+        MappedMemoryRegion<MaxMappedMemoryRegionBitSize, shared_status::Exclusive>,
+    cnode: LocalCap<LocalCNode>,
+    thread_authority: LocalCap<ThreadPriorityAuthority>,
+    vspace_paging_root: LocalCap<PagingRoot>,
+    user_image: UserImage<role::Local>,
+    irq_control: LocalCap<IRQControl>,
+    domain_set: TestDomainSet,
+    report_sender: Sender<TestReport, role::Local>,
+}
+
+// The test's CSpace is the root CNode, so its capabilities are the
+// same as seen from either side, and the test pointer is covered
+// above.
+unsafe impl AddressSpaceIndependent for TestProcess {}
+unsafe impl Send for TestProcess {}
+unsafe impl Sync for TestProcess {}
+
+impl RetypeForSetup for TestProcess {
+    type Output = TestProcess;
+}
+
+extern "C" fn run_test_process(process: TestProcess) {
+    let TestProcess {
+        test,
+        slots,
+        untyped,
+        asid_pool,
+        mut scratch,
+        mapped_memory_region,
+        cnode,
+        thread_authority,
+        vspace_paging_root,
+        user_image,
+        irq_control,
+        domain_set,
+        report_sender,
+    } = process;
+    let test = unsafe { &*test };
+    let report = test(
+        slots,
+        untyped,
+        asid_pool,
+        &mut scratch,
+        mapped_memory_region,
+        &cnode,
+        &thread_authority,
+        &vspace_paging_root,
+        &user_image,
+        irq_control,
+        &domain_set,
+    );
+    // The runner stops waiting once it has given up on the test, so
+    // a report it won't take is nothing to worry about.
+    let _ = report_sender.blocking_send(&report);
+}
+
+/// The parameter of the ticker's thread, which runs in the root task's
+/// own address space and CSpace.
+pub(super) struct TestTicker {
+    pub(super) timer: LocalCap<Notification>,
+    pub(super) cycles_per_tick: u64,
+}
+
+unsafe impl AddressSpaceIndependent for TestTicker {}
+unsafe impl Send for TestTicker {}
+unsafe impl Sync for TestTicker {}
+
+impl RetypeForSetup for TestTicker {
+    type Output = TestTicker;
+}
+
+/// Signal the test timer every `cycles_per_tick` cycles. The ticker
+/// runs at the same priority as the tests, and yields whenever it has
+/// nothing to do, so a test which spins rather than blocks still lets
+/// it in once per timeslice.
+pub(super) extern "C" fn run_test_ticker(ticker: TestTicker) {
+    let mut stopwatch = match Stopwatch::start() {
+        Ok(stopwatch) => stopwatch,
+        Err(_) => return,
+    };
+    let mut cycles = 0;
+    loop {
+        cycles += stopwatch.lap();
+        if cycles >= ticker.cycles_per_tick {
+            // Ticks missed while something else had the core would
+            // coalesce in the notification anyway, so only the
+            // remainder is carried over.
+            cycles %= ticker.cycles_per_tick;
+            ticker.timer.signal();
+        }
+        yield_now();
+    }
+}
+
+#[cfg(KernelMultipleDomains)]
+fn alias_domain_set(domain_set: &TestDomainSet) -> TestDomainSet {
+    Cap {
+        cptr: domain_set.cptr,
+        _role: PhantomData,
+        cap_data: PhantomCap::phantom_instance(),
+    }
+}
+
+#[cfg(not(KernelMultipleDomains))]
+fn alias_domain_set(_domain_set: &TestDomainSet) -> TestDomainSet {}
+
+fn alias_cnode<Role: CNodeRole>(cnode: &LocalCap<LocalCNode>) -> LocalCap<CNode<Role>> {
+    Cap {
+        cptr: cnode.cptr,
+        _role: PhantomData,
+        cap_data: CNode {
+            radix: cnode.cap_data.radix,
+            _role: PhantomData,
+        },
+    }
+}
+
+/// The runner's resources a test is handed, as aliased for it by
+/// `with_temporary_resources`.
+pub(super) struct TestResources<'t> {
+    pub(super) test: &'t RunTest,
+    pub(super) context: TestContext,
+    pub(super) slots: LocalCNodeSlots<MaxTestCNodeSlots>,
+    pub(super) untyped: LocalCap<Untyped<MaxTestUntypedSize>>,
+    pub(super) asid_pool: LocalCap<ASIDPool<MaxTestASIDPoolSize>>,
+    /// The stack of the test's thread or process, mapped in the root
+    /// task.
+    pub(super) stack: MappedMemoryRegion<TestStackBitSize, shared_status::Exclusive>,
+    pub(super) irq_control: LocalCap<IRQControl>,
+    pub(super) domain_set: &'t TestDomainSet,
+}
+
+impl Watchdog {
+    /// Run a test on a fresh thread or in a fresh process, as its
+    /// context asks, waiting at most `timeout_ticks` ticks of the test
+    /// timer for it to report back. However it ends, the thread or
+    /// process is stopped before this returns.
+    pub(super) fn run(
+        &mut self,
+        resources: TestResources<'_>,
+        cnode: &LocalCap<LocalCNode>,
+        thread_authority: &LocalCap<ThreadPriorityAuthority>,
+        user_image: &UserImage<role::Local>,
+    ) -> Result<TestRun, TestSetupError> {
+        let mut started = Started::default();
+        let run =
+            self.start_and_watch(&mut started, resources, cnode, thread_authority, user_image);
+
+        match self.tear_down(cnode, started) {
+            Ok(()) => run,
+            Err(TearDownError::NotDeleted(e)) => Err(e.into()),
+            // The test may still be running, and holding on to what it
+            // was handed, so the tests after it might fail too. That's
+            // still no reason to take the rest of the suite down.
+            Err(TearDownError::NotStopped {
+                suspend_error,
+                revoke_error,
+            }) => Ok(TestRun::NotStopped {
+                suspend_error,
+                revoke_error,
+            }),
+        }
+    }
+
+    /// Stop a test's thread or process, if it got as far as having a
+    /// TCB, and delete everything it was made of by revoking the
+    /// untyped and slots.
+    ///
+    /// The test must not be left running once the runner has
+    /// moved on, as it still holds capabilities to resources which
+    /// are about to be handed to the next test. Revoking the untyped
+    /// deletes its TCB, so that stops it even if suspending it
+    /// failed. Deleting a process's paging root frees its ASID, which
+    /// then goes back to the pool.
+    fn tear_down(
+        &mut self,
+        cnode: &LocalCap<LocalCNode>,
+        started: Started,
+    ) -> Result<(), TearDownError> {
+        let suspended = match started.tcb {
+            Some(tcb) => unsafe { seL4_TCB_Suspend(tcb) }
+                .as_result()
+                .map_err(|e| SeL4Error::TCBSuspend(e)),
+            None => Ok(()),
+        };
+        let revoked = unsafe {
+            seL4_CNode_Revoke(
+                cnode.cptr,
+                self.process_untyped.cptr,
+                seL4_WordBits as u8, // depth
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::CNodeRevoke(e));
+        unsafe { self.process_slots.revoke_in_reverse() }
+
+        match (suspended, revoked) {
+            (_, Ok(())) => {
+                if let Some(asid) = started.asid {
+                    match self.asid_pool_alias().reclaim(FreedASID { asid }) {
+                        Ok(pool) => self.asid_pool.cap_data.reclaimed = pool.cap_data.reclaimed,
+                        Err(_) => unreachable!("the ASID was allocated from the runner's pool"),
+                    }
+                }
+                Ok(())
+            }
+            (Ok(()), Err(e)) => Err(TearDownError::NotDeleted(e)),
+            (Err(suspend_error), Err(revoke_error)) => Err(TearDownError::NotStopped {
+                suspend_error,
+                revoke_error,
+            }),
+        }
+    }
+
+    fn asid_pool_alias(&self) -> LocalCap<ASIDPool<arch::ASIDPoolSize>> {
+        Cap {
+            cptr: self.asid_pool.cptr,
+            _role: PhantomData,
+            cap_data: ASIDPool {
+                id: self.asid_pool.cap_data.id,
+                next_free_slot: self.asid_pool.cap_data.next_free_slot,
+                reclaimed: self.asid_pool.cap_data.reclaimed,
+                _free_slots: PhantomData,
+            },
+        }
+    }
+
+    fn start_and_watch(
+        &mut self,
+        started: &mut Started,
+        resources: TestResources<'_>,
+        cnode: &LocalCap<LocalCNode>,
+        thread_authority: &LocalCap<ThreadPriorityAuthority>,
+        user_image: &UserImage<role::Local>,
+    ) -> Result<TestRun, TestSetupError> {
+        let mut slots = LocalCNodeSlots::<TestProcessSlotCount>::internal_new(
+            self.process_slots.cptr,
+            self.process_slots.cap_data.offset,
+        )
+        .weaken();
+        let untyped: LocalCap<Untyped<TestProcessUntypedBitSize>> = Cap {
+            cptr: self.process_untyped.cptr,
+            _role: PhantomData,
+            cap_data: Untyped {
+                _bit_size: PhantomData,
+                kind: self.process_untyped.cap_data.kind.clone(),
+            },
+        };
+        let mut uts = weak_ut_buddy(untyped.weaken());
+
+        let endpoint: LocalCap<Endpoint> = uts
+            .alloc(&mut slots, <Endpoint as DirectRetype>::SizeBits::U8)?
+            .retype(&mut slots)?;
+        let test_endpoint = endpoint.mint(
+            cnode,
+            slots.alloc_strong().map_err(RetypeError::from)?,
+            CapRights::RWG,
+            Badge::from(TEST_PROCESS_BADGE),
+        )?;
+        let fault_source = FaultSource {
+            endpoint: Cap {
+                cptr: test_endpoint.cptr,
+                _role: PhantomData,
+                cap_data: FaultEndpoint {},
+            },
+        };
+        let report_sender = Sender {
+            endpoint: test_endpoint,
+            _msg: PhantomData,
+        };
+
+        // Drop any tick left over from before the test started.
+        let _ = self.timer.poll();
+        match resources.context {
+            TestContext::Local => self.start_thread(
+                started,
+                &mut slots,
+                &mut uts,
+                resources,
+                fault_source,
+                report_sender,
+                cnode,
+                thread_authority,
+                user_image,
+            )?,
+            TestContext::Process => self.start_process(
+                started,
+                &mut slots,
+                &mut uts,
+                resources,
+                fault_source,
+                report_sender,
+                cnode,
+                thread_authority,
+                user_image,
+            )?,
+        }
+
+        let mut ticks = 0;
+        loop {
+            let mut sender: usize = 0;
+            let info: MessageInfo =
+                unsafe { seL4_Recv(endpoint.cptr, &mut sender as *mut usize) }.into();
+            if sender == TEST_PROCESS_BADGE {
+                if !info.has_null_fault_label() {
+                    return Ok(TestRun::Faulted(Fault::from((info, Badge::from(sender)))));
+                }
+                if info.length_words() == type_length_in_words::<TestReport>() {
+                    let buffer: IPCBuffer<TestReport, ()> = unsafe { IPCBuffer::unchecked_new() };
+                    let (name, outcome) = buffer.copy_req_from_buffer();
+                    return Ok(TestRun::Finished(name, outcome));
+                }
+            } else if sender & TEST_TIMER_BADGE != 0 {
+                // Ticks which arrive while the runner isn't waiting
+                // coalesce, so this errs on the side of waiting longer.
+                ticks += 1;
+                if ticks >= self.timeout_ticks {
+                    return Ok(TestRun::TimedOut);
+                }
+            }
+        }
+    }
+
+    /// Start the test on a thread in the root task's address space,
+    /// on the test stack as it's mapped there.
+    fn start_thread(
+        &mut self,
+        started: &mut Started,
+        slots: &mut WCNodeSlots,
+        uts: &mut WUTBuddy,
+        resources: TestResources<'_>,
+        fault_source: FaultSource<role::Child>,
+        report_sender: Sender<TestReport, role::Local>,
+        cnode: &LocalCap<LocalCNode>,
+        thread_authority: &LocalCap<ThreadPriorityAuthority>,
+        user_image: &UserImage<role::Local>,
+    ) -> Result<(), TestSetupError> {
+        let test_thread = TestProcess {
+            test: resources.test,
+            slots: resources.slots,
+            untyped: resources.untyped,
+            asid_pool: resources.asid_pool,
+            scratch: unsafe { self.scratch.dangerous_internal_alias() },
+            mapped_memory_region: unsafe { self.mapped_memory_region.dangerous_internal_alias() },
+            cnode: alias_cnode(cnode),
+            thread_authority: Cap {
+                cptr: thread_authority.cptr,
+                _role: PhantomData,
+                cap_data: PhantomCap::phantom_instance(),
+            },
+            vspace_paging_root: Cap {
+                cptr: self.paging_root.cptr,
+                _role: PhantomData,
+                cap_data: PagingRoot {},
+            },
+            user_image: user_image.alias(),
+            irq_control: resources.irq_control,
+            domain_set: alias_domain_set(resources.domain_set),
+            report_sender,
+        };
+
+        let stack_top = resources.stack.vaddr() + resources.stack.size_bytes();
+        let (mut registers, param_size_on_stack) = unsafe {
+            setup_initial_stack_and_regs(
+                &test_thread as *const TestProcess as *const usize,
+                core::mem::size_of::<TestProcess>(),
+                stack_top as *mut usize,
+                stack_top,
+            )
+        };
+        // The thread owns its parameter from here on.
+        core::mem::forget(test_thread);
+        registers.sp = stack_top - param_size_on_stack;
+        registers.pc = run_test_process as usize;
+        set_thread_link_register(&mut registers, yield_forever);
+
+        let mut test_tcb: LocalCap<ThreadControlBlock> = uts
+            .alloc(slots, <ThreadControlBlock as DirectRetype>::SizeBits::U8)?
+            .retype(slots)?;
+        test_tcb.configure(
+            alias_cnode(cnode),
+            Some(fault_source),
+            &self.paging_root,
+            Some(Cap {
+                cptr: self.ipc_buffer.cptr,
+                _role: PhantomData,
+                cap_data: Page {
+                    state: self.ipc_buffer.cap_data.state,
+                },
+            }),
+        )?;
+        started.tcb = Some(test_tcb.cptr);
+        unsafe {
+            seL4_TCB_WriteRegisters(
+                test_tcb.cptr,
+                0,
+                0,
+                core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
+                &mut registers,
+            )
+        }
+        .as_result()
+        .map_err(|e| SeL4Error::TCBWriteRegisters(e))?;
+        test_tcb.set_priority(thread_authority, 255)?;
+        unsafe { seL4_TCB_Resume(test_tcb.cptr) }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBResume(e))?;
+        Ok(())
+    }
+
+    /// Start the test in a process of its own, with a fresh address
+    /// space into which the code image is mapped read only.
+    fn start_process(
+        &mut self,
+        started: &mut Started,
+        slots: &mut WCNodeSlots,
+        uts: &mut WUTBuddy,
+        resources: TestResources<'_>,
+        fault_source: FaultSource<role::Child>,
+        report_sender: Sender<TestReport, role::Local>,
+        cnode: &LocalCap<LocalCNode>,
+        thread_authority: &LocalCap<ThreadPriorityAuthority>,
+        user_image: &UserImage<role::Local>,
+    ) -> Result<(), TestSetupError> {
+        // The pool moves on past this ASID whatever becomes of the
+        // process, so that it's never handed out while still in use.
+        // `tear_down` hands it back once the process is deleted.
+        let (asid, asid_pool) = self.asid_pool_alias().alloc();
+        self.asid_pool.cap_data.next_free_slot = asid_pool.cap_data.next_free_slot;
+        self.asid_pool.cap_data.reclaimed = asid_pool.cap_data.reclaimed;
+        started.asid = Some(asid.cap_data.asid);
+
+        let paging_root: LocalCap<PagingRoot> = uts
+            .alloc(slots, <PagingRoot as DirectRetype>::SizeBits::U8)?
+            .retype(slots)?;
+        let vspace_slots = slots
+            .alloc(TestProcessVSpaceSlotCount::USIZE)
+            .map_err(RetypeError::from)?;
+        let paging_untyped = uts.alloc(slots, TestProcessPagingBitSize::U8)?;
+        let mut vspace = VSpace::new(
+            paging_root,
+            asid,
+            vspace_slots,
+            paging_untyped,
+            ProcessCodeImageConfig::ReadOnly,
+            user_image,
+            cnode,
+        )?;
+        let vspace_paging_root = Cap {
+            cptr: vspace.root().cptr,
+            _role: PhantomData,
+            cap_data: PagingRoot {},
+        };
+
+        let scratch_page: LocalCap<Page<page_state::Unmapped>> =
+            uts.alloc(slots, PageBits::U8)?.retype(slots)?;
+        let scratch = vspace.reserve(scratch_page)?.as_scratch(&vspace)?;
+
+        let region_untyped: LocalCap<Untyped<MaxMappedMemoryRegionBitSize>> =
+            uts.alloc_strong(slots)?;
+        let region_slots = slots.alloc_strong().map_err(RetypeError::from)?;
+        let mapped_memory_region = vspace.map_region(
+            UnmappedMemoryRegion::new(region_untyped, region_slots)?,
+            CapRights::RW,
+            arch::vm_attributes::DEFAULT,
+        )?;
+
+        let mut test_process = StandardProcess::<TestStackBitSize>::new(
+            &mut vspace,
+            alias_cnode(cnode),
+            resources.stack,
+            cnode,
+            run_test_process as extern "C" fn(_) -> (),
+            TestProcess {
+                test: resources.test,
+                slots: resources.slots,
+                untyped: resources.untyped,
+                asid_pool: resources.asid_pool,
+                scratch,
+                mapped_memory_region,
+                cnode: alias_cnode(cnode),
+                thread_authority: Cap {
+                    cptr: thread_authority.cptr,
+                    _role: PhantomData,
+                    cap_data: PhantomCap::phantom_instance(),
+                },
+                vspace_paging_root,
+                user_image: user_image.alias(),
+                irq_control: resources.irq_control,
+                domain_set: alias_domain_set(resources.domain_set),
+                report_sender,
+            },
+            uts.alloc_strong(slots)?,
+            uts.alloc_strong(slots)?,
+            slots.alloc_strong().map_err(RetypeError::from)?,
+            thread_authority,
+            Some(fault_source),
+        )?;
+        started.tcb = Some(test_process.unsafe_get_tcb_cptr());
+        test_process.start()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use selfe_sys::*;
+
+use crate::cap::{
+    role, Badge, CNodeRole, Cap, ChildCNodeSlots, DirectRetype, Endpoint, LocalCNode,
+    LocalCNodeSlots, LocalCap, Notification, Untyped, BADGE_BITS,
+};
+use crate::error::SeL4Error;
+use crate::userland::{AddressSpaceIndependent, CapRights};
+
+use typenum::U2;
+
+/// Each participant checks in by setting its own bit of the arrival
+/// notification's badge, so there can be at most one per bit the
+/// kernel keeps.
+pub const MAX_BARRIER_PARTICIPANTS: usize = BADGE_BITS;
+
+#[derive(Debug)]
+pub enum BarrierError {
+    TooManyParticipants,
+    SeL4Error(SeL4Error),
+}
+
+impl From<SeL4Error> for BarrierError {
+    fn from(s: SeL4Error) -> Self {
+        BarrierError::SeL4Error(s)
+    }
+}
+
+/// Hands out participant ends of a barrier, and then becomes its
+/// coordinator.
+pub struct BarrierSetup {
+    arrival: LocalCap<Notification>,
+    release: LocalCap<Endpoint>,
+    participants: usize,
+}
+
+impl BarrierSetup {
+    pub fn new(
+        notification_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+        endpoint_ut: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<U2>,
+    ) -> Result<Self, SeL4Error> {
+        let (notification_slot, endpoint_slot) = slots.alloc();
+        Ok(BarrierSetup {
+            arrival: notification_ut.retype(notification_slot)?,
+            release: endpoint_ut.retype(endpoint_slot)?,
+            participants: 0,
+        })
+    }
+
+    /// Make the barrier end for one more participant, living in
+    /// `child_slots`.
+    pub fn add_participant(
+        &mut self,
+        local_cnode: &LocalCap<LocalCNode>,
+        child_slots: ChildCNodeSlots<U2>,
+    ) -> Result<Barrier<role::Child>, BarrierError> {
+        if self.participants == MAX_BARRIER_PARTICIPANTS {
+            return Err(BarrierError::TooManyParticipants);
+        }
+        let (arrival_slot, release_slot) = child_slots.alloc();
+        let arrival = self.arrival.mint(
+            local_cnode,
+            arrival_slot,
+            CapRights::W,
+            Badge::from(1 << self.participants),
+        )?;
+        let release = self.release.copy(local_cnode, release_slot, CapRights::R)?;
+        self.participants += 1;
+        Ok(Barrier { arrival, release })
+    }
+
+    pub fn coordinator(self) -> BarrierCoordinator {
+        BarrierCoordinator {
+            arrival: self.arrival,
+            release: self.release,
+            participants: self.participants,
+        }
+    }
+}
+
+/// A participant's end of a barrier.
+#[derive(Debug)]
+pub struct Barrier<Role: CNodeRole> {
+    arrival: Cap<Notification, Role>,
+    release: Cap<Endpoint, Role>,
+}
+
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for Barrier<Role> {}
+
+impl Barrier<role::Local> {
+    /// Check in, then block until every participant has checked in and
+    /// the coordinator has let everyone go.
+    pub fn wait(&self) {
+        self.arrive();
+        self.await_release();
+    }
+
+    /// Check in without waiting; `await_release` finishes the wait.
+    pub fn arrive(&self) {
+        unsafe { seL4_Signal(self.arrival.cptr) }
+    }
+
+    /// Block until the coordinator lets one participant go. Goes with
+    /// an earlier `arrive`.
+    pub fn await_release(&self) {
+        let mut sender: usize = 0;
+        unsafe { seL4_Recv(self.release.cptr, &mut sender as *mut usize) };
+    }
+}
+
+/// Tracks who has arrived at a barrier, and releases them once they
+/// all have.
+pub struct BarrierCoordinator {
+    arrival: LocalCap<Notification>,
+    release: LocalCap<Endpoint>,
+    participants: usize,
+}
+
+impl BarrierCoordinator {
+    /// Block until every participant has called `Barrier::wait`, then
+    /// release them all. May be called again for the next round.
+    pub fn release_when_ready(&self) {
+        // MAX_BARRIER_PARTICIPANTS is short of the word size, so this
+        // doesn't overflow.
+        let everyone: usize = (1 << self.participants) - 1;
+        // Signals are OR'd together into the notification's word, so
+        // each wait may report several arrivals at once.
+        let mut arrived: usize = 0;
+        while arrived != everyone {
+            let mut badge: usize = 0;
+            unsafe { seL4_Wait(self.arrival.cptr, &mut badge as *mut usize) };
+            arrived |= badge;
+        }
+        // Each send rendezvouses with exactly one participant's receive.
+        for _ in 0..self.participants {
+            unsafe { seL4_Send(self.release.cptr, seL4_MessageInfo_new(0, 0, 0, 0)) };
+        }
+    }
+}
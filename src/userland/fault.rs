@@ -1,19 +1,27 @@
 use core::marker::PhantomData;
 
+use arrayvec::ArrayVec;
 use selfe_sys::*;
+use typenum::Unsigned;
 
 use crate::arch::fault::Fault;
+use crate::arch::PageBytes;
 use crate::cap::{
-    role, Badge, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot, DirectRetype, Endpoint, LocalCNode,
-    LocalCNodeSlot, LocalCap, Untyped,
+    role, Badge, CNodeRole, CNodeSlot, Cap, ChildCNodeSlot, DirectRetype, Endpoint, FaultEndpoint,
+    FaultReplyEndpoint, LocalCNode, LocalCNodeSlot, LocalCap, Untyped,
 };
 use crate::error::SeL4Error;
-use crate::userland::{type_length_in_words, CapRights, IPCBuffer, IPCError, MessageInfo, Sender};
+use crate::userland::{
+    type_length_in_words, AddressSpaceIndependent, CapRights, IPCBuffer, IPCError, MessageInfo,
+    Sender,
+};
 
 #[derive(Debug)]
 pub enum FaultManagementError {
     SelfFaultHandlingForbidden,
     MessageSizeTooBig,
+    TooManyWatchedStacks,
+    TooManyFaultSources,
     SeL4Error(SeL4Error),
 }
 
@@ -25,12 +33,12 @@ impl From<SeL4Error> for FaultManagementError {
 
 pub struct FaultSinkSetup<SinkRole: CNodeRole> {
     // Local pointer to the endpoint, kept around for easy copying
-    local_endpoint: LocalCap<Endpoint>,
+    local_endpoint: LocalCap<FaultEndpoint>,
 
     // Copy of the same endpoint, set up with the correct rights,
     // living in the CSpace of the CNode that will become
     // the root of the fault-handling process.
-    sink_endpoint: Cap<Endpoint, SinkRole>,
+    sink_endpoint: Cap<FaultEndpoint, SinkRole>,
 
     // To enable checking whether there is an accidental attempt
     // to wire up a process root CSpace as its own fault handler
@@ -46,7 +54,7 @@ impl<SinkRole: CNodeRole> FaultSinkSetup<SinkRole> {
     ) -> Result<Self, SeL4Error> {
         let sink_cspace_local_cptr = fault_sink_slot.cptr;
 
-        let local_endpoint: LocalCap<Endpoint> = untyped.retype(endpoint_slot)?;
+        let local_endpoint: LocalCap<FaultEndpoint> = untyped.retype(endpoint_slot)?;
 
         let sink_endpoint = local_endpoint.copy(&local_cnode, fault_sink_slot, CapRights::RW)?;
 
@@ -101,15 +109,19 @@ pub fn setup_fault_endpoint_pair(
 /// The side of a fault endpoint that sends fault messages
 #[derive(Debug)]
 pub struct FaultSource<Role: CNodeRole> {
-    pub(crate) endpoint: Cap<Endpoint, Role>,
+    pub(crate) endpoint: Cap<FaultEndpoint, Role>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for FaultSource<Role> {}
+
 /// The side of a fault endpoint that receives fault messages
 #[derive(Debug)]
 pub struct FaultSink<Role: CNodeRole> {
-    pub(crate) endpoint: Cap<Endpoint, Role>,
+    pub(crate) endpoint: Cap<FaultEndpoint, Role>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for FaultSink<Role> {}
+
 impl FaultSink<role::Local> {
     pub fn wait_for_fault(&self) -> Fault {
         let mut sender: usize = 0;
@@ -153,11 +165,13 @@ pub fn fault_or_message_channel<Msg: Sized, HandlerRole: CNodeRole>(
         FaultSource {
             // Alias the endpoint harmlessly because FaultSource exposes no public methods
             // and is intended only to be used to tell the kernel where to route faults
-            // for the child thread's TCB
+            // for the child thread's TCB. This is the one place a fault
+            // source shares its endpoint with ordinary messages, which
+            // `FaultOrMessageHandler` tells apart by label.
             endpoint: Cap {
                 cptr: child_endpoint_fault_source.cptr,
                 _role: PhantomData,
-                cap_data: Endpoint {},
+                cap_data: FaultEndpoint {},
             },
         },
         Sender {
@@ -176,6 +190,8 @@ pub struct FaultOrMessageHandler<Msg: Sized, Role: CNodeRole> {
     _msg: PhantomData<Msg>,
 }
 
+unsafe impl<Msg, Role: CNodeRole> AddressSpaceIndependent for FaultOrMessageHandler<Msg, Role> {}
+
 #[derive(Debug)]
 pub enum FaultOrMessage<Msg: Sized> {
     Fault(Fault),
@@ -204,3 +220,271 @@ impl<Msg: Sized> FaultOrMessageHandler<Msg, role::Local> {
         }
     }
 }
+
+/// What a `FaultHandler` should do with a thread once its fault has
+/// been looked at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FaultDecision {
+    /// Reply to the fault, letting the thread retry the faulting
+    /// instruction. Only useful if the cause has been dealt with, e.g.
+    /// by mapping a page at the faulting address.
+    Resume,
+    /// Never reply; the thread stays blocked on its fault forever.
+    Kill,
+}
+
+/// A fault, as interpreted by a `FaultHandler`.
+#[derive(Debug)]
+pub enum FaultCause {
+    /// A thread touched one of the guard pages around its stack,
+    /// almost certainly by overflowing it. `tcb` is the local cptr of
+    /// the thread, as given to `FaultHandler::watch_stack`.
+    StackOverflow {
+        sender: Badge,
+        tcb: usize,
+        addr: usize,
+    },
+    /// Any fault without a more specific explanation.
+    Fault(Fault),
+}
+
+impl FaultCause {
+    pub fn sender(&self) -> Badge {
+        match self {
+            FaultCause::StackOverflow { sender, .. } => *sender,
+            FaultCause::Fault(f) => f.sender(),
+        }
+    }
+}
+
+/// The unmapped pages immediately below and above a thread's stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StackGuards {
+    pub below: usize,
+    pub above: usize,
+}
+
+impl StackGuards {
+    pub fn contains(&self, addr: usize) -> bool {
+        [self.below, self.above]
+            .iter()
+            .any(|&page| addr >= page && addr - page < PageBytes::USIZE)
+    }
+}
+
+pub const MAX_WATCHED_STACKS: usize = 32;
+
+struct WatchedStack {
+    badge: Badge,
+    tcb: usize,
+    guards: StackGuards,
+    faults: usize,
+    killed: bool,
+}
+
+/// Receives faults on a sink endpoint and replies to (or abandons) the
+/// faulting thread according to a caller-supplied decision.
+///
+/// The reply capability for each fault is saved into `reply_slot`
+/// before the decision is made, so the decision closure is free to make
+/// its own IPC calls without clobbering it.
+pub struct FaultHandler {
+    sink: FaultSink<role::Local>,
+    reply_slot: Option<LocalCNodeSlot>,
+    stacks: ArrayVec<[WatchedStack; MAX_WATCHED_STACKS]>,
+}
+
+impl FaultHandler {
+    pub fn new(sink: FaultSink<role::Local>, reply_slot: LocalCNodeSlot) -> Self {
+        FaultHandler {
+            sink,
+            reply_slot: Some(reply_slot),
+            stacks: ArrayVec::new(),
+        }
+    }
+
+    /// Report VM faults from the thread `tcb`, whose faults arrive
+    /// bearing `badge`, as `FaultCause::StackOverflow` when they land
+    /// in its stack guard pages.
+    pub fn watch_stack(
+        &mut self,
+        badge: Badge,
+        tcb: usize,
+        guards: StackGuards,
+    ) -> Result<(), FaultManagementError> {
+        self.stacks
+            .try_push(WatchedStack {
+                badge,
+                tcb,
+                guards,
+                faults: 0,
+                killed: false,
+            })
+            .map_err(|_| FaultManagementError::TooManyWatchedStacks)
+    }
+
+    /// How many faults bearing the badge of the thread `tcb` have been
+    /// handled since it was watched, if it's watched at all.
+    pub fn fault_count(&self, tcb: usize) -> Option<usize> {
+        self.stacks.iter().find(|s| s.tcb == tcb).map(|s| s.faults)
+    }
+
+    /// Whether the last fault bearing the badge of the thread `tcb` was
+    /// answered with `FaultDecision::Kill`, if it's watched at all.
+    pub fn has_killed(&self, tcb: usize) -> Option<bool> {
+        self.stacks.iter().find(|s| s.tcb == tcb).map(|s| s.killed)
+    }
+
+    fn classify(&self, fault: Fault) -> FaultCause {
+        if let Fault::VMFault(ref f) = fault {
+            if let Some(stack) = self
+                .stacks
+                .iter()
+                .find(|s| s.badge == f.sender && s.guards.contains(f.address))
+            {
+                return FaultCause::StackOverflow {
+                    sender: f.sender,
+                    tcb: stack.tcb,
+                    addr: f.address,
+                };
+            }
+        }
+        FaultCause::Fault(fault)
+    }
+
+    /// Block until the next fault arrives, let `decide` choose its fate,
+    /// and carry that out. The interpreted fault is handed back
+    /// afterwards.
+    ///
+    /// For VM faults, `Fault::VMFault` carries the instruction pointer,
+    /// faulting address, and whether it was a write or an instruction
+    /// fetch.
+    ///
+    /// Not yet available on MCS kernels; see
+    /// `FaultReplyEndpoint::save_caller_and_create`.
+    #[cfg(not(KernelIsMCS))]
+    pub fn handle_next<F: FnOnce(&FaultCause) -> FaultDecision>(
+        &mut self,
+        decide: F,
+    ) -> Result<FaultCause, SeL4Error> {
+        let cause = self.classify(self.sink.wait_for_fault());
+        let slot = self
+            .reply_slot
+            .take()
+            .expect("The reply slot is always returned after handling a fault");
+        let reply = LocalCap::<FaultReplyEndpoint>::save_caller_and_create(slot)?;
+        let decision = decide(&cause);
+        for stack in self.stacks.iter_mut().filter(|s| s.badge == cause.sender()) {
+            stack.faults += 1;
+            stack.killed = decision == FaultDecision::Kill;
+        }
+        let slot = match decision {
+            FaultDecision::Resume => reply.resume_faulted_thread(),
+            FaultDecision::Kill => reply.abandon()?,
+        };
+        self.reply_slot = Some(slot);
+        Ok(cause)
+    }
+
+    pub fn into_parts(self) -> (FaultSink<role::Local>, LocalCNodeSlot) {
+        (
+            self.sink,
+            self.reply_slot
+                .expect("The reply slot is always returned after handling a fault"),
+        )
+    }
+}
+
+pub const MAX_REACTOR_CHILDREN: usize = 32;
+
+/// A child-specific fault handler, as registered with a `FaultReactor`.
+pub type ChildFaultHandler<'h> = &'h mut dyn FnMut(&FaultCause) -> FaultDecision;
+
+/// A single fault endpoint shared by many children, with faults
+/// dispatched to a handler per child.
+///
+/// Each child's `FaultSource` is minted with its own badge, which is
+/// how the reactor tells its faults apart from everyone else's. Faults
+/// bearing a badge it didn't hand out are answered with
+/// `FaultDecision::Kill`.
+pub struct FaultReactor<'h> {
+    local_endpoint: LocalCap<FaultEndpoint>,
+    sink_cspace_local_cptr: usize,
+    handler: FaultHandler,
+    children: ArrayVec<[(Badge, ChildFaultHandler<'h>); MAX_REACTOR_CHILDREN]>,
+}
+
+impl<'h> FaultReactor<'h> {
+    pub fn new(setup: FaultSinkSetup<role::Local>, reply_slot: LocalCNodeSlot) -> Self {
+        FaultReactor {
+            local_endpoint: setup.local_endpoint,
+            sink_cspace_local_cptr: setup.sink_cspace_local_cptr,
+            handler: FaultHandler::new(
+                FaultSink {
+                    endpoint: setup.sink_endpoint,
+                },
+                reply_slot,
+            ),
+            children: ArrayVec::new(),
+        }
+    }
+
+    /// Mint a fault source for a new child, whose faults will be
+    /// passed to `on_fault`.
+    pub fn add_child(
+        &mut self,
+        local_cnode: &LocalCap<LocalCNode>,
+        fault_source_slot: ChildCNodeSlot,
+        on_fault: ChildFaultHandler<'h>,
+    ) -> Result<(FaultSource<role::Child>, Badge), FaultManagementError> {
+        if fault_source_slot.cptr == self.sink_cspace_local_cptr {
+            return Err(FaultManagementError::SelfFaultHandlingForbidden);
+        }
+        if self.children.is_full() {
+            return Err(FaultManagementError::TooManyFaultSources);
+        }
+        // Zero is left for unbadged senders.
+        let badge = Badge::from(self.children.len() + 1);
+        let endpoint =
+            self.local_endpoint
+                .mint_new(local_cnode, fault_source_slot, CapRights::RWG, badge)?;
+        self.children.push((badge, on_fault));
+        Ok((FaultSource { endpoint }, badge))
+    }
+
+    /// See `FaultHandler::watch_stack`.
+    pub fn watch_stack(
+        &mut self,
+        badge: Badge,
+        tcb: usize,
+        guards: StackGuards,
+    ) -> Result<(), FaultManagementError> {
+        self.handler.watch_stack(badge, tcb, guards)
+    }
+
+    /// Handle a single fault from any child, returning it along with
+    /// the badge of the child it came from.
+    #[cfg(not(KernelIsMCS))]
+    pub fn react_once(&mut self) -> Result<(Badge, FaultCause), SeL4Error> {
+        let children = &mut self.children;
+        let mut sender = Badge::from(0);
+        let cause = self.handler.handle_next(|cause| {
+            sender = cause.sender();
+            match children.iter_mut().find(|(badge, _)| *badge == sender) {
+                Some((_, on_fault)) => on_fault(cause),
+                None => FaultDecision::Kill,
+            }
+        })?;
+        Ok((sender, cause))
+    }
+
+    /// Handle faults forever, returning only if replying to one fails.
+    #[cfg(not(KernelIsMCS))]
+    pub fn run(&mut self) -> SeL4Error {
+        loop {
+            if let Err(e) = self.react_once() {
+                return e;
+            }
+        }
+    }
+}
@@ -4,15 +4,16 @@ use selfe_sys::*;
 
 use crate::arch;
 use crate::cap::{
-    role, Badge, CNode, CNodeRole, CNodeSlot, Cap, DirectRetype, Endpoint, LocalCNode,
+    role, Badge, CNode, CNodeRole, CNodeSlot, CNodeSlots, Cap, DirectRetype, Endpoint, LocalCNode,
     LocalCNodeSlot, LocalCNodeSlots, LocalCap, Notification, Untyped,
 };
 use crate::error::SeL4Error;
 use crate::userland::multi_consumer::WakerSetup;
 use crate::userland::shared_memory_ipc::WAKER_BADGE;
+use crate::userland::AddressSpaceIndependent;
 use crate::userland::CapRights;
 use crate::vspace::VSpaceError;
-use typenum::U2;
+use typenum::{Unsigned, U2};
 
 #[derive(Debug)]
 pub enum IPCError {
@@ -20,6 +21,15 @@ pub enum IPCError {
     ResponseSizeTooBig,
     ResponseSizeMismatch,
     RequestSizeMismatch,
+    /// The responder rejected the request, most likely because it
+    /// arrived at a size it didn't expect.
+    ProtocolError,
+    /// The call was abandoned, either because its cancel notification
+    /// had been signalled or because its channel was closed.
+    ResponderDead,
+    /// Badge zero is what an unbadged caller's requests carry, so it
+    /// can't be used to tell callers apart.
+    ZeroCallerBadge,
     SeL4Error(SeL4Error),
     VSpaceError(VSpaceError),
 }
@@ -108,7 +118,7 @@ pub fn call_channel_with_waker<Req: Send + Sync, Rsp: Send + Sync, ResponderRole
             _rsp: PhantomData,
             _role: PhantomData,
         },
-        Cap::wrap_cptr(notification.cptr),
+        unsafe { Cap::wrap_cptr(notification.cptr) },
         WakerSetup {
             interrupt_badge: Badge::from(WAKER_BADGE),
             notification,
@@ -116,6 +126,66 @@ pub fn call_channel_with_waker<Req: Send + Sync, Rsp: Send + Sync, ResponderRole
     ))
 }
 
+/// A call channel for a single caller that can give up on a call, see
+/// `CancellableCaller::call_with_cancel`. Replies come back over an
+/// endpoint of their own rather than through the kernel's reply cap,
+/// so the caller waits for them with a receive, which a signal to a
+/// notification bound to its thread interrupts.
+pub fn cancellable_call_channel<
+    Req: Send + Sync,
+    Rsp: Send + Sync,
+    CallerRole: CNodeRole,
+    ResponderRole: CNodeRole,
+>(
+    request_untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    reply_untyped: LocalCap<Untyped<<Endpoint as DirectRetype>::SizeBits>>,
+    local_cnode: &LocalCap<LocalCNode>,
+    local_slots: LocalCNodeSlots<U2>,
+    caller_slots: CNodeSlots<U2, CallerRole>,
+    responder_slots: CNodeSlots<U2, ResponderRole>,
+) -> Result<
+    (
+        CancellableCaller<Req, Rsp, CallerRole>,
+        CancellableResponder<Req, Rsp, ResponderRole>,
+    ),
+    IPCError,
+> {
+    let _ = IPCBuffer::<Req, Rsp>::new()?; // Check buffer fits Req and Rsp
+    let (request_slot, local_slots) = local_slots.alloc();
+    let request_endpoint: LocalCap<Endpoint> = request_untyped.retype(request_slot)?;
+    let (reply_slot, _local_slots) = local_slots.alloc();
+    let reply_endpoint: LocalCap<Endpoint> = reply_untyped.retype(reply_slot)?;
+
+    let (caller_request_slot, caller_slots) = caller_slots.alloc();
+    let (caller_reply_slot, _caller_slots) = caller_slots.alloc();
+    let (responder_request_slot, responder_slots) = responder_slots.alloc();
+    let (responder_reply_slot, _responder_slots) = responder_slots.alloc();
+
+    Ok((
+        CancellableCaller {
+            request_endpoint: request_endpoint.copy(
+                local_cnode,
+                caller_request_slot,
+                CapRights::W,
+            )?,
+            reply_endpoint: reply_endpoint.copy(local_cnode, caller_reply_slot, CapRights::R)?,
+            cancelled: core::cell::Cell::new(false),
+            _req: PhantomData,
+            _rsp: PhantomData,
+        },
+        CancellableResponder {
+            request_endpoint: request_endpoint.copy(
+                local_cnode,
+                responder_request_slot,
+                CapRights::R,
+            )?,
+            reply_endpoint: reply_endpoint.copy(local_cnode, responder_reply_slot, CapRights::W)?,
+            _req: PhantomData,
+            _rsp: PhantomData,
+        },
+    ))
+}
+
 impl<'a, Req, Rsp> IpcSetup<'a, Req, Rsp> {
     pub fn create_caller<Role: CNodeRole>(
         &self,
@@ -131,6 +201,73 @@ impl<'a, Req, Rsp> IpcSetup<'a, Req, Rsp> {
             _rsp: PhantomData,
         })
     }
+
+    /// Create a caller whose requests arrive bearing `badge`, so that
+    /// a responder using `reply_recv_with_sender` can tell it apart
+    /// from other callers. `badge` must not be zero.
+    pub fn create_badged_caller<Role: CNodeRole>(
+        &self,
+        caller_slot: CNodeSlot<Role>,
+        badge: Badge,
+    ) -> Result<Caller<Req, Rsp, Role>, IPCError> {
+        if badge == Badge::from(0) {
+            return Err(IPCError::ZeroCallerBadge);
+        }
+        let caller_endpoint =
+            self.endpoint
+                .mint(&self.endpoint_cnode, caller_slot, CapRights::RWG, badge)?;
+
+        Ok(Caller {
+            endpoint: caller_endpoint,
+            _req: PhantomData,
+            _rsp: PhantomData,
+        })
+    }
+
+    /// Create a caller in each of `slots`, badged with its position in
+    /// the range, counting from 1. See `create_badged_caller`.
+    pub fn create_callers<'s, Role: CNodeRole, Count: Unsigned>(
+        &'s self,
+        slots: CNodeSlots<Count, Role>,
+    ) -> impl Iterator<Item = Result<Caller<Req, Rsp, Role>, IPCError>> + 's
+    where
+        Req: 's,
+        Rsp: 's,
+    {
+        slots
+            .iter()
+            .enumerate()
+            .map(move |(i, slot)| self.create_badged_caller(slot, Badge::from(i + 1)))
+    }
+
+    /// Stop making callers, keeping the channel's endpoint so that the
+    /// channel can be closed later.
+    pub fn into_connection(self) -> Connection {
+        Connection {
+            endpoint: self.endpoint,
+        }
+    }
+}
+
+/// The endpoint behind a call channel, held onto once all its callers
+/// have been made.
+#[derive(Debug)]
+pub struct Connection {
+    endpoint: LocalCap<Endpoint>,
+}
+
+impl Connection {
+    /// Revoke the responder and every caller made for this channel,
+    /// wherever they were copied to, then delete the endpoint and hand
+    /// back its slot. The untyped the endpoint was retyped from isn't
+    /// recovered until that untyped is itself revoked.
+    ///
+    /// Calls waiting for the responder to take them, and any made
+    /// afterwards, return `IPCError::ResponderDead`.
+    pub fn close(self, cnode: &LocalCap<LocalCNode>) -> Result<LocalCNodeSlot, SeL4Error> {
+        self.endpoint.revoke(cnode)?;
+        self.endpoint.delete_reclaiming_slot(cnode)
+    }
 }
 
 #[derive(Debug)]
@@ -140,6 +277,8 @@ pub struct Caller<Req: Sized, Rsp: Sized, Role: CNodeRole> {
     _rsp: PhantomData<Rsp>,
 }
 
+unsafe impl<Req, Rsp, Role: CNodeRole> AddressSpaceIndependent for Caller<Req, Rsp, Role> {}
+
 /// Internal convenience for working with IPC Buffer instances
 /// *Note:* In a given thread or process, all instances of
 /// IPCBuffer wrap a pointer to the very same underlying buffer.
@@ -228,9 +367,35 @@ fn unchecked_raw_ipc_buffer<'a>() -> &'a mut seL4_IPCBuffer {
     unsafe { &mut *seL4_GetIPCBuffer() }
 }
 
+/// Run `f` over this thread's message registers, all
+/// `seL4_MsgMaxLength` of them, for talking to a component whose
+/// protocol doesn't fit `Caller` and `Responder`, e.g. one generated
+/// by CAmkES. Pair it with `MessageInfo::new` and the raw `seL4_Call`,
+/// `seL4_Send` or `seL4_Recv`.
+///
+/// This is unsafe because it sidesteps everything the typed channels
+/// check. Nothing here knows the layout the other side expects, nor
+/// how many registers it will read, and every caller and responder in
+/// the thread uses the same registers, so `f` mustn't hold on to what
+/// it finds there across another IPC.
+pub unsafe fn with_mr<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut [usize]) -> R,
+{
+    f(&mut unchecked_raw_ipc_buffer().msg[..])
+}
+
+/// Whether a `T` can travel in a single message register.
+fn fits_in_register<T>() -> bool {
+    core::mem::size_of::<T>() <= core::mem::size_of::<seL4_Word>()
+}
+
+/// How many message registers a `T` takes up. Counted in kernel words
+/// rather than `usize`s, as that's what the message length is measured
+/// in.
 pub(crate) fn type_length_in_words<T>() -> usize {
     let t_bytes = core::mem::size_of::<T>();
-    let usize_bytes = core::mem::size_of::<usize>();
+    let usize_bytes = core::mem::size_of::<arch::Word>();
     if t_bytes == 0 {
         return 0;
     }
@@ -246,7 +411,26 @@ pub(crate) fn type_length_in_words<T>() -> usize {
     }
 }
 
-fn type_length_message_info<T>() -> seL4_MessageInfo_t {
+/// The label a responder replies with when it couldn't make sense of
+/// a request. Ordinary replies are labelled zero.
+const PROTOCOL_ERROR_LABEL: usize = 1;
+
+/// The label the kernel answers a call with when the caller's endpoint
+/// cap is gone, as it is once `Connection::close` has revoked it.
+const CLOSED_CHANNEL_LABEL: usize = seL4_Error_seL4_InvalidCapability as usize;
+
+fn protocol_error_message_info() -> seL4_MessageInfo_t {
+    unsafe {
+        seL4_MessageInfo_new(
+            arch::to_sel4_word(PROTOCOL_ERROR_LABEL), // label,
+            0,                                        // capsUnwrapped,
+            0,                                        // extraCaps,
+            0,                                        // length in words
+        )
+    }
+}
+
+pub(crate) fn type_length_message_info<T>() -> seL4_MessageInfo_t {
     unsafe {
         seL4_MessageInfo_new(
             0,                                               // label,
@@ -257,28 +441,69 @@ fn type_length_message_info<T>() -> seL4_MessageInfo_t {
     }
 }
 
+/// A message type whose values don't all need `size_of::<Self>()`
+/// bytes to be sent, such as an enum with a few large variants among
+/// many small ones. Used with `Caller::blocking_call_variable` and
+/// `Responder::reply_recv_variable`, only the first
+/// `length_in_words` words of each value are sent, and a receiver
+/// accepts any message up to the size of the type.
+///
+/// This is unsafe to implement because the receiver sees whatever was
+/// last in its IPC buffer past the words that were sent; every part of
+/// the value that matters must be within `length_in_words`.
+pub unsafe trait VariableMessage: Sized {
+    fn length_in_words(&self) -> usize;
+}
+
+fn value_length_message_info<T: VariableMessage>(t: &T) -> seL4_MessageInfo_t {
+    let words = core::cmp::min(t.length_in_words(), type_length_in_words::<T>());
+    unsafe {
+        seL4_MessageInfo_new(
+            0,                         // label,
+            0,                         // capsUnwrapped,
+            0,                         // extraCaps,
+            arch::to_sel4_word(words), // length in words!
+        )
+    }
+}
+
 pub struct MessageInfo {
     inner: seL4_MessageInfo_t,
 }
 
 impl MessageInfo {
+    /// A message with the given label and length, and no caps, for
+    /// sending by hand after filling in its registers with `with_mr`.
+    pub fn new(label: usize, length_words: usize) -> MessageInfo {
+        MessageInfo {
+            inner: unsafe {
+                seL4_MessageInfo_new(
+                    arch::to_sel4_word(label),        // label,
+                    0,                                // capsUnwrapped,
+                    0,                                // extraCaps,
+                    arch::to_sel4_word(length_words), // length in words
+                )
+            },
+        }
+    }
+
     pub fn label(&self) -> usize {
-        unsafe {
+        arch::from_sel4_word(unsafe {
             seL4_MessageInfo_ptr_get_label(
                 &self.inner as *const seL4_MessageInfo_t as *mut seL4_MessageInfo_t,
-            ) as usize
-        }
+            )
+        })
     }
 
     /// Length of the message in words, ought to be
     /// less than the length of the IPC Buffer's msg array,
     /// an array of `usize` words.
-    pub(crate) fn length_words(&self) -> usize {
-        unsafe {
+    pub fn length_words(&self) -> usize {
+        arch::from_sel4_word(unsafe {
             seL4_MessageInfo_ptr_get_length(
                 &self.inner as *const seL4_MessageInfo_t as *mut seL4_MessageInfo_t,
-            ) as usize
-        }
+            )
+        })
     }
 
     /// Does this message info have the label tag
@@ -295,6 +520,12 @@ impl From<seL4_MessageInfo_t> for MessageInfo {
     }
 }
 
+impl From<MessageInfo> for seL4_MessageInfo_t {
+    fn from(info: MessageInfo) -> Self {
+        info.inner
+    }
+}
+
 impl<Req, Rsp> Caller<Req, Rsp, role::Child> {
     pub fn as_cap(self) -> Cap<Endpoint, role::Child> {
         self.endpoint
@@ -302,7 +533,12 @@ impl<Req, Rsp> Caller<Req, Rsp, role::Child> {
 }
 
 impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
-    pub fn wrap_cptr(cptr: usize) -> Caller<Req, Rsp, role::Local> {
+    /// Treat the endpoint at `cptr` in the local CSpace as a `Caller`.
+    ///
+    /// This is unsafe for the same reasons as `Cap::wrap_cptr`, and
+    /// also because nothing checks that whoever is at the other end of
+    /// the endpoint agrees on `Req` and `Rsp`.
+    pub unsafe fn wrap_cptr(cptr: usize) -> Caller<Req, Rsp, role::Local> {
         Caller {
             endpoint: Cap::wrap_cptr(cptr),
             _req: PhantomData,
@@ -312,7 +548,14 @@ impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
 }
 
 impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
+    /// Send `request` and wait for the response.
+    ///
+    /// When both `Req` and `Rsp` fit in a single word, they're passed
+    /// in a message register and never touch the IPC buffer.
     pub fn blocking_call<'a>(&self, request: &Req) -> Result<Rsp, IPCError> {
+        if fits_in_register::<Req>() && fits_in_register::<Rsp>() {
+            return self.blocking_call_in_register(request);
+        }
         // Can safely use unchecked_new because we check sizing during the creation of Caller
         let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
         let msg_info: MessageInfo = unsafe {
@@ -320,11 +563,87 @@ impl<Req, Rsp> Caller<Req, Rsp, role::Local> {
             seL4_Call(self.endpoint.cptr, type_length_message_info::<Req>())
         }
         .into();
+        if msg_info.label() == CLOSED_CHANNEL_LABEL {
+            return Err(IPCError::ResponderDead);
+        }
+        if msg_info.label() == PROTOCOL_ERROR_LABEL {
+            return Err(IPCError::ProtocolError);
+        }
         if msg_info.length_words() != type_length_in_words::<Rsp>() {
             return Err(IPCError::ResponseSizeMismatch);
         }
         Ok(ipc_buffer.copy_rsp_from_buffer())
     }
+
+    /// `blocking_call` for messages of a word or less. Plain `seL4_Call`
+    /// has libsel4 load the first message registers from the IPC
+    /// buffer and store them back afterwards; handing the values to
+    /// `seL4_CallWithMRs` instead keeps the whole round trip in
+    /// registers.
+    fn blocking_call_in_register(&self, request: &Req) -> Result<Rsp, IPCError> {
+        let mut mr0: seL4_Word = 0;
+        let (mut mr1, mut mr2, mut mr3): (seL4_Word, seL4_Word, seL4_Word) = (0, 0, 0);
+        let msg_info: MessageInfo = unsafe {
+            core::ptr::copy_nonoverlapping(
+                request as *const Req as *const u8,
+                &mut mr0 as *mut seL4_Word as *mut u8,
+                core::mem::size_of::<Req>(),
+            );
+            seL4_CallWithMRs(
+                self.endpoint.cptr,
+                type_length_message_info::<Req>(),
+                &mut mr0,
+                &mut mr1,
+                &mut mr2,
+                &mut mr3,
+            )
+        }
+        .into();
+        if msg_info.label() == CLOSED_CHANNEL_LABEL {
+            return Err(IPCError::ResponderDead);
+        }
+        if msg_info.label() == PROTOCOL_ERROR_LABEL {
+            return Err(IPCError::ProtocolError);
+        }
+        if msg_info.length_words() != type_length_in_words::<Rsp>() {
+            return Err(IPCError::ResponseSizeMismatch);
+        }
+        let mut response = core::mem::MaybeUninit::<Rsp>::uninit();
+        unsafe {
+            core::ptr::copy_nonoverlapping(
+                &mr0 as *const seL4_Word as *const u8,
+                response.as_mut_ptr() as *mut u8,
+                core::mem::size_of::<Rsp>(),
+            );
+            Ok(response.assume_init())
+        }
+    }
+
+    /// Like `blocking_call`, but only sends as much of `request` as it
+    /// says it needs, and accepts any response that fits in `Rsp`.
+    pub fn blocking_call_variable(&self, request: &Req) -> Result<Rsp, IPCError>
+    where
+        Req: VariableMessage,
+        Rsp: VariableMessage,
+    {
+        // Can safely use unchecked_new because we check sizing during the creation of Caller
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let msg_info: MessageInfo = unsafe {
+            ipc_buffer.copy_req_into_buffer(request);
+            seL4_Call(self.endpoint.cptr, value_length_message_info(request))
+        }
+        .into();
+        if msg_info.label() == CLOSED_CHANNEL_LABEL {
+            return Err(IPCError::ResponderDead);
+        }
+        if msg_info.label() == PROTOCOL_ERROR_LABEL {
+            return Err(IPCError::ProtocolError);
+        }
+        if msg_info.length_words() > type_length_in_words::<Rsp>() {
+            return Err(IPCError::ResponseSizeMismatch);
+        }
+        Ok(ipc_buffer.copy_rsp_from_buffer())
+    }
 }
 
 #[derive(Debug)]
@@ -335,6 +654,8 @@ pub struct Responder<Req: Sized, Rsp: Sized, Role: CNodeRole> {
     _role: PhantomData<Role>,
 }
 
+unsafe impl<Req, Rsp, Role: CNodeRole> AddressSpaceIndependent for Responder<Req, Rsp, Role> {}
+
 impl<Req, Rsp> Responder<Req, Rsp, role::Child> {
     pub fn as_cap(self) -> Cap<Endpoint, role::Child> {
         self.endpoint
@@ -342,7 +663,9 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Child> {
 }
 
 impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
-    pub fn wrap_cptr(cptr: usize) -> Responder<Req, Rsp, role::Local> {
+    /// The `Responder` counterpart of `Caller::wrap_cptr`, and unsafe
+    /// for the same reasons.
+    pub unsafe fn wrap_cptr(cptr: usize) -> Responder<Req, Rsp, role::Local> {
         Responder {
             endpoint: Cap::wrap_cptr(cptr),
             _req: PhantomData,
@@ -369,11 +692,126 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
         self.reply_recv_with_notification(initial_state, f, move |_sender_badge, state| state)
     }
 
+    /// Like `reply_recv_with_state`, for a thread with a notification
+    /// bound to it. `g` is run for each signal on that notification,
+    /// and given the signalling cap's badge.
+    ///
+    /// Signals which arrive while this thread is busy accumulate into
+    /// one badge, the OR of theirs, so `g` is run once per set bit of
+    /// what's received. Each source should have a badge of its own
+    /// bit for none of them to be missed.
     pub fn reply_recv_with_notification<F, G, State>(
+        self,
+        initial_state: State,
+        f: F,
+        g: G,
+    ) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req, State) -> (Rsp, State),
+        G: FnMut(usize, State) -> State,
+    {
+        self.reply_recv_internal(initial_state, f, g, false, |_| {
+            type_length_message_info::<Rsp>()
+        })
+    }
+
+    /// Like `reply_recv`, but for message types whose values may be
+    /// sent in fewer words than the whole type. Requests of any length
+    /// up to the size of `Req` are accepted.
+    pub fn reply_recv_variable<F>(self, mut f: F) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req) -> (Rsp),
+        Req: VariableMessage,
+        Rsp: VariableMessage,
+    {
+        self.reply_recv_variable_with_state((), move |req, state| (f(req), state))
+    }
+
+    pub fn reply_recv_variable_with_state<F, State>(
+        self,
+        initial_state: State,
+        f: F,
+    ) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req, State) -> (Rsp, State),
+        Req: VariableMessage,
+        Rsp: VariableMessage,
+    {
+        self.reply_recv_internal(
+            initial_state,
+            f,
+            move |_sender_badge, state| state,
+            true,
+            value_length_message_info::<Rsp>,
+        )
+    }
+
+    /// Like `reply_recv_with_state`, but for an endpoint shared by
+    /// callers from `IpcSetup::create_badged_caller` or
+    /// `create_callers`. `f` is told the badge of the caller it's
+    /// responding to. Every badge is taken to be a caller's, so a
+    /// notification must not be bound to this thread.
+    pub fn reply_recv_with_sender<F, State>(
+        self,
+        initial_state: State,
+        mut f: F,
+    ) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Badge, Req, State) -> (Rsp, State),
+    {
+        // Can safely use unchecked_new because we check sizing during the creation of Responder
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let mut sender_badge: usize = 0;
+        let mut msg_info: MessageInfo =
+            unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }.into();
+
+        let request_length_in_words = type_length_in_words::<Req>();
+        let mut state = initial_state;
+        loop {
+            if msg_info.length_words() != request_length_in_words {
+                // As in reply_recv_internal, reject the request and wait
+                // for the next.
+                debug_println!(
+                    "Request size incoming ({} words) does not match static size expectation ({} words).",
+                    msg_info.length_words(),
+                    request_length_in_words
+                );
+                msg_info = unsafe {
+                    seL4_ReplyRecv(
+                        self.endpoint.cptr,
+                        protocol_error_message_info(),
+                        &mut sender_badge as *mut usize,
+                    )
+                }
+                .into();
+                continue;
+            }
+            let (response, next_state) = f(
+                Badge::from(sender_badge),
+                ipc_buffer.copy_req_from_buffer(),
+                state,
+            );
+            state = next_state;
+
+            ipc_buffer.copy_rsp_into_buffer(&response);
+            msg_info = unsafe {
+                seL4_ReplyRecv(
+                    self.endpoint.cptr,
+                    type_length_message_info::<Rsp>(),
+                    &mut sender_badge as *mut usize,
+                )
+            }
+            .into();
+        }
+    }
+
+    fn reply_recv_internal<F, G, State>(
         self,
         initial_state: State,
         mut f: F,
         mut g: G,
+        variable_length: bool,
+        response_info: fn(&Rsp) -> seL4_MessageInfo_t,
     ) -> Result<Rsp, IPCError>
     where
         F: FnMut(Req, State) -> (Rsp, State),
@@ -392,18 +830,29 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
         loop {
             // if the badge is zero, it's a regular IPC
             if sender_badge == 0 {
-                if msg_info.length_words() != request_length_in_words {
+                let length_ok = if variable_length {
+                    msg_info.length_words() <= request_length_in_words
+                } else {
+                    msg_info.length_words() == request_length_in_words
+                };
+                if !length_ok {
                     // A wrong-sized message length is an indication of unforeseen or
                     // misunderstood kernel operations. Using the checks established in
                     // the creation of Caller/Responder sets should prevent the creation
                     // of wrong-sized messages through their expected paths.
                     //
-                    // Not knowing what this incoming message is, we drop it and spin-fail the loop.
-                    // Note that `continue`'ing from here will cause this process
-                    // to loop forever doing this check with no fresh data, most likely leaving the
-                    // caller perpetually blocked.
+                    // Not knowing what this incoming message is, we tell the caller so
+                    // rather than leave it blocked, and go back to waiting.
                     debug_println!("Request size incoming ({} words) does not match static size expectation ({} words).",
                 msg_info.length_words(), request_length_in_words);
+                    msg_info = unsafe {
+                        seL4_ReplyRecv(
+                            self.endpoint.cptr,
+                            protocol_error_message_info(),
+                            &mut sender_badge as *mut usize,
+                        )
+                    }
+                    .into();
                     continue;
                 }
                 let out = f(ipc_buffer.copy_req_from_buffer(), state);
@@ -414,14 +863,17 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
                 msg_info = unsafe {
                     seL4_ReplyRecv(
                         self.endpoint.cptr,
-                        type_length_message_info::<Rsp>(),
+                        response_info(&response),
                         &mut sender_badge as *mut usize,
                     )
                 }
                 .into();
             } else {
-                // nonzero badges are from a notification
-                state = g(sender_badge, state);
+                // nonzero badges are from a notification, maybe
+                // several signals' worth
+                for bit in Badge::from(sender_badge).bits() {
+                    state = g(usize::from(bit), state);
+                }
 
                 msg_info =
                     unsafe { seL4_Recv(self.endpoint.cptr, &mut sender_badge as *mut usize) }
@@ -448,11 +900,11 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
             // the creation of Caller/Responder sets should prevent the creation
             // of wrong-sized messages through their expected paths.
             //
-            // Not knowing what this incoming message is, we drop it and spin-fail the loop.
-            // Note that `continue`'ing from here will cause this process
-            // to loop forever doing this check with no fresh data, most likely leaving the caller perpetually blocked.
+            // Not knowing what this incoming message is, we tell the caller so
+            // rather than leave it blocked.
             debug_println!("Request size incoming ({} words) does not match static size expectation ({} words).",
                 msg_info.length_words(), request_length_in_words);
+            unsafe { seL4_Reply(protocol_error_message_info()) };
             return Err(IPCError::RequestSizeMismatch);
         }
 
@@ -467,12 +919,130 @@ impl<Req, Rsp> Responder<Req, Rsp, role::Local> {
     }
 }
 
+/// The caller's end of a `cancellable_call_channel`.
+#[derive(Debug)]
+pub struct CancellableCaller<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    request_endpoint: Cap<Endpoint, Role>,
+    reply_endpoint: Cap<Endpoint, Role>,
+    cancelled: core::cell::Cell<bool>,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+unsafe impl<Req, Rsp, Role: CNodeRole> AddressSpaceIndependent
+    for CancellableCaller<Req, Rsp, Role>
+{
+}
+
+impl<Req, Rsp> CancellableCaller<Req, Rsp, role::Local> {
+    /// Send `request` and wait for either the response or a signal on
+    /// `cancel`, e.g. from a `FaultReactor` handler for the responder's
+    /// process, whichever comes first. `cancel` must be bound to this
+    /// thread, with `StandardProcess::bind_notification` or the like,
+    /// for a signal to end the wait, and has to be signalled through a
+    /// badged copy, since an unbadged signal looks like a reply.
+    ///
+    /// Once cancelled, this and every later call return
+    /// `IPCError::ResponderDead` without sending anything. Sending
+    /// waits for the responder to take the request, which a signal
+    /// can't interrupt, so a responder that dies between calls is
+    /// only noticed if `cancel` was signalled before the next one.
+    pub fn call_with_cancel(
+        &self,
+        request: &Req,
+        cancel: &LocalCap<Notification>,
+    ) -> Result<Rsp, IPCError> {
+        if self.cancelled.get() || cancel.poll().is_some() {
+            self.cancelled.set(true);
+            return Err(IPCError::ResponderDead);
+        }
+        // Can safely use unchecked_new because we check sizing during the creation of the channel
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let mut badge: usize = 0;
+        let msg_info: MessageInfo = unsafe {
+            ipc_buffer.copy_req_into_buffer(request);
+            seL4_Send(
+                self.request_endpoint.cptr,
+                type_length_message_info::<Req>(),
+            );
+            seL4_Recv(self.reply_endpoint.cptr, &mut badge as *mut usize)
+        }
+        .into();
+        // The responder's cap to the reply endpoint is unbadged, so a
+        // badge means the bound notification ended the wait.
+        if badge != 0 {
+            self.cancelled.set(true);
+            return Err(IPCError::ResponderDead);
+        }
+        if msg_info.label() == PROTOCOL_ERROR_LABEL {
+            return Err(IPCError::ProtocolError);
+        }
+        if msg_info.length_words() != type_length_in_words::<Rsp>() {
+            return Err(IPCError::ResponseSizeMismatch);
+        }
+        Ok(ipc_buffer.copy_rsp_from_buffer())
+    }
+}
+
+/// The responder's end of a `cancellable_call_channel`.
+#[derive(Debug)]
+pub struct CancellableResponder<Req: Sized, Rsp: Sized, Role: CNodeRole> {
+    request_endpoint: Cap<Endpoint, Role>,
+    reply_endpoint: Cap<Endpoint, Role>,
+    _req: PhantomData<Req>,
+    _rsp: PhantomData<Rsp>,
+}
+
+unsafe impl<Req, Rsp, Role: CNodeRole> AddressSpaceIndependent
+    for CancellableResponder<Req, Rsp, Role>
+{
+}
+
+impl<Req, Rsp> CancellableResponder<Req, Rsp, role::Local> {
+    /// Answer each request with `f`, sending the response back over
+    /// the channel's reply endpoint. Signals to a notification bound
+    /// to this thread are ignored.
+    pub fn reply_recv<F>(self, mut f: F) -> Result<Rsp, IPCError>
+    where
+        F: FnMut(Req) -> (Rsp),
+    {
+        // Can safely use unchecked_new because we check sizing during the creation of the channel
+        let mut ipc_buffer = unsafe { IPCBuffer::unchecked_new() };
+        let request_length_in_words = type_length_in_words::<Req>();
+        loop {
+            let mut sender_badge: usize = 0;
+            let msg_info: MessageInfo =
+                unsafe { seL4_Recv(self.request_endpoint.cptr, &mut sender_badge as *mut usize) }
+                    .into();
+            if sender_badge != 0 {
+                continue;
+            }
+            if msg_info.length_words() != request_length_in_words {
+                // As in reply_recv_internal, reject the request and wait
+                // for the next.
+                debug_println!(
+                    "Request size incoming ({} words) does not match static size expectation ({} words).",
+                    msg_info.length_words(),
+                    request_length_in_words
+                );
+                unsafe { seL4_Send(self.reply_endpoint.cptr, protocol_error_message_info()) };
+                continue;
+            }
+            let response = f(ipc_buffer.copy_req_from_buffer());
+            ipc_buffer.copy_rsp_into_buffer(&response);
+            unsafe { seL4_Send(self.reply_endpoint.cptr, type_length_message_info::<Rsp>()) };
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Sender<Msg: Sized, Role: CNodeRole> {
     pub(crate) endpoint: Cap<Endpoint, Role>,
     pub(crate) _msg: PhantomData<Msg>,
 }
 
+unsafe impl<Msg, Role: CNodeRole> AddressSpaceIndependent for Sender<Msg, Role> {}
+
 impl<Msg: Sized> Sender<Msg, role::Local> {
     pub fn blocking_send<'a>(&self, message: &Msg) -> Result<(), IPCError> {
         // Using unchecked_new is acceptable here because we check the message size
@@ -1,6 +1,7 @@
 use crate::cap::irq_handler::irq_state;
 use crate::cap::irq_handler::weak::{self, WIRQHandler};
 use crate::cap::{CNodeRole, Cap, IRQControl, IRQError, LocalCap, MaxIRQCount, WCNodeSlotsData};
+use crate::userland::AddressSpaceIndependent;
 use arrayvec::ArrayVec;
 use typenum::*;
 
@@ -14,6 +15,8 @@ pub struct WIRQHandlerCollection<Role: CNodeRole> {
     handlers: ArrayVec<[Cap<WIRQHandler<irq_state::Unset>, Role>; MaxIRQCount::USIZE]>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for WIRQHandlerCollection<Role> {}
+
 impl<Role: CNodeRole> WIRQHandlerCollection<Role> {
     pub fn new(
         irq_control: &mut LocalCap<IRQControl>,
@@ -0,0 +1,192 @@
+//! Logging which doesn't depend on the kernel's debug port, and so
+//! works on release kernels too.
+//!
+//! A logger process owns a `LogSink`, which wraps the receiving end of
+//! a `Consumer1` queue of `LogLine`s. Each client process is given a
+//! `Logger`, made from a `Producer` onto that queue with
+//! `Producer::new`, so any number of processes can log to the same
+//! sink. The sink writes what it receives to whatever `fmt::Write`
+//! it's given, e.g. a UART driver.
+//!
+//! `Logger` implements `fmt::Write`, so `write!` and `writeln!` work
+//! against it just as they do against `DebugOutHandle`.
+use core::fmt;
+
+use typenum::*;
+
+use crate::cap::{role, CNodeRole, MaxIRQCount};
+use crate::userland::process::yield_now;
+use crate::userland::{AddressSpaceIndependent, Consumer1, Producer, QueueFullError};
+
+/// Longer lines are cut short.
+pub const LOG_LINE_BYTES: usize = 120;
+
+/// One line of log output, or part of one, as it travels through the
+/// queue.
+#[derive(Clone, Copy)]
+pub struct LogLine {
+    len: usize,
+    bytes: [u8; LOG_LINE_BYTES],
+    /// Whether this is the last part of its line. Lines longer than
+    /// `LOG_LINE_BYTES` are sent in several parts.
+    pub ends_line: bool,
+}
+
+impl LogLine {
+    /// The first `LOG_LINE_BYTES` bytes' worth of `line`, without
+    /// splitting a character.
+    pub fn new(line: &str) -> LogLine {
+        let mut len = core::cmp::min(line.len(), LOG_LINE_BYTES);
+        while !line.is_char_boundary(len) {
+            len -= 1;
+        }
+        let mut bytes = [0; LOG_LINE_BYTES];
+        bytes[..len].copy_from_slice(&line.as_bytes()[..len]);
+        LogLine {
+            len,
+            bytes,
+            ends_line: true,
+        }
+    }
+
+    fn empty() -> LogLine {
+        LogLine {
+            len: 0,
+            bytes: [0; LOG_LINE_BYTES],
+            ends_line: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).unwrap_or("<invalid>")
+    }
+}
+
+impl fmt::Debug for LogLine {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+/// A client process's handle onto a `LogSink`.
+///
+/// Text written through `fmt::Write` is held back until a newline
+/// completes the line, or there's a `LOG_LINE_BYTES`-long part of it
+/// to send. Parts of long lines from different loggers may be
+/// interleaved at the sink.
+pub struct Logger<Role: CNodeRole> {
+    producer: Producer<Role, LogLine>,
+    pending: LogLine,
+}
+
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for Logger<Role> {}
+
+impl<Role: CNodeRole> Logger<Role> {
+    pub fn new(producer: Producer<Role, LogLine>) -> Self {
+        Logger {
+            producer,
+            pending: LogLine::empty(),
+        }
+    }
+}
+
+impl Logger<role::Local> {
+    /// Send `line` to the sink, in as many parts as it takes, yielding
+    /// until there's room for each in the queue.
+    pub fn log(&mut self, line: &str) {
+        self.flush();
+        let mut rest = line;
+        loop {
+            let mut part = LogLine::new(rest);
+            rest = &rest[part.len..];
+            part.ends_line = rest.is_empty();
+            self.send(part);
+            if rest.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// Send whatever has been written since the last newline as a
+    /// line of its own.
+    pub fn flush(&mut self) {
+        if self.pending.len > 0 {
+            let mut line = core::mem::replace(&mut self.pending, LogLine::empty());
+            line.ends_line = true;
+            self.send(line);
+        }
+    }
+
+    fn send(&self, line: LogLine) {
+        let mut line = line;
+        while let Err(QueueFullError(rejected)) = self.producer.send(line) {
+            line = rejected;
+            yield_now();
+        }
+    }
+}
+
+impl fmt::Write for Logger<role::Local> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            if c == '\n' {
+                let mut line = core::mem::replace(&mut self.pending, LogLine::empty());
+                line.ends_line = true;
+                self.send(line);
+                continue;
+            }
+            let len = self.pending.len;
+            if len + c.len_utf8() > LOG_LINE_BYTES {
+                let part = core::mem::replace(&mut self.pending, LogLine::empty());
+                self.send(part);
+            }
+            let len = self.pending.len;
+            c.encode_utf8(&mut self.pending.bytes[len..]);
+            self.pending.len += c.len_utf8();
+        }
+        Ok(())
+    }
+}
+
+/// The logger process's end, receiving lines from every `Logger`.
+pub struct LogSink<Role: CNodeRole, IRQ: Unsigned = U0>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    consumer: Consumer1<Role, LogLine, IRQ>,
+}
+
+unsafe impl<Role: CNodeRole, IRQ: Unsigned> AddressSpaceIndependent for LogSink<Role, IRQ> where
+    IRQ: IsLess<MaxIRQCount, Output = True>
+{
+}
+
+impl<Role: CNodeRole, IRQ: Unsigned> LogSink<Role, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    pub fn new(consumer: Consumer1<Role, LogLine, IRQ>) -> Self {
+        LogSink { consumer }
+    }
+}
+
+impl<IRQ: Unsigned> LogSink<role::Local, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+    /// Write each line to `out` as it arrives, rejoining the parts of
+    /// long lines.
+    pub fn run<W: fmt::Write>(self, out: W) -> ! {
+        self.consumer.consume(
+            out,
+            |out| out,
+            |line, mut out| {
+                let _ = out.write_str(line.as_str());
+                if line.ends_line {
+                    let _ = out.write_str("\n");
+                }
+                out
+            },
+        )
+    }
+}
@@ -1,15 +1,23 @@
+mod barrier;
 mod fault;
 mod ipc;
 mod irq;
+mod log;
 mod multi_consumer;
+mod panic;
 pub(crate) mod process;
-mod rights;
+pub mod rights;
 mod shared_memory_ipc;
+mod spsc_queue;
 
+pub use crate::userland::barrier::*;
 pub use crate::userland::fault::*;
 pub use crate::userland::ipc::*;
 pub use crate::userland::irq::*;
+pub use crate::userland::log::*;
 pub use crate::userland::multi_consumer::*;
+pub use crate::userland::panic::*;
 pub use crate::userland::process::*;
 pub use crate::userland::rights::*;
 pub use crate::userland::shared_memory_ipc::*;
+pub use crate::userland::spsc_queue::*;
@@ -52,7 +52,8 @@ use crate::cap::{
 };
 use crate::error::SeL4Error;
 use crate::pow::{Pow, _Pow};
-use crate::userland::CapRights;
+use crate::userland::rights::rights;
+use crate::userland::{AddressSpaceIndependent, CapRights};
 use crate::vspace::{
     shared_status, KernelRetypeFanOutLimit, MappedMemoryRegion, NumPages, ScratchRegion,
     UnmappedMemoryRegion, VSpace, VSpaceError,
@@ -71,6 +72,13 @@ where
     notification: Cap<Notification, Role>,
 }
 
+unsafe impl<IRQ: Unsigned, Role: CNodeRole> AddressSpaceIndependent
+    for InterruptConsumer<IRQ, Role>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+}
+
 /// A multi-consumer that consumes interrupt-style notifications and from 1 queue
 ///
 /// Designed to be handed to a new process as a member of the
@@ -86,6 +94,15 @@ where
     queue: QueueHandle<T, Role>,
 }
 
+// A queue's address is where it was mapped for the process the
+// consumer or producer was made for, which needn't be this one.
+unsafe impl<Role: CNodeRole, T: Sized + Sync + Send, IRQ: Unsigned> AddressSpaceIndependent
+    for Consumer1<Role, T, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+}
+
 /// A multi-consumer that consumes interrupt-style notifications and from 2 queues
 ///
 /// Designed to be handed to a new process as a member of the
@@ -100,6 +117,13 @@ where
     queues: ((Badge, QueueHandle<E, Role>), (Badge, QueueHandle<F, Role>)),
 }
 
+unsafe impl<Role: CNodeRole, E, F, IRQ: Unsigned> AddressSpaceIndependent
+    for Consumer2<Role, E, F, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+}
+
 /// A multi-consumer that consumes interrupt-style notifications and from 3 queues
 ///
 /// Designed to be handed to a new process as a member of the
@@ -118,6 +142,13 @@ where
     ),
 }
 
+unsafe impl<Role: CNodeRole, E, F, G, IRQ: Unsigned> AddressSpaceIndependent
+    for Consumer3<Role, E, F, G, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+}
+
 /// A multi-consumer that consumes interrupt-style notifications and from 4 queues
 ///
 /// Designed to be handed to a new process as a member of the
@@ -137,6 +168,13 @@ where
     ),
 }
 
+unsafe impl<Role: CNodeRole, E, F, G, H, IRQ: Unsigned> AddressSpaceIndependent
+    for Consumer4<Role, E, F, G, H, IRQ>
+where
+    IRQ: IsLess<MaxIRQCount, Output = True>,
+{
+}
+
 /// Wrapper around the necessary support and capabilities for a given
 /// thread to push elements to an ingest queue for a multi-consumer
 /// (e.g. `Consumer1`, `Consumer2`,etc).
@@ -148,6 +186,8 @@ pub struct Producer<Role: CNodeRole, T: Sized + Sync + Send> {
     queue: QueueHandle<T, Role>,
 }
 
+unsafe impl<Role: CNodeRole, T: Sized + Sync + Send> AddressSpaceIndependent for Producer<Role, T> {}
+
 struct QueueHandle<T: Sized, Role: CNodeRole> {
     // Only valid in the VSpace context of a particular process
     shared_queue: usize,
@@ -653,7 +693,7 @@ where
         GLen: IsGreater<U0, Output = True>,
         ScratchPages: IsGreaterOrEqual<NumPages<GQueueSizeBits>, Output = True>,
 
-        // needed by temporarily_map_region
+        // needed by with_temporary_mapping
         GQueueSizeBits: IsGreaterOrEqual<PageBits>,
         GQueueSizeBits: Sub<PageBits>,
         <GQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
@@ -755,7 +795,7 @@ where
         HLen: IsGreater<U0, Output = True>,
         ScratchPages: IsGreaterOrEqual<NumPages<HQueueSizeBits>, Output = True>,
 
-        // needed by temporarily_map_region
+        // needed by with_temporary_mapping
         HQueueSizeBits: IsGreaterOrEqual<PageBits>,
         HQueueSizeBits: Sub<PageBits>,
         <HQueueSizeBits as Sub<PageBits>>::Output: Unsigned,
@@ -851,7 +891,7 @@ where
     QLen: IsGreater<U0, Output = True>,
     ScratchPages: IsGreaterOrEqual<NumPages<QSizeBits>, Output = True>,
 
-    // needed by temporarily_map_region
+    // needed by with_temporary_mapping
     QSizeBits: IsGreaterOrEqual<PageBits>,
     QSizeBits: Sub<PageBits>,
     <QSizeBits as Sub<PageBits>>::Output: Unsigned,
@@ -870,7 +910,7 @@ where
     let mut region = UnmappedMemoryRegion::new(shared_region_ut, umr_slots)?;
 
     // Put some data in there. Specifically, an `ArrayQueue`.
-    local_vspace_scratch.temporarily_map_region(&mut region, |mapped_region| unsafe {
+    local_vspace_scratch.with_temporary_mapping(&mut region, |mapped_region| unsafe {
         let aq_ptr = core::mem::transmute(mapped_region.vaddr());
 
         // Operate directly on a pointer to an uninitialized/zeroed pointer
@@ -887,7 +927,7 @@ where
     consumer_vspace.skip_pages(1)?;
     let consumer_shared_region = consumer_vspace.map_shared_region(
         &shared_region,
-        CapRights::RW,
+        rights::RW,
         arch::vm_attributes::DEFAULT,
         shared_slots,
         local_cnode,
@@ -906,6 +946,8 @@ pub struct Waker<Role: CNodeRole> {
     notification: Cap<Notification, Role>,
 }
 
+unsafe impl<Role: CNodeRole> AddressSpaceIndependent for Waker<Role> {}
+
 impl Waker<role::Child> {
     pub fn new(
         setup: &WakerSetup,
@@ -1383,7 +1425,7 @@ impl<T: Sized + Sync + Send, Role: CNodeRole> Producer<Role, T> {
         }
         let producer_shared_region = dest_vspace.map_shared_region(
             &setup.shared_region,
-            CapRights::RW,
+            rights::RW,
             arch::vm_attributes::DEFAULT,
             local_slots,
             &local_cnode,
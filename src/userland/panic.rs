@@ -0,0 +1,176 @@
+//! Getting a panic's message and location out of a process which has
+//! no console of its own.
+//!
+//! ferros doesn't define a `#[panic_handler]`; a process opts in by
+//! calling `report_panic` from its own. The destination is a
+//! `Sender<PanicReport, _>`, typically from `fault_or_message_channel`
+//! so that the same handler receives the process's faults and its
+//! panics. A forked process is given its sender at spawn time, with
+//! `StandardProcessBuilder::panic_sender`; any other process installs
+//! one itself with `install_panic_sender`.
+//!
+//! The installed endpoint is kept in the user data word of the
+//! thread's IPC buffer rather than in a static, so that it works in a
+//! process whose image is mapped read-only. That makes it per thread:
+//! each thread reports to whatever was installed on it.
+use core::fmt;
+use core::mem;
+use core::panic::PanicInfo;
+
+use selfe_sys::*;
+
+use crate::cap::role;
+use crate::userland::ipc::{type_length_message_info, IPCBuffer};
+use crate::userland::process::yield_forever;
+use crate::userland::Sender;
+
+/// Longer file paths keep only their last `PANIC_FILE_BYTES` bytes.
+pub const PANIC_FILE_BYTES: usize = 64;
+/// Longer messages are cut short.
+pub const PANIC_MESSAGE_BYTES: usize = 256;
+
+/// A panic's message and location, sized to fit in a single IPC
+/// message on every supported architecture.
+#[derive(Clone, Copy)]
+pub struct PanicReport {
+    file: [u8; PANIC_FILE_BYTES],
+    file_len: usize,
+    pub line: u32,
+    pub column: u32,
+    message: [u8; PANIC_MESSAGE_BYTES],
+    message_len: usize,
+}
+
+impl PanicReport {
+    pub fn from_panic_info(info: &PanicInfo) -> PanicReport {
+        let mut report = PanicReport {
+            file: [0; PANIC_FILE_BYTES],
+            file_len: 0,
+            line: 0,
+            column: 0,
+            message: [0; PANIC_MESSAGE_BYTES],
+            message_len: 0,
+        };
+        if let Some(location) = info.location() {
+            // The tail of a path is the informative part.
+            let file = location.file();
+            let mut start = file.len().saturating_sub(PANIC_FILE_BYTES);
+            while !file.is_char_boundary(start) {
+                start += 1;
+            }
+            let file = &file.as_bytes()[start..];
+            report.file[..file.len()].copy_from_slice(file);
+            report.file_len = file.len();
+            report.line = location.line();
+            report.column = location.column();
+        }
+        let mut writer = TruncatingWriter {
+            buf: &mut report.message,
+            len: 0,
+        };
+        let _ = fmt::write(&mut writer, format_args!("{}", info));
+        report.message_len = writer.len;
+        report
+    }
+
+    pub fn file(&self) -> &str {
+        core::str::from_utf8(&self.file[..self.file_len]).unwrap_or("<invalid>")
+    }
+
+    /// The panic as `PanicInfo` displays it, location included.
+    pub fn message(&self) -> &str {
+        core::str::from_utf8(&self.message[..self.message_len]).unwrap_or("<invalid>")
+    }
+}
+
+impl fmt::Debug for PanicReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+/// Formats into a fixed buffer, dropping whatever doesn't fit without
+/// splitting a character.
+struct TruncatingWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> fmt::Write for TruncatingWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let mut n = core::cmp::min(s.len(), self.buf.len() - self.len);
+        while !s.is_char_boundary(n) {
+            n -= 1;
+        }
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Send future panics on this thread to `sender`. Replaces any
+/// previously installed sender.
+pub fn install_panic_sender(sender: Sender<PanicReport, role::Local>) {
+    set_panic_endpoint(sender.endpoint.cptr);
+}
+
+/// The cptr of the endpoint this thread's panics are reported to, or
+/// `seL4_CapNull` if none has been installed. A fresh IPC buffer is
+/// zeroed, which reads as the latter.
+fn panic_endpoint() -> usize {
+    unsafe { (*seL4_GetIPCBuffer()).userData as usize }
+}
+
+fn set_panic_endpoint(endpoint: usize) {
+    unsafe { (*seL4_GetIPCBuffer()).userData = endpoint as seL4_Word }
+}
+
+/// What a process given a panic sender at spawn time is really
+/// started with: its entry point and parameter, plus the endpoint to
+/// install before calling it.
+pub(crate) struct PanicFrame<T> {
+    pub(crate) entry: usize,
+    pub(crate) returns_status: bool,
+    pub(crate) panic_endpoint: usize,
+    pub(crate) param: T,
+}
+
+/// The entry point of a process given a panic sender at spawn time,
+/// which installs it and then calls the real one. The endpoint comes
+/// in on the stack with the parameter, and nothing in the image is
+/// written.
+pub(crate) extern "C" fn panic_reporting_entry<T>(frame: PanicFrame<T>) {
+    set_panic_endpoint(frame.panic_endpoint);
+    if frame.returns_status {
+        // Nothing collects the status of a process that isn't joinable.
+        let entry: extern "C" fn(T) -> i32 = unsafe { mem::transmute(frame.entry) };
+        let _ = entry(frame.param);
+    } else {
+        let entry: extern "C" fn(T) = unsafe { mem::transmute(frame.entry) };
+        entry(frame.param)
+    }
+}
+
+/// Report a panic and halt. Meant to be called from a process's
+/// `#[panic_handler]`.
+///
+/// With a sender installed, the report goes out as a call whose reply
+/// never comes, so the panicking thread stays blocked rather than
+/// spinning; a `FaultOrMessageHandler` never replies. Without one,
+/// the report is printed with `debug_println!` instead.
+pub fn report_panic(info: &PanicInfo) -> ! {
+    let report = PanicReport::from_panic_info(info);
+    let endpoint = panic_endpoint();
+    if endpoint == seL4_CapNull as usize {
+        debug_println!("{:?}", report);
+    } else {
+        // PanicReport is well under the size of an IPC buffer on any
+        // architecture.
+        let mut ipc_buffer: IPCBuffer<PanicReport, ()> = unsafe { IPCBuffer::unchecked_new() };
+        ipc_buffer.copy_req_into_buffer(&report);
+        unsafe {
+            seL4_Call(endpoint, type_length_message_info::<PanicReport>());
+        }
+    }
+    yield_forever()
+}
@@ -0,0 +1,45 @@
+use core::ptr;
+
+use selfe_sys::seL4_Signal;
+use typenum::Unsigned;
+
+use crate::cap::{LocalCap, Notification};
+use crate::vspace::{shared_status, MappedMemoryRegion};
+
+use super::yield_forever;
+
+/// Space set aside at the very top of a joinable process' stack for
+/// its exit status. Kept at 16 bytes so the stack stays aligned for
+/// aarch64.
+pub(crate) const EXIT_STATUS_RESERVATION: usize = 16;
+
+/// Where a joinable process' entry point returns to, by way of
+/// `ferros_joinable_exit`: leaves what it returned as the exit status
+/// and signals the parent.
+#[no_mangle]
+extern "C" fn ferros_report_exit(status: i32, exit_status: usize, exit_notification: usize) -> ! {
+    unsafe {
+        ptr::write_volatile(exit_status as *mut i32, status);
+        seL4_Signal(exit_notification);
+    }
+    yield_forever()
+}
+
+/// The parent's side of a joinable process, from
+/// `StandardProcess::new_joinable`.
+pub struct JoinHandle<StackBitSize: Unsigned> {
+    pub(crate) exit_notification: LocalCap<Notification>,
+    /// The parent's own mapping of the process' stack, at the top of
+    /// which the exit status is left.
+    pub(crate) stack: MappedMemoryRegion<StackBitSize, shared_status::Shared>,
+}
+
+impl<StackBitSize: Unsigned> JoinHandle<StackBitSize> {
+    /// Block until the process' entry point has returned, and give
+    /// back what it returned.
+    pub fn join(self) -> i32 {
+        let _ = self.exit_notification.wait();
+        let status_addr = self.stack.vaddr() + self.stack.size_bytes() - EXIT_STATUS_RESERVATION;
+        unsafe { ptr::read_volatile(status_addr as *const i32) }
+    }
+}
@@ -1,9 +1,11 @@
 use core::marker::PhantomData;
 
+use arrayvec::{Array, ArrayVec};
 use selfe_sys::seL4_Yield;
 
 use typenum::*;
 
+use crate::cap::{LocalCap, Notification, RetypeError};
 use crate::error::*;
 use crate::vspace::VSpaceError;
 
@@ -13,22 +15,129 @@ mod thread;
 pub use thread::{Thread, ThreadSetupError};
 
 mod standard;
-pub use standard::StandardProcess;
+#[cfg(KernelIsMCS)]
+pub use standard::SchedBudget;
+pub use standard::{StandardProcess, StandardProcessBuilder, ThreadState, Unset};
 
 mod self_hosted;
 pub use self_hosted::SelfHostedProcess;
 
+mod pool;
+pub use pool::{MaxPoolWorkers, PoolWorker, WorkerPool, WorkerSetup};
+
+mod join;
+pub use join::JoinHandle;
+
+mod tls;
+pub use tls::TlsImage;
+
 pub type DefaultStackBitSize = U20;
 pub type DefaultStackPageCount = op!((U1 << U20) / U4096);
 pub type DefaultPrepareThreadCNodeSlots = op!(DefaultStackPageCount + U64);
 
 // TODO - consider renaming for clarity
-pub trait RetypeForSetup: Sized + Send + Sync {
-    type Output: Sized + Send + Sync;
+pub trait RetypeForSetup: AddressSpaceIndependent + Sized + Send + Sync {
+    type Output: AddressSpaceIndependent + Sized + Send + Sync;
 }
 
 pub type SetupVer<X> = <X as RetypeForSetup>::Output;
 
+/// `#[derive(RetypeForSetup)]` writes the usual impl for a parameter
+/// struct, along with its `AddressSpaceIndependent` impl.
+pub use ferros_derive::RetypeForSetup;
+
+/// Types which mean the same thing after being copied, byte for byte,
+/// into another address space, as a child's parameters are.
+///
+/// References and raw pointers don't qualify, nor does anything
+/// holding one. Caps do, since a cap is just its cptr; which CSpace
+/// that cptr is good in is the business of its role, not this trait.
+///
+/// This is unsafe to implement by hand, because nothing checks the
+/// claim; `#[derive(AddressSpaceIndependent)]` implements it for a
+/// type whose fields all are.
+pub unsafe trait AddressSpaceIndependent {}
+
+pub use ferros_derive::AddressSpaceIndependent;
+
+macro_rules! address_space_independent {
+    ($($t:ty),*) => {
+        $(
+            unsafe impl AddressSpaceIndependent for $t {}
+        )*
+    };
+}
+
+address_space_independent!(
+    (),
+    bool,
+    char,
+    u8,
+    u16,
+    u32,
+    u64,
+    u128,
+    usize,
+    i8,
+    i16,
+    i32,
+    i64,
+    i128,
+    isize,
+    f32,
+    f64
+);
+
+unsafe impl<T: AddressSpaceIndependent, const N: usize> AddressSpaceIndependent for [T; N] {}
+
+unsafe impl<T: AddressSpaceIndependent> AddressSpaceIndependent for Option<T> {}
+
+unsafe impl<T: AddressSpaceIndependent, E: AddressSpaceIndependent> AddressSpaceIndependent
+    for Result<T, E>
+{
+}
+
+// Nothing is stored, so whatever it's over doesn't matter.
+unsafe impl<T: ?Sized> AddressSpaceIndependent for PhantomData<T> {}
+
+unsafe impl<A: Array> AddressSpaceIndependent for ArrayVec<A> where A::Item: AddressSpaceIndependent {}
+
+macro_rules! address_space_independent_tuple {
+    ($(($($t:ident),+)),*) => {
+        $(
+            unsafe impl<$($t: AddressSpaceIndependent),+> AddressSpaceIndependent for ($($t,)+) {}
+        )*
+    };
+}
+
+address_space_independent_tuple!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F)
+);
+
+// A process parameter may be a list of up to N others, of which the
+// child sees however many the parent pushed. The whole `ArrayVec` is
+// copied onto the child's stack, so N counts against the stack size
+// however few entries are in use.
+macro_rules! array_vec_retype_for_setup {
+    ($($n:expr),*) => {
+        $(
+            impl<T: RetypeForSetup> RetypeForSetup for ArrayVec<[T; $n]> {
+                type Output = ArrayVec<[SetupVer<T>; $n]>;
+            }
+        )*
+    };
+}
+
+array_vec_retype_for_setup!(
+    1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26,
+    27, 28, 29, 30, 31, 32, 64
+);
+
 /// A helper zero-sized struct that forces structures
 /// which have a field of its type to not auto-implement
 /// core::marker::Send or core::marker::Sync.
@@ -44,11 +153,27 @@ impl core::default::Default for NeitherSendNorSync {
     }
 }
 
+/// Give up the rest of this thread's timeslice to any other thread
+/// of the same priority.
+pub fn yield_now() {
+    unsafe { seL4_Yield() }
+}
+
 pub fn yield_forever() -> ! {
-    unsafe {
-        loop {
-            seL4_Yield();
-        }
+    loop {
+        yield_now();
+    }
+}
+
+/// Block until `timer` has been signalled `ticks` times.
+///
+/// `timer` is expected to be signalled once per tick, e.g. by a driver
+/// thread which handles a periodic timer's IRQ and acks it. Signals which arrive while this
+/// thread isn't waiting coalesce into one, so a thread which doesn't
+/// get back to waiting promptly will sleep for longer than asked.
+pub fn sleep_ticks(timer: &LocalCap<Notification>, ticks: usize) {
+    for _ in 0..ticks {
+        let _ = timer.wait();
     }
 }
 
@@ -58,8 +183,24 @@ pub enum ProcessSetupError {
     ProcessParameterHandoffSizeMismatch,
     NotEnoughCNodeSlots,
     ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
+    TlsRegionTooSmall,
+    /// A `TlsImage` whose `.tdata` is bigger than the whole block, or
+    /// whose alignment isn't a power of two.
+    MalformedTlsImage,
+    /// Only forked processes whose entry point returns an exit status
+    /// can be joined.
+    EntryPointNotJoinable,
+    /// A panic sender can only be installed in a forked process, which
+    /// shares ferros with its parent.
+    PanicSenderRequiresFork,
+    /// Affinity was requested from a single core kernel.
+    AffinityRequiresSMP,
+    /// A `WorkerPool` was given more or fewer worker setups or
+    /// parameters than it has workers.
+    WorkerCountMismatch,
     VSpaceError(VSpaceError),
     SeL4Error(SeL4Error),
+    RetypeError(RetypeError),
     ElfParseError(&'static str),
 }
 
@@ -74,3 +215,9 @@ impl From<SeL4Error> for ProcessSetupError {
         ProcessSetupError::SeL4Error(e)
     }
 }
+
+impl From<RetypeError> for ProcessSetupError {
+    fn from(e: RetypeError) -> Self {
+        ProcessSetupError::RetypeError(e)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::arch::*;
+use crate::cap::KernelRetypeFanOutLimit;
+use crate::cap::*;
+use crate::pow::{Pow, _Pow};
+use crate::userland::{FaultHandler, FaultManagementError, FaultSource, StackGuards};
+use crate::vspace::*;
+use core::ops::Sub;
+
+use selfe_sys::*;
+use typenum::*;
+
+use crate::error::{ErrorExt, SeL4Error};
+
+use super::standard::{configure_thread, ChildStack};
+use super::*;
+
+/// The most workers one `WorkerPool` can hold.
+pub type MaxPoolWorkers = U32;
+
+/// What each worker in a `WorkerPool` is started with: its place in
+/// the pool, counting from zero, and the parameter it was given.
+pub struct PoolWorker<T> {
+    pub index: usize,
+    pub params: T,
+}
+
+unsafe impl<T: AddressSpaceIndependent> AddressSpaceIndependent for PoolWorker<T> {}
+
+impl<T: RetypeForSetup> RetypeForSetup for PoolWorker<T> {
+    type Output = PoolWorker<SetupVer<T>>;
+}
+
+/// The address space, CSpace and stack of one worker in a
+/// `WorkerPool`, which are its alone. `stack` is mapped in the parent,
+/// and `stack_slots` holds the copies of its pages mapped into
+/// `vspace`.
+pub struct WorkerSetup<'v, StackBitSize: Unsigned, StackSlots: Unsigned> {
+    pub vspace: &'v mut VSpace,
+    pub cspace: LocalCap<ChildCNode>,
+    pub stack: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+    pub stack_slots: LocalCNodeSlots<StackSlots>,
+    pub fault_source: Option<FaultSource<role::Child>>,
+}
+
+struct PoolThread {
+    tcb: LocalCap<ThreadControlBlock>,
+    stack_guards: StackGuards,
+}
+
+/// `Count` child processes, all starting at the same entry point.
+///
+/// Each worker is a process of its own, with its own address space,
+/// CSpace and stack, so one can't touch another's data. What they
+/// share is the code image: make each worker's `VSpace` with
+/// `ProcessCodeImageConfig::ReadOnly` and the same pages of it are
+/// mapped into all of them rather than copied. Their TCBs and IPC
+/// buffers are made together, in two retypes.
+pub struct WorkerPool<Count: Unsigned, StackBitSize: Unsigned = DefaultStackBitSize> {
+    workers: ArrayVec<[PoolThread; MaxPoolWorkers::USIZE]>,
+    _count: PhantomData<Count>,
+    _stack_bit_size: PhantomData<StackBitSize>,
+}
+
+impl<Count: Unsigned, StackBitSize: Unsigned> WorkerPool<Count, StackBitSize> {
+    /// Set up `Count` workers, each of which runs `entry_point` once
+    /// the pool is started. Worker `i` is made in the `i`th of
+    /// `workers` and is handed the `i`th of `params`, along with `i`
+    /// itself, as a `PoolWorker`. Both must yield exactly `Count`
+    /// items.
+    ///
+    /// All the workers' TCBs and IPC buffers are made out of `untyped`,
+    /// in slots from `slots`. If setting up any worker fails, the TCBs
+    /// are all deleted again, so none is left half configured.
+    pub fn new<'v, T: RetypeForSetup, BitSize: Unsigned, StackSlots: Unsigned>(
+        workers: impl IntoIterator<Item = WorkerSetup<'v, StackBitSize, StackSlots>>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: extern "C" fn(PoolWorker<T>) -> (),
+        params: impl IntoIterator<Item = SetupVer<T>>,
+        untyped: LocalCap<Untyped<BitSize>>,
+        slots: &mut WCNodeSlots,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+    ) -> Result<WorkerPool<Count, StackBitSize>, ProcessSetupError>
+    where
+        Count: IsLessOrEqual<MaxPoolWorkers, Output = True>,
+        Count: IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+
+        BitSize: IsGreaterOrEqual<PageBits, Output = True>,
+        BitSize: IsGreaterOrEqual<<ThreadControlBlock as DirectRetype>::SizeBits, Output = True>,
+
+        StackSlots: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        // TODO - lift these checks to compile-time, as static assertions
+        if core::mem::size_of::<PoolWorker<SetupVer<T>>>() > 2usize.pow(StackBitSize::U32) {
+            return Err(ProcessSetupError::ProcessParameterTooBigForStack);
+        }
+        if core::mem::size_of::<PoolWorker<SetupVer<T>>>() != core::mem::size_of::<PoolWorker<T>>()
+        {
+            return Err(ProcessSetupError::ProcessParameterHandoffSizeMismatch);
+        }
+
+        let ipc_buffer_slots = slots
+            .alloc_strong::<Count>()
+            .map_err(|_| ProcessSetupError::NotEnoughCNodeSlots)?;
+        let tcb_slots = slots
+            .alloc_strong::<Count>()
+            .map_err(|_| ProcessSetupError::NotEnoughCNodeSlots)?;
+        // The pages are asked for first, since they're the larger
+        // objects and so lose the least of the untyped to alignment.
+        let (ipc_buffers, tcbs) = untyped
+            .retype_batch()
+            .retype::<Page<page_state::Unmapped>, role::Local, Count>(ipc_buffer_slots)
+            .retype::<ThreadControlBlock, role::Local, Count>(tcb_slots)
+            .finish()
+            .map_err(|(e, _untyped)| e)?;
+        let tcb_cptr = |index: usize| -> LocalCap<ThreadControlBlock> {
+            Cap {
+                cptr: tcbs.start_cptr + index,
+                cap_data: PhantomCap::phantom_instance(),
+                _role: PhantomData,
+            }
+        };
+
+        let mut workers = workers.into_iter();
+        let mut params = params.into_iter();
+        let mut threads = ArrayVec::new();
+        let set_up_all = || -> Result<(), ProcessSetupError> {
+            for (index, ipc_buffer) in ipc_buffers.into_iter().enumerate() {
+                let (setup, worker_params) = match (workers.next(), params.next()) {
+                    (Some(setup), Some(worker_params)) => (setup, worker_params),
+                    _ => return Err(ProcessSetupError::WorkerCountMismatch),
+                };
+
+                let stack = ChildStack::map(
+                    &mut *setup.vspace,
+                    setup.stack,
+                    parent_cnode,
+                    setup.stack_slots,
+                )?;
+                let mut registers = stack.push_param(
+                    &PoolWorker {
+                        index,
+                        params: worker_params,
+                    },
+                    0,
+                )?;
+                registers.pc = entry_point as usize;
+
+                set_thread_link_register(&mut registers, yield_forever);
+
+                let mut tcb = tcb_cptr(index);
+                configure_thread(
+                    &mut tcb,
+                    setup.vspace,
+                    setup.cspace,
+                    setup.fault_source,
+                    ipc_buffer,
+                    &mut registers,
+                    priority_authority,
+                    255,
+                )?;
+                threads.push(PoolThread {
+                    tcb,
+                    stack_guards: stack.guards,
+                });
+            }
+            if workers.next().is_some() || params.next().is_some() {
+                return Err(ProcessSetupError::WorkerCountMismatch);
+            }
+            Ok(())
+        };
+
+        if let Err(e) = set_up_all() {
+            // None of the workers has been started, so deleting the
+            // only caps to their TCBs destroys them before they run.
+            for index in 0..Count::USIZE {
+                let _ = tcb_cptr(index).delete(parent_cnode);
+            }
+            return Err(e);
+        }
+
+        Ok(WorkerPool {
+            workers: threads,
+            _count: PhantomData,
+            _stack_bit_size: PhantomData,
+        })
+    }
+
+    /// Start every worker, in index order.
+    pub fn start(&mut self) -> Result<(), SeL4Error> {
+        for worker in self.workers.iter() {
+            unsafe { seL4_TCB_Resume(worker.tcb.cptr) }
+                .as_result()
+                .map_err(|e| SeL4Error::TCBResume(e))?;
+        }
+        Ok(())
+    }
+
+    /// The unmapped pages either side of worker `index`'s stack.
+    pub fn stack_guards(&self, index: usize) -> Option<StackGuards> {
+        self.workers.get(index).map(|w| w.stack_guards)
+    }
+
+    /// Have `handler` report faults in the workers' stack guard pages
+    /// as `FaultCause::StackOverflow`. `badges` are those of the
+    /// workers' fault sources, in index order; workers past the last
+    /// of them aren't watched.
+    pub fn watch_stack_guards(
+        &self,
+        handler: &mut FaultHandler,
+        badges: impl IntoIterator<Item = Badge>,
+    ) -> Result<(), FaultManagementError> {
+        for (worker, badge) in self.workers.iter().zip(badges) {
+            handler.watch_stack(badge, worker.tcb.cptr, worker.stack_guards)?;
+        }
+        Ok(())
+    }
+}
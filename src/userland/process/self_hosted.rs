@@ -9,6 +9,7 @@ use crate::cap::{
     role, CNodeRole, CNodeSlotsError, Cap, ChildCNode, DirectRetype, LocalCNode, LocalCNodeSlots,
     LocalCap, ThreadControlBlock, ThreadPriorityAuthority, Untyped, WCNodeSlotsData,
 };
+use crate::userland::rights::rights;
 use crate::userland::CapRights;
 use crate::vspace::*;
 
@@ -110,10 +111,10 @@ impl<StackBitSize: Unsigned> SelfHostedProcess<StackBitSize> {
         // Map the stack to the target address space
         let stack_top = parent_mapped_region.vaddr() + parent_mapped_region.size_bytes();
         let (unmapped_stack_pages, local_stack_pages) =
-            parent_mapped_region.share(stack_slots, parent_cnode, CapRights::RW)?;
+            parent_mapped_region.share(stack_slots, parent_cnode, rights::RW)?;
         let mapped_stack_pages = vspace.map_shared_region_and_consume(
             unmapped_stack_pages,
-            CapRights::RW,
+            rights::RW,
             arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
         )?;
 
@@ -1,7 +1,9 @@
 use crate::arch::{self, *};
 use crate::cap::*;
 use crate::pow::{Pow, _Pow};
-use crate::userland::rights::CapRights;
+use crate::userland::panic::{panic_reporting_entry, PanicFrame};
+use crate::userland::rights::{rights, CapRights};
+use crate::userland::{FaultHandler, FaultManagementError, PanicReport, Sender, StackGuards};
 use crate::vspace::*;
 use core::ops::{Add, Sub};
 
@@ -10,6 +12,7 @@ use typenum::*;
 
 use crate::error::{ErrorExt, SeL4Error};
 
+use super::join::EXIT_STATUS_RESERVATION;
 use super::*;
 
 /// A standard process in Ferros is a TCB associated with a VSpace
@@ -23,11 +26,33 @@ use super::*;
 ///    TCB.
 pub struct StandardProcess<StackBitSize: Unsigned = DefaultStackBitSize> {
     tcb: LocalCap<ThreadControlBlock>,
+    stack_guards: StackGuards,
+    started: bool,
+    #[cfg(KernelIsMCS)]
+    sched_context: Option<LocalCap<SchedContext>>,
     _stack_bit_size: PhantomData<StackBitSize>,
 }
 
+/// What's become of a process' thread, as far as ferros has seen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadState {
+    /// Set up, but not yet started.
+    NotStarted,
+    /// Started, and not known to be stuck. It may be running, waiting
+    /// to run or blocked on IPC.
+    Started,
+    /// Left blocked on a fault for good by its fault handler.
+    Killed,
+}
+
 pub enum EntryPoint<'a, T> {
     Fork(extern "C" fn(T) -> ()),
+    /// A forked entry point which returns an exit status. The status
+    /// is only collected for joinable processes.
+    ForkWithStatus(extern "C" fn(T) -> i32),
+    /// Start at the ELF's entry point. The ELF's loadable segments are
+    /// not mapped here; the child's VSpace must have been made with
+    /// `VSpace::new_from_elf` from the same image.
     Elf(&'a [u8]),
 }
 
@@ -42,6 +67,12 @@ impl<'a, T> From<extern "C" fn(T) -> ()> for EntryPoint<'a, T> {
     }
 }
 
+impl<'a, T> From<extern "C" fn(T) -> i32> for EntryPoint<'a, T> {
+    fn from(f: extern "C" fn(T) -> i32) -> Self {
+        EntryPoint::ForkWithStatus(f)
+    }
+}
+
 impl<'a, T> From<&'a [u8]> for EntryPoint<'a, T> {
     fn from(elf_data: &'a [u8]) -> Self {
         EntryPoint::Elf(elf_data)
@@ -76,14 +107,177 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         <StackBitSize as Sub<PageBits>>::Output: _Pow,
         Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
     {
-        let entry_point = entry_point.into();
+        let (process, _) = Self::new_internal(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            entry_point.into(),
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            255,
+            fault_source,
+            None,
+            None,
+            None,
+        )?;
+        Ok(process)
+    }
 
-        if parent_mapped_region.asid() == vspace.asid() {
-            return Err(
-                ProcessSetupError::ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
-            );
-        }
+    /// Like `new`, but with `bound_notification` bound to the process'
+    /// thread before `new` returns. Signals sent to it from the moment
+    /// the process starts, such as interrupts, are delivered rather
+    /// than lost.
+    pub fn new_with_notification<'a, T: RetypeForSetup, EP: Into<EntryPoint<'a, T>>>(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: EP,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        bound_notification: &LocalCap<Notification>,
+    ) -> Result<StandardProcess<StackBitSize>, ProcessSetupError>
+    where
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let (process, _) = Self::new_internal(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            entry_point.into(),
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            255,
+            fault_source,
+            None,
+            None,
+            Some(bound_notification),
+        )?;
+        Ok(process)
+    }
 
+    /// Like `new`, but the process signals `exit_notification` once
+    /// its entry point returns, so that the returned `JoinHandle` can
+    /// wait for it and collect the returned exit status.
+    /// `exit_notification_slot` is where the process' copy of the
+    /// notification will live.
+    pub fn new_joinable<T: RetypeForSetup>(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: extern "C" fn(T) -> i32,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        exit_notification: LocalCap<Notification>,
+        exit_notification_slot: ChildCNodeSlot,
+    ) -> Result<(StandardProcess<StackBitSize>, JoinHandle<StackBitSize>), ProcessSetupError>
+    where
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let child_exit_notification =
+            exit_notification.copy(parent_cnode, exit_notification_slot, CapRights::W)?;
+        let (process, stack) = Self::new_internal(
+            vspace,
+            cspace,
+            parent_mapped_region,
+            parent_cnode,
+            EntryPoint::ForkWithStatus(entry_point),
+            process_parameter,
+            ipc_buffer_ut,
+            tcb_ut,
+            slots,
+            priority_authority,
+            255,
+            fault_source,
+            Some(child_exit_notification),
+            None,
+            None,
+        )?;
+        Ok((
+            process,
+            JoinHandle {
+                exit_notification,
+                stack,
+            },
+        ))
+    }
+
+    fn new_internal<'a, T: RetypeForSetup>(
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        entry_point: EntryPoint<'a, T>,
+        process_parameter: SetupVer<T>,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+        tcb_ut: LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        priority: usize,
+        fault_source: Option<crate::userland::FaultSource<role::Child>>,
+        exit_notification: Option<Cap<Notification, role::Child>>,
+        panic_sender: Option<Sender<PanicReport, role::Child>>,
+        bound_notification: Option<&LocalCap<Notification>>,
+    ) -> Result<
+        (
+            StandardProcess<StackBitSize>,
+            MappedMemoryRegion<StackBitSize, shared_status::Shared>,
+        ),
+        ProcessSetupError,
+    >
+    where
+        NumPages<StackBitSize>: Add<U2>,
+        Sum<NumPages<StackBitSize>, U2>: Unsigned,
+
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        StackBitSize: Sub<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        <StackBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
         let (misc_slots, stack_slots) = slots.alloc::<U2>();
         // TODO - lift these checks to compile-time, as static assertions
         // Note - This comparison is conservative because technically
@@ -95,39 +289,48 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             return Err(ProcessSetupError::ProcessParameterHandoffSizeMismatch);
         }
 
-        // Reserve a guard page before the stack
-        vspace.skip_pages(1)?;
-
-        // Map the stack to the target address space
-        let stack_top = parent_mapped_region.vaddr() + parent_mapped_region.size_bytes();
-        let (unmapped_stack_pages, local_stack_pages): (UnmappedMemoryRegion<StackBitSize, _>, _) =
-            parent_mapped_region.share(stack_slots, parent_cnode, CapRights::RW)?;
-        let mapped_stack_pages = vspace.map_shared_region_and_consume(
-            unmapped_stack_pages,
-            CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
-        )?;
+        let stack = ChildStack::map(vspace, parent_mapped_region, parent_cnode, stack_slots)?;
 
-        // map the child stack into local memory so we can copy the contents
-        // of the process params into it
-        let (mut registers, param_size_on_stack) = unsafe {
-            setup_initial_stack_and_regs(
-                &process_parameter as *const SetupVer<T> as *const usize,
-                core::mem::size_of::<SetupVer<T>>(),
-                stack_top as *mut usize,
-                mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes(),
-            )
+        // A joinable process leaves its exit status at the very top of
+        // its stack, above its parameter.
+        let mut registers = match exit_notification {
+            None => match panic_sender {
+                None => stack.push_param(&process_parameter, 0)?,
+                // It installs a panic sender given at spawn time in
+                // a trampoline before calling the real entry point.
+                Some(ref panic_sender) => {
+                    let (entry, returns_status) = match &entry_point {
+                        EntryPoint::Fork(f) => (*f as usize, false),
+                        EntryPoint::ForkWithStatus(f) => (*f as usize, true),
+                        EntryPoint::Elf(_) => {
+                            return Err(ProcessSetupError::PanicSenderRequiresFork)
+                        }
+                    };
+                    let frame = PanicFrame {
+                        entry,
+                        returns_status,
+                        panic_endpoint: panic_sender.endpoint.cptr,
+                        param: process_parameter,
+                    };
+                    stack.push_param(&frame, 0)?
+                }
+            },
+            Some(_) => match &entry_point {
+                EntryPoint::ForkWithStatus(_) => {
+                    stack.push_param(&process_parameter, EXIT_STATUS_RESERVATION)?
+                }
+                _ => return Err(ProcessSetupError::EntryPointNotJoinable),
+            },
         };
 
-        local_stack_pages.flush()?;
-
-        let stack_pointer =
-            mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes() - param_size_on_stack;
-
-        registers.sp = stack_pointer;
-
         registers.pc = match entry_point {
+            EntryPoint::Fork(_) | EntryPoint::ForkWithStatus(_)
+                if panic_sender.is_some() && exit_notification.is_none() =>
+            {
+                panic_reporting_entry::<T> as extern "C" fn(_) -> () as usize
+            }
             EntryPoint::Fork(f) => f as usize,
+            EntryPoint::ForkWithStatus(f) => f as usize,
             EntryPoint::Elf(elf_data) => {
                 let elf =
                     xmas_elf::ElfFile::new(elf_data).map_err(ProcessSetupError::ElfParseError)?;
@@ -136,59 +339,116 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         };
 
         // TODO - Probably ought to suspend or destroy the thread instead of endlessly yielding
-        match entry_point {
+        match (&entry_point, &exit_notification) {
+            // A joinable process returns into reporting its exit status
+            (EntryPoint::ForkWithStatus(_), Some(exit_notification)) => set_thread_exit_stub(
+                &mut registers,
+                stack.child_top(EXIT_STATUS_RESERVATION),
+                exit_notification.cptr,
+            ),
             // This doesn't work for elf procs, since yield_forever isn't there
-            EntryPoint::Fork(_) => {
+            (EntryPoint::Fork(_), _) | (EntryPoint::ForkWithStatus(_), _) => {
                 set_thread_link_register(&mut registers, yield_forever);
                 ()
             }
             _ => (),
         };
 
-        // Reserve a guard page after the stack
-        vspace.skip_pages(1)?;
-
-        // Allocate and map the ipc buffer
         let (ipc_slots, misc_slots) = misc_slots.alloc();
         let ipc_buffer = ipc_buffer_ut.retype(ipc_slots)?;
-        let ipc_buffer = vspace.map_region(
-            ipc_buffer.to_region(),
-            CapRights::RW,
-            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
-        )?;
 
         //// allocate the thread control block
         let (tcb_slots, _slots) = misc_slots.alloc();
         let mut tcb = tcb_ut.retype(tcb_slots)?;
 
-        tcb.configure(
+        configure_thread(
+            &mut tcb,
+            vspace,
             cspace,
             fault_source,
-            &vspace.root(),
-            Some(ipc_buffer.to_page()),
+            ipc_buffer,
+            &mut registers,
+            priority_authority,
+            priority,
         )?;
-        unsafe {
-            seL4_TCB_WriteRegisters(
-                tcb.cptr,
-                0,
-                0,
-                // all the regs
-                core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
-                &mut registers,
-            )
-            .as_result()
-            .map_err(|e| ProcessSetupError::SeL4Error(SeL4Error::TCBWriteRegisters(e)))?;
+        if let Some(notification) = bound_notification {
+            unsafe { seL4_TCB_BindNotification(tcb.cptr, notification.cptr) }
+                .as_result()
+                .map_err(|e| SeL4Error::TCBBindNotification(e))?;
+        }
+        Ok((
+            StandardProcess {
+                tcb,
+                stack_guards: stack.guards,
+                started: false,
+                #[cfg(KernelIsMCS)]
+                sched_context: None,
+                _stack_bit_size: PhantomData,
+            },
+            stack.local_pages,
+        ))
+    }
+
+    /// The unmapped pages either side of this process' stack.
+    pub fn stack_guards(&self) -> StackGuards {
+        self.stack_guards
+    }
 
-            // TODO - priority management could be exposed once we
-            // plan on actually using it
-            tcb.set_priority(priority_authority, 255)?;
+    /// Have `handler` report faults in this process' stack guard pages
+    /// as `FaultCause::StackOverflow`. `badge` is that of the fault
+    /// source the process was set up with.
+    pub fn watch_stack_guards(
+        &self,
+        handler: &mut FaultHandler,
+        badge: Badge,
+    ) -> Result<(), FaultManagementError> {
+        handler.watch_stack(badge, self.tcb.cptr, self.stack_guards)
+    }
+
+    /// Give the process' thread a thread-local storage area, laid out
+    /// from `image` in `parent_mapped_region` and then shared into the
+    /// process' address space. Returns the new thread pointer.
+    ///
+    /// This must be done before the process is started if anything in
+    /// it uses `#[thread_local]` statics.
+    pub fn setup_tls<TlsBitSize: Unsigned>(
+        &mut self,
+        vspace: &mut VSpace,
+        mut parent_mapped_region: MappedMemoryRegion<TlsBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        image: &TlsImage,
+        slots: LocalCNodeSlots<NumPages<TlsBitSize>>,
+    ) -> Result<usize, ProcessSetupError>
+    where
+        TlsBitSize: IsGreaterOrEqual<PageBits>,
+        TlsBitSize: Sub<PageBits>,
+        <TlsBitSize as Sub<PageBits>>::Output: Unsigned,
+        <TlsBitSize as Sub<PageBits>>::Output: _Pow,
+        Pow<<TlsBitSize as Sub<PageBits>>::Output>: Unsigned,
+        NumPages<TlsBitSize>: IsEqual<NumPages<TlsBitSize>, Output = True>,
+    {
+        if parent_mapped_region.asid() == vspace.asid() {
+            return Err(
+                ProcessSetupError::ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
+            );
         }
-        Ok(StandardProcess {
-            tcb,
-            _stack_bit_size: PhantomData,
-        })
+        image.write_to(parent_mapped_region.as_mut_slice())?;
+        let (unmapped_tls_pages, local_tls_pages) =
+            parent_mapped_region.share(slots, parent_cnode, rights::RW)?;
+        local_tls_pages.flush()?;
+        let mapped_tls_pages = vspace.map_shared_region_and_consume(
+            unmapped_tls_pages,
+            rights::RW,
+            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+        )?;
+
+        let thread_pointer = mapped_tls_pages.vaddr();
+        self.tcb.set_tls_base(thread_pointer)?;
+        Ok(thread_pointer)
     }
 
+    /// Name the process' thread, as it will appear in the output of
+    /// `ferros::debug::dump_scheduler`.
     pub fn set_name(&mut self, name: &str) {
         let mut c_str = [0u8; 256];
         for (n, byte) in name.bytes().take(255).enumerate() {
@@ -200,6 +460,58 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         }
     }
 
+    /// Move the process' thread into scheduling domain `domain`, so it
+    /// only runs during that domain's slices of the kernel's domain
+    /// schedule. Processes start out in the domain of their parent.
+    #[cfg(KernelMultipleDomains)]
+    pub fn set_domain(
+        &mut self,
+        domain_set: &LocalCap<DomainSet>,
+        domain: u8,
+    ) -> Result<(), SeL4Error> {
+        domain_set.set_domain(&mut self.tcb, domain)
+    }
+
+    /// Give the process' thread a scheduling context with `budget_us`
+    /// microseconds of CPU time every `period_us`, and set its
+    /// priority and maximum controlled priority `mcp` along with it.
+    /// On an MCS kernel a process won't run until it has one. The
+    /// scheduling context is kept with the process; a second call
+    /// fails rather than drop the first. See
+    /// `ThreadControlBlock::set_sched_params` for `fault_endpoint`.
+    #[cfg(KernelIsMCS)]
+    pub fn set_sched_context(
+        &mut self,
+        priority_authority: &LocalCap<ThreadPriorityAuthority>,
+        budget: SchedBudget<'_>,
+        mcp: usize,
+        priority: usize,
+        fault_endpoint: Option<&LocalCap<FaultEndpoint>>,
+    ) -> Result<(), SeL4Error> {
+        if self.sched_context.is_some() {
+            return Err(SeL4Error::TCBSetSchedParams(
+                crate::error::KernelError::IllegalOperation,
+            ));
+        }
+        let mut sched_context = budget.untyped.retype_sched_context(budget.slot)?;
+        budget
+            .sched_control
+            .configure(&mut sched_context, budget.budget_us, budget.period_us)?;
+        self.tcb.set_sched_params(
+            priority_authority,
+            mcp,
+            priority,
+            &sched_context,
+            fault_endpoint,
+        )?;
+        self.sched_context = Some(sched_context);
+        Ok(())
+    }
+
+    /// Bind `notification` to the process' thread. If the process is
+    /// already running, it may block on an endpoint before the binding
+    /// is in place and miss being woken by a signal, so prefer calling
+    /// this before `start`, or use `new_with_notification`.
     pub fn bind_notification(
         &mut self,
         notification: &LocalCap<Notification>,
@@ -209,7 +521,57 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
             .map_err(|e| SeL4Error::TCBBindNotification(e))
     }
 
+    /// Read the process' thread's registers, e.g. to find out where a
+    /// faulted child was when it died.
+    pub fn read_registers(&self) -> Result<seL4_UserContext, SeL4Error> {
+        self.tcb.read_registers()
+    }
+
+    /// Overwrite the process' thread's registers. This does not start
+    /// or resume the thread.
+    pub fn write_registers(&mut self, registers: &seL4_UserContext) -> Result<(), SeL4Error> {
+        self.tcb.write_registers(registers)
+    }
+
     pub fn start(&mut self) -> Result<(), SeL4Error> {
+        unsafe { seL4_TCB_Resume(self.tcb.cptr) }
+            .as_result()
+            .map_err(|e| SeL4Error::TCBResume(e))?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// How many of the process' faults `handler` has dealt with, if it
+    /// was set watching the process with `watch_stack_guards`.
+    pub fn fault_count(&self, handler: &FaultHandler) -> Option<usize> {
+        handler.fault_count(self.tcb.cptr)
+    }
+
+    /// What's become of the process' thread. The kernel has no call to
+    /// read a thread's state back, so this is pieced together from
+    /// whether the process has been started and, given the `handler`
+    /// watching it, what was done about its last fault. For the
+    /// kernel's own view, see `ferros::debug::dump_scheduler`.
+    pub fn thread_state(&self, handler: Option<&FaultHandler>) -> ThreadState {
+        if !self.started {
+            ThreadState::NotStarted
+        } else if handler.and_then(|h| h.has_killed(self.tcb.cptr)) == Some(true) {
+            ThreadState::Killed
+        } else {
+            ThreadState::Started
+        }
+    }
+
+    /// Have the process' thread give up a call it's waiting on the
+    /// reply to, once the channel the call went over has been closed
+    /// with `Connection::close`. The thread makes the call again, finds
+    /// its endpoint gone, and gets `IPCError::ResponderDead`.
+    ///
+    /// Whatever else the thread may be blocked on is waited for afresh,
+    /// but a call over a channel that's still open would be sent a
+    /// second time, so only do this after closing it.
+    pub fn abandon_call(&mut self) -> Result<(), SeL4Error> {
+        // Resuming a blocked thread restarts the system call it's in.
         unsafe { seL4_TCB_Resume(self.tcb.cptr) }
             .as_result()
             .map_err(|e| SeL4Error::TCBResume(e))
@@ -223,3 +585,528 @@ impl<StackBitSize: Unsigned> StandardProcess<StackBitSize> {
         self.tcb.cptr
     }
 }
+
+/// A child's stack, mapped into its address space between guard
+/// pages and still mapped in the parent, so that its initial contents
+/// can be written from there.
+pub(super) struct ChildStack<StackBitSize: Unsigned> {
+    pub(super) local_pages: MappedMemoryRegion<StackBitSize, shared_status::Shared>,
+    pub(super) guards: StackGuards,
+    child_top: usize,
+}
+
+impl<StackBitSize: Unsigned> ChildStack<StackBitSize>
+where
+    StackBitSize: IsGreaterOrEqual<PageBits>,
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Share `parent_mapped_region` into `vspace`, using `stack_slots`
+    /// for the copies of its pages, with a guard page either side.
+    pub(super) fn map<StackSlots: Unsigned>(
+        vspace: &mut VSpace,
+        parent_mapped_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        parent_cnode: &LocalCap<LocalCNode>,
+        stack_slots: LocalCNodeSlots<StackSlots>,
+    ) -> Result<Self, ProcessSetupError>
+    where
+        StackSlots: IsEqual<NumPages<StackBitSize>, Output = True>,
+    {
+        if parent_mapped_region.asid() == vspace.asid() {
+            return Err(
+                ProcessSetupError::ParentMappedMemoryRegionASIDShouldNotMatchChildVSpaceASID,
+            );
+        }
+
+        // Reserve a guard page before the stack
+        let guard_below = vspace.skip_pages(1)?;
+
+        // Map the stack to the target address space
+        let (unmapped_stack_pages, local_pages): (UnmappedMemoryRegion<StackBitSize, _>, _) =
+            parent_mapped_region.share(stack_slots, parent_cnode, rights::RW)?;
+        let mapped_stack_pages = vspace.map_shared_region_and_consume(
+            unmapped_stack_pages,
+            rights::RW,
+            arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+        )?;
+
+        // Reserve a guard page after the stack
+        let guard_above = vspace.skip_pages(1)?;
+
+        Ok(ChildStack {
+            local_pages,
+            guards: StackGuards {
+                below: guard_below,
+                above: guard_above,
+            },
+            child_top: mapped_stack_pages.vaddr() + mapped_stack_pages.size_bytes(),
+        })
+    }
+
+    /// The child's address for the top of the stack, below the
+    /// `reserved` bytes kept at its very top.
+    pub(super) fn child_top(&self, reserved: usize) -> usize {
+        self.child_top - reserved
+    }
+
+    /// Copy `param` onto the stack, just below `reserved` bytes, and
+    /// return the registers which hand it to the entry point, with the
+    /// stack pointer set below it.
+    pub(super) fn push_param<P>(
+        &self,
+        param: &P,
+        reserved: usize,
+    ) -> Result<seL4_UserContext, ProcessSetupError> {
+        if core::mem::size_of::<P>() > 2usize.pow(StackBitSize::U32) - reserved {
+            return Err(ProcessSetupError::ProcessParameterTooBigForStack);
+        }
+        let stack_top = self.local_pages.vaddr() + self.local_pages.size_bytes() - reserved;
+        let (mut registers, param_size_on_stack) = unsafe {
+            setup_initial_stack_and_regs(
+                param as *const P as *const usize,
+                core::mem::size_of::<P>(),
+                stack_top as *mut usize,
+                self.child_top(reserved),
+            )
+        };
+
+        self.local_pages.flush()?;
+
+        registers.sp = self.child_top(reserved) - param_size_on_stack;
+        Ok(registers)
+    }
+}
+
+/// Map `ipc_buffer` into `vspace`, and set `tcb` up to run there, in
+/// `cspace`, from `registers`, at `priority`. The thread is left
+/// suspended.
+pub(super) fn configure_thread(
+    tcb: &mut LocalCap<ThreadControlBlock>,
+    vspace: &mut VSpace,
+    cspace: LocalCap<ChildCNode>,
+    fault_source: Option<crate::userland::FaultSource<role::Child>>,
+    ipc_buffer: LocalCap<Page<page_state::Unmapped>>,
+    registers: &mut seL4_UserContext,
+    priority_authority: &LocalCap<ThreadPriorityAuthority>,
+    priority: usize,
+) -> Result<(), ProcessSetupError> {
+    let ipc_buffer = vspace.map_region(
+        ipc_buffer.to_region(),
+        CapRights::RW,
+        arch::vm_attributes::DEFAULT | arch::vm_attributes::EXECUTE_NEVER,
+    )?;
+
+    tcb.configure(
+        cspace,
+        fault_source,
+        &vspace.root(),
+        Some(ipc_buffer.to_page()),
+    )?;
+    unsafe {
+        seL4_TCB_WriteRegisters(
+            tcb.cptr,
+            0,
+            0,
+            // all the regs
+            core::mem::size_of::<seL4_UserContext>() / core::mem::size_of::<usize>(),
+            registers,
+        )
+    }
+    .as_result()
+    .map_err(|e| ProcessSetupError::SeL4Error(SeL4Error::TCBWriteRegisters(e)))?;
+
+    tcb.set_priority(priority_authority, priority)?;
+    Ok(())
+}
+
+/// Gathers up the resources for a `StandardProcess` one named setter
+/// at a time, rather than as a long list of similar-looking positional
+/// arguments to `StandardProcess::new`.
+///
+/// The stack region, IPC buffer and TCB untypeds, slots and priority
+/// are required. Each starts out `Unset` in the builder's type and can
+/// be given once; `build` is only there once all of them have been.
+pub struct StandardProcessBuilder<
+    'a,
+    'b,
+    T: RetypeForSetup,
+    StackBitSize: Unsigned,
+    Stack = Unset,
+    IPCBufferUt = Unset,
+    TCBUt = Unset,
+    Slots = Unset,
+    Priority = Unset,
+> {
+    entry_point: EntryPoint<'a, T>,
+    process_parameter: SetupVer<T>,
+    stack_region: Stack,
+    ipc_buffer_ut: IPCBufferUt,
+    tcb_ut: TCBUt,
+    slots: Slots,
+    priority: Priority,
+    fault_source: Option<crate::userland::FaultSource<role::Child>>,
+    panic_sender: Option<Sender<PanicReport, role::Child>>,
+    notification: Option<&'b LocalCap<Notification>>,
+    affinity: Option<usize>,
+    #[cfg(KernelIsMCS)]
+    sched_budget: Option<SchedBudget<'b>>,
+    #[cfg(KernelMultipleDomains)]
+    domain: Option<(&'b LocalCap<DomainSet>, u8)>,
+    _stack_bit_size: PhantomData<StackBitSize>,
+}
+
+/// A required `StandardProcessBuilder` argument that hasn't been given.
+pub struct Unset;
+
+/// What's needed to give a process a scheduling context on an MCS
+/// kernel: `budget_us` microseconds of CPU time every `period_us`.
+#[cfg(KernelIsMCS)]
+pub struct SchedBudget<'c> {
+    pub sched_control: &'c LocalCap<SchedControl>,
+    pub untyped: LocalCap<Untyped<SchedContextBits>>,
+    pub slot: LocalCNodeSlot,
+    pub budget_us: u64,
+    pub period_us: u64,
+}
+
+type TCBUntyped = LocalCap<Untyped<<ThreadControlBlock as DirectRetype>::SizeBits>>;
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize>
+{
+    pub fn new<EP: Into<EntryPoint<'a, T>>>(
+        entry_point: EP,
+        process_parameter: SetupVer<T>,
+    ) -> Self {
+        StandardProcessBuilder {
+            entry_point: entry_point.into(),
+            process_parameter,
+            stack_region: Unset,
+            ipc_buffer_ut: Unset,
+            tcb_ut: Unset,
+            slots: Unset,
+            priority: Unset,
+            fault_source: None,
+            panic_sender: None,
+            notification: None,
+            affinity: None,
+            #[cfg(KernelIsMCS)]
+            sched_budget: None,
+            #[cfg(KernelMultipleDomains)]
+            domain: None,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, I, C, S, P>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, Unset, I, C, S, P>
+{
+    /// The region, mapped into the parent, which will become the
+    /// process' stack.
+    pub fn stack_region(
+        self,
+        stack_region: MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+    ) -> StandardProcessBuilder<
+        'a,
+        'b,
+        T,
+        StackBitSize,
+        MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        I,
+        C,
+        S,
+        P,
+    > {
+        StandardProcessBuilder {
+            entry_point: self.entry_point,
+            process_parameter: self.process_parameter,
+            stack_region,
+            ipc_buffer_ut: self.ipc_buffer_ut,
+            tcb_ut: self.tcb_ut,
+            slots: self.slots,
+            priority: self.priority,
+            fault_source: self.fault_source,
+            panic_sender: self.panic_sender,
+            notification: self.notification,
+            affinity: self.affinity,
+            #[cfg(KernelIsMCS)]
+            sched_budget: self.sched_budget,
+            #[cfg(KernelMultipleDomains)]
+            domain: self.domain,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, R, C, S, P>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, R, Unset, C, S, P>
+{
+    pub fn ipc_buffer_ut(
+        self,
+        ipc_buffer_ut: LocalCap<Untyped<PageBits>>,
+    ) -> StandardProcessBuilder<'a, 'b, T, StackBitSize, R, LocalCap<Untyped<PageBits>>, C, S, P>
+    {
+        StandardProcessBuilder {
+            entry_point: self.entry_point,
+            process_parameter: self.process_parameter,
+            stack_region: self.stack_region,
+            ipc_buffer_ut,
+            tcb_ut: self.tcb_ut,
+            slots: self.slots,
+            priority: self.priority,
+            fault_source: self.fault_source,
+            panic_sender: self.panic_sender,
+            notification: self.notification,
+            affinity: self.affinity,
+            #[cfg(KernelIsMCS)]
+            sched_budget: self.sched_budget,
+            #[cfg(KernelMultipleDomains)]
+            domain: self.domain,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, R, I, S, P>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, R, I, Unset, S, P>
+{
+    pub fn tcb_ut(
+        self,
+        tcb_ut: TCBUntyped,
+    ) -> StandardProcessBuilder<'a, 'b, T, StackBitSize, R, I, TCBUntyped, S, P> {
+        StandardProcessBuilder {
+            entry_point: self.entry_point,
+            process_parameter: self.process_parameter,
+            stack_region: self.stack_region,
+            ipc_buffer_ut: self.ipc_buffer_ut,
+            tcb_ut,
+            slots: self.slots,
+            priority: self.priority,
+            fault_source: self.fault_source,
+            panic_sender: self.panic_sender,
+            notification: self.notification,
+            affinity: self.affinity,
+            #[cfg(KernelIsMCS)]
+            sched_budget: self.sched_budget,
+            #[cfg(KernelMultipleDomains)]
+            domain: self.domain,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, R, I, C, P>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, R, I, C, Unset, P>
+where
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    NumPages<StackBitSize>: Add<U2>,
+    Sum<NumPages<StackBitSize>, U2>: Unsigned,
+{
+    pub fn slots(
+        self,
+        slots: LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+    ) -> StandardProcessBuilder<
+        'a,
+        'b,
+        T,
+        StackBitSize,
+        R,
+        I,
+        C,
+        LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        P,
+    > {
+        StandardProcessBuilder {
+            entry_point: self.entry_point,
+            process_parameter: self.process_parameter,
+            stack_region: self.stack_region,
+            ipc_buffer_ut: self.ipc_buffer_ut,
+            tcb_ut: self.tcb_ut,
+            slots,
+            priority: self.priority,
+            fault_source: self.fault_source,
+            panic_sender: self.panic_sender,
+            notification: self.notification,
+            affinity: self.affinity,
+            #[cfg(KernelIsMCS)]
+            sched_budget: self.sched_budget,
+            #[cfg(KernelMultipleDomains)]
+            domain: self.domain,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, R, I, C, S>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, R, I, C, S, Unset>
+{
+    pub fn priority(
+        self,
+        priority_authority: &'b LocalCap<ThreadPriorityAuthority>,
+        priority: usize,
+    ) -> StandardProcessBuilder<
+        'a,
+        'b,
+        T,
+        StackBitSize,
+        R,
+        I,
+        C,
+        S,
+        (&'b LocalCap<ThreadPriorityAuthority>, usize),
+    > {
+        StandardProcessBuilder {
+            entry_point: self.entry_point,
+            process_parameter: self.process_parameter,
+            stack_region: self.stack_region,
+            ipc_buffer_ut: self.ipc_buffer_ut,
+            tcb_ut: self.tcb_ut,
+            slots: self.slots,
+            priority: (priority_authority, priority),
+            fault_source: self.fault_source,
+            panic_sender: self.panic_sender,
+            notification: self.notification,
+            affinity: self.affinity,
+            #[cfg(KernelIsMCS)]
+            sched_budget: self.sched_budget,
+            #[cfg(KernelMultipleDomains)]
+            domain: self.domain,
+            _stack_bit_size: PhantomData,
+        }
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned, R, I, C, S, P>
+    StandardProcessBuilder<'a, 'b, T, StackBitSize, R, I, C, S, P>
+{
+    pub fn fault_source(mut self, fault_source: crate::userland::FaultSource<role::Child>) -> Self {
+        self.fault_source = Some(fault_source);
+        self
+    }
+
+    /// Install `panic_sender` as the process' panic sender before its
+    /// entry point runs, as `install_panic_sender` would from inside
+    /// it. Only forked processes can have one.
+    pub fn panic_sender(mut self, panic_sender: Sender<PanicReport, role::Child>) -> Self {
+        self.panic_sender = Some(panic_sender);
+        self
+    }
+
+    /// Bind `notification` to the process' thread before it can run;
+    /// see `StandardProcess::new_with_notification`.
+    pub fn notification(mut self, notification: &'b LocalCap<Notification>) -> Self {
+        self.notification = Some(notification);
+        self
+    }
+
+    /// Pin the process to core `affinity`. Only multicore kernels
+    /// support this.
+    pub fn affinity(mut self, affinity: usize) -> Self {
+        self.affinity = Some(affinity);
+        self
+    }
+
+    /// Give the process a scheduling context with this budget, which
+    /// it needs to run at all on an MCS kernel.
+    #[cfg(KernelIsMCS)]
+    pub fn sched_budget(mut self, budget: SchedBudget<'b>) -> Self {
+        self.sched_budget = Some(budget);
+        self
+    }
+
+    /// Move the process into scheduling domain `domain` before it can
+    /// run; see `StandardProcess::set_domain`.
+    #[cfg(KernelMultipleDomains)]
+    pub fn domain(mut self, domain_set: &'b LocalCap<DomainSet>, domain: u8) -> Self {
+        self.domain = Some((domain_set, domain));
+        self
+    }
+}
+
+impl<'a, 'b, T: RetypeForSetup, StackBitSize: Unsigned>
+    StandardProcessBuilder<
+        'a,
+        'b,
+        T,
+        StackBitSize,
+        MappedMemoryRegion<StackBitSize, shared_status::Exclusive>,
+        LocalCap<Untyped<PageBits>>,
+        TCBUntyped,
+        LocalCNodeSlots<Sum<NumPages<StackBitSize>, U2>>,
+        (&'b LocalCap<ThreadPriorityAuthority>, usize),
+    >
+where
+    StackBitSize: Sub<PageBits>,
+    <StackBitSize as Sub<PageBits>>::Output: _Pow,
+    NumPages<StackBitSize>: Add<U2>,
+    Sum<NumPages<StackBitSize>, U2>: Unsigned,
+{
+    pub fn build(
+        self,
+        vspace: &mut VSpace,
+        cspace: LocalCap<ChildCNode>,
+        parent_cnode: &LocalCap<LocalCNode>,
+    ) -> Result<StandardProcess<StackBitSize>, ProcessSetupError>
+    where
+        Sum<NumPages<StackBitSize>, U2>: Sub<U2>,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: Unsigned,
+        Diff<Sum<NumPages<StackBitSize>, U2>, U2>: IsEqual<NumPages<StackBitSize>, Output = True>,
+
+        StackBitSize: IsGreaterOrEqual<PageBits>,
+        <StackBitSize as Sub<PageBits>>::Output: Unsigned,
+        Pow<<StackBitSize as Sub<PageBits>>::Output>: Unsigned,
+    {
+        #[cfg(not(KernelSMP))]
+        {
+            if self.affinity.is_some() {
+                return Err(ProcessSetupError::AffinityRequiresSMP);
+            }
+        }
+
+        let (priority_authority, priority) = self.priority;
+        #[allow(unused_mut)]
+        let (mut process, _) = StandardProcess::new_internal(
+            vspace,
+            cspace,
+            self.stack_region,
+            parent_cnode,
+            self.entry_point,
+            self.process_parameter,
+            self.ipc_buffer_ut,
+            self.tcb_ut,
+            self.slots,
+            priority_authority,
+            priority,
+            self.fault_source,
+            None,
+            self.panic_sender,
+            self.notification,
+        )?;
+        #[cfg(KernelSMP)]
+        {
+            if let Some(affinity) = self.affinity {
+                process.tcb.set_affinity(affinity)?;
+            }
+        }
+        #[cfg(KernelIsMCS)]
+        {
+            if let Some(budget) = self.sched_budget {
+                // The maximum controlled priority is left at the
+                // kernel's default of 0, as it is on non-MCS kernels,
+                // so the process can't raise any thread's priority.
+                process.set_sched_context(priority_authority, budget, 0, priority, None)?;
+            }
+        }
+        #[cfg(KernelMultipleDomains)]
+        {
+            if let Some((domain_set, domain)) = self.domain {
+                process.set_domain(domain_set, domain)?;
+            }
+        }
+        Ok(process)
+    }
+}
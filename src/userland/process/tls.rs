@@ -0,0 +1,79 @@
+use core::mem;
+
+use super::ProcessSetupError;
+
+/// The initial contents of a thread-local storage block, as described
+/// by an ELF's `PT_TLS` segment: `.tdata` followed by the zeroed
+/// `.tbss`.
+#[derive(Debug, Clone, Copy)]
+pub struct TlsImage<'a> {
+    /// `.tdata`, copied into the start of the block.
+    pub initialized: &'a [u8],
+    /// The size of the whole block, including `.tbss`.
+    pub size: usize,
+    pub align: usize,
+}
+
+/// ARM uses TLS variant I: the thread pointer points at a two-word
+/// thread control block, and the TLS block follows it.
+const TLS_TCB_SIZE: usize = 2 * mem::size_of::<usize>();
+
+impl<'a> TlsImage<'a> {
+    /// Find the TLS template of an ELF, if it has one.
+    pub fn from_elf(elf_data: &'a [u8]) -> Result<Option<TlsImage<'a>>, &'static str> {
+        let elf = xmas_elf::ElfFile::new(elf_data)?;
+        let header = match elf
+            .program_iter()
+            .find(|h| h.get_type() == Ok(xmas_elf::program::Type::Tls))
+        {
+            Some(h) => h,
+            None => return Ok(None),
+        };
+        let offset = header.offset() as usize;
+        let file_size = header.file_size() as usize;
+        let initialized = elf_data
+            .get(offset..offset + file_size)
+            .ok_or("TLS segment extends past the end of the ELF")?;
+        let image = TlsImage {
+            initialized,
+            size: header.mem_size() as usize,
+            align: core::cmp::max(header.align() as usize, 1),
+        };
+        image
+            .footprint()
+            .map_err(|_| "TLS segment is larger in the file than in memory, or misaligned")?;
+        Ok(Some(image))
+    }
+
+    /// Where the TLS block starts, relative to the thread pointer.
+    /// The alignment has to be a power of two, as ELF requires.
+    pub(crate) fn block_offset(&self) -> Result<usize, ProcessSetupError> {
+        if !self.align.is_power_of_two() {
+            return Err(ProcessSetupError::MalformedTlsImage);
+        }
+        Ok((TLS_TCB_SIZE + self.align - 1) & !(self.align - 1))
+    }
+
+    /// How much memory, starting at the thread pointer, the thread
+    /// control block and TLS block need together.
+    pub fn footprint(&self) -> Result<usize, ProcessSetupError> {
+        if self.initialized.len() > self.size {
+            return Err(ProcessSetupError::MalformedTlsImage);
+        }
+        Ok(self.block_offset()? + self.size)
+    }
+
+    /// Lay out the TLS area in `dest`, whose start will be the
+    /// thread pointer.
+    pub(crate) fn write_to(&self, dest: &mut [u8]) -> Result<(), ProcessSetupError> {
+        if self.footprint()? > dest.len() {
+            return Err(ProcessSetupError::TlsRegionTooSmall);
+        }
+        for b in dest.iter_mut() {
+            *b = 0;
+        }
+        let start = self.block_offset()?;
+        dest[start..start + self.initialized.len()].copy_from_slice(self.initialized);
+        Ok(())
+    }
+}
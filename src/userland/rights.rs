@@ -9,6 +9,11 @@ pub enum CapRights {
     WG,
     /// Can Grant ReplY
     Y,
+    /// Write, and grant reply capabilities, but not arbitrary caps.
+    /// Enough for a client to `seL4_Call` a server.
+    WY,
+    /// Any other combination, as made by `CapRights::builder`.
+    Custom(CapRightsBuilder),
 }
 
 impl From<CapRights> for seL4_CapRights_t {
@@ -20,34 +25,182 @@ impl From<CapRights> for seL4_CapRights_t {
             CapRights::RWG => unsafe { seL4_CapRights_new(0, 1, 1, 1) },
             CapRights::WG => unsafe { seL4_CapRights_new(0, 1, 0, 1) },
             CapRights::Y => unsafe { seL4_CapRights_new(1, 0, 0, 0) },
+            CapRights::WY => unsafe { seL4_CapRights_new(1, 0, 0, 1) },
+            CapRights::Custom(b) => unsafe {
+                seL4_CapRights_new(
+                    b.grant_reply as usize,
+                    b.grant as usize,
+                    b.read as usize,
+                    b.write as usize,
+                )
+            },
         }
     }
 }
 
 impl CapRights {
+    /// The right to grant reply capabilities, without the full grant
+    /// right. Another name for `Y`.
+    #[allow(non_upper_case_globals)]
+    pub const GrantReply: CapRights = CapRights::Y;
+
+    /// Start building a set of rights from nothing, for combinations
+    /// not covered by the shorthand variants.
+    pub fn builder() -> CapRightsBuilder {
+        CapRightsBuilder::default()
+    }
+
     pub fn is_writable(&self) -> bool {
         use CapRights::*;
 
         match self {
-            W | RW | RWG | WG => true,
+            W | RW | RWG | WG | WY => true,
+            Custom(b) => b.write,
             _ => false,
         }
     }
+
+    /// Whether every right in `other` is also in `self`.
+    pub fn permits(&self, other: CapRights) -> bool {
+        let (mine, theirs) = (self.flags(), other.flags());
+        (mine.read || !theirs.read)
+            && (mine.write || !theirs.write)
+            && (mine.grant || !theirs.grant)
+            && (mine.grant_reply || !theirs.grant_reply)
+    }
+
+    fn flags(&self) -> CapRightsBuilder {
+        match *self {
+            CapRights::R => CapRights::builder().read(),
+            CapRights::W => CapRights::builder().write(),
+            CapRights::RW => CapRights::builder().read().write(),
+            CapRights::RWG => CapRights::builder().read().write().grant(),
+            CapRights::WG => CapRights::builder().write().grant(),
+            CapRights::Y => CapRights::builder().grant_reply(),
+            CapRights::WY => CapRights::builder().write().grant_reply(),
+            CapRights::Custom(b) => b,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct CapRightsBuilder {
+    read: bool,
+    write: bool,
+    grant: bool,
+    grant_reply: bool,
+}
+
+impl CapRightsBuilder {
+    pub fn read(self) -> Self {
+        CapRightsBuilder { read: true, ..self }
+    }
+
+    pub fn write(self) -> Self {
+        CapRightsBuilder {
+            write: true,
+            ..self
+        }
+    }
+
+    pub fn grant(self) -> Self {
+        CapRightsBuilder {
+            grant: true,
+            ..self
+        }
+    }
+
+    pub fn grant_reply(self) -> Self {
+        CapRightsBuilder {
+            grant_reply: true,
+            ..self
+        }
+    }
+
+    /// Produce the rights, using a shorthand variant where one exists
+    /// so that the result compares equal to it.
+    pub fn build(self) -> CapRights {
+        match (self.read, self.write, self.grant, self.grant_reply) {
+            (true, false, false, false) => CapRights::R,
+            (false, true, false, false) => CapRights::W,
+            (true, true, false, false) => CapRights::RW,
+            (true, true, true, false) => CapRights::RWG,
+            (false, true, true, false) => CapRights::WG,
+            (false, false, false, true) => CapRights::Y,
+            (false, true, false, true) => CapRights::WY,
+            _ => CapRights::Custom(self),
+        }
+    }
+}
+
+impl From<CapRightsBuilder> for CapRights {
+    fn from(b: CapRightsBuilder) -> Self {
+        b.build()
+    }
+}
+
+impl From<CapRightsBuilder> for seL4_CapRights_t {
+    fn from(b: CapRightsBuilder) -> Self {
+        b.build().into()
+    }
 }
 
 pub trait Rights: private::SealedRights {
     fn as_caprights() -> CapRights;
 }
 
+/// Holds when `Self` grants nothing that `R` doesn't, so that asking
+/// for more rights than a region was shared with is a type error.
+pub trait SubsetOf<R: Rights>: Rights {}
+
+macro_rules! subset_of {
+    ($sub:ident: $($sup:ident),*) => {
+        $(impl SubsetOf<rights::$sup> for rights::$sub {})*
+    };
+}
+
+subset_of!(R: R, RW, RWG);
+subset_of!(W: W, RW, RWG, WG, WY);
+subset_of!(RW: RW, RWG);
+subset_of!(RWG: RWG);
+subset_of!(WG: WG, RWG);
+subset_of!(WY: WY);
+
+/// Rights that a region shared with rights `R` may be mapped or shared
+/// again with. Type-level rights are checked against `R` at compile
+/// time; a `CapRights` value, for callers that only know the rights at
+/// runtime, is checked when it's used.
+pub trait RightsWithin<R: Rights> {
+    /// These rights as a `CapRights`, or `None` if they exceed `R`.
+    fn within(self) -> Option<CapRights>;
+}
+
+impl<R: Rights, MapRights: SubsetOf<R>> RightsWithin<R> for MapRights {
+    fn within(self) -> Option<CapRights> {
+        Some(MapRights::as_caprights())
+    }
+}
+
+impl<R: Rights> RightsWithin<R> for CapRights {
+    fn within(self) -> Option<CapRights> {
+        if R::as_caprights().permits(self) {
+            Some(self)
+        } else {
+            None
+        }
+    }
+}
+
 #[allow(unused)]
 pub mod rights {
     use super::*;
 
-    pub struct R {}
-    pub struct W {}
-    pub struct RW {}
-    pub struct RWG {}
-    pub struct WG {}
+    pub struct R;
+    pub struct W;
+    pub struct RW;
+    pub struct RWG;
+    pub struct WG;
+    pub struct WY;
 
     impl Rights for R {
         fn as_caprights() -> CapRights {
@@ -78,6 +231,12 @@ pub mod rights {
             CapRights::WG
         }
     }
+
+    impl Rights for WY {
+        fn as_caprights() -> CapRights {
+            CapRights::WY
+        }
+    }
 }
 mod private {
     use super::*;
@@ -87,4 +246,5 @@ mod private {
     impl SealedRights for rights::RW {}
     impl SealedRights for rights::RWG {}
     impl SealedRights for rights::WG {}
+    impl SealedRights for rights::WY {}
 }
@@ -9,6 +9,7 @@ use crate::cap::{
     Notification, Untyped,
 };
 use crate::userland::multi_consumer::WakerSetup;
+use crate::userland::rights::rights;
 use crate::userland::{CapRights, IPCError};
 use crate::vspace::{UnmappedMemoryRegion, VSpace};
 
@@ -52,7 +53,7 @@ pub mod sync {
         let (slot, local_slots) = local_slots.alloc();
         let caller_shared_region = caller_vspace.map_shared_region(
             &shared_region,
-            CapRights::RW,
+            rights::RW,
             arch::vm_attributes::DEFAULT,
             slot,
             &local_cnode,
@@ -60,7 +61,7 @@ pub mod sync {
 
         let responder_shared_region = responder_vspace.map_shared_region_and_consume(
             shared_region,
-            CapRights::RW,
+            rights::RW,
             arch::vm_attributes::DEFAULT,
         )?;
 
@@ -225,15 +226,19 @@ pub mod sync {
             loop {
                 unsafe {
                     seL4_Wait(inner.request_ready.cptr, &mut sender_badge as *mut usize);
-                    if sender_badge == WAKER_BADGE {
-                        // nonzero badges are from a notification
-                        state = g(sender_badge, state);
-                    } else {
-                        let out = f(inner.unchecked_copy_from_buffer(), state);
-                        response = out.0;
-                        state = out.1;
-                        inner.unchecked_copy_into_buffer(&response);
-                        seL4_Signal(inner.response_ready.cptr);
+                    // A request and a wakeup which both arrive before
+                    // the wait come back together, as one badge with
+                    // both bits set.
+                    for bit in Badge::from(sender_badge).bits() {
+                        if usize::from(bit) == WAKER_BADGE {
+                            state = g(WAKER_BADGE, state);
+                        } else {
+                            let out = f(inner.unchecked_copy_from_buffer(), state);
+                            response = out.0;
+                            state = out.1;
+                            inner.unchecked_copy_into_buffer(&response);
+                            seL4_Signal(inner.response_ready.cptr);
+                        }
                     }
                 }
             }
@@ -0,0 +1,401 @@
+//! A single-producer, single-consumer queue living in a region of
+//! memory shared between two processes. Made with `spsc_queue`, each
+//! side simply polls. Made with `spsc_queue_with_wakeups`, a pair of
+//! notifications lets each side block until the other has made
+//! progress.
+//!
+//! The queue does no cache maintenance of its own, so it refuses to be
+//! built anywhere the two sides might not see each other's writes:
+//! its region must be retyped from general (not device) memory, and
+//! both sides map it with the default cacheable attributes, with no
+//! way to ask for others.
+use core::marker::PhantomData;
+use core::mem::{align_of, size_of};
+use core::ops::Sub;
+use core::ptr;
+use core::sync::atomic::{fence, AtomicUsize, Ordering};
+
+use selfe_sys::{seL4_Signal, seL4_Wait, seL4_Yield};
+
+use typenum::*;
+
+use crate::arch::{self, PageBits};
+use crate::cap::{
+    memory_kind, role, Badge, CNodeRole, CNodeSlots, Cap, DirectRetype, LocalCNode,
+    LocalCNodeSlots, LocalCap, Notification, Untyped,
+};
+use crate::pow::{Pow, _Pow};
+use crate::userland::rights::rights;
+use crate::userland::{AddressSpaceIndependent, CapRights, MultiConsumerError, QueueFullError};
+use crate::vspace::{KernelRetypeFanOutLimit, NumPages, UnmappedMemoryRegion, VSpace};
+
+/// The indices at the start of the shared region. Both only ever
+/// increase (wrapping), and an element's slot is its index modulo the
+/// capacity. The capacity is a power of two, so that stays true
+/// across the wrap from `usize::MAX` to zero. `head` is written only by the consumer and `tail` only
+/// by the producer.
+#[repr(C)]
+struct Header {
+    head: AtomicUsize,
+    tail: AtomicUsize,
+}
+
+fn slots_offset<T>() -> usize {
+    let align = align_of::<T>();
+    (size_of::<Header>() + align - 1) & !(align - 1)
+}
+
+#[derive(Debug)]
+struct QueueView<T, Role: CNodeRole> {
+    vaddr: usize,
+    capacity: usize,
+    _t: PhantomData<T>,
+    _role: PhantomData<Role>,
+}
+
+impl<T, Role: CNodeRole> QueueView<T, Role> {
+    fn header(&self) -> &Header {
+        unsafe { &*(self.vaddr as *const Header) }
+    }
+
+    fn slot(&self, index: usize) -> *mut T {
+        (self.vaddr + slots_offset::<T>() + (index & (self.capacity - 1)) * size_of::<T>())
+            as *mut T
+    }
+}
+
+/// One side's notifications: the one it signals when the other side
+/// may be waiting on it, and the one it waits on itself.
+#[derive(Debug)]
+struct Wakeups<Role: CNodeRole> {
+    signal: Cap<Notification, Role>,
+    wait: Cap<Notification, Role>,
+}
+
+impl Wakeups<role::Local> {
+    fn signal(&self) {
+        unsafe { seL4_Signal(self.signal.cptr) }
+    }
+}
+
+/// Block until the other side has (probably) made progress. Without
+/// wakeups, all we can do is get out of its way.
+fn wait_for_progress(wakeups: &Option<Wakeups<role::Local>>) {
+    match wakeups {
+        Some(w) => {
+            let mut badge: usize = 0;
+            unsafe { seL4_Wait(w.wait.cptr, &mut badge as *mut usize) };
+        }
+        None => unsafe { seL4_Yield() },
+    }
+}
+
+/// The sending half of an SPSC queue.
+#[derive(Debug)]
+pub struct SpscProducer<T: Sized + Send + Sync, Role: CNodeRole> {
+    queue: QueueView<T, Role>,
+    wakeups: Option<Wakeups<Role>>,
+}
+
+// Each end's view of the queue is at the address it was mapped at in
+// that end's address space.
+unsafe impl<T: Sized + Send + Sync, Role: CNodeRole> AddressSpaceIndependent
+    for SpscProducer<T, Role>
+{
+}
+
+/// The receiving half of an SPSC queue.
+#[derive(Debug)]
+pub struct SpscConsumer<T: Sized + Send + Sync, Role: CNodeRole> {
+    queue: QueueView<T, Role>,
+    wakeups: Option<Wakeups<Role>>,
+}
+
+unsafe impl<T: Sized + Send + Sync, Role: CNodeRole> AddressSpaceIndependent
+    for SpscConsumer<T, Role>
+{
+}
+
+/// Make an SPSC queue holding up to `QLen` elements in a fresh
+/// region retyped from `shared_region_ut`, and map it into both the
+/// producer's and the consumer's address spaces. `QLen` must be a
+/// power of two.
+pub fn spsc_queue<T, QLen, QSizeBits, ProducerRole, ConsumerRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    shared_region_ut: LocalCap<Untyped<QSizeBits, memory_kind::General>>,
+    region_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    producer_vspace: &mut VSpace,
+    producer_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    consumer_vspace: &mut VSpace,
+) -> Result<(SpscProducer<T, ProducerRole>, SpscConsumer<T, ConsumerRole>), MultiConsumerError>
+where
+    T: Sized + Send + Sync,
+    QLen: Unsigned + PowerOfTwo,
+    ProducerRole: CNodeRole,
+    ConsumerRole: CNodeRole,
+
+    QSizeBits: Unsigned,
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    let (producer_queue, consumer_queue) = map_queue::<_, QLen, _, _, _>(
+        local_cnode,
+        shared_region_ut,
+        region_slots,
+        producer_vspace,
+        producer_slots,
+        consumer_vspace,
+    )?;
+    Ok((
+        SpscProducer {
+            queue: producer_queue,
+            wakeups: None,
+        },
+        SpscConsumer {
+            queue: consumer_queue,
+            wakeups: None,
+        },
+    ))
+}
+
+/// Like `spsc_queue`, but with a pair of notifications so that
+/// `SpscProducer::push_blocking` and `SpscConsumer::pop_blocking` can
+/// wait rather than spin. The producer signals when it may have pushed
+/// into an empty queue, and the consumer when it may have popped from
+/// a full one.
+pub fn spsc_queue_with_wakeups<T, QLen, QSizeBits, ProducerRole, ConsumerRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    shared_region_ut: LocalCap<Untyped<QSizeBits, memory_kind::General>>,
+    region_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    producer_vspace: &mut VSpace,
+    producer_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    consumer_vspace: &mut VSpace,
+    not_empty_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    not_full_ut: LocalCap<Untyped<<Notification as DirectRetype>::SizeBits>>,
+    local_slots: LocalCNodeSlots<U2>,
+    producer_cap_slots: CNodeSlots<U2, ProducerRole>,
+    consumer_cap_slots: CNodeSlots<U2, ConsumerRole>,
+) -> Result<(SpscProducer<T, ProducerRole>, SpscConsumer<T, ConsumerRole>), MultiConsumerError>
+where
+    T: Sized + Send + Sync,
+    QLen: Unsigned + PowerOfTwo,
+    ProducerRole: CNodeRole,
+    ConsumerRole: CNodeRole,
+
+    QSizeBits: Unsigned,
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    let (producer_queue, consumer_queue) = map_queue::<_, QLen, _, _, _>(
+        local_cnode,
+        shared_region_ut,
+        region_slots,
+        producer_vspace,
+        producer_slots,
+        consumer_vspace,
+    )?;
+
+    let (slot, local_slots) = local_slots.alloc();
+    let not_empty: LocalCap<Notification> = not_empty_ut.retype(slot)?;
+    let (slot, _local_slots) = local_slots.alloc();
+    let not_full: LocalCap<Notification> = not_full_ut.retype(slot)?;
+
+    let (signal_slot, wait_slot) = producer_cap_slots.alloc();
+    let producer_wakeups = Wakeups {
+        signal: not_empty.mint(local_cnode, signal_slot, CapRights::W, Badge::from(1))?,
+        wait: not_full.mint(local_cnode, wait_slot, CapRights::R, Badge::from(0))?,
+    };
+    let (signal_slot, wait_slot) = consumer_cap_slots.alloc();
+    let consumer_wakeups = Wakeups {
+        signal: not_full.mint(local_cnode, signal_slot, CapRights::W, Badge::from(1))?,
+        wait: not_empty.mint(local_cnode, wait_slot, CapRights::R, Badge::from(0))?,
+    };
+
+    Ok((
+        SpscProducer {
+            queue: producer_queue,
+            wakeups: Some(producer_wakeups),
+        },
+        SpscConsumer {
+            queue: consumer_queue,
+            wakeups: Some(consumer_wakeups),
+        },
+    ))
+}
+
+/// The region comes straight from the kernel, and so is already
+/// zeroed, which is exactly the initial state of an empty queue.
+///
+/// Both mappings use `vm_attributes::DEFAULT` and nothing else: an
+/// uncached mapping on one side and a cached one on the other would
+/// each be able to miss the other's writes.
+fn map_queue<T, QLen, QSizeBits, ProducerRole, ConsumerRole>(
+    local_cnode: &LocalCap<LocalCNode>,
+    shared_region_ut: LocalCap<Untyped<QSizeBits, memory_kind::General>>,
+    region_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    producer_vspace: &mut VSpace,
+    producer_slots: LocalCNodeSlots<NumPages<QSizeBits>>,
+    consumer_vspace: &mut VSpace,
+) -> Result<(QueueView<T, ProducerRole>, QueueView<T, ConsumerRole>), MultiConsumerError>
+where
+    T: Sized + Send + Sync,
+    QLen: Unsigned + PowerOfTwo,
+    ProducerRole: CNodeRole,
+    ConsumerRole: CNodeRole,
+
+    QSizeBits: Unsigned,
+    QSizeBits: IsGreaterOrEqual<PageBits>,
+    QSizeBits: Sub<PageBits>,
+    <QSizeBits as Sub<PageBits>>::Output: Unsigned,
+    <QSizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>: Unsigned,
+    Pow<<QSizeBits as Sub<PageBits>>::Output>:
+        IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+{
+    if slots_offset::<T>() + QLen::USIZE * size_of::<T>() > 1 << QSizeBits::USIZE {
+        return Err(MultiConsumerError::QueueTooBig);
+    }
+    if producer_vspace.asid() == consumer_vspace.asid() {
+        return Err(MultiConsumerError::ProduceToOwnQueueForbidden);
+    }
+
+    let shared_region = UnmappedMemoryRegion::new(shared_region_ut, region_slots)?.to_shared();
+    let producer_region = producer_vspace.map_shared_region(
+        &shared_region,
+        rights::RW,
+        arch::vm_attributes::DEFAULT,
+        producer_slots,
+        local_cnode,
+    )?;
+    let consumer_region = consumer_vspace.map_shared_region_and_consume(
+        shared_region,
+        rights::RW,
+        arch::vm_attributes::DEFAULT,
+    )?;
+
+    Ok((
+        QueueView {
+            vaddr: producer_region.vaddr(),
+            capacity: QLen::USIZE,
+            _t: PhantomData,
+            _role: PhantomData,
+        },
+        QueueView {
+            vaddr: consumer_region.vaddr(),
+            capacity: QLen::USIZE,
+            _t: PhantomData,
+            _role: PhantomData,
+        },
+    ))
+}
+
+impl<T: Sized + Send + Sync> SpscProducer<T, role::Local> {
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity
+    }
+
+    pub fn is_full(&self) -> bool {
+        let header = self.queue.header();
+        let tail = header.tail.load(Ordering::Relaxed);
+        tail.wrapping_sub(header.head.load(Ordering::Acquire)) == self.queue.capacity
+    }
+
+    pub fn push(&mut self, t: T) -> Result<(), QueueFullError<T>> {
+        let header = self.queue.header();
+        let tail = header.tail.load(Ordering::Relaxed);
+        // Acquire, so the consumer's read out of the slot we're about
+        // to reuse has finished.
+        if tail.wrapping_sub(header.head.load(Ordering::Acquire)) == self.queue.capacity {
+            return Err(QueueFullError(t));
+        }
+        unsafe { ptr::write(self.queue.slot(tail), t) };
+        // Release, so the element is visible before the new tail is.
+        header.tail.store(tail.wrapping_add(1), Ordering::Release);
+
+        if let Some(wakeups) = &self.wakeups {
+            // The consumer stores its head and then checks our tail
+            // before waiting; we store our tail and then check its
+            // head. The fences make sure at least one of us sees the
+            // other's store, so either it finds this element or we
+            // see it has caught up with us and wake it.
+            fence(Ordering::SeqCst);
+            if header.head.load(Ordering::Relaxed) == tail {
+                wakeups.signal();
+            }
+        }
+        Ok(())
+    }
+
+    /// Push, waiting for room if the queue is full.
+    pub fn push_blocking(&mut self, t: T) {
+        let mut t = t;
+        loop {
+            match self.push(t) {
+                Ok(()) => return,
+                Err(QueueFullError(rejected)) => t = rejected,
+            }
+            fence(Ordering::SeqCst);
+            if self.is_full() {
+                wait_for_progress(&self.wakeups);
+            }
+        }
+    }
+}
+
+impl<T: Sized + Send + Sync> SpscConsumer<T, role::Local> {
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let header = self.queue.header();
+        header.head.load(Ordering::Relaxed) == header.tail.load(Ordering::Acquire)
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        let header = self.queue.header();
+        let head = header.head.load(Ordering::Relaxed);
+        if head == header.tail.load(Ordering::Acquire) {
+            return None;
+        }
+        let t = unsafe { ptr::read(self.queue.slot(head)) };
+        header.head.store(head.wrapping_add(1), Ordering::Release);
+
+        if let Some(wakeups) = &self.wakeups {
+            // The mirror image of the check in `SpscProducer::push`:
+            // if the queue was full as of the producer's latest push,
+            // it may be waiting for the room we just made.
+            fence(Ordering::SeqCst);
+            if header.tail.load(Ordering::Relaxed).wrapping_sub(head) == self.queue.capacity {
+                wakeups.signal();
+            }
+        }
+        Some(t)
+    }
+
+    /// Pop, waiting for an element if the queue is empty.
+    pub fn pop_blocking(&mut self) -> T {
+        loop {
+            if let Some(t) = self.pop() {
+                return t;
+            }
+            fence(Ordering::SeqCst);
+            // Check again after the fence; a wakeup may have been
+            // left pending by a push we've already seen, in which
+            // case we go around again.
+            if self.is_empty() {
+                wait_for_progress(&self.wakeups);
+            }
+        }
+    }
+}
@@ -5,23 +5,33 @@
 //! memory _regions_ rather than expose the granules that each layer
 //! in the addressing structures is responsible for mapping.
 use core::marker::PhantomData;
-use core::ops::Sub;
+use core::ops::{Add, Sub};
 
 use typenum::*;
 
-use crate::alloc::ut_buddy::{self, UTBuddyError, WUTBuddy};
-use crate::arch::{self, AddressSpace, PageBits, PageBytes, PagingRoot, PagingRootLowerLevel};
-use crate::bootstrap::UserImage;
+use crate::alloc::ut_buddy::{self, FreeError, UTBuddyError, WUTBuddy};
+#[cfg(target_arch = "aarch64")]
+use crate::arch::cap::HugePage;
+use crate::arch::cap::LargePage;
+use crate::arch::{
+    self, AddressSpace, LargePageAddressSpace, LargePageBits, PageBits, PageBytes, PagingRoot,
+    PagingRootLowerLevel,
+};
+use crate::bootstrap::{UserImage, UserImageSection};
 use crate::cap::{
-    memory_kind, page_state, role, AssignedASID, CNodeRole, CNodeSlots, Cap, CapRange, CapType,
-    ChildCNodeSlot, DirectRetype, InternalASID, LocalCNode, LocalCNodeSlots, LocalCap, Page,
+    memory_kind, page_state, role, AssignedASID, CNodeRole, CNodeSlots, Cap, CapRange,
+    CapRangeDataReconstruction, CapType, ChildCNodeSlot, DirectRetype, Frame, FreedASID,
+    InternalASID, LocalCNode, LocalCNodeSlot, LocalCNodeSlots, LocalCap, Page, PageTable,
     PhantomCap, RetypeError, UnassignedASID, Untyped, WCNodeSlots, WCNodeSlotsData, WUntyped,
     WeakCapRange, WeakCopyError,
 };
-use crate::error::SeL4Error;
-use crate::pow::{Pow, _Pow};
+use crate::error::{KernelError, SeL4Error};
+use crate::pow::{_Pow, Pow};
+use crate::userland::rights::{Rights, RightsWithin};
 use crate::userland::CapRights;
+mod on_demand;
 mod region;
+pub use on_demand::*;
 pub use region::*;
 
 include!(concat!(env!("OUT_DIR"), "/KERNEL_RETYPE_FAN_OUT_LIMIT"));
@@ -43,6 +53,14 @@ pub trait ElfProc: Sized {
 
     /// How much memory is needed for the process stack, as a bitsize.
     type StackSizeBits: Unsigned;
+
+    /// If the image is embedded compressed, its size once decompressed
+    /// with `ferros::lz4::decompress`.
+    ///
+    /// A decompressed image doesn't live in the user image, so all of
+    /// its segments are copied into fresh memory rather than just the
+    /// writable ones; `RequiredMemoryBits` accounts for this.
+    const DECOMPRESSED_SIZE: Option<usize> = None;
 }
 
 pub trait VSpaceState: private::SealedVSpaceState {}
@@ -102,6 +120,13 @@ pub enum MappingError {
     /// is broken out to differentiate between a failure at the leaf
     /// rather than during branch construction.
     PageMapFailure(SeL4Error),
+    /// The kernel turned the page away as it does a cap which is
+    /// already mapped, whether elsewhere in this address space
+    /// (`InvalidArgument`) or in another (`InvalidCapability`). A frame
+    /// cap can only be mapped once; copy it and map the copy. The same
+    /// errors can also mean a bad address or paging root, so the
+    /// kernel's is kept.
+    PageMaybeAlreadyMapped(SeL4Error),
     /// A failure to map one of the intermediate layers.
     IntermediateLayerFailure(SeL4Error),
     /// The error was specific the allocation of an untyped preceeding
@@ -152,8 +177,45 @@ pub enum VSpaceError {
     /// special-sized granules.
     TriedToMapTooManyPagesAtOnce,
     InvalidRegionSize,
+    /// The frames backing a region are not one physically contiguous
+    /// run of memory.
+    NotPhysicallyContiguous,
+    /// The region is mapped without write rights.
+    RegionNotWritable,
+    /// A page cap was probably already mapped elsewhere; see
+    /// `MappingError::PageMaybeAlreadyMapped`. Each mapping needs a cap
+    /// of its own, so copy the cap and map the copy instead.
+    PageMaybeAlreadyMapped(SeL4Error),
+    /// A shared region was to be mapped or shared again with rights
+    /// beyond those it was shared with.
+    RightsExceedSharedRegion,
+    /// A read or write would have run past the end of the region.
+    RegionAccessOutOfBounds,
     ElfParseError(&'static str),
     InsufficientResourcesForElf,
+    /// The VSpace ran out of untyped memory from which to create an
+    /// intermediate paging structure. Hand it more with
+    /// `replenish_paging_untyped` and try again.
+    OutOfPagingMemory,
+}
+
+impl From<MappingError> for VSpaceError {
+    fn from(e: MappingError) -> VSpaceError {
+        match e {
+            MappingError::PageMapFailure(se) | MappingError::IntermediateLayerFailure(se) => {
+                VSpaceError::SeL4Error(se)
+            }
+            MappingError::UTBuddyError(UTBuddyError::CannotAllocateRequestedSize(_))
+            | MappingError::RetypeError(RetypeError::NotBigEnough) => {
+                VSpaceError::OutOfPagingMemory
+            }
+            MappingError::UTBuddyError(UTBuddyError::NotEnoughSlots) => {
+                VSpaceError::InsufficientCNodeSlots
+            }
+            MappingError::PageMaybeAlreadyMapped(e) => VSpaceError::PageMaybeAlreadyMapped(e),
+            e => VSpaceError::MappingError(e),
+        }
+    }
 }
 
 impl From<RetypeError> for VSpaceError {
@@ -322,6 +384,24 @@ impl VSpace<vspace_state::Empty, role::Local> {
 }
 
 impl<State: VSpaceState, CapRole: CNodeRole> VSpace<State, CapRole> {
+    /// The most untyped memory, in bytes, that mapping a region of
+    /// `2^SizeBits` bytes at `vaddr` could draw from a VSpace's paging
+    /// reservoir, assuming none of the intermediate paging structures
+    /// it falls under exist yet.
+    ///
+    /// Provisioning at least this much up front means the mapping
+    /// can't fail with `VSpaceError::OutOfPagingMemory`.
+    pub fn paging_overhead_for<SizeBits: Unsigned>(vaddr: usize) -> usize {
+        let last = vaddr.saturating_add((1 << SizeBits::USIZE) - 1);
+        arch::INTERMEDIATE_PAGING_LAYERS
+            .iter()
+            .map(|&(span_bits, object_bits)| {
+                let objects = (last >> span_bits) - (vaddr >> span_bits) + 1;
+                objects << object_bits
+            })
+            .sum()
+    }
+
     /// This address space's id.
     pub(crate) fn asid(&self) -> InternalASID {
         self.asid
@@ -333,6 +413,107 @@ impl<State: VSpaceState, CapRole: CNodeRole> VSpace<State, CapRole> {
 }
 
 impl<State: VSpaceState> VSpace<State, role::Local> {
+    /// Tear down this address space by deleting its paging root,
+    /// which frees its ASID for `ASIDPool::reclaim`. The slot the root
+    /// was in is returned too.
+    ///
+    /// This must be the last cap to the paging root for the kernel to
+    /// let go of the ASID, so any thread running in the address space
+    /// should be deleted first. The paging structures and pages that
+    /// were mapped are not recovered; their memory goes back only when
+    /// the untypeds it came from are revoked.
+    pub fn destroy(
+        self,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(FreedASID, LocalCNodeSlot), SeL4Error> {
+        let asid = self.asid;
+        let slot = self.root.delete_reclaiming_slot(cnode)?;
+        Ok((FreedASID { asid }, slot))
+    }
+
+    /// Give this address space more untyped memory to build its
+    /// intermediate paging structures from, for when mapping has
+    /// failed with `VSpaceError::OutOfPagingMemory`. If the untyped
+    /// can't be taken, it's handed back in the error.
+    pub fn replenish_paging_untyped(
+        &mut self,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
+    ) -> Result<(), FreeError> {
+        self.untyped.free_weak(ut)
+    }
+
+    /// Create every intermediate paging structure covering the
+    /// `2^SizeBits` bytes at `vaddr`, without mapping any pages there.
+    ///
+    /// Afterwards, mapping a page anywhere in that range draws no
+    /// untyped memory and takes a single syscall, e.g. from a demand
+    /// paging fault handler. Page tables which already exist are left
+    /// as they are; the spare ones made while finding that out are
+    /// deleted from `cnode`, and their memory and slots handed back to
+    /// this address space.
+    pub fn populate_page_tables<SizeBits: Unsigned>(
+        &mut self,
+        vaddr: usize,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<(), VSpaceError> {
+        let span_bits = arch::INTERMEDIATE_PAGING_LAYERS[0].0;
+        let last = vaddr
+            .checked_add((1 << SizeBits::USIZE) - 1)
+            .ok_or(VSpaceError::ExceededAddressableSpace)?;
+        for table_index in (vaddr >> span_bits)..=(last >> span_bits) {
+            let ut = self
+                .untyped
+                .alloc(&mut self.slots, <PageTable as DirectRetype>::SizeBits::U8)
+                .map_err(|e| VSpaceError::from(MappingError::from(e)))?;
+            // Both the retype and the table's deletion consume the
+            // untyped's cap, so it's rebuilt to hand it back.
+            let ut_cptr = ut.cptr;
+            let free_ut = |untyped: &mut WUTBuddy| {
+                untyped
+                    .free_weak(Cap {
+                        cptr: ut_cptr,
+                        cap_data: WUntyped {
+                            kind: memory_kind::General,
+                            size_bits: <PageTable as DirectRetype>::SizeBits::U8,
+                        },
+                        _role: PhantomData,
+                    })
+                    .map_err(|e| VSpaceError::from(MappingError::from(e.error)))
+            };
+            let table: LocalCap<PageTable> = match ut.retype(&mut self.slots) {
+                Ok(table) => table,
+                Err(e) => {
+                    free_ut(&mut self.untyped)?;
+                    return Err(VSpaceError::from(MappingError::from(e)));
+                }
+            };
+            match self.layers.next.map_layer(
+                &table,
+                table_index << span_bits,
+                &mut self.root,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+                &mut self.untyped,
+                &mut self.slots,
+            ) {
+                Ok(()) => (),
+                // There's already a table (or a larger page) here.
+                Err(MappingError::IntermediateLayerFailure(SeL4Error::PageTableMap(
+                    KernelError::DeleteFirst,
+                ))) => {
+                    let slot = table.delete_reclaiming_slot(cnode)?;
+                    // Nothing is allocated between the table's retype
+                    // and a refusal to map it, so its slot is always
+                    // the last one taken.
+                    let _ = self.slots.free_last(slot);
+                    free_ut(&mut self.untyped)?;
+                }
+                Err(e) => return Err(VSpaceError::from(e)),
+            }
+        }
+        Ok(())
+    }
+
     /// A thin wrapper around self.layers.map_layer that reduces the amount
     /// of repetitive, visible self-reference
     fn map_page_at_addr_without_watermarking(
@@ -363,15 +544,38 @@ impl<State: VSpaceState> VSpace<State, role::Local> {
                     },
                 },
             })
-            .map_err(|e| match e {
-                MappingError::PageMapFailure(se) | MappingError::IntermediateLayerFailure(se) => {
-                    VSpaceError::SeL4Error(se)
-                }
-                e => VSpaceError::MappingError(e),
-            })
+            .map_err(VSpaceError::from)
     }
 }
 
+/// The rights and attributes to map the user image page at `vaddr`
+/// with, going by the sections which touch it. A page shared by
+/// sections gets the most permissive of them, and rights never exceed
+/// `max_rights`.
+fn user_image_page_mapping(
+    sections: &[UserImageSection],
+    vaddr: usize,
+    max_rights: CapRights,
+) -> (CapRights, arch::VMAttributes) {
+    let mut writable = false;
+    let mut executable = false;
+    for section in sections.iter().filter(|s| s.touches_page(vaddr)) {
+        writable |= section.rights.is_writable();
+        executable |= section.executable;
+    }
+    let rights = if writable && max_rights.is_writable() {
+        CapRights::RW
+    } else {
+        CapRights::R
+    };
+    let vm_attributes = if executable {
+        arch::vm_attributes::PROGRAM_CODE
+    } else {
+        arch::vm_attributes::PROGRAM_DATA
+    };
+    (rights, vm_attributes)
+}
+
 // 0xfff, for 4k pages
 const PAGE_MASK: usize = (1 << arch::PageBits::USIZE) - 1;
 
@@ -541,6 +745,11 @@ impl VSpace<vspace_state::Imaged, role::Local> {
 
         let elf = xmas_elf::ElfFile::new(elf_data).map_err(VSpaceError::ElfParseError)?;
 
+        // Read-only segments can share the pages of the user image, but
+        // only if that's where the elf data lives (it won't if it was
+        // e.g. decompressed somewhere).
+        let elf_in_user_image = user_image.contains_vaddr(elf_data.as_ptr() as usize);
+
         let mut writable_segment_pages_iter =
             elf_writable_mem.retype_pages(&mut page_slots)?.into_iter();
 
@@ -560,9 +769,15 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 arch::vm_attributes::PROGRAM_DATA
             };
 
-            if flags.is_write() {
-                // Writable segments need to be copied into memory owned by the
-                // new process.
+            if flags.is_write() || !elf_in_user_image {
+                // Writable segments, and any others we can't share from the
+                // user image, need to be copied into memory owned by the new
+                // process.
+                let rights = if flags.is_write() {
+                    CapRights::RW
+                } else {
+                    CapRights::R
+                };
 
                 // how much space this segment occupies in memory. For writable
                 // segments, this is often larger than the size in the file, for
@@ -586,7 +801,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                         .ok_or(VSpaceError::InsufficientResourcesForElf)?;
 
                     let mut unmapped_region = dest_page.to_region();
-                    let _ = local_vspace_scratch.temporarily_map_region::<PageBits, _, _>(
+                    let _ = local_vspace_scratch.with_temporary_mapping::<PageBits, _, _>(
                         &mut unmapped_region,
                         |temp_mapped_region| {
                             let dest_mem = temp_mapped_region.as_mut_slice();
@@ -623,7 +838,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     let _ = vspace.map_page_at_addr_without_watermarking(
                         unmapped_region.to_page(),
                         curr_page_vaddr,
-                        CapRights::RW,
+                        rights,
                         vm_attrs,
                     )?;
 
@@ -643,6 +858,11 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 // in the local address space
                 let start_page_vaddr_here = elf_vaddr_here & !PAGE_MASK;
 
+                // The segment need not begin on a page boundary (e.g. a code
+                // segment packed in after read-only data), but its offset
+                // within the page is the same in the file as in memory.
+                let target_page_vaddr = target_vaddr & !PAGE_MASK;
+
                 // TODO just index into the pages, instead of iterating past them
                 for user_image_page in user_image
                     .pages_iter()
@@ -651,7 +871,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 {
                     let page_vaddr_here = user_image_page.cap_data.state.vaddr;
                     let page_offset = page_vaddr_here - start_page_vaddr_here;
-                    let child_vaddr = target_vaddr + page_offset;
+                    let child_vaddr = target_page_vaddr + page_offset;
 
                     let copied_page_cap = user_image_page.copy(
                         &parent_cnode,
@@ -704,6 +924,10 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         user_image: &UserImage<role::Local>,
         parent_cnode: &LocalCap<LocalCNode>,
     ) -> Result<Self, VSpaceError> {
+        // Map each page of the image according to the sections in it,
+        // rather than all alike, so that data isn't executable and code
+        // isn't writable.
+        let sections = user_image.sections()?;
         let code_slots = match slots.alloc(user_image.pages_count()) {
             Ok(t) => t,
             Err(_) => return Err(VSpaceError::InsufficientCNodeSlots),
@@ -718,13 +942,15 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     user_image.pages_iter().zip(code_slots.into_strong_iter())
                 {
                     let address = user_image_page.cap_data.state.vaddr;
+                    let (_, vm_attributes) =
+                        user_image_page_mapping(&sections, address, CapRights::R);
                     let copied_page_cap =
                         user_image_page.copy(&parent_cnode, slot, CapRights::R)?;
                     let _ = vspace.map_page_at_addr_without_watermarking(
                         copied_page_cap,
                         address,
                         CapRights::R,
-                        arch::vm_attributes::DEFAULT,
+                        vm_attributes,
                     )?;
                     vspace
                         .available_address_range
@@ -751,7 +977,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                     // from `user_image` to the new page.
                     let address = user_image_page.cap_data.state.vaddr;
                     let mut unmapped_region = fresh_page.to_region();
-                    let _ = parent_vspace_scratch.temporarily_map_region::<PageBits, _, _>(
+                    let _ = parent_vspace_scratch.with_temporary_mapping::<PageBits, _, _>(
                         &mut unmapped_region,
                         |temp_mapped_region| {
                             unsafe {
@@ -766,11 +992,13 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                             };
                         },
                     )?;
+                    let (rights, vm_attributes) =
+                        user_image_page_mapping(&sections, address, CapRights::RW);
                     let _ = vspace.map_page_at_addr_without_watermarking(
                         unmapped_region.to_page(),
                         address,
-                        CapRights::RW,
-                        arch::vm_attributes::DEFAULT,
+                        rights,
+                        vm_attributes,
                     )?;
                     vspace
                         .available_address_range
@@ -797,18 +1025,37 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         cslots: WCNodeSlots,
         asid: LocalCap<AssignedASID>,
         ut: LocalCap<WUntyped<memory_kind::General>>,
+    ) -> Self {
+        let root = Cap {
+            cptr: root_vspace_cptr,
+            cap_data: PagingRoot::phantom_instance(),
+            _role: PhantomData,
+        };
+        VSpace::adopt(root, asid, next_addr, cslots, ut)
+    }
+
+    /// Wrap an address space which is already populated, e.g. that of
+    /// a running child, so that its manager can go on mapping things
+    /// into it. `asid` must be the one assigned to `root`.
+    ///
+    /// The caller asserts that nothing is mapped at or above
+    /// `next_addr`, where automatic placement of regions will begin.
+    /// `slots` and `ut` are used to build any paging structures that
+    /// later mappings need.
+    pub fn adopt(
+        root: LocalCap<PagingRoot>,
+        asid: LocalCap<AssignedASID>,
+        next_addr: usize,
+        slots: WCNodeSlots,
+        ut: LocalCap<WUntyped<memory_kind::General>>,
     ) -> Self {
         let mut available_address_range = AvailableAddressRange::default();
         available_address_range.bottom = next_addr;
         VSpace {
             layers: AddressSpace::new(),
-            root: Cap {
-                cptr: root_vspace_cptr,
-                cap_data: PagingRoot::phantom_instance(),
-                _role: PhantomData,
-            },
+            root,
             untyped: ut_buddy::weak_ut_buddy(ut),
-            slots: cslots,
+            slots,
             available_address_range,
             asid: asid.cap_data.asid,
             _state: PhantomData,
@@ -887,25 +1134,11 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 &mut self.untyped,
                 &mut self.slots,
             ) {
-                Err(MappingError::PageMapFailure(e))
-                | Err(MappingError::IntermediateLayerFailure(e)) => {
-                    // Rollback the pages we've mapped thus far.
-                    let _ = unmap_mapped_page_cptrs(mapped_pages);
-                    return Err((
-                        VSpaceError::SeL4Error(e),
-                        WeakMemoryRegion::unchecked_new(
-                            cptr,
-                            page_state::Unmapped,
-                            kind,
-                            size_bits,
-                        ),
-                    ));
-                }
                 Err(e) => {
                     // Rollback the pages we've mapped thus far.
                     let _ = unmap_mapped_page_cptrs(mapped_pages);
                     return Err((
-                        VSpaceError::MappingError(e),
+                        VSpaceError::from(e),
                         WeakMemoryRegion::unchecked_new(
                             cptr,
                             page_state::Unmapped,
@@ -967,6 +1200,212 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         ))
     }
 
+    /// Map a large page at `vaddr`, which must be aligned to its size,
+    /// creating any paging structures it needs. If it can't be mapped,
+    /// the page is handed back along with the error.
+    pub fn map_large_page(
+        &mut self,
+        page: LocalCap<LargePage<page_state::Unmapped>>,
+        vaddr: usize,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<
+        LocalCap<LargePage<page_state::Mapped>>,
+        (VSpaceError, LocalCap<LargePage<page_state::Unmapped>>),
+    > {
+        match self.map_frame_at_addr(
+            LargePageAddressSpace::new(),
+            &page,
+            vaddr,
+            LargePageBits::U8,
+            rights,
+            vm_attributes,
+        ) {
+            Ok(()) => Ok(Cap {
+                cptr: page.cptr,
+                cap_data: Frame {
+                    state: page_state::Mapped {
+                        vaddr,
+                        asid: self.asid,
+                        rights,
+                    },
+                    _size_bits: PhantomData,
+                },
+                _role: PhantomData,
+            }),
+            Err(e) => Err((e, page)),
+        }
+    }
+
+    /// Like `map_large_page`, for a huge page.
+    #[cfg(target_arch = "aarch64")]
+    pub fn map_huge_page(
+        &mut self,
+        page: LocalCap<HugePage<page_state::Unmapped>>,
+        vaddr: usize,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<
+        LocalCap<HugePage<page_state::Mapped>>,
+        (VSpaceError, LocalCap<HugePage<page_state::Unmapped>>),
+    > {
+        match self.map_frame_at_addr(
+            arch::HugePageAddressSpace::new(),
+            &page,
+            vaddr,
+            arch::HugePageBits::U8,
+            rights,
+            vm_attributes,
+        ) {
+            Ok(()) => Ok(Cap {
+                cptr: page.cptr,
+                cap_data: Frame {
+                    state: page_state::Mapped {
+                        vaddr,
+                        asid: self.asid,
+                        rights,
+                    },
+                    _size_bits: PhantomData,
+                },
+                _role: PhantomData,
+            }),
+            Err(e) => Err((e, page)),
+        }
+    }
+
+    /// Map `frame`, which is `1 << size_bits` bytes long, at `vaddr`
+    /// through `layers`, the paging structures frames of its size are
+    /// mapped by.
+    fn map_frame_at_addr<L: PagingLayer>(
+        &mut self,
+        mut layers: L,
+        frame: &LocalCap<L::Item>,
+        vaddr: usize,
+        size_bits: u8,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<(), VSpaceError> {
+        if vaddr.checked_add(bytes_from_size_bits(size_bits)).is_none() {
+            return Err(VSpaceError::ExceededAddressableSpace);
+        }
+        layers.map_layer(
+            frame,
+            vaddr,
+            &mut self.root,
+            rights,
+            vm_attributes,
+            &mut self.untyped,
+            &mut self.slots,
+        )?;
+        self.available_address_range
+            .observe_mapping(vaddr, size_bits)
+    }
+
+    /// Retype a device untyped into pages and map them, giving a
+    /// region whose frames are physically contiguous, as DMA engines
+    /// need. Returns the region along with the physical address of its
+    /// start.
+    ///
+    /// `vm_attributes` will usually be `vm_attributes::UNCACHED`; a
+    /// cacheable DMA buffer has to be flushed around every transfer.
+    pub fn new_dma_region<SizeBits: Unsigned>(
+        &mut self,
+        device_ut: LocalCap<Untyped<SizeBits, memory_kind::Device>>,
+        slots: LocalCNodeSlots<NumPages<SizeBits>>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<
+        (
+            MappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+            usize,
+        ),
+        VSpaceError,
+    >
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+        Pow<<SizeBits as Sub<PageBits>>::Output>:
+            IsLessOrEqual<KernelRetypeFanOutLimit, Output = True>,
+    {
+        let paddr = device_ut.paddr();
+        let region = UnmappedMemoryRegion::new_device(device_ut, slots)?;
+        let mapped = self.map_region(region, rights, vm_attributes)?;
+        Ok((mapped, paddr))
+    }
+
+    /// Change the rights and attributes of a mapped region without
+    /// unmapping it; it keeps its address and caps. This is how to go
+    /// from writing code into a region to executing it, for instance.
+    ///
+    /// Should a page fail to remap, the pages before it will already
+    /// have their new rights, but the region will still report the old.
+    pub fn remap_region<SizeBits: Unsigned, SS: SharedStatus>(
+        &mut self,
+        region: &mut MappedMemoryRegion<SizeBits, SS>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<(), VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let page_count = region.caps.len();
+        self.remap_pages(
+            region.caps.start_cptr,
+            &mut region.caps.start_cap_data,
+            page_count,
+            rights,
+            vm_attributes,
+        )
+    }
+
+    /// Change the rights and attributes of a mapped weak region; see
+    /// `remap_region`.
+    pub fn weak_remap_region<SS: SharedStatus>(
+        &mut self,
+        region: &mut WeakMappedMemoryRegion<SS>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<(), VSpaceError> {
+        let page_count = region.caps.len();
+        self.remap_pages(
+            region.caps.start_cptr,
+            &mut region.caps.start_cap_data,
+            page_count,
+            rights,
+            vm_attributes,
+        )
+    }
+
+    fn remap_pages(
+        &mut self,
+        start_cptr: usize,
+        start_page: &mut Page<page_state::Mapped>,
+        page_count: usize,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<(), VSpaceError> {
+        if start_page.state.asid != self.asid {
+            return Err(VSpaceError::ASIDMismatch);
+        }
+        for index in 0..page_count {
+            let mut page: LocalCap<Page<page_state::Mapped>> = Cap {
+                cptr: start_cptr + index,
+                _role: PhantomData,
+                cap_data: Page::reconstruct(index, start_page),
+            };
+            page.remap(&mut self.root, rights, vm_attributes)?;
+        }
+        start_page.state.rights = rights;
+        Ok(())
+    }
+
     /// Map a region of memory at some address, I don't care where.
     pub fn map_region<SizeBits: Unsigned>(
         &mut self,
@@ -1067,15 +1506,19 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     /// this region are copied using the slots and cnode provided.
     /// The incoming `UnmappedMemoryRegion` is only borrowed and one
     /// also gets back a new `MappedMemoryRegion` indexed with the
-    /// status `Shared`.
-    pub fn map_shared_region<SizeBits: Unsigned>(
+    /// status `Shared`. The region is mapped with `rights`, which must
+    /// be within those it was shared with. Asking for more doesn't
+    /// compile when `rights` are type-level rights, such as
+    /// `rights::R`, and fails with `RightsExceedSharedRegion` when
+    /// they're a `CapRights`.
+    pub fn map_shared_region<SizeBits: Unsigned, R: Rights, MapRights: RightsWithin<R>>(
         &mut self,
-        region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
-        rights: CapRights,
+        region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared<R>>,
+        rights: MapRights,
         vm_attributes: arch::VMAttributes,
         slots: LocalCNodeSlots<NumPages<SizeBits>>,
         cnode: &LocalCap<LocalCNode>,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared<R>>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1083,7 +1526,10 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
-        let unmapped_sr: UnmappedMemoryRegion<_, shared_status::Shared> =
+        let rights = rights
+            .within()
+            .ok_or(VSpaceError::RightsExceedSharedRegion)?;
+        let unmapped_sr: UnmappedMemoryRegion<_, shared_status::Shared<R>> =
             UnmappedMemoryRegion::from_caps(region.caps.copy(cnode, slots, rights)?, region.kind);
         self.map_region_internal(unmapped_sr, rights, vm_attributes)
     }
@@ -1092,15 +1538,22 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     /// this region are copied using the slots and cnode provided.
     /// The incoming `UnmappedMemoryRegion` is only borrowed and one
     /// also gets back a new `MappedMemoryRegion` indexed with the
-    /// status `Shared`.
-    pub fn weak_map_shared_region(
+    /// status `Shared`. The region is mapped with `rights`, which must
+    /// be within those it was shared with. Asking for more doesn't
+    /// compile when `rights` are type-level rights, such as
+    /// `rights::R`, and fails with `RightsExceedSharedRegion` when
+    /// they're a `CapRights`.
+    pub fn weak_map_shared_region<R: Rights, MapRights: RightsWithin<R>>(
         &mut self,
-        region: &WeakUnmappedMemoryRegion<shared_status::Shared>,
-        rights: CapRights,
+        region: &WeakUnmappedMemoryRegion<shared_status::Shared<R>>,
+        rights: MapRights,
         vm_attributes: arch::VMAttributes,
         slots: &mut LocalCap<WCNodeSlotsData<role::Local>>,
         cnode: &LocalCap<LocalCNode>,
-    ) -> Result<WeakMappedMemoryRegion<shared_status::Shared>, VSpaceError> {
+    ) -> Result<WeakMappedMemoryRegion<shared_status::Shared<R>>, VSpaceError> {
+        let rights = rights
+            .within()
+            .ok_or(VSpaceError::RightsExceedSharedRegion)?;
         let caps_copy = region
             .caps
             .copy(cnode, slots, rights)
@@ -1108,23 +1561,80 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 WeakCopyError::NotEnoughSlots => VSpaceError::InsufficientCNodeSlots,
                 WeakCopyError::SeL4Error(e) => VSpaceError::SeL4Error(e),
             })?;
-        let unmapped_sr: WeakUnmappedMemoryRegion<shared_status::Shared> =
+        let unmapped_sr: WeakUnmappedMemoryRegion<shared_status::Shared<R>> =
             WeakMemoryRegion::try_from_caps(caps_copy, region.kind, region.size_bits())
                 .map_err(|_| VSpaceError::InvalidRegionSize)?;
         self.weak_map_region_internal(unmapped_sr, rights, vm_attributes)
     }
 
+    /// Map part of a _shared_ region: the `1 << SubSizeBits` bytes
+    /// starting `OffsetPages` pages into it. As with
+    /// `map_shared_region`, the caps for those pages are copied using
+    /// the slots and cnode provided, and the region is only borrowed.
+    pub fn map_region_subrange<
+        SizeBits: Unsigned,
+        SubSizeBits: Unsigned,
+        OffsetPages: Unsigned,
+        R: Rights,
+        MapRights: RightsWithin<R>,
+    >(
+        &mut self,
+        region: &UnmappedMemoryRegion<SizeBits, shared_status::Shared<R>>,
+        rights: MapRights,
+        vm_attributes: arch::VMAttributes,
+        slots: LocalCNodeSlots<NumPages<SubSizeBits>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<MappedMemoryRegion<SubSizeBits, shared_status::Shared<R>>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+
+        SubSizeBits: IsGreaterOrEqual<PageBits>,
+        SubSizeBits: Sub<PageBits>,
+        <SubSizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SubSizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SubSizeBits as Sub<PageBits>>::Output>: Unsigned,
+
+        OffsetPages: Add<NumPages<SubSizeBits>>,
+        Sum<OffsetPages, NumPages<SubSizeBits>>: IsLessOrEqual<NumPages<SizeBits>, Output = True>,
+    {
+        let rights = rights
+            .within()
+            .ok_or(VSpaceError::RightsExceedSharedRegion)?;
+        let subrange: CapRange<Page<page_state::Unmapped>, role::Local, NumPages<SubSizeBits>> =
+            CapRange::new(
+                region.caps.start_cptr + OffsetPages::USIZE,
+                Page {
+                    state: page_state::Unmapped,
+                },
+            );
+        let kind = region
+            .kind
+            .offset_by(OffsetPages::USIZE * arch::PageBytes::USIZE);
+        let unmapped_sr: UnmappedMemoryRegion<_, shared_status::Shared<R>> =
+            UnmappedMemoryRegion::from_caps(subrange.copy(cnode, slots, rights)?, kind);
+        self.map_region_internal(unmapped_sr, rights, vm_attributes)
+    }
+
     /// For cases when one does not want to continue to duplicate the
     /// region's constituent caps—meaning that there is only one final
     /// address space in which this region will be mapped—that
     /// unmapped region can be consumed and a mapped region is
-    /// returned.
-    pub fn map_shared_region_and_consume<SizeBits: Unsigned>(
+    /// returned. As with `map_shared_region`, `rights` must be
+    /// within the rights the region was shared with.
+    pub fn map_shared_region_and_consume<
+        SizeBits: Unsigned,
+        R: Rights,
+        MapRights: RightsWithin<R>,
+    >(
         &mut self,
-        region: UnmappedMemoryRegion<SizeBits, shared_status::Shared>,
-        rights: CapRights,
+        region: UnmappedMemoryRegion<SizeBits, shared_status::Shared<R>>,
+        rights: MapRights,
         vm_attributes: arch::VMAttributes,
-    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared>, VSpaceError>
+    ) -> Result<MappedMemoryRegion<SizeBits, shared_status::Shared<R>>, VSpaceError>
     where
         SizeBits: IsGreaterOrEqual<PageBits>,
         SizeBits: Sub<PageBits>,
@@ -1132,6 +1642,9 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
     {
+        let rights = rights
+            .within()
+            .ok_or(VSpaceError::RightsExceedSharedRegion)?;
         self.map_region_internal(region, rights, vm_attributes)
     }
 
@@ -1187,11 +1700,7 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 &mut self.untyped,
                 &mut self.slots,
             ) {
-                Err(MappingError::PageMapFailure(e))
-                | Err(MappingError::IntermediateLayerFailure(e)) => {
-                    return Err(VSpaceError::SeL4Error(e))
-                }
-                Err(e) => return Err(VSpaceError::MappingError(e)),
+                Err(e) => return Err(VSpaceError::from(e)),
                 Ok(_) => self
                     .available_address_range
                     .observe_mapping(vaddr, PageBits::U8)?,
@@ -1204,7 +1713,10 @@ impl VSpace<vspace_state::Imaged, role::Local> {
         Ok(mapped_region)
     }
 
-    pub(crate) fn skip_pages(&mut self, count: usize) -> Result<(), VSpaceError> {
+    /// Leave `count` pages unmapped, returning the address of the first
+    /// of them.
+    pub(crate) fn skip_pages(&mut self, count: usize) -> Result<usize, VSpaceError> {
+        let mut first_address = None;
         for _ in 0..count {
             let starting_address = self
                 .available_address_range
@@ -1212,8 +1724,75 @@ impl VSpace<vspace_state::Imaged, role::Local> {
                 .map_err(|_| VSpaceError::ExceededAddressableSpace)?;
             self.available_address_range
                 .observe_mapping(starting_address, PageBits::U8)?;
+            first_address.get_or_insert(starting_address);
         }
-        Ok(())
+        Ok(first_address.unwrap_or(self.available_address_range.bottom))
+    }
+
+    /// Claim an unmapped, `1 << size_bits` byte span of address space,
+    /// returning its start. Nothing is mapped there, but later
+    /// automatic placement will steer clear of it.
+    pub(crate) fn reserve_span(&mut self, size_bits: u8) -> Result<usize, VSpaceError> {
+        let starting_address = self
+            .available_address_range
+            .auto_propose_region_start(size_bits)
+            .map_err(|_| VSpaceError::InsufficientAddressSpaceAvailableToMapRegion)?;
+        self.available_address_range
+            .observe_mapping(starting_address, size_bits)?;
+        Ok(starting_address)
+    }
+
+    /// The address at which the next automatically placed region
+    /// would start, were it to fit.
+    pub fn current_addr(&self) -> usize {
+        self.available_address_range.bottom
+    }
+
+    /// Set aside address space for a `1 << SizeBits` byte region to be
+    /// mapped later with `map_region_at_reservation`. Nothing else is
+    /// automatically placed there in the meantime.
+    pub fn reserve_address_range<SizeBits: Unsigned>(
+        &mut self,
+    ) -> Result<VirtualReservation<SizeBits>, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits, Output = True>,
+    {
+        let vaddr = self.reserve_span(SizeBits::U8)?;
+        Ok(VirtualReservation {
+            vaddr,
+            asid: self.asid,
+            _size_bits: PhantomData,
+        })
+    }
+
+    /// Map a region into space set aside for it by
+    /// `reserve_address_range`.
+    pub fn map_region_at_reservation<SizeBits: Unsigned, SS: SharedStatus>(
+        &mut self,
+        region: UnmappedMemoryRegion<SizeBits, SS>,
+        reservation: VirtualReservation<SizeBits>,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<
+        MappedMemoryRegion<SizeBits, SS>,
+        (
+            VSpaceError,
+            Option<UnmappedMemoryRegion<SizeBits, SS>>,
+            VirtualReservation<SizeBits>,
+        ),
+    >
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+    {
+        if reservation.asid != self.asid {
+            return Err((VSpaceError::ASIDMismatch, Some(region), reservation));
+        }
+        self.map_region_at_addr(region, reservation.vaddr, rights, vm_attributes)
+            .map_err(|(e, r)| (e, r, reservation))
     }
 
     pub fn reserve<PageCount: Unsigned>(
@@ -1227,6 +1806,23 @@ impl VSpace<vspace_state::Imaged, role::Local> {
     }
 }
 
+/// Address space in a VSpace set aside, by
+/// `VSpace::reserve_address_range`, for a `1 << SizeBits` byte region.
+/// Nothing is mapped there until the reservation is redeemed with
+/// `VSpace::map_region_at_reservation`.
+#[derive(Debug)]
+pub struct VirtualReservation<SizeBits: Unsigned> {
+    vaddr: usize,
+    asid: InternalASID,
+    _size_bits: PhantomData<SizeBits>,
+}
+
+impl<SizeBits: Unsigned> VirtualReservation<SizeBits> {
+    pub fn vaddr(&self) -> usize {
+        self.vaddr
+    }
+}
+
 /// A region of memory in a VSpace that has been reserved
 /// for future scratch-style/temporary usage.
 ///
@@ -1313,17 +1909,57 @@ impl<PageCount: Unsigned> ScratchRegion<PageCount> {
         }
     }
 
+    #[cfg(feature = "test_support")]
+    /// Super dangerous copy-aliasing
+    pub(crate) unsafe fn dangerous_internal_alias(&mut self) -> Self {
+        ScratchRegion {
+            reserved_region: ReservedRegion {
+                vaddr: self.reserved_region.vaddr,
+                asid: self.reserved_region.asid,
+                _page_count: PhantomData,
+            },
+            paging_root: Cap {
+                cptr: self.paging_root.cptr,
+                cap_data: PagingRoot::phantom_instance(),
+                _role: PhantomData,
+            },
+        }
+    }
+
     // TODO - add more safety rails to prevent returning something from the
     // inner function that becomes invalid when the page is unmapped locally
     //
-    /// Map a region temporarily and do with it as thou wilt with `f`.
+    /// Map a region temporarily and do with it as thou wilt with `f`,
+    /// then unmap it again.
     ///
     /// Note that this is defined on a region which has the shared
     /// status of `Exclusive`. The idea here is to do the initial
-    /// region-filling work with `temporarily_map_region` _before_
+    /// region-filling work with `with_temporary_mapping` _before_
     /// sharing this page and mapping it into other address
     /// spaces. This enforced order ought to prevent one from
     /// forgetting to do the region-filling initialization.
+    ///
+    /// If mapping fails part of the way through, the pages mapped so
+    /// far are unmapped before the error is returned. The region must
+    /// fit within the scratch area.
+    pub fn with_temporary_mapping<SizeBits: Unsigned, F, Out>(
+        &mut self,
+        region: &mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        f: F,
+    ) -> Result<Out, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+        F: FnOnce(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
+    {
+        self.temporarily_map_region_with_attributes(region, arch::vm_attributes::DEFAULT, f)
+    }
+
+    /// The old name for `with_temporary_mapping`.
+    #[deprecated(note = "use `with_temporary_mapping`")]
     pub fn temporarily_map_region<SizeBits: Unsigned, F, Out>(
         &mut self,
         region: &mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
@@ -1335,26 +1971,55 @@ impl<PageCount: Unsigned> ScratchRegion<PageCount> {
         <SizeBits as Sub<PageBits>>::Output: Unsigned,
         <SizeBits as Sub<PageBits>>::Output: _Pow,
         Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
-        F: Fn(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
+        F: FnOnce(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
     {
+        self.with_temporary_mapping(region, f)
+    }
+
+    /// As `with_temporary_mapping`, but with the given attributes
+    /// rather than the default cacheable ones. A region which will be
+    /// mapped uncached elsewhere (a DMA buffer, say) should be filled
+    /// through an uncached mapping too, so that nothing lingers in the
+    /// cache.
+    pub fn temporarily_map_region_with_attributes<SizeBits: Unsigned, F, Out>(
+        &mut self,
+        region: &mut UnmappedMemoryRegion<SizeBits, shared_status::Exclusive>,
+        vm_attributes: arch::VMAttributes,
+        f: F,
+    ) -> Result<Out, VSpaceError>
+    where
+        SizeBits: IsGreaterOrEqual<PageBits>,
+        SizeBits: Sub<PageBits>,
+        <SizeBits as Sub<PageBits>>::Output: Unsigned,
+        <SizeBits as Sub<PageBits>>::Output: _Pow,
+        Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+        F: FnOnce(&mut MappedMemoryRegion<SizeBits, shared_status::Exclusive>) -> Out,
+    {
+        if region.size_bytes() > PageCount::USIZE * arch::PageBytes::USIZE {
+            return Err(VSpaceError::InsufficientAddressSpaceAvailableToMapRegion);
+        }
+
         let start_vaddr = self.reserved_region.vaddr;
-        let mut next_addr = start_vaddr;
+        let mut mapping = ScratchMapping {
+            start_cptr: region.caps.start_cptr,
+            vaddr: start_vaddr,
+            asid: self.reserved_region.asid,
+            mapped_pages: 0,
+        };
 
-        let res: Result<(), SeL4Error> = region.caps.for_each::<SeL4Error, _>(|page| {
+        let paging_root = &mut self.paging_root;
+        region.caps.for_each::<SeL4Error, _>(|page| {
             unsafe {
                 page.unchecked_page_map(
-                    next_addr,
-                    &mut self.paging_root,
+                    start_vaddr + mapping.mapped_pages * arch::PageBytes::USIZE,
+                    paging_root,
                     CapRights::RW,
-                    arch::vm_attributes::DEFAULT,
+                    vm_attributes,
                 )?;
             }
-            next_addr += arch::PageBytes::USIZE;
-            let res: Result<(), SeL4Error> = Ok(());
-            res
-        });
-
-        res?;
+            mapping.mapped_pages += 1;
+            Ok(())
+        })?;
 
         // synthesize a MappedMemoryRegion to pass to the callback
         let mut mapped_region = MemoryRegion::unchecked_new(
@@ -1369,12 +2034,45 @@ impl<PageCount: Unsigned> ScratchRegion<PageCount> {
 
         let res = f(&mut mapped_region);
 
-        // unmap everything
-        for page in mapped_region.caps.into_iter() {
+        mapping.unmap()?;
+        Ok(res)
+    }
+}
+
+/// The pages of a region mapped into a scratch area, which are
+/// unmapped again when this is dropped if they haven't been already.
+struct ScratchMapping {
+    start_cptr: usize,
+    vaddr: usize,
+    asid: InternalASID,
+    mapped_pages: usize,
+}
+
+impl ScratchMapping {
+    fn unmap(&mut self) -> Result<(), SeL4Error> {
+        while self.mapped_pages > 0 {
+            let index = self.mapped_pages - 1;
+            let page: LocalCap<Page<page_state::Mapped>> = Cap {
+                cptr: self.start_cptr + index,
+                cap_data: Page {
+                    state: page_state::Mapped {
+                        vaddr: self.vaddr + index * arch::PageBytes::USIZE,
+                        asid: self.asid,
+                        rights: CapRights::RW,
+                    },
+                },
+                _role: PhantomData,
+            };
             page.unmap()?;
+            self.mapped_pages = index;
         }
+        Ok(())
+    }
+}
 
-        Ok(res)
+impl Drop for ScratchMapping {
+    fn drop(&mut self) {
+        let _ = self.unmap();
     }
 }
 
@@ -0,0 +1,373 @@
+//! Demand paging: a span of a VSpace which is claimed up front but
+//! only backed by memory a page at a time, as the owning process first
+//! touches it.
+//!
+//! The process is given the span's address, and some thread holding
+//! the VSpace sits in a loop calling `OnDemandRegion::handle_next_fault`
+//! on the sink of the process's fault endpoint.
+//!
+//! Copy-on-write regions (see `VSpace::map_cow_region`) work the same
+//! way, except that pages start out mapped read-only and a write fault
+//! replaces the page with a private copy.
+use core::marker::PhantomData;
+
+use typenum::*;
+
+use super::region::num_pages;
+use super::{
+    shared_status, ScratchRegion, VSpace, VSpaceError, WeakMappedMemoryRegion,
+    WeakUnmappedMemoryRegion, PAGE_MASK,
+};
+use crate::alloc::ut_buddy::{UTBuddyError, WUTBuddy};
+use crate::arch::fault::Fault;
+use crate::arch::{self, PageBits, PageBytes};
+use crate::cap::{
+    page_state, role, Cap, InternalASID, LocalCNode, LocalCap, Page, RetypeError, WCNodeSlots,
+    WCNodeSlotsData,
+};
+use crate::error::SeL4Error;
+use crate::userland::rights::rights;
+use crate::userland::{CapRights, FaultCause, FaultDecision, FaultHandler};
+
+#[derive(Debug)]
+pub enum OnDemandError {
+    /// The address given to `populate` isn't within the region.
+    AddressOutsideRegion,
+    VSpaceError(VSpaceError),
+    UTBuddyError(UTBuddyError),
+    RetypeError(RetypeError),
+    SeL4Error(SeL4Error),
+}
+
+impl From<VSpaceError> for OnDemandError {
+    fn from(e: VSpaceError) -> Self {
+        OnDemandError::VSpaceError(e)
+    }
+}
+
+impl From<UTBuddyError> for OnDemandError {
+    fn from(e: UTBuddyError) -> Self {
+        OnDemandError::UTBuddyError(e)
+    }
+}
+
+impl From<RetypeError> for OnDemandError {
+    fn from(e: RetypeError) -> Self {
+        OnDemandError::RetypeError(e)
+    }
+}
+
+impl From<SeL4Error> for OnDemandError {
+    fn from(e: SeL4Error) -> Self {
+        OnDemandError::SeL4Error(e)
+    }
+}
+
+/// A reserved span of address space whose pages are mapped lazily.
+#[derive(Debug)]
+pub struct OnDemandRegion {
+    vaddr: usize,
+    size_bits: u8,
+    asid: InternalASID,
+    rights: CapRights,
+    vm_attributes: arch::VMAttributes,
+}
+
+impl OnDemandRegion {
+    /// Reserve a `1 << size_bits` byte span in `vspace`. Pages mapped
+    /// there later are given `rights` and `vm_attributes`.
+    pub fn new(
+        vspace: &mut VSpace,
+        size_bits: u8,
+        rights: CapRights,
+        vm_attributes: arch::VMAttributes,
+    ) -> Result<Self, VSpaceError> {
+        if size_bits < PageBits::U8 {
+            return Err(VSpaceError::InvalidRegionSize);
+        }
+        let vaddr = vspace.reserve_span(size_bits)?;
+        Ok(OnDemandRegion {
+            vaddr,
+            size_bits,
+            asid: vspace.asid(),
+            rights,
+            vm_attributes,
+        })
+    }
+
+    pub fn vaddr(&self) -> usize {
+        self.vaddr
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        1 << self.size_bits
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.vaddr && addr - self.vaddr < self.size_bytes()
+    }
+
+    /// Back the page containing `addr` with a freshly retyped (and so
+    /// zeroed) page from `untyped`.
+    pub fn populate(
+        &self,
+        vspace: &mut VSpace,
+        addr: usize,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+    ) -> Result<LocalCap<Page<page_state::Mapped>>, OnDemandError> {
+        if vspace.asid() != self.asid {
+            return Err(VSpaceError::ASIDMismatch.into());
+        }
+        if !self.contains(addr) {
+            return Err(OnDemandError::AddressOutsideRegion);
+        }
+        let page: LocalCap<Page<page_state::Unmapped>> =
+            untyped.alloc(slots, PageBits::U8)?.retype(slots)?;
+        Ok(vspace.map_page_at_addr_without_watermarking(
+            page,
+            addr & !PAGE_MASK,
+            self.rights,
+            self.vm_attributes,
+        )?)
+    }
+
+    /// Wait for the next fault to arrive at `handler`. A VM fault
+    /// within this region gets a page mapped at the faulting address
+    /// and its thread resumed. Any other fault leaves its thread
+    /// blocked, for the caller to deal with as it sees fit; either
+    /// way the interpreted fault is returned.
+    ///
+    /// Two threads faulting on the same page at once are not handled:
+    /// the second mapping fails and that thread is left blocked.
+    #[cfg(not(KernelIsMCS))]
+    pub fn handle_next_fault(
+        &self,
+        handler: &mut FaultHandler,
+        vspace: &mut VSpace,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+    ) -> Result<FaultCause, OnDemandError> {
+        let mut populated = Ok(());
+        let fault = handler.handle_next(|fault| match fault {
+            FaultCause::Fault(Fault::VMFault(f)) if self.contains(f.address) => {
+                match self.populate(vspace, f.address, untyped, slots) {
+                    Ok(_) => FaultDecision::Resume,
+                    Err(e) => {
+                        populated = Err(e);
+                        FaultDecision::Kill
+                    }
+                }
+            }
+            _ => FaultDecision::Kill,
+        })?;
+        populated.map(|_| fault)
+    }
+}
+
+/// The largest copy-on-write region, in pages, whose copied pages can be
+/// tracked.
+pub const COW_MAX_PAGES: usize = 1024;
+
+impl VSpace {
+    /// Map a shared region read-only. The first write to each of its
+    /// pages faults, and is expected to be handled by a
+    /// `CowRegion::handle_next_fault` loop, which swaps in a private,
+    /// writable copy of that page.
+    ///
+    /// As with `weak_map_shared_region`, the region's caps are copied
+    /// using the slots and cnode provided. The original caps are
+    /// borrowed for as long as the `CowRegion` lives, as they're the
+    /// source of each page copy.
+    pub fn map_cow_region<'r>(
+        &mut self,
+        region: &'r WeakUnmappedMemoryRegion<shared_status::Shared>,
+        vm_attributes: arch::VMAttributes,
+        slots: &mut LocalCap<WCNodeSlotsData<role::Local>>,
+        cnode: &LocalCap<LocalCNode>,
+    ) -> Result<CowRegion<'r>, VSpaceError> {
+        match num_pages(region.size_bits()) {
+            Ok(count) if count <= COW_MAX_PAGES => (),
+            _ => return Err(VSpaceError::InvalidRegionSize),
+        }
+        let mapped = self.weak_map_shared_region(region, rights::R, vm_attributes, slots, cnode)?;
+        Ok(CowRegion {
+            source: region,
+            mapped,
+            vm_attributes,
+            copied: [0; COW_MAX_PAGES / 32],
+        })
+    }
+}
+
+/// A shared region mapped copy-on-write by `VSpace::map_cow_region`.
+pub struct CowRegion<'r> {
+    source: &'r WeakUnmappedMemoryRegion<shared_status::Shared>,
+    /// The read-only mapping, whose pages are unmapped one by one as
+    /// they're replaced with copies.
+    mapped: WeakMappedMemoryRegion<shared_status::Shared>,
+    vm_attributes: arch::VMAttributes,
+    /// A bit per page, set once that page has been copied.
+    copied: [u32; COW_MAX_PAGES / 32],
+}
+
+impl<'r> CowRegion<'r> {
+    pub fn vaddr(&self) -> usize {
+        self.mapped.vaddr()
+    }
+
+    pub fn contains(&self, addr: usize) -> bool {
+        addr >= self.vaddr() && addr - self.vaddr() < self.mapped.size_bytes()
+    }
+
+    /// Whether the page containing `addr` has been replaced by a
+    /// private copy.
+    pub fn is_copied(&self, addr: usize) -> bool {
+        self.contains(addr) && self.is_copied_index(self.page_index(addr))
+    }
+
+    fn page_index(&self, addr: usize) -> usize {
+        (addr - self.vaddr()) >> PageBits::USIZE
+    }
+
+    fn is_copied_index(&self, index: usize) -> bool {
+        self.copied[index / 32] & (1 << (index % 32)) != 0
+    }
+
+    /// Replace the read-only page containing `addr` with a writable copy
+    /// of it, built from a page of `untyped`. `vspace` is the one this
+    /// region was mapped into; `scratch` belongs to the calling thread's
+    /// own VSpace and is where the copy is made.
+    ///
+    /// Pages which have already been copied are left alone.
+    pub fn copy_page<PageCount: Unsigned>(
+        &mut self,
+        addr: usize,
+        vspace: &mut VSpace,
+        scratch: &mut ScratchRegion<PageCount>,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+    ) -> Result<(), OnDemandError>
+    where
+        PageCount: IsGreaterOrEqual<U2, Output = True>,
+    {
+        if vspace.asid() != self.mapped.asid() {
+            return Err(VSpaceError::ASIDMismatch.into());
+        }
+        if !self.contains(addr) {
+            return Err(OnDemandError::AddressOutsideRegion);
+        }
+        let index = self.page_index(addr);
+        if self.is_copied_index(index) {
+            return Ok(());
+        }
+
+        let copy: LocalCap<Page<page_state::Unmapped>> =
+            untyped.alloc(slots, PageBits::U8)?.retype(slots)?;
+        let original: LocalCap<Page<page_state::Unmapped>> =
+            unsafe { Cap::wrap_cptr(self.source.caps.start_cptr + index) };
+
+        let source_vaddr = scratch.reserved_region.vaddr;
+        let copy_vaddr = source_vaddr + PageBytes::USIZE;
+        let scratch_asid = scratch.reserved_region.asid;
+        unsafe {
+            original.unchecked_page_map(
+                source_vaddr,
+                &mut scratch.paging_root,
+                CapRights::R,
+                arch::vm_attributes::DEFAULT,
+            )?;
+            if let Err(e) = copy.unchecked_page_map(
+                copy_vaddr,
+                &mut scratch.paging_root,
+                CapRights::RW,
+                arch::vm_attributes::DEFAULT,
+            ) {
+                let _ =
+                    mapped_page(original.cptr, source_vaddr, scratch_asid, CapRights::R).unmap();
+                return Err(e.into());
+            }
+            core::ptr::copy_nonoverlapping(
+                source_vaddr as *const u8,
+                copy_vaddr as *mut u8,
+                PageBytes::USIZE,
+            );
+        }
+        mapped_page(original.cptr, source_vaddr, scratch_asid, CapRights::R).unmap()?;
+        // Unmapping a page doesn't write back its dirty cache lines on
+        // ARM, so clean the copy while it's still mapped here to have it
+        // in memory before the child sees it, however the child maps it.
+        unsafe { arch::clean_page_range(copy.cptr, 0, PageBytes::USIZE) }?;
+        let copy = mapped_page(copy.cptr, copy_vaddr, scratch_asid, CapRights::RW).unmap()?;
+
+        let page_vaddr = self.vaddr() + index * PageBytes::USIZE;
+        mapped_page(
+            self.mapped.caps.start_cptr + index,
+            page_vaddr,
+            self.mapped.asid(),
+            CapRights::R,
+        )
+        .unmap()?;
+        vspace.map_page_at_addr_without_watermarking(
+            copy,
+            page_vaddr,
+            CapRights::RW,
+            self.vm_attributes,
+        )?;
+
+        self.copied[index / 32] |= 1 << (index % 32);
+        Ok(())
+    }
+
+    /// Wait for the next fault to arrive at `handler`. A write fault
+    /// within this region gets the faulting page copied and its
+    /// thread resumed. Any other fault leaves its thread blocked;
+    /// either way the interpreted fault is returned.
+    #[cfg(not(KernelIsMCS))]
+    pub fn handle_next_fault<PageCount: Unsigned>(
+        &mut self,
+        handler: &mut FaultHandler,
+        vspace: &mut VSpace,
+        scratch: &mut ScratchRegion<PageCount>,
+        untyped: &mut WUTBuddy,
+        slots: &mut WCNodeSlots,
+    ) -> Result<FaultCause, OnDemandError>
+    where
+        PageCount: IsGreaterOrEqual<U2, Output = True>,
+    {
+        let mut copied = Ok(());
+        let fault = handler.handle_next(|fault| match fault {
+            FaultCause::Fault(Fault::VMFault(f)) if f.is_write && self.contains(f.address) => {
+                match self.copy_page(f.address, vspace, scratch, untyped, slots) {
+                    Ok(()) => FaultDecision::Resume,
+                    Err(e) => {
+                        copied = Err(e);
+                        FaultDecision::Kill
+                    }
+                }
+            }
+            _ => FaultDecision::Kill,
+        })?;
+        copied.map(|_| fault)
+    }
+}
+
+/// Describe an existing page cap as mapped, so that it can be unmapped.
+fn mapped_page(
+    cptr: usize,
+    vaddr: usize,
+    asid: InternalASID,
+    rights: CapRights,
+) -> LocalCap<Page<page_state::Mapped>> {
+    Cap {
+        cptr,
+        _role: PhantomData,
+        cap_data: Page {
+            state: page_state::Mapped {
+                vaddr,
+                asid,
+                rights,
+            },
+        },
+    }
+}
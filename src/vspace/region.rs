@@ -1,39 +1,57 @@
 use core::cmp;
 use core::marker::PhantomData;
-use core::ops::Sub;
+use core::ops::{Add, Deref, Sub};
 
 use typenum::*;
 
 use super::{KernelRetypeFanOutLimit, NumPages, VSpaceError};
 use crate::arch::{self, PageBits, PageBytes};
 use crate::cap::{
-    memory_kind, page_state, role, CNode, CNodeRole, CNodeSlots, Cap, CapRange, InternalASID,
-    LocalCNodeSlots, LocalCap, MemoryKind, Page, PageState, RetypeError, Untyped, WCNodeSlots,
-    WUntyped, WeakCapRange, WeakMemoryKind,
+    memory_kind, page_state, role, CNode, CNodeRole, CNodeSlots, Cap, CapRange,
+    CapRangeDataReconstruction, InternalASID, LocalCNodeSlots, LocalCap, MemoryKind, Page,
+    PageState, RetypeError, Untyped, WCNodeSlots, WUntyped, WeakCapRange, WeakMemoryKind,
 };
 use crate::error::SeL4Error;
 
 use crate::pow::{Pow, _Pow};
-use crate::userland::CapRights;
+use crate::userland::rights::{Rights, SubsetOf};
+use crate::userland::{AddressSpaceIndependent, CapRights};
 
-pub trait SharedStatus: private::SealedSharedStatus {}
+pub trait SharedStatus: private::SealedSharedStatus {
+    /// The most a region with this status may be mapped or shared
+    /// with.
+    type Rights: Rights;
+}
 
 pub mod shared_status {
+    use core::marker::PhantomData;
+
     use super::SharedStatus;
+    use crate::userland::rights::{rights, Rights};
 
-    pub struct Shared;
-    impl SharedStatus for Shared {}
+    /// Shared with at most rights `R`, wherever the region ends up
+    /// mapped. Mapping or sharing it again with anything more is an
+    /// error, so a region shared as `Shared<rights::R>` stays
+    /// read-only in every address space.
+    pub struct Shared<R: Rights = rights::RWG>(PhantomData<R>);
+    impl<R: Rights> SharedStatus for Shared<R> {
+        type Rights = R;
+    }
 
     pub struct Exclusive;
-    impl SharedStatus for Exclusive {}
+    impl SharedStatus for Exclusive {
+        type Rights = rights::RWG;
+    }
 }
 
 mod private {
     use super::shared_status::{Exclusive, Shared};
+    use crate::userland::rights::Rights;
     pub trait SealedSharedStatus {}
-    impl SealedSharedStatus for Shared {}
+    impl<R: Rights> SealedSharedStatus for Shared<R> {}
     impl SealedSharedStatus for Exclusive {}
 }
+
 /// A `1 << SizeBits` bytes region of unmapped memory. It can be
 /// shared or owned exclusively. The ramifications of its shared
 /// status are described more completely in the `mapped_shared_region`
@@ -77,6 +95,19 @@ pub struct MemoryRegion<
     _shared_status: PhantomData<SS>,
 }
 
+// A mapped region's address is wherever it was mapped, which for one
+// made for a child is in the child's address space.
+unsafe impl<State: PageState, SizeBits: Unsigned, SS: SharedStatus, CapRole: CNodeRole>
+    AddressSpaceIndependent for MemoryRegion<State, SizeBits, SS, CapRole>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+}
+
 impl<State: PageState, SizeBits: Unsigned, SS: SharedStatus, CapRole: CNodeRole>
     MemoryRegion<State, SizeBits, SS, CapRole>
 where
@@ -151,22 +182,31 @@ where
 
     /// In the Ok case, returns a shared, unmapped copy of the memory
     /// region (backed by fresh page-caps) along with this self-same
-    /// memory region, marked as shared.
-    pub fn share<CNodeSlotCount: Unsigned, DestRole: CNodeRole>(
+    /// memory region, marked as shared. A region which is already
+    /// shared keeps its limit on rights, and the copies' rights,
+    /// `ShareRights`, must fall within it.
+    pub fn share<CNodeSlotCount: Unsigned, DestRole: CNodeRole, ShareRights>(
         self,
         slots: CNodeSlots<CNodeSlotCount, DestRole>,
         cnode: &LocalCap<CNode<CapRole>>,
-        rights: CapRights,
+        _rights: ShareRights,
     ) -> Result<
         (
-            MemoryRegion<page_state::Unmapped, SizeBits, shared_status::Shared, DestRole>,
-            MemoryRegion<State, SizeBits, shared_status::Shared, CapRole>,
+            MemoryRegion<
+                page_state::Unmapped,
+                SizeBits,
+                shared_status::Shared<SS::Rights>,
+                DestRole,
+            >,
+            MemoryRegion<State, SizeBits, shared_status::Shared<SS::Rights>, CapRole>,
         ),
         VSpaceError,
     >
     where
         CNodeSlotCount: IsEqual<NumPages<SizeBits>, Output = True>,
+        ShareRights: SubsetOf<SS::Rights>,
     {
+        let rights = ShareRights::as_caprights();
         let pages_offset = self.caps.start_cptr;
         let original_mapped_state = self.caps.start_cap_data.state.clone();
         let slots_offset = slots.cap_data.offset;
@@ -222,6 +262,19 @@ where
         Ok(UnmappedMemoryRegion::from_caps(page_caps, kind.weaken()))
     }
 
+    /// Adopt a range of page caps which already exist, such as device
+    /// frames handed over by another process, as a region.
+    ///
+    /// `kind` is taken on trust. In particular, claiming
+    /// `WeakMemoryKind::Device` asserts that the pages are physically
+    /// contiguous, starting at the given address.
+    pub fn from_page_caps(
+        caps: CapRange<Page<page_state::Unmapped>, role::Local, NumPages<SizeBits>>,
+        kind: WeakMemoryKind,
+    ) -> Self {
+        UnmappedMemoryRegion::from_caps(caps, kind)
+    }
+
     pub fn new_device<Role: CNodeRole>(
         ut: LocalCap<Untyped<SizeBits, memory_kind::Device>>,
         slots: CNodeSlots<NumPages<SizeBits>, Role>,
@@ -241,6 +294,15 @@ where
     pub fn to_shared(self) -> UnmappedMemoryRegion<SizeBits, shared_status::Shared> {
         UnmappedMemoryRegion::from_caps(self.caps, self.kind)
     }
+
+    /// As `to_shared`, but never to be mapped with more than rights
+    /// `R`, e.g. `to_shared_with_rights::<rights::R>()` for a region
+    /// which must be read-only everywhere.
+    pub fn to_shared_with_rights<R: Rights>(
+        self,
+    ) -> UnmappedMemoryRegion<SizeBits, shared_status::Shared<R>> {
+        UnmappedMemoryRegion::from_caps(self.caps, self.kind)
+    }
 }
 
 impl<SizeBits: Unsigned, SS: SharedStatus> MappedMemoryRegion<SizeBits, SS>
@@ -263,6 +325,32 @@ where
         self.caps.start_cap_data.state.rights
     }
 
+    /// The page caps backing this region, in address order.
+    pub fn pages(&self) -> impl Iterator<Item = RegionPage<'_>> + '_ {
+        region_pages(
+            &self.caps.start_cap_data,
+            self.caps.start_cptr,
+            self.caps.len(),
+        )
+    }
+
+    /// The page cap backing the `index`th page of this region, which
+    /// starts at `vaddr() + index * PageBytes::USIZE`.
+    pub fn page(&self, index: usize) -> Option<RegionPage<'_>> {
+        self.pages().nth(index)
+    }
+
+    /// The physical address of the start of this region.
+    ///
+    /// Regions retyped from general untypeds are not necessarily
+    /// backed by contiguous frames, so every page is checked, and
+    /// `VSpaceError::NotPhysicallyContiguous` returned if any is out of
+    /// place. A region made from a single device untyped always
+    /// passes.
+    pub fn start_paddr(&self) -> Result<usize, VSpaceError> {
+        contiguous_start_paddr(self.pages())
+    }
+
     pub fn as_slice(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.vaddr() as *const u8, self.size_bytes()) }
     }
@@ -271,6 +359,34 @@ where
         unsafe { core::slice::from_raw_parts_mut(self.vaddr() as *mut u8, self.size_bytes()) }
     }
 
+    /// Copy `data` into the region, starting `offset` bytes in, and
+    /// flush the pages it touched.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<(), VSpaceError> {
+        if !self.rights().is_writable() {
+            return Err(VSpaceError::RegionNotWritable);
+        }
+        self.check_access_bounds(offset, data.len())?;
+        self.as_mut_slice()[offset..offset + data.len()].copy_from_slice(data);
+        if !data.is_empty() {
+            self.flush_range(self.vaddr() + offset, data.len())?;
+        }
+        Ok(())
+    }
+
+    /// Fill `buf` from the region, starting `offset` bytes in.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<(), VSpaceError> {
+        self.check_access_bounds(offset, buf.len())?;
+        buf.copy_from_slice(&self.as_slice()[offset..offset + buf.len()]);
+        Ok(())
+    }
+
+    fn check_access_bounds(&self, offset: usize, len: usize) -> Result<(), VSpaceError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.size_bytes() => Ok(()),
+            _ => Err(VSpaceError::RegionAccessOutOfBounds),
+        }
+    }
+
     pub fn flush(&self) -> Result<(), SeL4Error> {
         self.caps.for_each::<SeL4Error, _>(|cap| {
             unsafe {
@@ -282,18 +398,72 @@ where
         Ok(())
     }
 
+    /// Clean and invalidate the data cache for the `size` bytes
+    /// starting at `vaddr`. Only the pages the range touches are
+    /// flushed, and within each only the part it covers, rounded out
+    /// to whole cache lines. Any part of the range outside this region
+    /// is ignored.
+    ///
+    /// See `clean_range` and `invalidate_range` for the one-way
+    /// operations DMA usually calls for.
     pub fn flush_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
-        let bottom = vaddr & !0xFFF;
-        let top = vaddr + cmp::max(PageBytes::USIZE, size);
-        let range = bottom..top;
-        self.caps.for_each::<SeL4Error, _>(|cap| {
-            if range.contains(&cap.vaddr()) {
-                unsafe {
-                    arch::flush_page(cap.cptr)?;
-                }
-            }
-            Ok(())
-        })?;
+        self.for_each_page_in_range(vaddr, size, |cptr, start, end| unsafe {
+            arch::flush_page_range(cptr, start, end)
+        })
+    }
+
+    /// Write any dirty cache lines in the given range back to memory,
+    /// as is needed before a device reads it. Covers the same part of
+    /// the region as `flush_range`.
+    pub fn clean_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
+        self.for_each_page_in_range(vaddr, size, |cptr, start, end| unsafe {
+            arch::clean_page_range(cptr, start, end)
+        })
+    }
+
+    /// Discard any cached lines in the given range, as is needed
+    /// before reading memory a device has written. Covers the same part
+    /// of the region as `flush_range`.
+    ///
+    /// Lines only partly inside the range are discarded whole, taking
+    /// any unwritten changes to the rest of them along; keep DMA
+    /// buffers cache line aligned.
+    pub fn invalidate_range(&self, vaddr: usize, size: usize) -> Result<(), SeL4Error> {
+        self.for_each_page_in_range(vaddr, size, |cptr, start, end| unsafe {
+            arch::invalidate_page_range(cptr, start, end)
+        })
+    }
+
+    /// Call `f` with the cptr of each page overlapping the given
+    /// range, along with the start and end offsets of the overlap
+    /// within that page.
+    fn for_each_page_in_range<F>(
+        &self,
+        vaddr: usize,
+        size: usize,
+        mut f: F,
+    ) -> Result<(), SeL4Error>
+    where
+        F: FnMut(usize, usize, usize) -> Result<(), SeL4Error>,
+    {
+        let region_start = self.vaddr();
+        let start = cmp::max(vaddr, region_start);
+        let end = cmp::min(vaddr.saturating_add(size), region_start + self.size_bytes());
+        if start >= end {
+            return Ok(());
+        }
+
+        let first_page = (start - region_start) / PageBytes::USIZE;
+        let last_page = (end - 1 - region_start) / PageBytes::USIZE;
+        for index in first_page..=last_page {
+            let page_start = region_start + index * PageBytes::USIZE;
+            let page_end = page_start + PageBytes::USIZE;
+            f(
+                self.caps.start_cptr + index,
+                cmp::max(start, page_start) - page_start,
+                cmp::min(end, page_end) - page_start,
+            )?;
+        }
 
         Ok(())
     }
@@ -313,6 +483,11 @@ where
     }
 
     /// Halve a region into two regions.
+    ///
+    /// The halves are the lower and upper parts of the original's
+    /// address range, in that order, and keep its ASID, rights and
+    /// shared status. No kernel calls are made; the existing mappings
+    /// and page caps are simply divided between the two.
     pub fn split(
         self,
     ) -> Result<
@@ -341,6 +516,7 @@ where
         };
 
         let new_offset = self.caps.start_cptr + (self.caps.len() / 2);
+        let half_size_bytes = self.size_bytes() / 2;
 
         Ok((
             MappedMemoryRegion {
@@ -369,13 +545,56 @@ where
                         },
                     },
                 ),
-                kind: self.kind,
+                kind: self.kind.offset_by(half_size_bytes),
                 _size_bits: PhantomData,
                 _shared_status: PhantomData,
             },
         ))
     }
 
+    /// Rejoin two regions which sit side by side, `self` first, into
+    /// one of twice the size: the inverse of `split`.
+    ///
+    /// Besides being adjacent in the address space, they must share an
+    /// ASID, rights and memory kind, and their page caps must occupy
+    /// consecutive slots. If any of that doesn't hold, both regions are
+    /// handed back untouched.
+    pub fn join(
+        self,
+        other: Self,
+    ) -> Result<MappedMemoryRegion<op!(SizeBits + U1), SS>, (Self, Self)>
+    where
+        SizeBits: Add<U1>,
+        <SizeBits as Add<U1>>::Output: Unsigned,
+        <SizeBits as Add<U1>>::Output: IsGreaterOrEqual<PageBits>,
+        <SizeBits as Add<U1>>::Output: Sub<PageBits>,
+        <<SizeBits as Add<U1>>::Output as Sub<PageBits>>::Output: Unsigned,
+        <<SizeBits as Add<U1>>::Output as Sub<PageBits>>::Output: _Pow,
+        Pow<<<SizeBits as Add<U1>>::Output as Sub<PageBits>>::Output>: Unsigned,
+    {
+        let size_bytes = self.size_bytes();
+        let adjacent = self.vaddr().checked_add(size_bytes) == Some(other.vaddr());
+        let consecutive_caps = self.caps.start_cptr + self.caps.len() == other.caps.start_cptr;
+        let same_mapping = self.asid() == other.asid() && self.rights() == other.rights();
+        let same_kind = match (self.kind, other.kind) {
+            (WeakMemoryKind::General, WeakMemoryKind::General) => true,
+            (WeakMemoryKind::Device { paddr: a }, WeakMemoryKind::Device { paddr: b }) => {
+                a.checked_add(size_bytes) == Some(b)
+            }
+            _ => false,
+        };
+        if !(adjacent && consecutive_caps && same_mapping && same_kind) {
+            return Err((self, other));
+        }
+
+        Ok(MappedMemoryRegion {
+            caps: CapRange::new(self.caps.start_cptr, self.caps.start_cap_data),
+            kind: self.kind,
+            _size_bits: PhantomData,
+            _shared_status: PhantomData,
+        })
+    }
+
     /// Splits a range into a specific size and a SizeBits-1 region.
     ///
     /// NB: This function drops on the floor the leftovers between
@@ -437,6 +656,33 @@ where
     }
 }
 
+impl<SizeBits: Unsigned> MappedMemoryRegion<SizeBits, shared_status::Exclusive>
+where
+    SizeBits: IsGreaterOrEqual<PageBits>,
+    SizeBits: Sub<PageBits>,
+    <SizeBits as Sub<PageBits>>::Output: Unsigned,
+    <SizeBits as Sub<PageBits>>::Output: _Pow,
+    Pow<<SizeBits as Sub<PageBits>>::Output>: Unsigned,
+{
+    /// Zero the whole region, then flush it out of the cache.
+    pub fn zero(&mut self) -> Result<(), VSpaceError> {
+        self.fill(0)
+    }
+
+    /// Set every byte of the region to `byte`, then flush it out of
+    /// the cache.
+    ///
+    /// Only exclusive regions can be filled, since a shared one may be
+    /// in use by another process.
+    pub fn fill(&mut self, byte: u8) -> Result<(), VSpaceError> {
+        if !self.rights().is_writable() {
+            return Err(VSpaceError::RegionNotWritable);
+        }
+        unsafe { core::ptr::write_bytes(self.vaddr() as *mut u8, byte, self.size_bytes()) };
+        Ok(self.flush()?)
+    }
+}
+
 pub struct WeakMemoryRegion<State: PageState, SS: SharedStatus, CapRole: CNodeRole = role::Local> {
     pub(super) caps: WeakCapRange<Page<State>, CapRole>,
     pub(super) kind: WeakMemoryKind,
@@ -444,6 +690,11 @@ pub struct WeakMemoryRegion<State: PageState, SS: SharedStatus, CapRole: CNodeRo
     _shared_status: PhantomData<SS>,
 }
 
+unsafe impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> AddressSpaceIndependent
+    for WeakMemoryRegion<State, SS, CapRole>
+{
+}
+
 impl WeakMemoryRegion<page_state::Unmapped, shared_status::Exclusive> {
     pub fn new<MemKind: MemoryKind>(
         untyped: LocalCap<WUntyped<MemKind>>,
@@ -523,7 +774,26 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
         ))
     }
 
-    pub fn to_shared(self) -> WeakMemoryRegion<State, shared_status::Shared, CapRole> {
+    /// Mark this region as shared. One which is already shared keeps
+    /// its limit on rights.
+    pub fn to_shared(self) -> WeakMemoryRegion<State, shared_status::Shared<SS::Rights>, CapRole> {
+        WeakMemoryRegion {
+            caps: self.caps,
+            kind: self.kind,
+            size_bits: self.size_bits,
+            _shared_status: PhantomData,
+        }
+    }
+}
+
+impl<State: PageState, CapRole: CNodeRole>
+    WeakMemoryRegion<State, shared_status::Exclusive, CapRole>
+{
+    /// As `to_shared`, but never to be mapped with more than rights
+    /// `R`.
+    pub fn to_shared_with_rights<R: Rights>(
+        self,
+    ) -> WeakMemoryRegion<State, shared_status::Shared<R>, CapRole> {
         WeakMemoryRegion {
             caps: self.caps,
             kind: self.kind,
@@ -533,6 +803,29 @@ impl<State: PageState, SS: SharedStatus, CapRole: CNodeRole> WeakMemoryRegion<St
     }
 }
 
+impl<SS: SharedStatus> WeakMappedMemoryRegion<SS> {
+    /// The page caps backing this region, in address order.
+    pub fn pages(&self) -> impl Iterator<Item = RegionPage<'_>> + '_ {
+        region_pages(
+            &self.caps.start_cap_data,
+            self.caps.start_cptr,
+            self.caps.len(),
+        )
+    }
+
+    /// The page cap backing the `index`th page of this region, which
+    /// starts at `vaddr() + index * PageBytes::USIZE`.
+    pub fn page(&self, index: usize) -> Option<RegionPage<'_>> {
+        self.pages().nth(index)
+    }
+
+    /// The physical address of the start of this region, which must be
+    /// physically contiguous; see `MappedMemoryRegion::start_paddr`.
+    pub fn start_paddr(&self) -> Result<usize, VSpaceError> {
+        contiguous_start_paddr(self.pages())
+    }
+}
+
 impl<SS: SharedStatus, CapRole: CNodeRole> WeakMappedMemoryRegion<SS, CapRole> {
     pub fn vaddr(&self) -> usize {
         self.caps.start_cap_data.state.vaddr
@@ -551,6 +844,51 @@ impl<SS: SharedStatus, CapRole: CNodeRole> WeakMappedMemoryRegion<SS, CapRole> {
     }
 }
 
+/// One of the pages backing a mapped region. It derefs to the page's
+/// cap, which remains owned by the region.
+pub struct RegionPage<'a> {
+    cap: LocalCap<Page<page_state::Mapped>>,
+    _region: PhantomData<&'a ()>,
+}
+
+impl<'a> Deref for RegionPage<'a> {
+    type Target = LocalCap<Page<page_state::Mapped>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.cap
+    }
+}
+
+fn region_pages<'a>(
+    start_cap_data: &'a Page<page_state::Mapped>,
+    start_cptr: usize,
+    len: usize,
+) -> impl Iterator<Item = RegionPage<'a>> + 'a {
+    (0..len).map(move |index| RegionPage {
+        cap: Cap {
+            cptr: start_cptr + index,
+            _role: PhantomData,
+            cap_data: Page::reconstruct(index, start_cap_data),
+        },
+        _region: PhantomData,
+    })
+}
+
+fn contiguous_start_paddr<'a>(
+    mut pages: impl Iterator<Item = RegionPage<'a>>,
+) -> Result<usize, VSpaceError> {
+    let start = match pages.next() {
+        Some(page) => page.paddr()?,
+        None => return Err(VSpaceError::InvalidRegionSize),
+    };
+    for (index, page) in pages.enumerate() {
+        if page.paddr()? != start + (index + 1) * PageBytes::USIZE {
+            return Err(VSpaceError::NotPhysicallyContiguous);
+        }
+    }
+    Ok(start)
+}
+
 #[derive(Debug, PartialEq)]
 pub(super) enum InvalidSizeBits {
     TooSmallToRepresentAPage,